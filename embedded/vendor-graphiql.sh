@@ -0,0 +1,16 @@
+#!/bin/sh
+# Download the pinned GraphiQL bundle into the "graphiql" directory, which is embedded into the
+# binary. Run it after changing the versions and commit the files.
+set -eu
+
+REACT_VERSION=18.3.1
+GRAPHIQL_VERSION=3.7.1
+
+cd "$(dirname "$0")/graphiql"
+fetch() {
+  curl --fail --silent --show-error --location --output "$2" "https://unpkg.com/$1"
+}
+fetch "react@$REACT_VERSION/umd/react.production.min.js" react.production.min.js
+fetch "react-dom@$REACT_VERSION/umd/react-dom.production.min.js" react-dom.production.min.js
+fetch "graphiql@$GRAPHIQL_VERSION/graphiql.min.js" graphiql.min.js
+fetch "graphiql@$GRAPHIQL_VERSION/graphiql.min.css" graphiql.min.css
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use log::{error, info};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use strum::IntoEnumIterator;
+use tokio::{select, sync::mpsc};
+
+use crate::{
+    audio::SoundLibrary,
+    core::ShutdownNotify,
+    device::piano::Piano,
+    files::{Asset, AssetsDir, BaseDir, Sound},
+};
+
+/// Reloads the changed sounds and the recording cover image, so a restart is not required.
+/// The site and GraphiQL are read on each request, so they don't need it.
+pub struct AssetsWatcher {
+    assets_dir: AssetsDir,
+    sounds: SoundLibrary,
+    piano: Piano,
+}
+
+impl AssetsWatcher {
+    pub fn new(assets_dir: AssetsDir, sounds: SoundLibrary, piano: Piano) -> Self {
+        Self {
+            assets_dir,
+            sounds,
+            piano,
+        }
+    }
+
+    /// Returns on shutdown or if the watcher fails.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) -> notify::Result<()> {
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        // Callback is called on the watcher thread.
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = event_tx.send(event);
+        })?;
+        watcher.watch(self.assets_dir.root(), RecursiveMode::Recursive)?;
+        info!("Watching for the assets changes");
+
+        loop {
+            select! {
+                Some(event) = event_rx.recv() => match event {
+                    Ok(event) => self.handle_event(event).await,
+                    Err(e) => error!("Failed to watch the assets: {e}"),
+                },
+                _ = shutdown_notify.notified() => break,
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_event(&self, event: Event) {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return;
+        }
+        for path in &event.paths {
+            if let Some(sound) = self.find_sound(path) {
+                info!("Sound \"{sound}\" changed, it will be reloaded");
+                self.sounds.unload(sound);
+            } else if path
+                == self
+                    .assets_dir
+                    .path(Asset::PianoRecordingCoverJPEG)
+                    .as_path()
+            {
+                info!("Recordings cover image changed, reloading it");
+                self.piano.reload_recording_cover().await;
+            }
+        }
+    }
+
+    fn find_sound(&self, path: &Path) -> Option<Sound> {
+        Sound::iter().find(|sound| path == self.assets_dir.path(Asset::Sound(*sound)).as_path())
+    }
+}
@@ -0,0 +1,79 @@
+use std::{env, io, process::Stdio};
+
+use log::warn;
+use tokio::{fs, io::AsyncWriteExt, process::Command};
+
+use super::{AudioSource, AudioSourceError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClipError {
+    #[error("Only WAVE and Ogg Opus clips are supported")]
+    UnsupportedFormat,
+    #[error("Failed to run ffmpeg: {0}")]
+    RunFailed(io::Error),
+    #[error("ffmpeg failed: {0}")]
+    ConversionFailed(String),
+    #[error("Unable to read the converted clip: {0}")]
+    ReadFailed(io::Error),
+    #[error("Unable to decode the clip: {0}")]
+    DecodeFailed(AudioSourceError),
+}
+
+/// Decode a short clip (e.g. a voice announcement recorded on a phone), so it can be played
+/// using the secondary sink. Opus is converted to WAVE using ffmpeg (it must be installed).
+pub async fn decode(clip: Vec<u8>) -> Result<AudioSource, ClipError> {
+    if clip.starts_with(b"RIFF") {
+        AudioSource::wav_unbuffered(clip).map_err(ClipError::DecodeFailed)
+    } else if clip.starts_with(b"OggS") {
+        let wav = opus_to_wav(&clip).await?;
+        AudioSource::wav_unbuffered(wav).map_err(ClipError::DecodeFailed)
+    } else {
+        Err(ClipError::UnsupportedFormat)
+    }
+}
+
+async fn opus_to_wav(opus: &[u8]) -> Result<Vec<u8>, ClipError> {
+    // ffmpeg can't write a proper WAVE header to stdout as the length is unknown.
+    let wav_path = env::temp_dir().join(format!(
+        "{}-clip-{}.wav",
+        env!("CARGO_PKG_NAME"),
+        uuid::Uuid::new_v4()
+    ));
+    let mut child = Command::new("ffmpeg")
+        .args(["-nostdin", "-nostats", "-loglevel", "error"])
+        .args(["-f", "ogg", "-i", "pipe:0", "-y"])
+        .arg(&wav_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(ClipError::RunFailed)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // ffmpeg may exit before reading everything if the input is broken,
+        // the reason is reported using stderr.
+        let _ = stdin.write_all(opus).await;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(ClipError::RunFailed)?;
+
+    let result = if output.status.success() {
+        fs::read(&wav_path).await.map_err(ClipError::ReadFailed)
+    } else {
+        Err(ClipError::ConversionFailed(
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .last()
+                .unwrap_or("unknown error")
+                .to_string(),
+        ))
+    };
+    if let Err(e) = fs::remove_file(&wav_path).await {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove {}: {e}", wav_path.to_string_lossy());
+        }
+    }
+    result
+}
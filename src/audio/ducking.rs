@@ -0,0 +1,135 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Sample, Source};
+
+/// Volume of the ducked source while a sound is playing.
+const DUCKED_VOLUME: f32 = 0.3;
+/// How long it takes to lower or restore the volume.
+const DUCKING_RAMP: Duration = Duration::from_millis(150);
+
+/// Links the sources of a single output: while any sound of the group is playing,
+/// the ducked sources are played quieter.
+#[derive(Clone, Default)]
+pub struct DuckingGroup {
+    active_sounds: Arc<AtomicUsize>,
+}
+
+impl DuckingGroup {
+    fn is_ducking(&self) -> bool {
+        self.active_sounds.load(Ordering::Relaxed) != 0
+    }
+}
+
+pub enum DuckingRole {
+    /// Source which is lowered while the sounds are playing (e.g. a recording).
+    Ducked(DuckingGroup),
+    /// Source which lowers others (e.g. a feedback sound).
+    Sound(DuckingGroup),
+}
+
+/// Applies [DuckingRole] to the inner source. If role is [None], samples are passed as is.
+pub struct Ducked<S> {
+    input: S,
+    role: Option<DuckingRole>,
+    /// Current multiplier of the ducked source.
+    volume: f32,
+    /// Change of the volume per sample.
+    volume_step: f32,
+}
+
+impl<S> Ducked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    pub fn new(input: S, role: Option<DuckingRole>) -> Self {
+        if let Some(DuckingRole::Sound(group)) = &role {
+            group.active_sounds.fetch_add(1, Ordering::Relaxed);
+        }
+        let samples_per_ramp =
+            DUCKING_RAMP.as_secs_f32() * input.sample_rate() as f32 * input.channels() as f32;
+        Self {
+            input,
+            role,
+            volume: 1.0,
+            volume_step: (1.0 - DUCKED_VOLUME) / samples_per_ramp.max(1.0),
+        }
+    }
+}
+
+impl<S> Ducked<S> {
+    /// Let the ducked sources restore the volume. Does nothing if it's already called.
+    fn release_sound(&mut self) {
+        if let Some(DuckingRole::Sound(group)) = self.role.take() {
+            group.active_sounds.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<S> Iterator for Ducked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let Some(sample) = self.input.next() else {
+            self.release_sound();
+            return None;
+        };
+        let Some(DuckingRole::Ducked(group)) = &self.role else {
+            return Some(sample);
+        };
+        let target = if group.is_ducking() {
+            DUCKED_VOLUME
+        } else {
+            1.0
+        };
+        if self.volume > target {
+            self.volume = (self.volume - self.volume_step).max(target);
+        } else if self.volume < target {
+            self.volume = (self.volume + self.volume_step).min(target);
+        }
+        Some(sample.amplify(self.volume))
+    }
+}
+
+impl<S> Source for Ducked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+impl<S> Drop for Ducked<S> {
+    fn drop(&mut self) {
+        // Sound can be stopped before it's finished.
+        self.release_sound();
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Sample, Source};
+
+/// Enables the fade out at the end of a source which is already playing
+/// (e.g. when the next source is queued after it).
+#[derive(Clone, Default)]
+pub struct FadeOutControl {
+    /// Zero means the fade out is disabled.
+    duration_ms: Arc<AtomicU64>,
+}
+
+impl FadeOutControl {
+    pub fn enable(&self, duration: Duration) {
+        self.duration_ms
+            .store(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms.load(Ordering::Relaxed))
+    }
+}
+
+/// Lets to find out whether a queued source has started playing
+/// and to skip it (e.g. when the queue is cleared).
+#[derive(Clone, Default)]
+pub struct SkipControl {
+    started: Arc<AtomicBool>,
+    skipped: Arc<AtomicBool>,
+}
+
+impl SkipControl {
+    pub fn has_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Source will end right away.
+    pub fn skip(&self) {
+        self.skipped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Constant gain and an optional fade out at the end of the inner source.
+pub struct Envelope<S> {
+    input: S,
+    gain: f32,
+    fade_out: Option<FadeOutControl>,
+    skip: Option<SkipControl>,
+    /// [None] if the total duration is unknown, then fade out is not applied.
+    total_samples: Option<u64>,
+    elapsed_samples: u64,
+}
+
+impl<S> Envelope<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    pub fn new(
+        input: S,
+        gain: f32,
+        fade_out: Option<FadeOutControl>,
+        skip: Option<SkipControl>,
+    ) -> Self {
+        let total_samples = input
+            .total_duration()
+            .map(|duration| Self::samples_in(&input, duration));
+        Self {
+            input,
+            gain,
+            fade_out,
+            skip,
+            total_samples,
+            elapsed_samples: 0,
+        }
+    }
+
+    fn samples_in(input: &S, duration: Duration) -> u64 {
+        (duration.as_secs_f64() * input.sample_rate() as f64 * input.channels() as f64) as u64
+    }
+
+    fn fade_out_gain(&self) -> f32 {
+        let (Some(fade_out), Some(total_samples)) = (&self.fade_out, self.total_samples) else {
+            return 1.0;
+        };
+        let fade_samples = Self::samples_in(&self.input, fade_out.duration());
+        let remaining_samples = total_samples.saturating_sub(self.elapsed_samples);
+        if remaining_samples >= fade_samples {
+            1.0
+        } else {
+            remaining_samples as f32 / fade_samples as f32
+        }
+    }
+}
+
+impl<S> Iterator for Envelope<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        if let Some(skip) = &self.skip {
+            if skip.skipped.load(Ordering::Relaxed) {
+                return None;
+            }
+            if self.elapsed_samples == 0 {
+                skip.started.store(true, Ordering::Relaxed);
+            }
+        }
+        let sample = self.input.next()?;
+        self.elapsed_samples += 1;
+        Some(sample.amplify(self.gain * self.fade_out_gain()))
+    }
+}
+
+impl<S> Source for Envelope<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.elapsed_samples = Self::samples_in(&self.input, pos);
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use async_graphql::SimpleObject;
+use claxon::FlacReader;
+use ebur128::{EbuR128, Mode};
+
+/// Loudness the recordings are brought to when the normalization is enabled.
+/// It's the ReplayGain 2.0 reference level, so the stored gain is compatible with other players.
+const REFERENCE_LUFS: f64 = -18.0;
+const GAIN_TAG: &str = "REPLAYGAIN_TRACK_GAIN";
+const PEAK_TAG: &str = "REPLAYGAIN_TRACK_PEAK";
+/// Number of samples passed to the meter at once.
+const ANALYZE_CHUNK_SAMPLES: usize = 8192;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoudnessError {
+    #[error("Unable to open the file: {0}")]
+    OpenFile(io::Error),
+    #[error("FLAC decode failed: {0}")]
+    DecodeFlac(claxon::Error),
+    #[error("Loudness measurement failed: {0}")]
+    Measure(ebur128::Error),
+    #[error("Audio is silent")]
+    Silent,
+    #[error("Unable to save the FLAC tag: {0}")]
+    SaveTag(metaflac::Error),
+}
+
+/// EBU R128 loudness of a recording.
+#[derive(Clone, Copy, SimpleObject)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub integrated_lufs: f64,
+    /// Maximum true peak of all channels in dBTP.
+    pub true_peak_dbtp: f64,
+}
+
+impl Loudness {
+    /// Decodes **whole** FLAC file, so it can take a long time.
+    pub fn analyze_flac(flac_file: &Path) -> Result<Self, LoudnessError> {
+        let mut reader = FlacReader::new(BufReader::new(
+            File::open(flac_file).map_err(LoudnessError::OpenFile)?,
+        ))
+        .map_err(LoudnessError::DecodeFlac)?;
+        let streaminfo = reader.streaminfo();
+        let mut meter = EbuR128::new(
+            streaminfo.channels,
+            streaminfo.sample_rate,
+            Mode::I | Mode::TRUE_PEAK,
+        )
+        .map_err(LoudnessError::Measure)?;
+
+        // Meter expects the samples to take the whole 32-bit range.
+        let shift = 32 - streaminfo.bits_per_sample;
+        // Chunk must contain only complete frames.
+        let chunk_len =
+            ANALYZE_CHUNK_SAMPLES - ANALYZE_CHUNK_SAMPLES % streaminfo.channels as usize;
+        let mut chunk = Vec::with_capacity(chunk_len);
+        for sample in reader.samples() {
+            chunk.push(sample.map_err(LoudnessError::DecodeFlac)? << shift);
+            if chunk.len() == chunk_len {
+                meter
+                    .add_frames_i32(&chunk)
+                    .map_err(LoudnessError::Measure)?;
+                chunk.clear();
+            }
+        }
+        meter
+            .add_frames_i32(&chunk)
+            .map_err(LoudnessError::Measure)?;
+
+        let integrated_lufs = meter.loudness_global().map_err(LoudnessError::Measure)?;
+        let mut true_peak: f64 = 0.0;
+        for channel in 0..streaminfo.channels {
+            true_peak = true_peak.max(meter.true_peak(channel).map_err(LoudnessError::Measure)?);
+        }
+        if !integrated_lufs.is_finite() || true_peak == 0.0 {
+            return Err(LoudnessError::Silent);
+        }
+        Ok(Self {
+            integrated_lufs,
+            true_peak_dbtp: 20.0 * true_peak.log10(),
+        })
+    }
+
+    /// Returns [None] if the recording is not analyzed yet.
+    pub fn from_tag(tag: &metaflac::Tag) -> Option<Self> {
+        let comments = tag.vorbis_comments()?;
+        let read = |key: &str| {
+            comments
+                .get(key)?
+                .first()?
+                .trim_end_matches("dB")
+                .trim()
+                .parse::<f64>()
+                .ok()
+        };
+        let (gain, peak) = (read(GAIN_TAG)?, read(PEAK_TAG)?);
+        Some(Self {
+            integrated_lufs: REFERENCE_LUFS - gain,
+            true_peak_dbtp: 20.0 * peak.log10(),
+        })
+    }
+
+    /// Store the loudness as ReplayGain values, so they are used by other players too.
+    pub fn write_tag(&self, flac_file: &Path) -> Result<(), LoudnessError> {
+        let mut tag = metaflac::Tag::read_from_path(flac_file).map_err(LoudnessError::SaveTag)?;
+        let comments = tag.vorbis_comments_mut();
+        comments.set(
+            GAIN_TAG,
+            vec![format!("{:.2} dB", REFERENCE_LUFS - self.integrated_lufs)],
+        );
+        comments.set(
+            PEAK_TAG,
+            vec![format!("{:.6}", 10f64.powf(self.true_peak_dbtp / 20.0))],
+        );
+        tag.save().map_err(LoudnessError::SaveTag)
+    }
+
+    /// Multiplier for samples to bring the audio to the reference loudness.
+    /// Gain is limited to avoid clipping.
+    pub fn playback_gain(&self) -> f32 {
+        let gain_db = (REFERENCE_LUFS - self.integrated_lufs).min(-self.true_peak_dbtp);
+        10f64.powf(gain_db / 20.0) as f32
+    }
+}
@@ -1,23 +1,39 @@
+pub mod clip;
+pub mod ducking;
+pub mod envelope;
+pub mod loudness;
 pub mod player;
 pub mod recorder;
+pub mod resampler;
+pub mod router;
+pub mod transcode;
+pub mod tts;
+pub mod waveform;
 
 use std::{
     collections::HashMap,
     fs::{self, File},
     io::{self, BufReader, Cursor, Read, Seek, Write},
     path::Path,
-    sync::Arc,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
     time::{Duration, Instant},
 };
 
 use claxon::FlacReader;
-use cpal::SupportedStreamConfig;
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    SupportedStreamConfig,
+};
 use hound::{WavSpec, WavWriter};
-use log::debug;
+use log::{debug, error, warn};
 use rodio::{decoder::DecoderError, source, Decoder, Sink, Source};
 use strum::IntoEnumIterator;
 
 use crate::files::{Asset, AssetsDir, BaseDir, Sound};
+use ducking::{Ducked, DuckingRole};
+use envelope::{Envelope, FadeOutControl, SkipControl};
+use resampler::{Resample, Resampled};
 
 type BufferedDecoder<T> = source::Buffered<Decoder<T>>;
 
@@ -31,6 +47,8 @@ pub enum AudioSourceError {
     BuildDecoder(DecoderError),
     #[error("FLAC decode failed: {0}")]
     DecodeFlac(FlacToWavError),
+    #[error("Loading task failed: {0}")]
+    LoadTaskFailed(tokio::task::JoinError),
 }
 
 #[derive(Default)]
@@ -39,6 +57,15 @@ pub struct AudioSourceProperties {
     pub fade_in: Option<Duration>,
     /// Whether to repeat an audio source forever.
     pub repeat: bool,
+    /// Convert the sample rate if it differs from the output one.
+    /// If [None], conversion is left to [rodio].
+    pub resample: Option<Resample>,
+    pub ducking: Option<DuckingRole>,
+    /// Multiplier for samples of this source only, unlike the sink volume.
+    pub gain: Option<f32>,
+    /// Lets to enable the fade out when the source is already playing.
+    pub fade_out: Option<FadeOutControl>,
+    pub skip: Option<SkipControl>,
 }
 
 /// Every modification of a source leads to the new object with different type.
@@ -93,20 +120,12 @@ impl AudioSource {
     ///
     /// _Decoding can take a long time_, depending on file size and compression level.
     pub fn flac_decoded_unbuffered(flac_file: &Path) -> Result<Self, AudioSourceError> {
-        let flac_reader =
-            BufReader::new(File::open(flac_file).map_err(AudioSourceError::OpenFile)?);
-        let mut wav_writer = Cursor::new(Vec::new());
-
-        let decode_start = Instant::now();
-        flac_to_wav(flac_reader, &mut wav_writer).map_err(AudioSourceError::DecodeFlac)?;
-        debug!(
-            "FLAC file {} decoded in {} ms",
-            flac_file.to_string_lossy(),
-            decode_start.elapsed().as_millis()
-        );
-
-        wav_writer.set_position(0);
-        Decoder::new_wav(wav_writer)
+        Self::wav_unbuffered(decode_flac_file(flac_file)?)
+    }
+
+    /// Returns [AudioSource::UnbufferedMemory] reading the given WAVE data.
+    pub fn wav_unbuffered(wav: Vec<u8>) -> Result<Self, AudioSourceError> {
+        Decoder::new_wav(Cursor::new(wav))
             .map(|decoder| Self::UnbufferedMemory(Box::new(decoder)))
             .map_err(AudioSourceError::BuildDecoder)
     }
@@ -121,17 +140,42 @@ impl AudioSource {
 
     pub fn append_to(self, sink: &Sink, properties: AudioSourceProperties) {
         match self {
-            AudioSource::File(buf_reader) => {
-                append_source_to_sink!(sink, buf_reader, properties)
-            }
-            AudioSource::Memory(cursor) => append_source_to_sink!(sink, cursor, properties),
-            AudioSource::UnbufferedMemory(cursor) => {
-                append_source_to_sink!(sink, *cursor, properties)
-            }
+            AudioSource::File(buf_reader) => append_source(sink, buf_reader, properties),
+            AudioSource::Memory(cursor) => append_source(sink, cursor, properties),
+            AudioSource::UnbufferedMemory(cursor) => append_source(sink, *cursor, properties),
         };
     }
 }
 
+fn append_source<S>(sink: &Sink, source: S, mut properties: AudioSourceProperties)
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    let source = Envelope::new(
+        Ducked::new(source, properties.ducking.take()),
+        properties.gain.unwrap_or(1.0),
+        properties.fade_out.take(),
+        properties.skip.take(),
+    );
+    let (channels, sample_rate) = (source.channels(), source.sample_rate());
+    let resampler = properties
+        .resample
+        .filter(|resample| resample.sample_rate != sample_rate)
+        .and_then(|resample| {
+            resampler::new_resampler(channels, sample_rate, resample)
+                .map(|resampler| (resampler, resample.sample_rate))
+                .inspect_err(|e| warn!("Unable to convert the sample rate: {e}"))
+                .ok()
+        });
+    match resampler {
+        Some((resampler, output_sample_rate)) => {
+            let source = Resampled::new(source.convert_samples(), resampler, output_sample_rate);
+            append_source_to_sink!(sink, source, properties)
+        }
+        None => append_source_to_sink!(sink, source, properties),
+    }
+}
+
 impl Clone for AudioSource {
     fn clone(&self) -> Self {
         match self {
@@ -157,6 +201,21 @@ pub enum FlacToWavError {
     UpdateWaveHeader(hound::Error),
 }
 
+/// Decodes **whole** FLAC file into the WAVE data kept in the memory.
+fn decode_flac_file(flac_file: &Path) -> Result<Vec<u8>, AudioSourceError> {
+    let flac_reader = BufReader::new(File::open(flac_file).map_err(AudioSourceError::OpenFile)?);
+    let mut wav_writer = Cursor::new(Vec::new());
+
+    let decode_start = Instant::now();
+    flac_to_wav(flac_reader, &mut wav_writer).map_err(AudioSourceError::DecodeFlac)?;
+    debug!(
+        "FLAC file {} decoded in {} ms",
+        flac_file.to_string_lossy(),
+        decode_start.elapsed().as_millis()
+    );
+    Ok(wav_writer.into_inner())
+}
+
 /// Decodes **whole** FLAC data into the WAV. Metadata will be **lost**!
 ///
 /// Use `BufReader` / `BufWriter` if data not in the memory.
@@ -182,30 +241,116 @@ where
     writer.finalize().map_err(FlacToWavError::UpdateWaveHeader)
 }
 
+/// Sound card to play on.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    async_graphql::Enum,
+    serde::Serialize,
+    strum::Display,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "kebab-case")]
+pub enum AudioOutput {
+    #[default]
+    Piano,
+    /// Additional output-only device (see `monitor_output` in the configuration).
+    Monitor,
+    /// Multi-room speakers (see `snapcast` in the configuration).
+    Snapcast,
+}
+
 #[derive(Debug, strum::Display)]
 pub enum AudioObject {
     Player,
     Recorder,
 }
 
+/// Sounds are loaded into the memory on the first use.
 #[derive(Clone)]
-pub struct SoundLibrary(Arc<HashMap<Sound, AudioSource>>);
+pub struct SoundLibrary {
+    assets_dir: AssetsDir,
+    loaded: Arc<Mutex<HashMap<Sound, AudioSource>>>,
+}
 
 impl SoundLibrary {
-    /// Pre-load all sounds into the memory.
-    pub fn load(assets_dir: &AssetsDir) -> Result<Self, AudioSourceError> {
-        let mut sounds = HashMap::new();
-        for sound in Sound::iter() {
-            sounds.insert(
-                sound,
-                AudioSource::memory(&assets_dir.path(Asset::Sound(sound)))?,
-            );
+    /// If `preload` is set, all sounds are loaded in parallel right away,
+    /// so a missing one is reported on the startup.
+    pub fn new(assets_dir: AssetsDir, preload: bool) -> Result<Self, AudioSourceError> {
+        let library = Self {
+            assets_dir,
+            loaded: Arc::default(),
+        };
+        if preload {
+            let sounds = thread::scope(|scope| {
+                let library = &library;
+                let handles: Vec<_> = Sound::iter()
+                    .map(|sound| (sound, scope.spawn(move || library.load(sound))))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|(sound, handle)| {
+                        let source = handle.join().expect("sound loading panicked")?;
+                        Ok((sound, source))
+                    })
+                    .collect::<Result<HashMap<_, _>, _>>()
+            })?;
+            *library.lock() = sounds;
         }
-        Ok(Self(Arc::new(sounds)))
+        Ok(library)
+    }
+
+    pub async fn get(&self, sound: Sound) -> Result<AudioSource, AudioSourceError> {
+        if let Some(source) = self.lock().get(&sound) {
+            return Ok(source.clone());
+        }
+        // Decoding is blocking, so it's done outside of the runtime and the lock.
+        // Concurrent calls may load the same sound twice, the last one is kept.
+        let library = self.clone();
+        let source = tokio::task::spawn_blocking(move || library.load(sound))
+            .await
+            .map_err(AudioSourceError::LoadTaskFailed)??;
+        self.lock().insert(sound, source.clone());
+        Ok(source)
+    }
+
+    /// Remove `sound` from the memory, so it's loaded again on the next use.
+    pub fn unload(&self, sound: Sound) {
+        self.lock().remove(&sound);
+    }
+
+    fn load(&self, sound: Sound) -> Result<AudioSource, AudioSourceError> {
+        AudioSource::memory(&self.assets_dir.path(Asset::Sound(sound)))
     }
 
-    pub fn get(&self, sound: Sound) -> AudioSource {
-        self.0.get(&sound).expect("not all sounds loaded").clone()
+    fn lock(&self) -> MutexGuard<'_, HashMap<Sound, AudioSource>> {
+        self.loaded.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Negotiated format of an audio stream.
+#[derive(Clone, async_graphql::SimpleObject)]
+pub struct StreamFormat {
+    /// The same description as in the logs.
+    pub info: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// E.g. `i16` or `f32`.
+    pub sample_format: String,
+}
+
+impl From<&SupportedStreamConfig> for StreamFormat {
+    fn from(config: &SupportedStreamConfig) -> Self {
+        Self {
+            info: stream_info(config),
+            channels: config.channels(),
+            sample_rate: config.sample_rate().0,
+            sample_format: config.sample_format().to_string(),
+        }
     }
 }
 
@@ -219,3 +364,44 @@ pub fn stream_info(config: &SupportedStreamConfig) -> String {
         config.sample_format(),
     )
 }
+
+/// Names of the devices which can be used for the audio output.
+pub fn output_device_names() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            error!("Failed to list the output audio devices: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Find an output device by its full name (see [output_device_names]).
+pub fn find_output_device(name: &str) -> Option<cpal::Device> {
+    cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+}
+
+/// Find an ALSA device of the sound card with the `card_id` identifier (see /proc/asound/cards).
+pub fn find_device(alsa_plugin: &str, card_id: &str) -> Option<cpal::Device> {
+    let devices = match cpal::default_host().devices() {
+        Ok(devices) => devices,
+        Err(e) => {
+            error!("Failed to list the audio devices: {e}");
+            return None;
+        }
+    };
+    for device in devices {
+        match device.name() {
+            Ok(name) => {
+                if name.starts_with(&format!("{alsa_plugin}:CARD={card_id}")) {
+                    return Some(device);
+                }
+            }
+            Err(e) => error!("Failed to get an audio device name: {e}"),
+        }
+    }
+    None
+}
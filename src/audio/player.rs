@@ -1,18 +1,46 @@
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
-use cpal::{Device, Sample, SupportedStreamConfig};
+use cpal::{traits::DeviceTrait, Device, Sample, SupportedStreamConfig};
 use log::{error, info, warn};
-use rodio::{source::SeekError, OutputStream, OutputStreamHandle, PlayError, Sink, StreamError};
-use tokio::{sync::mpsc, task};
+use rodio::{
+    dynamic_mixer::{self, DynamicMixer, DynamicMixerController},
+    source::SeekError,
+    OutputStream, OutputStreamHandle, PlayError, Sink, StreamError,
+};
+use tokio::{
+    sync::mpsc::{self, error::TryRecvError},
+    task,
+};
 
 use crate::{
-    audio::{AudioSource, AudioSourceProperties},
-    core::human_duration,
+    audio::{
+        ducking::{DuckingGroup, DuckingRole},
+        envelope::FadeOutControl,
+        resampler::Resample,
+        AudioOutput, AudioSource, AudioSourceProperties, StreamFormat,
+    },
+    config::ResampleQuality,
+    core::{human_duration, panic::SupervisedThread},
     graphql::GraphQLError,
 };
 
 type PlayerResult<T> = Result<T, PlayerError>;
 
+/// Network output is always stereo.
+const NETWORK_CHANNELS: u16 = 2;
+/// Samples (of all channels) written to the network output at once.
+/// It's about 20 ms, so the commands are handled without noticeable delay.
+const NETWORK_CHUNK_MS: u32 = 20;
+/// How often to check whether the end of the loop region is reached.
+const LOOP_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
 #[derive(Debug, strum::AsRefStr, thiserror::Error)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum PlayerError {
@@ -20,6 +48,8 @@ pub enum PlayerError {
     CreateOutputStreamError(StreamError),
     #[error("Failed to create a sink: {0}")]
     CreateSinkError(PlayError),
+    #[error("Failed to connect to the network output: {0}")]
+    ConnectFailed(io::Error),
     #[error("Playback stream closed")]
     StreamClosed,
 
@@ -44,6 +74,8 @@ pub struct PlaybackProperties {
     /// Multiplier for samples.
     pub volume: f32,
     pub source_props: AudioSourceProperties,
+    /// Used by the owner of the players to pick the right one. Ignored by [Player] itself.
+    pub output: AudioOutput,
 }
 
 impl Default for PlaybackProperties {
@@ -52,17 +84,24 @@ impl Default for PlaybackProperties {
             secondary: false,
             volume: f32::IDENTITY,
             source_props: AudioSourceProperties::default(),
+            output: AudioOutput::default(),
         }
     }
 }
 
 #[derive(Clone, Copy)]
 pub struct PlaybackPosition {
-    current: Duration,
+    pub current: Duration,
     /// [None] if total duration is unknown.
     total: Option<Duration>,
 }
 
+impl PlaybackPosition {
+    pub fn total(&self) -> Option<Duration> {
+        self.total
+    }
+}
+
 #[async_graphql::Object]
 impl PlaybackPosition {
     async fn current_ms(&self) -> u64 {
@@ -87,6 +126,7 @@ impl PlaybackPosition {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum SeekTo {
     /// Seek to `total_duration * percents`. Number is in range `[0.00, 1.00]`.
     Percents(f64),
@@ -94,9 +134,18 @@ pub enum SeekTo {
     Position(Duration),
 }
 
+/// Part of the source which is played repeatedly.
+#[derive(Clone, Copy)]
+pub struct LoopRegion {
+    pub from: Duration,
+    pub to: Duration,
+}
+
 #[derive(strum::Display)]
 enum Command {
     Play(AudioSource, PlaybackProperties),
+    /// Append to the primary sink with the given transition.
+    Enqueue(AudioSource, PlaybackProperties, Duration),
 
     // The following commands applicable for the primary sink only.
     IsPlaying,
@@ -104,6 +153,8 @@ enum Command {
     Pause,
     GetPosition,
     Seek(SeekTo),
+    /// Pass [None] to clear the region.
+    SetLoopRegion(Option<LoopRegion>),
 }
 
 enum Response {
@@ -117,50 +168,135 @@ enum Response {
     Position(Option<PlaybackPosition>),
 }
 
+/// Where the mixed audio goes.
+pub enum PlayerOutput {
+    Device(Device, SupportedStreamConfig),
+    /// Raw PCM (16-bit little-endian, stereo) sent to a TCP server (e.g. the Snapcast `tcp`
+    /// stream source in the server mode). Thread finishes if the connection breaks.
+    Network {
+        address: String,
+        sample_rate: u32,
+    },
+}
+
 pub struct Player {
     // When the command sender drops, playback thread finishes as well.
     command_tx: mpsc::Sender<Command>,
     result_rx: mpsc::Receiver<PlayerResult<Response>>,
+    /// Name and stream format of the device. [None] for [PlayerOutput::Network].
+    device: Option<(String, StreamFormat)>,
 }
 
 impl Player {
+    /// If `resample_quality` is [None], sample rate conversion is left to [rodio].
     pub async fn new(
-        device: Device,
-        output_stream_config: SupportedStreamConfig,
+        output: PlayerOutput,
+        resample_quality: Option<ResampleQuality>,
     ) -> PlayerResult<Self> {
         let (command_tx, mut command_rx) = mpsc::channel::<Command>(1);
         let (result_tx, mut result_rx) = mpsc::channel(1);
+        let device = match &output {
+            PlayerOutput::Device(device, config) => Some((
+                device.name().unwrap_or_default(),
+                StreamFormat::from(config),
+            )),
+            PlayerOutput::Network { .. } => None,
+        };
 
         task::spawn_blocking(move || {
+            // Panic finishes the thread, so the player is recreated by the supervisor.
+            let _supervised = SupervisedThread::enter();
             let send_error = |err| {
                 error!("Player error: {err}");
                 let _ = result_tx.blocking_send(Err(err));
             };
 
-            let (_stream, stream_handle) =
-                match OutputStream::try_from_device_config(&device, output_stream_config) {
-                    Ok(result) => result,
-                    Err(e) => return send_error(PlayerError::CreateOutputStreamError(e)),
-                };
-            let primary_sink = match Sink::try_new(&stream_handle) {
+            // Stream must be alive until the thread finishes.
+            let (_stream, mixer, mut network_output, sample_rate) = match output {
+                PlayerOutput::Device(device, config) => {
+                    let sample_rate = config.sample_rate().0;
+                    match OutputStream::try_from_device_config(&device, config) {
+                        Ok((stream, handle)) => {
+                            (Some(stream), Mixer::Stream(handle), None, sample_rate)
+                        }
+                        Err(e) => return send_error(PlayerError::CreateOutputStreamError(e)),
+                    }
+                }
+                PlayerOutput::Network {
+                    address,
+                    sample_rate,
+                } => {
+                    let connection = match TcpStream::connect(&address) {
+                        Ok(connection) => connection,
+                        Err(e) => return send_error(PlayerError::ConnectFailed(e)),
+                    };
+                    let (controller, mixer) = dynamic_mixer::mixer(NETWORK_CHANNELS, sample_rate);
+                    let network_output = NetworkOutput {
+                        connection,
+                        mixer,
+                        chunk_len: (sample_rate * NETWORK_CHUNK_MS / 1000) as usize
+                            * NETWORK_CHANNELS as usize,
+                        buffer: Vec::new(),
+                    };
+                    (
+                        None,
+                        Mixer::Controller(controller),
+                        Some(network_output),
+                        sample_rate,
+                    )
+                }
+            };
+            let resample = resample_quality.map(|quality| Resample {
+                sample_rate,
+                quality,
+            });
+            let primary_sink = match mixer.new_sink() {
                 Ok(sink) => sink,
                 Err(e) => return send_error(PlayerError::CreateSinkError(e)),
             };
             let _ = result_tx.blocking_send(Ok(Response::Initialized));
             info!("Playback started");
 
-            let mut current_source_duration = None;
-            while let Some(command) = command_rx.blocking_recv() {
-                match handle_command(HandleInput {
+            let mut primary_queue = PrimaryQueue::default();
+            let ducking_group = DuckingGroup::default();
+            let handle =
+                |command, primary_queue: &mut PrimaryQueue| match handle_command(HandleInput {
                     command,
-                    stream_handle: &stream_handle,
+                    mixer: &mixer,
                     primary_sink: &primary_sink,
-                    current_source_duration: &mut current_source_duration,
+                    primary_queue,
+                    resample,
+                    ducking_group: &ducking_group,
                 }) {
                     Ok(response) => {
                         let _ = result_tx.blocking_send(Ok(response));
                     }
                     Err(e) => send_error(e),
+                };
+            loop {
+                // Nothing to do between the commands, so wait for them.
+                let command = if network_output.is_none() && primary_queue.loop_region.is_none() {
+                    command_rx.blocking_recv().ok_or(TryRecvError::Disconnected)
+                } else {
+                    command_rx.try_recv()
+                };
+                match command {
+                    Ok(command) => handle(command, &mut primary_queue),
+                    Err(TryRecvError::Disconnected) => break,
+                    Err(TryRecvError::Empty) => {
+                        match &mut network_output {
+                            // Writing blocks while the server is not ready to receive,
+                            // so the output is paced by the server.
+                            Some(network_output) => {
+                                if let Err(e) = network_output.write_chunk() {
+                                    error!("Network output failed: {e}");
+                                    break;
+                                }
+                            }
+                            None => thread::sleep(LOOP_CHECK_INTERVAL),
+                        }
+                        primary_queue.repeat_loop_region(&primary_sink);
+                    }
                 }
             }
             info!("Playback thread finished");
@@ -173,10 +309,22 @@ impl Player {
                 result.map(|_| Self {
                     command_tx,
                     result_rx,
+                    device,
                 })
             })
     }
 
+    /// Name and stream format of the output device.
+    /// Returns [None] if the output is not a device.
+    pub fn device(&self) -> Option<&(String, StreamFormat)> {
+        self.device.as_ref()
+    }
+
+    /// Returns `false` if the playback thread finished (for example, because of a panic).
+    pub fn is_alive(&self) -> bool {
+        !self.command_tx.is_closed()
+    }
+
     /// If the primary sink chosen and it's already playing a source, then it will be replaced.
     pub async fn play(
         &mut self,
@@ -186,6 +334,23 @@ impl Player {
         self.perform(Command::Play(source, props)).await.map(|_| ())
     }
 
+    /// Play `source` in the primary sink after the current one finishes.
+    /// If `transition` is zero, there is no gap between the sources.
+    /// Otherwise the current source fades out at its end and the new one fades in.
+    ///
+    /// Starts playing immediately if the primary sink is empty.
+    /// [PlaybackProperties::secondary] is ignored.
+    pub async fn enqueue(
+        &mut self,
+        source: AudioSource,
+        props: PlaybackProperties,
+        transition: Duration,
+    ) -> PlayerResult<()> {
+        self.perform(Command::Enqueue(source, props, transition))
+            .await
+            .map(|_| ())
+    }
+
     /// Returns `false` if the primary sink is not playing.
     pub async fn is_playing(&mut self) -> PlayerResult<bool> {
         self.perform_and_get_bool(Command::IsPlaying).await
@@ -221,6 +386,20 @@ impl Player {
         self.perform_and_get_bool(Command::Seek(to)).await
     }
 
+    /// Play `region` of the current source repeatedly, starting from its beginning.
+    /// The region is cleared when another source is played.
+    /// Returns `false` if the primary sink is empty.
+    pub async fn set_loop_region(&mut self, region: LoopRegion) -> PlayerResult<bool> {
+        self.perform_and_get_bool(Command::SetLoopRegion(Some(region)))
+            .await
+    }
+
+    /// Returns `false` if there was no loop region.
+    pub async fn clear_loop_region(&mut self) -> PlayerResult<bool> {
+        self.perform_and_get_bool(Command::SetLoopRegion(None))
+            .await
+    }
+
     async fn perform_and_get_bool(&mut self, command: Command) -> PlayerResult<bool> {
         self.perform(command).await.map(|response| match response {
             Response::BoolResult(result) => result,
@@ -245,16 +424,109 @@ impl Player {
     }
 }
 
+/// Creates the sinks which are played together.
+enum Mixer {
+    Stream(OutputStreamHandle),
+    Controller(Arc<DynamicMixerController<f32>>),
+}
+
+impl Mixer {
+    fn new_sink(&self) -> Result<Sink, PlayError> {
+        match self {
+            Self::Stream(handle) => Sink::try_new(handle),
+            Self::Controller(controller) => {
+                let (sink, output) = Sink::new_idle();
+                controller.add(output);
+                Ok(sink)
+            }
+        }
+    }
+}
+
+struct NetworkOutput {
+    connection: TcpStream,
+    mixer: DynamicMixer<f32>,
+    /// Number of samples written at once.
+    chunk_len: usize,
+    buffer: Vec<u8>,
+}
+
+impl NetworkOutput {
+    /// Silence is written if there is nothing to play, so the stream keeps going.
+    fn write_chunk(&mut self) -> io::Result<()> {
+        self.buffer.clear();
+        for _ in 0..self.chunk_len {
+            let sample = self.mixer.next().unwrap_or(f32::EQUILIBRIUM);
+            self.buffer
+                .extend_from_slice(&sample.to_sample::<i16>().to_le_bytes());
+        }
+        self.connection.write_all(&self.buffer)
+    }
+}
+
+/// Sources appended to the primary sink.
+#[derive(Default)]
+struct PrimaryQueue {
+    /// Total durations in the playing order, the first one is of the current source.
+    /// Finished sources are removed lazily.
+    durations: VecDeque<Option<Duration>>,
+    /// Belongs to the last appended source.
+    last_fade_out: Option<FadeOutControl>,
+    /// Applied to the current source.
+    loop_region: Option<LoopRegion>,
+}
+
+impl PrimaryQueue {
+    fn clear(&mut self) {
+        self.durations.clear();
+        self.last_fade_out = None;
+        self.loop_region = None;
+    }
+
+    /// Seek to the beginning of the loop region if its end is reached.
+    fn repeat_loop_region(&mut self, sink: &Sink) {
+        let Some(region) = self.loop_region else {
+            return;
+        };
+        if sink.empty() {
+            self.loop_region = None;
+        } else if sink.get_pos() >= region.to {
+            if let Err(e) = sink.try_seek(region.from) {
+                warn!("Failed to repeat the loop region: {e}");
+                self.loop_region = None;
+            }
+        }
+    }
+
+    /// Returns [None] if duration is unknown or the sink is empty.
+    fn current_duration(&mut self, sink: &Sink) -> Option<Duration> {
+        while self.durations.len() > sink.len() {
+            self.durations.pop_front();
+        }
+        self.durations.front().copied().flatten()
+    }
+}
+
 struct HandleInput<'a> {
     command: Command,
-    stream_handle: &'a OutputStreamHandle,
+    mixer: &'a Mixer,
     primary_sink: &'a Sink,
-    current_source_duration: &'a mut Option<Duration>,
+    primary_queue: &'a mut PrimaryQueue,
+    resample: Option<Resample>,
+    /// Secondary sounds lower the volume of the primary sink.
+    ducking_group: &'a DuckingGroup,
 }
 
 fn handle_command(input: HandleInput) -> PlayerResult<Response> {
     let response = match input.command {
-        Command::Play(source, props) => {
+        Command::Play(source, mut props) => {
+            props.source_props.resample = input.resample;
+            let ducking_group = input.ducking_group.clone();
+            props.source_props.ducking = Some(if props.secondary {
+                DuckingRole::Sound(ducking_group)
+            } else {
+                DuckingRole::Ducked(ducking_group)
+            });
             let duration = source.duration();
             let play = |sink: &Sink, seek_to_zero: bool| {
                 sink.set_volume(props.volume);
@@ -266,18 +538,53 @@ fn handle_command(input: HandleInput) -> PlayerResult<Response> {
                 sink.play();
             };
             if props.secondary {
-                let secondary_sink =
-                    Sink::try_new(input.stream_handle).map_err(PlayerError::CreateSinkError)?;
+                let secondary_sink = input
+                    .mixer
+                    .new_sink()
+                    .map_err(PlayerError::CreateSinkError)?;
                 play(&secondary_sink, false);
                 secondary_sink.detach();
             } else {
                 // Empty the queue.
                 input.primary_sink.stop();
+                input.primary_queue.clear();
                 play(input.primary_sink, true);
-                *input.current_source_duration = duration;
+                input.primary_queue.durations.push_back(duration);
             }
             Response::PlayStarted
         }
+        Command::Enqueue(source, mut props, transition) => {
+            let sink = input.primary_sink;
+            props.source_props.resample = input.resample;
+            props.source_props.ducking = Some(DuckingRole::Ducked(input.ducking_group.clone()));
+            let fade_out = FadeOutControl::default();
+            props.source_props.fade_out = Some(fade_out.clone());
+            let duration = source.duration();
+
+            if sink.empty() {
+                input.primary_queue.clear();
+                sink.set_volume(props.volume);
+                source.append_to(sink, props.source_props);
+                let _ = sink.try_seek(Duration::ZERO);
+                sink.play();
+            } else {
+                if !transition.is_zero() {
+                    if let Some(last_fade_out) = &input.primary_queue.last_fade_out {
+                        last_fade_out.enable(transition);
+                    }
+                    props.source_props.fade_in = Some(transition);
+                }
+                // Sink volume is shared by all sources in the queue.
+                let sink_volume = sink.volume();
+                if sink_volume > 0.0 {
+                    props.source_props.gain = Some(props.volume / sink_volume);
+                }
+                source.append_to(sink, props.source_props);
+            }
+            input.primary_queue.durations.push_back(duration);
+            input.primary_queue.last_fade_out = Some(fade_out);
+            Response::PlayStarted
+        }
         Command::IsPlaying => Response::BoolResult(is_playing(input.primary_sink)),
         Command::Resume => Response::BoolResult(
             if !input.primary_sink.is_paused() || input.primary_sink.empty() {
@@ -295,7 +602,7 @@ fn handle_command(input: HandleInput) -> PlayerResult<Response> {
         Command::GetPosition => {
             Response::Position((!input.primary_sink.empty()).then(|| PlaybackPosition {
                 current: input.primary_sink.get_pos(),
-                total: *input.current_source_duration,
+                total: input.primary_queue.current_duration(input.primary_sink),
             }))
         }
         Command::Seek(to) => Response::BoolResult(if input.primary_sink.empty() {
@@ -303,7 +610,8 @@ fn handle_command(input: HandleInput) -> PlayerResult<Response> {
         } else {
             let pos = match to {
                 SeekTo::Percents(percents) => input
-                    .current_source_duration
+                    .primary_queue
+                    .current_duration(input.primary_sink)
                     .ok_or(PlayerError::UnknownTotalDuration)?
                     .mul_f64(percents),
                 SeekTo::Position(duration) => duration,
@@ -314,6 +622,21 @@ fn handle_command(input: HandleInput) -> PlayerResult<Response> {
                 .map_err(PlayerError::SeekFailed)?;
             true
         }),
+        Command::SetLoopRegion(Some(region)) => {
+            Response::BoolResult(if input.primary_sink.empty() {
+                false
+            } else {
+                input
+                    .primary_sink
+                    .try_seek(region.from)
+                    .map_err(PlayerError::SeekFailed)?;
+                input.primary_queue.loop_region = Some(region);
+                true
+            })
+        }
+        Command::SetLoopRegion(None) => {
+            Response::BoolResult(input.primary_queue.loop_region.take().is_some())
+        }
     };
     Ok(response)
 }
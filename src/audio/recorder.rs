@@ -1,33 +1,40 @@
 use std::{
     cmp,
+    collections::VecDeque,
     fs::{self, File},
     io, mem,
     path::PathBuf,
     sync::{
-        atomic::{self, AtomicBool},
+        atomic::{self, AtomicBool, AtomicU64},
         mpsc::{self as std_mpsc, RecvTimeoutError},
-        Arc,
+        Arc, Mutex, PoisonError,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
+use async_graphql::SimpleObject;
 use cpal::{
     traits::{DeviceTrait, StreamTrait},
-    BuildStreamError, Device, PlayStreamError, Sample, SampleFormat, StreamError,
-    SupportedStreamConfig, SupportedStreamConfigsError,
+    BuildStreamError, Device, InputCallbackInfo, PlayStreamError, Sample, SampleFormat,
+    StreamError, StreamInstant, SupportedStreamConfig, SupportedStreamConfigsError,
 };
 use flac_bound::{FlacEncoder, FlacEncoderConfig, FlacEncoderState};
 use futures::{executor, future::BoxFuture};
-use log::{error, info};
+use log::{error, info, warn};
 use metaflac::block::PictureType;
+use serde::Serialize;
 use tokio::{
     select,
     sync::{mpsc as tokio_mpsc, watch},
     task,
 };
 
-use crate::{audio, config, core::ShutdownNotify};
+use crate::{
+    audio::{self, StreamFormat},
+    config,
+    core::{timezone, ShutdownNotify},
+};
 
 pub const RECORDING_EXTENSION: &str = ".flac";
 
@@ -35,6 +42,13 @@ pub const RECORDING_EXTENSION: &str = ".flac";
 type FLACSampleMax = i32;
 /// Maximum interval between checks whether audio processing should be stopped.
 const MAX_STOP_HANDLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Gap between the captured buffers which exceeds their duration by this value
+/// is considered as an overrun.
+const XRUN_TOLERANCE: Duration = Duration::from_millis(20);
+/// Silence is kept in memory until a sound is captured, so it can be dropped if the recording
+/// ends. If it's longer, the older part is written, so the trailing silence is trimmed
+/// only to this duration.
+const MAX_TRAILING_SILENCE: Duration = Duration::from_secs(60);
 
 pub struct RecordParams {
     /// Path of the output FLAC file. It will be created, so it must **not** exists.
@@ -45,15 +59,35 @@ pub struct RecordParams {
     pub artist: Option<String>,
     /// Recording's front cover image in the JPEG format.
     pub front_cover_jpeg: Option<Vec<u8>>,
+    /// Skip the leading and trailing silence if `trim_silence` is configured.
+    pub trim_silence: bool,
+    /// Called once if `stop_on_silence` is configured and there is no sound for the set time.
+    /// The recorder keeps running, so it's up to the callback to stop it.
+    pub on_prolonged_silence: Option<TimepointCallback>,
 }
 
 pub struct TimepointHandler {
-    /// How long to wait (since the recorder started) before calling the callback.
+    /// How long to record (since the recorder started, excluding pauses)
+    /// before calling the callback.
     pub at: Duration,
     pub callback: TimepointCallback,
 }
 
-type TimepointCallback = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+pub type TimepointCallback = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Details of the active recording.
+#[derive(SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecorderStatus {
+    /// Time of the captured audio, excluding the pauses.
+    pub elapsed_ms: u64,
+    /// Current size of the output file.
+    pub file_size_bytes: u64,
+    /// Selected input stream format.
+    pub stream_format: String,
+    /// Number of the input buffer overruns. Each of them means that some audio is lost.
+    pub xruns: u64,
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum RecordError {
@@ -61,6 +95,8 @@ pub enum RecordError {
     AlreadyRecording,
     #[error("Recorder has not been started")]
     NotRecording,
+    #[error("Device doesn't support FLAC-compatible input stream formats anymore")]
+    NoSupportedFormats,
     #[error("Unable to create a new output file ({0})")]
     CreateFileError(io::Error),
     #[error("Failed to prepare the FLAC encoder: {0}")]
@@ -104,8 +140,9 @@ impl RecordError {
 
 pub struct Recorder {
     device: Device,
+    config: config::Recorder,
+    /// Re-selected on each start, because the device may renegotiate the formats.
     stream_config: SupportedStreamConfig,
-    flac_compression_level: u32,
 
     /// Used to stop the recorder if the program is terminating.
     shutdown_notify: ShutdownNotify,
@@ -117,21 +154,48 @@ struct RecordHandlers {
     status_rx: tokio_mpsc::Receiver<StatusMessage>,
     // Stop trigger initiates by the caller to be handled by the processing thread.
     stop_trigger: Arc<AtomicBool>,
+    /// Samples are discarded by the processing thread while it's set.
+    pause_trigger: Arc<AtomicBool>,
+    /// Locked while [Self::pause_trigger] is changed.
+    paused_time: Arc<Mutex<PausedTime>>,
+    out_flac: PathBuf,
+    started_at: Instant,
+    /// Incremented by the input stream callback.
+    xruns: Arc<AtomicU64>,
 }
 
 impl RecordHandlers {
-    fn new() -> (Self, tokio_mpsc::Sender<StatusMessage>) {
+    fn new(out_flac: PathBuf) -> (Self, tokio_mpsc::Sender<StatusMessage>) {
         let (status_tx, status_rx) = tokio_mpsc::channel(1);
         (
             Self {
                 status_rx,
                 stop_trigger: Arc::default(),
+                pause_trigger: Arc::default(),
+                paused_time: Arc::default(),
+                out_flac,
+                started_at: Instant::now(),
+                xruns: Arc::default(),
             },
             status_tx,
         )
     }
 }
 
+#[derive(Default)]
+struct PausedTime {
+    /// Set while the recording is paused.
+    since: Option<Instant>,
+    /// Duration of the finished pauses.
+    total: Duration,
+}
+
+impl PausedTime {
+    fn elapsed(&self) -> Duration {
+        self.total + self.since.map(|since| since.elapsed()).unwrap_or_default()
+    }
+}
+
 enum StatusMessage {
     Error(RecordError),
     /// Processing successfully started.
@@ -156,8 +220,8 @@ impl Recorder {
             );
             Ok(Self {
                 device,
+                config,
                 stream_config,
-                flac_compression_level: config.flac_compression_level,
 
                 shutdown_notify,
                 record_handlers: None,
@@ -169,12 +233,13 @@ impl Recorder {
 
     pub async fn start(
         &mut self,
-        params: RecordParams,
+        mut params: RecordParams,
         timepoint_handler: Option<TimepointHandler>,
     ) -> Result<(), RecordError> {
         if self.record_handlers.is_some() {
             return Err(RecordError::AlreadyRecording);
         }
+        self.reselect_stream_config()?;
 
         let mut file = File::create_new(&params.out_flac).map_err(RecordError::CreateFileError)?;
         // To avoid cloning of the entire RecordParams which can be huge,
@@ -183,18 +248,40 @@ impl Recorder {
 
         // We can't create stream encoder here, because it can't be moved between threads.
         let device = self.device.clone();
-        let (stream_config, flac_compression_level) =
-            (self.stream_config.clone(), self.flac_compression_level);
+        let (stream_config, flac_compression_level) = (
+            self.stream_config.clone(),
+            self.config.flac_compression_level,
+        );
+        let silence_trimmer = self
+            .config
+            .trim_silence
+            .as_ref()
+            .filter(|_| params.trim_silence)
+            .map(|trim_silence| SilenceTrimmer::new(trim_silence, &stream_config));
+        let silence_watchdog = self
+            .config
+            .stop_on_silence
+            .as_ref()
+            .zip(params.on_prolonged_silence.take())
+            .map(|(stop_on_silence, callback)| {
+                SilenceWatchdog::new(stop_on_silence, &stream_config, callback)
+            });
 
         let shutdown_notify = self.shutdown_notify.clone();
-        let (mut handlers, status_tx) = RecordHandlers::new();
+        let (mut handlers, status_tx) = RecordHandlers::new(out_flac.clone());
         let stop_trigger = Arc::clone(&handlers.stop_trigger);
+        let pause_trigger = Arc::clone(&handlers.pause_trigger);
+        let mut xrun_detector = XrunDetector::new(&stream_config, Arc::clone(&handlers.xruns));
 
         // Recording starts when a change notification received.
         // If sender is dropped, it means that recorder finished (successfully or not).
         let (timepoint_handler_tx, timepoint_handler_rx) = watch::channel(());
         if let Some(timepoint_handler) = timepoint_handler {
-            spawn_timepoint_handler(timepoint_handler, timepoint_handler_rx);
+            spawn_timepoint_handler(
+                timepoint_handler,
+                Arc::clone(&handlers.paused_time),
+                timepoint_handler_rx,
+            );
         }
 
         task::spawn_blocking(move || {
@@ -245,7 +332,8 @@ impl Recorder {
             let stream = match stream_config.sample_format() {
                 SampleFormat::I8 => device.build_input_stream(
                     build_config,
-                    move |samples: &[i8], _| {
+                    move |samples: &[i8], info| {
+                        xrun_detector.check(info, samples.len());
                         scale_and_send_samples(samples, params.amplitude_scale, &samples_tx)
                     },
                     err_callback,
@@ -253,7 +341,8 @@ impl Recorder {
                 ),
                 SampleFormat::I16 => device.build_input_stream(
                     build_config,
-                    move |samples: &[i16], _| {
+                    move |samples: &[i16], info| {
+                        xrun_detector.check(info, samples.len());
                         scale_and_send_samples(samples, params.amplitude_scale, &samples_tx)
                     },
                     err_callback,
@@ -261,7 +350,8 @@ impl Recorder {
                 ),
                 SampleFormat::I32 => device.build_input_stream(
                     build_config,
-                    move |samples: &[i32], _| {
+                    move |samples: &[i32], info| {
+                        xrun_detector.check(info, samples.len());
                         scale_and_send_samples(samples, params.amplitude_scale, &samples_tx)
                     },
                     err_callback,
@@ -288,8 +378,11 @@ impl Recorder {
                 params,
                 stream_config,
                 encoder,
+                silence_trimmer,
+                silence_watchdog,
                 shutdown_notify,
                 stop_trigger,
+                pause_trigger,
                 samples_rx,
             });
             drop(stream);
@@ -304,6 +397,7 @@ impl Recorder {
         match handlers.status_rx.recv().await {
             Some(StatusMessage::Error(e)) => Err(e),
             Some(StatusMessage::Initialized) => {
+                handlers.started_at = Instant::now();
                 self.record_handlers = Some(handlers);
                 Ok(())
             }
@@ -312,6 +406,105 @@ impl Recorder {
         }
     }
 
+    /// Discard the captured audio until [Recorder::resume] is called, so the recording
+    /// continues in the same file. Returns `false` if it's already paused.
+    pub fn pause(&self) -> Result<bool, RecordError> {
+        let handlers = self
+            .record_handlers
+            .as_ref()
+            .ok_or(RecordError::NotRecording)?;
+        let mut paused_time = handlers
+            .paused_time
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let paused = !handlers.pause_trigger.swap(true, atomic::Ordering::Relaxed);
+        if paused {
+            paused_time.since = Some(Instant::now());
+            info!("Recording paused");
+        }
+        Ok(paused)
+    }
+
+    /// Returns `false` if it's not paused.
+    pub fn resume(&self) -> Result<bool, RecordError> {
+        let handlers = self
+            .record_handlers
+            .as_ref()
+            .ok_or(RecordError::NotRecording)?;
+        let mut paused_time = handlers
+            .paused_time
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let resumed = handlers
+            .pause_trigger
+            .swap(false, atomic::Ordering::Relaxed);
+        if resumed {
+            if let Some(since) = paused_time.since.take() {
+                paused_time.total += since.elapsed();
+            }
+            info!("Recording resumed");
+        }
+        Ok(resumed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.record_handlers
+            .as_ref()
+            .is_some_and(|handlers| handlers.pause_trigger.load(atomic::Ordering::Relaxed))
+    }
+
+    /// Query the supported formats again, as they can change while the device is in use
+    /// (e.g. after an A2DP source connected and disconnected). If they can't be queried,
+    /// the previous format is used.
+    fn reselect_stream_config(&mut self) -> Result<(), RecordError> {
+        let stream_config = match flac_supported_input_configs(&self.config, &self.device) {
+            Ok(configs) => configs
+                .into_iter()
+                .next()
+                .ok_or(RecordError::NoSupportedFormats)?,
+            Err(e) => {
+                warn!("Unable to query the input stream formats, using the previous one: {e}");
+                return Ok(());
+            }
+        };
+        if stream_config != self.stream_config {
+            info!(
+                "Input stream format changed to {}",
+                audio::stream_info(&stream_config)
+            );
+            self.stream_config = stream_config;
+        }
+        Ok(())
+    }
+
+    pub fn stream_format(&self) -> StreamFormat {
+        StreamFormat::from(&self.stream_config)
+    }
+
+    /// Returns [None] if the recorder is not started.
+    pub async fn status(&self) -> Option<RecorderStatus> {
+        let handlers = self.record_handlers.as_ref()?;
+        // Guard must be dropped before awaiting.
+        let paused = handlers
+            .paused_time
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed();
+        Some(RecorderStatus {
+            elapsed_ms: handlers
+                .started_at
+                .elapsed()
+                .saturating_sub(paused)
+                .as_millis() as u64,
+            file_size_bytes: tokio::fs::metadata(&handlers.out_flac)
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or_default(),
+            stream_format: audio::stream_info(&self.stream_config),
+            xruns: handlers.xruns.load(atomic::Ordering::Relaxed),
+        })
+    }
+
     pub async fn stop(&mut self) -> Result<(), RecordError> {
         if let Some(mut handlers) = self.record_handlers.take() {
             handlers.stop_trigger.store(true, atomic::Ordering::Relaxed);
@@ -340,20 +533,184 @@ impl Drop for Recorder {
     }
 }
 
-fn spawn_timepoint_handler(handler: TimepointHandler, mut proceed_rx: watch::Receiver<()>) {
+fn spawn_timepoint_handler(
+    handler: TimepointHandler,
+    paused_time: Arc<Mutex<PausedTime>>,
+    mut proceed_rx: watch::Receiver<()>,
+) {
     tokio::spawn(async move {
         // Wait until the recorder starts.
         if proceed_rx.changed().await.is_err() {
             // Processing thread closed.
             return;
         }
-        select! {
-            _ = tokio::time::sleep(handler.at) => (handler.callback)().await,
-            _ = proceed_rx.changed() => {}
+        let started_at = Instant::now();
+        loop {
+            let paused = paused_time
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .elapsed();
+            let recorded = started_at.elapsed().saturating_sub(paused);
+            // Pauses may happen while sleeping, so the remaining time is checked again.
+            let Some(remaining) = handler
+                .at
+                .checked_sub(recorded)
+                .filter(|duration| !duration.is_zero())
+            else {
+                (handler.callback)().await;
+                return;
+            };
+            select! {
+                _ = tokio::time::sleep(remaining) => {}
+                _ = proceed_rx.changed() => return,
+            }
         }
     });
 }
 
+/// Overruns are recovered silently by the audio library,
+/// so they are detected using the gaps between the capture timestamps.
+struct XrunDetector {
+    frame_duration: Duration,
+    channels: usize,
+    /// Capture time and duration of the previous buffer.
+    previous: Option<(StreamInstant, Duration)>,
+    xruns: Arc<AtomicU64>,
+}
+
+impl XrunDetector {
+    fn new(stream_config: &SupportedStreamConfig, xruns: Arc<AtomicU64>) -> Self {
+        Self {
+            frame_duration: Duration::from_secs(1) / stream_config.sample_rate().0,
+            channels: stream_config.channels() as usize,
+            previous: None,
+            xruns,
+        }
+    }
+
+    fn check(&mut self, info: &InputCallbackInfo, samples_count: usize) {
+        let capture = info.timestamp().capture;
+        if let Some((previous_capture, previous_duration)) = self.previous {
+            if capture
+                .duration_since(&previous_capture)
+                .is_some_and(|gap| gap > previous_duration + XRUN_TOLERANCE)
+            {
+                self.xruns.fetch_add(1, atomic::Ordering::Relaxed);
+            }
+        }
+        let frames = (samples_count / self.channels) as u32;
+        self.previous = Some((capture, self.frame_duration * frames));
+    }
+}
+
+/// Drops the leading silence and holds the silent buffers back until a sound is captured,
+/// so the trailing silence is not written.
+struct SilenceTrimmer {
+    /// Maximum absolute sample value of silence.
+    threshold: FLACSampleMax,
+    max_pending_samples: usize,
+    sound_captured: bool,
+    pending: VecDeque<Vec<FLACSampleMax>>,
+    pending_samples: usize,
+}
+
+impl SilenceTrimmer {
+    fn new(config: &config::TrimSilence, stream_config: &SupportedStreamConfig) -> Self {
+        Self {
+            threshold: silence_threshold(config.threshold_dbfs, stream_config),
+            max_pending_samples: (MAX_TRAILING_SILENCE.as_secs() * samples_per_sec(stream_config))
+                as _,
+            sound_captured: false,
+            pending: VecDeque::new(),
+            pending_samples: 0,
+        }
+    }
+
+    /// Returns the buffers to encode.
+    fn process(&mut self, samples: Vec<FLACSampleMax>) -> Vec<Vec<FLACSampleMax>> {
+        if !is_silent(&samples, self.threshold) {
+            if !self.sound_captured {
+                self.sound_captured = true;
+                info!("Sound captured, stopped trimming the silence");
+            }
+            self.pending_samples = 0;
+            let mut buffers: Vec<_> = self.pending.drain(..).collect();
+            buffers.push(samples);
+            return buffers;
+        }
+        if !self.sound_captured {
+            return Vec::new();
+        }
+        self.pending_samples += samples.len();
+        self.pending.push_back(samples);
+        let mut buffers = Vec::new();
+        while self.pending_samples > self.max_pending_samples {
+            let Some(buffer) = self.pending.pop_front() else {
+                break;
+            };
+            self.pending_samples -= buffer.len();
+            buffers.push(buffer);
+        }
+        buffers
+    }
+}
+
+/// Calls the callback once there is no sound for the configured time.
+struct SilenceWatchdog {
+    threshold: FLACSampleMax,
+    max_silent_samples: u64,
+    silent_samples: u64,
+    /// Taken when it's called.
+    callback: Option<TimepointCallback>,
+}
+
+impl SilenceWatchdog {
+    fn new(
+        config: &config::StopOnSilence,
+        stream_config: &SupportedStreamConfig,
+        callback: TimepointCallback,
+    ) -> Self {
+        Self {
+            threshold: silence_threshold(config.threshold_dbfs, stream_config),
+            max_silent_samples: u64::from(config.after_mins) * 60 * samples_per_sec(stream_config),
+            silent_samples: 0,
+            callback: Some(callback),
+        }
+    }
+
+    fn check(&mut self, samples: &[FLACSampleMax]) {
+        if !is_silent(samples, self.threshold) {
+            self.silent_samples = 0;
+            return;
+        }
+        self.silent_samples += samples.len() as u64;
+        if self.silent_samples >= self.max_silent_samples {
+            if let Some(callback) = self.callback.take() {
+                info!("Prolonged silence detected");
+                // Processing thread is spawned by the runtime, so its context is available.
+                tokio::spawn(callback());
+            }
+        }
+    }
+}
+
+/// Maximum absolute sample value which is considered as silence.
+fn silence_threshold(threshold_dbfs: f32, stream_config: &SupportedStreamConfig) -> FLACSampleMax {
+    let full_scale = (1_u64 << (stream_config.sample_format().sample_size() * 8 - 1)) as f32;
+    (10_f32.powf(threshold_dbfs / 20.0) * full_scale) as _
+}
+
+fn is_silent(samples: &[FLACSampleMax], threshold: FLACSampleMax) -> bool {
+    samples
+        .iter()
+        .all(|sample| sample.saturating_abs() <= threshold)
+}
+
+/// Number of samples of all channels.
+fn samples_per_sec(stream_config: &SupportedStreamConfig) -> u64 {
+    u64::from(stream_config.sample_rate().0) * u64::from(stream_config.channels())
+}
+
 type SamplesResult = Result<Vec<FLACSampleMax>, StreamError>;
 
 fn scale_and_send_samples<T>(
@@ -381,13 +738,16 @@ struct ProcessingLoopInput<'a> {
     /// Using it because in [cpal::StreamConfig] sample format is omitted.
     stream_config: SupportedStreamConfig,
     encoder: FlacEncoder<'a>,
+    silence_trimmer: Option<SilenceTrimmer>,
+    silence_watchdog: Option<SilenceWatchdog>,
     shutdown_notify: ShutdownNotify,
     stop_trigger: Arc<AtomicBool>,
+    pause_trigger: Arc<AtomicBool>,
     samples_rx: std_mpsc::Receiver<SamplesResult>,
 }
 
-// TODO: add an option for the silence trimming.
 fn processing_loop(mut input: ProcessingLoopInput) -> Result<(), RecordError> {
+    let channels = input.stream_config.channels() as usize;
     let mut total_samples_per_channel = 0;
     let mut result = loop {
         if input.stop_trigger.load(atomic::Ordering::Relaxed)
@@ -397,16 +757,28 @@ fn processing_loop(mut input: ProcessingLoopInput) -> Result<(), RecordError> {
         }
 
         match input.samples_rx.recv_timeout(MAX_STOP_HANDLE_INTERVAL) {
+            // Stream keeps capturing, so the samples don't pile up in the channel.
+            Ok(Ok(_)) if input.pause_trigger.load(atomic::Ordering::Relaxed) => {}
             Ok(Ok(samples)) => {
-                let samples_per_channel = samples.len() / input.stream_config.channels() as usize;
-                let result = input
-                    .encoder
-                    .process_interleaved(&samples, samples_per_channel as u32)
-                    .map_err(|_| input.encoder.state());
+                if let Some(watchdog) = &mut input.silence_watchdog {
+                    watchdog.check(&samples);
+                }
+                let buffers = match &mut input.silence_trimmer {
+                    Some(trimmer) => trimmer.process(samples),
+                    None => vec![samples],
+                };
+                let result = buffers.iter().try_for_each(|samples| {
+                    let samples_per_channel = samples.len() / channels;
+                    input
+                        .encoder
+                        .process_interleaved(samples, samples_per_channel as u32)
+                        .map_err(|_| input.encoder.state())?;
+                    total_samples_per_channel += samples_per_channel as u64;
+                    Ok(())
+                });
                 if let Err(e) = result {
                     break Err(RecordError::ProcessSamplesFailed(e));
                 }
-                total_samples_per_channel += samples_per_channel as u64;
             }
             Ok(Err(e)) => {
                 break Err(RecordError::StreamError(e));
@@ -442,7 +814,7 @@ fn embed_metadata(params: RecordParams, total_samples: u64) -> metaflac::Result<
     tag.set_streaminfo(stream_info);
 
     let vorbis_comments = tag.vorbis_comments_mut();
-    vorbis_comments.set_title(vec![chrono::Local::now()
+    vorbis_comments.set_title(vec![timezone::now()
         .format("%-d %B %Y, %R") // 6 November 2024, 15:58
         .to_string()]);
     if let Some(artist) = &params.artist {
@@ -494,3 +866,65 @@ fn flac_encoder_config(
             .compression_level(compression_level)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use cpal::{SampleRate, SupportedBufferSize};
+
+    use super::*;
+
+    /// Mono 1 Hz stream, so the trailing silence is limited by 60 samples.
+    fn trimmer() -> SilenceTrimmer {
+        let stream_config = SupportedStreamConfig::new(
+            1,
+            SampleRate(1),
+            SupportedBufferSize::Unknown,
+            SampleFormat::I16,
+        );
+        SilenceTrimmer::new(&config::TrimSilence::default(), &stream_config)
+    }
+
+    #[test]
+    fn threshold() {
+        let stream_config = SupportedStreamConfig::new(
+            2,
+            SampleRate(44_100),
+            SupportedBufferSize::Unknown,
+            SampleFormat::I16,
+        );
+        assert_eq!(silence_threshold(-50.0, &stream_config), 103);
+        assert_eq!(silence_threshold(0.0, &stream_config), 32768);
+    }
+
+    #[test]
+    fn leading_silence_dropped() {
+        let mut trimmer = trimmer();
+        assert!(trimmer.process(vec![0; 10]).is_empty());
+        assert!(trimmer.process(vec![-103, 103]).is_empty());
+        assert_eq!(trimmer.process(vec![0, 1000]), vec![vec![0, 1000]]);
+    }
+
+    #[test]
+    fn silence_between_sounds_kept() {
+        let mut trimmer = trimmer();
+        trimmer.process(vec![1000]);
+        assert!(trimmer.process(vec![0; 10]).is_empty());
+        assert!(trimmer.process(vec![5; 10]).is_empty());
+        assert_eq!(
+            trimmer.process(vec![-500]),
+            vec![vec![0; 10], vec![5; 10], vec![-500]]
+        );
+    }
+
+    #[test]
+    fn long_silence_released() {
+        let mut trimmer = trimmer();
+        trimmer.process(vec![1000]);
+        for _ in 0..6 {
+            assert!(trimmer.process(vec![0; 10]).is_empty());
+        }
+        // Exceeds 60 pending samples, so the oldest buffer is written.
+        assert_eq!(trimmer.process(vec![1; 10]), vec![vec![0; 10]]);
+        assert_eq!(trimmer.pending_samples, 60);
+    }
+}
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use log::error;
+use rodio::{source::SeekError, Source};
+use rubato::{
+    Resampler as _, ResamplerConstructionError, SincFixedIn, SincInterpolationParameters,
+    SincInterpolationType, WindowFunction,
+};
+
+use crate::config::ResampleQuality;
+
+/// Number of input frames converted at once.
+const CHUNK_FRAMES: usize = 1024;
+
+/// Sample rate conversion to apply before the source goes to an output.
+#[derive(Clone, Copy)]
+pub struct Resample {
+    /// Sample rate of the output stream.
+    pub sample_rate: u32,
+    pub quality: ResampleQuality,
+}
+
+pub fn new_resampler(
+    channels: u16,
+    source_sample_rate: u32,
+    resample: Resample,
+) -> Result<SincFixedIn<f32>, ResamplerConstructionError> {
+    let params = match resample.quality {
+        ResampleQuality::Fast => SincInterpolationParameters {
+            sinc_len: 64,
+            f_cutoff: 0.915,
+            oversampling_factor: 32,
+            interpolation: SincInterpolationType::Linear,
+            window: WindowFunction::Hann2,
+        },
+        ResampleQuality::Balanced => SincInterpolationParameters {
+            sinc_len: 128,
+            f_cutoff: 0.95,
+            oversampling_factor: 128,
+            interpolation: SincInterpolationType::Quadratic,
+            window: WindowFunction::Blackman2,
+        },
+        ResampleQuality::High => SincInterpolationParameters {
+            sinc_len: 256,
+            f_cutoff: 0.95,
+            oversampling_factor: 256,
+            interpolation: SincInterpolationType::Cubic,
+            window: WindowFunction::BlackmanHarris2,
+        },
+    };
+    SincFixedIn::new(
+        resample.sample_rate as f64 / source_sample_rate as f64,
+        // The ratio is fixed.
+        1.0,
+        params,
+        CHUNK_FRAMES,
+        channels as usize,
+    )
+}
+
+/// Source with the converted sample rate.
+pub struct Resampled<S> {
+    source: S,
+    resampler: SincFixedIn<f32>,
+    channels: u16,
+    sample_rate: u32,
+    /// Deinterleaved frames of the next chunk.
+    input: Vec<Vec<f32>>,
+    /// Interleaved converted samples.
+    output: Vec<f32>,
+    output_pos: usize,
+    state: State,
+}
+
+#[derive(PartialEq, Eq)]
+enum State {
+    Reading,
+    /// Inner source ended, delayed samples must be taken from the resampler.
+    Flushing,
+    Finished,
+}
+
+impl<S: Source<Item = f32>> Resampled<S> {
+    /// `resampler` must be created for the channels and sample rate of `source`.
+    pub fn new(source: S, resampler: SincFixedIn<f32>, sample_rate: u32) -> Self {
+        let channels = source.channels();
+        Self {
+            source,
+            resampler,
+            channels,
+            sample_rate,
+            input: vec![Vec::with_capacity(CHUNK_FRAMES); channels as usize],
+            output: Vec::new(),
+            output_pos: 0,
+            state: State::Reading,
+        }
+    }
+
+    /// Returns `false` if there are no more samples.
+    fn convert_next_chunk(&mut self) -> bool {
+        self.input.iter_mut().for_each(Vec::clear);
+        let result = match self.state {
+            State::Reading => {
+                let frames = self.resampler.input_frames_next();
+                'frames: for _ in 0..frames {
+                    for channel in 0..self.input.len() {
+                        match self.source.next() {
+                            Some(sample) => self.input[channel].push(sample),
+                            None => {
+                                self.state = State::Flushing;
+                                break 'frames;
+                            }
+                        }
+                    }
+                }
+                if self.state == State::Reading {
+                    self.resampler.process(&self.input, None)
+                } else {
+                    // Drop the incomplete frame.
+                    let frames = self.input.iter().map(Vec::len).min().unwrap_or_default();
+                    self.input
+                        .iter_mut()
+                        .for_each(|channel| channel.truncate(frames));
+                    self.resampler
+                        .process_partial(Some(self.input.as_slice()), None)
+                }
+            }
+            State::Flushing => {
+                self.state = State::Finished;
+                self.resampler.process_partial::<Vec<f32>>(None, None)
+            }
+            State::Finished => return false,
+        };
+
+        let chunk = match result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error!("Failed to convert the sample rate: {e}");
+                self.state = State::Finished;
+                return false;
+            }
+        };
+        self.output.clear();
+        self.output_pos = 0;
+        let frames = chunk.first().map_or(0, Vec::len);
+        for frame in 0..frames {
+            self.output
+                .extend(chunk.iter().map(|channel| channel[frame]));
+        }
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Resampled<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        while self.output_pos == self.output.len() {
+            if !self.convert_next_chunk() {
+                return None;
+            }
+        }
+        self.output_pos += 1;
+        Some(self.output[self.output_pos - 1])
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Resampled<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        // Samples before the new position must not leak into the output.
+        self.resampler.reset();
+        self.output.clear();
+        self.output_pos = 0;
+        self.state = State::Reading;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use async_graphql::InputObject;
+use cpal::Sample;
+
+use super::{AudioOutput, AudioSource, AudioSourceError};
+use crate::graphql::GraphQLError;
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum RouteError {
+    #[error("At least one output must be passed")]
+    NoOutputs,
+    #[error("Output {0} is passed more than once")]
+    DuplicateOutput(AudioOutput),
+    #[error("Volume must not be negative")]
+    NegativeVolume,
+}
+
+impl GraphQLError for RouteError {}
+
+/// Output to play on with its own volume.
+#[derive(Clone, Copy, InputObject)]
+pub struct OutputTarget {
+    pub output: AudioOutput,
+    /// Multiplier for samples. `1.0` is the original volume.
+    #[graphql(default = 1.0)]
+    pub volume: f32,
+}
+
+impl From<AudioOutput> for OutputTarget {
+    fn from(output: AudioOutput) -> Self {
+        Self {
+            output,
+            volume: f32::IDENTITY,
+        }
+    }
+}
+
+/// Outputs playing the same source simultaneously. The first one is _primary_:
+/// playback position is taken from it and only it's moved by the automatic routing.
+/// Others are _mirrors_ which follow the playback control commands.
+#[derive(Clone)]
+pub struct OutputRoute(Vec<OutputTarget>);
+
+impl OutputRoute {
+    pub fn new(targets: Vec<OutputTarget>) -> Result<Self, RouteError> {
+        if targets.is_empty() {
+            return Err(RouteError::NoOutputs);
+        }
+        for (index, target) in targets.iter().enumerate() {
+            if target.volume < 0.0 {
+                return Err(RouteError::NegativeVolume);
+            }
+            if targets[..index]
+                .iter()
+                .any(|previous| previous.output == target.output)
+            {
+                return Err(RouteError::DuplicateOutput(target.output));
+            }
+        }
+        Ok(Self(targets))
+    }
+
+    pub fn targets(&self) -> &[OutputTarget] {
+        &self.0
+    }
+
+    pub fn primary(&self) -> AudioOutput {
+        self.0[0].output
+    }
+
+    pub fn mirrors(&self) -> impl Iterator<Item = AudioOutput> + '_ {
+        self.0[1..].iter().map(|target| target.output)
+    }
+
+    pub fn contains(&self, output: AudioOutput) -> bool {
+        self.0.iter().any(|target| target.output == output)
+    }
+
+    /// Remove a mirror (e.g. if it failed to start). The primary output can't be removed.
+    pub fn remove_mirror(&mut self, output: AudioOutput) {
+        if self.primary() != output {
+            self.0.retain(|target| target.output != output);
+        }
+    }
+}
+
+impl Default for OutputRoute {
+    fn default() -> Self {
+        AudioOutput::default().into()
+    }
+}
+
+impl From<AudioOutput> for OutputRoute {
+    fn from(output: AudioOutput) -> Self {
+        Self(vec![output.into()])
+    }
+}
+
+/// Decoded audio which can be turned into independent sources, one for each output.
+/// Every source has its own position, so outputs can be seeked separately.
+pub struct FanoutSource {
+    /// WAVE data.
+    wav: Vec<u8>,
+}
+
+impl FanoutSource {
+    /// _Decoding can take a long time_, depending on file size and compression level.
+    pub fn decode_flac(flac_file: &Path) -> Result<Self, AudioSourceError> {
+        super::decode_flac_file(flac_file).map(|wav| Self { wav })
+    }
+
+    /// Returns [AudioSource::UnbufferedMemory], so it supports seeking.
+    pub fn source(&self) -> Result<AudioSource, AudioSourceError> {
+        AudioSource::wav_unbuffered(self.wav.clone())
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use actix_web::web;
+use async_graphql::{Enum, SimpleObject};
+use async_stream::stream;
+use futures::{pin_mut, Stream, StreamExt};
+use log::info;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+    process::Command,
+    sync::Semaphore,
+};
+
+use crate::{config, core::stdout_reader::StdoutReader, graphql::GraphQLError};
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum TranscodeError {
+    #[error("Too many transcoding jobs, try again later")]
+    QueueFull,
+    #[error("Failed to run ffmpeg: {0}")]
+    RunFailed(io::Error),
+    #[error("ffmpeg failed: {0}")]
+    ConversionFailed(String),
+}
+
+impl GraphQLError for TranscodeError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum, Deserialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum TranscodeFormat {
+    Opus,
+    Mp3,
+    /// Ogg Vorbis.
+    Ogg,
+    /// Lossless, but takes much more space than FLAC.
+    Wav,
+}
+
+impl TranscodeFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Opus => ".opus",
+            Self::Mp3 => ".mp3",
+            Self::Ogg => ".ogg",
+            Self::Wav => ".wav",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Opus | Self::Ogg => "audio/ogg",
+            Self::Mp3 => "audio/mpeg",
+            Self::Wav => "audio/wav",
+        }
+    }
+
+    fn codec_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Opus => &["-codec:a", "libopus", "-b:a", "160k"],
+            Self::Mp3 => &["-codec:a", "libmp3lame", "-q:a", "2"],
+            Self::Ogg => &["-codec:a", "libvorbis", "-q:a", "5"],
+            // Recordings can be 24-bit.
+            Self::Wav => &["-codec:a", "pcm_s24le"],
+        }
+    }
+
+    /// Container name, which is required when writing to a pipe.
+    fn muxer(self) -> &'static str {
+        match self {
+            Self::Opus => "opus",
+            Self::Mp3 => "mp3",
+            Self::Ogg => "ogg",
+            Self::Wav => "wav",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum TranscodeState {
+    /// Waiting for a free slot.
+    Queued,
+    Running,
+}
+
+#[derive(Clone, Copy, SimpleObject)]
+pub struct TranscodeJob {
+    id: u64,
+    format: TranscodeFormat,
+    state: TranscodeState,
+    /// Number from 0.00 to 1.00.
+    progress: f64,
+}
+
+/// Converts FLAC files using ffmpeg. Number of the simultaneous conversions is limited,
+/// so they don't take all CPU time.
+#[derive(Clone)]
+pub struct TranscodeQueue {
+    config: config::Transcode,
+    output_dir: PathBuf,
+    slots: Arc<Semaphore>,
+    next_id: Arc<AtomicU64>,
+    /// Only waiting and running jobs. Lock is never held across an await point.
+    jobs: Arc<Mutex<HashMap<u64, TranscodeJob>>>,
+}
+
+impl TranscodeQueue {
+    pub fn new(config: config::Transcode, output_dir: PathBuf) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(config.max_parallel_jobs)),
+            config,
+            output_dir,
+            next_id: Arc::default(),
+            jobs: Arc::default(),
+        }
+    }
+
+    /// Waiting and running jobs ordered by the submission time.
+    pub fn jobs(&self) -> Vec<TranscodeJob> {
+        let mut jobs: Vec<_> = self.lock_jobs().values().copied().collect();
+        jobs.sort_by_key(|job| job.id);
+        jobs
+    }
+
+    /// Convert `flac_file` which plays `duration` (used to report the progress).
+    /// Returns path of the new file, the caller must remove it.
+    /// If the returned future is dropped, ffmpeg is killed.
+    pub async fn transcode(
+        &self,
+        flac_file: &Path,
+        duration: Duration,
+        format: TranscodeFormat,
+    ) -> Result<PathBuf, TranscodeError> {
+        let job = JobGuard::submit(self, format)?;
+        let _slot = self
+            .slots
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        job.update(|job| job.state = TranscodeState::Running);
+
+        let output = self
+            .output_dir
+            .join(format!("{}{}", job.id, format.extension()));
+        let result = self.run_ffmpeg(&job, flac_file, &output, duration).await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&output).await;
+        }
+        result.map(|_| output)
+    }
+
+    /// Convert `flac_file` on the fly: the output is streamed while ffmpeg is running.
+    /// The job stays in the queue until the stream ends. If the stream is dropped,
+    /// ffmpeg is killed. Progress is not reported.
+    pub async fn stream(
+        &self,
+        flac_file: &Path,
+        format: TranscodeFormat,
+    ) -> Result<impl Stream<Item = io::Result<web::Bytes>>, TranscodeError> {
+        let job = JobGuard::submit(self, format)?;
+        let slot = Arc::clone(&self.slots)
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        job.update(|job| job.state = TranscodeState::Running);
+
+        info!(
+            "Streaming {} converted to {format}...",
+            flac_file.to_string_lossy()
+        );
+        let mut child = Command::new("ffmpeg")
+            .args(["-nostdin", "-nostats", "-loglevel", "error", "-i"])
+            .arg(flac_file)
+            .args(["-map_metadata", "0"])
+            .args(format.codec_args())
+            .args(["-f", format.muxer(), "pipe:1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            // Nobody reads it while streaming, so it could fill up and block ffmpeg.
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(TranscodeError::RunFailed)?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let chunks = StdoutReader::new(stdout).stream().await;
+        Ok(stream! {
+            let _job = job;
+            let _slot = slot;
+            pin_mut!(chunks);
+            while let Some(chunk) = chunks.next().await {
+                yield chunk;
+            }
+            match child.wait().await {
+                Ok(status) if status.success() => {}
+                Ok(status) => yield Err(io::Error::other(format!("ffmpeg failed ({status})"))),
+                Err(e) => yield Err(e),
+            }
+        })
+    }
+
+    async fn run_ffmpeg(
+        &self,
+        job: &JobGuard,
+        input: &Path,
+        output: &Path,
+        duration: Duration,
+    ) -> Result<(), TranscodeError> {
+        info!(
+            "Converting {} to {}...",
+            input.to_string_lossy(),
+            job.format
+        );
+        let mut child = Command::new("ffmpeg")
+            .args(["-nostdin", "-nostats", "-loglevel", "error", "-i"])
+            .arg(input)
+            .args(["-map_metadata", "0"])
+            .args(job.format.codec_args())
+            .args(["-progress", "pipe:1", "-y"])
+            .arg(output)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(TranscodeError::RunFailed)?;
+
+        // Progress is reported as "key=value" lines.
+        if let Some(stdout) = child.stdout.take() {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Some(out_time_us) = line
+                    .strip_prefix("out_time_us=")
+                    .and_then(|value| value.parse::<u64>().ok())
+                else {
+                    continue;
+                };
+                let progress =
+                    Duration::from_micros(out_time_us).as_secs_f64() / duration.as_secs_f64();
+                job.update(|job| job.progress = progress.clamp(0.0, 1.0));
+            }
+        }
+
+        let mut stderr = String::new();
+        if let Some(mut child_stderr) = child.stderr.take() {
+            let _ = child_stderr.read_to_string(&mut stderr).await;
+        }
+        let status = child.wait().await.map_err(TranscodeError::RunFailed)?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(TranscodeError::ConversionFailed(
+                stderr.lines().last().unwrap_or("unknown error").to_string(),
+            ))
+        }
+    }
+
+    fn lock_jobs(&self) -> std::sync::MutexGuard<'_, HashMap<u64, TranscodeJob>> {
+        // Poisoning is not possible: the lock is never held while calling a code which may panic.
+        self.jobs.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Registered job which is removed from the queue when it's dropped.
+struct JobGuard {
+    queue: TranscodeQueue,
+    id: u64,
+    format: TranscodeFormat,
+}
+
+impl JobGuard {
+    fn submit(queue: &TranscodeQueue, format: TranscodeFormat) -> Result<Self, TranscodeError> {
+        let mut jobs = queue.lock_jobs();
+        if jobs.len() >= queue.config.max_parallel_jobs + queue.config.max_queued_jobs {
+            return Err(TranscodeError::QueueFull);
+        }
+        let id = queue.next_id.fetch_add(1, Ordering::Relaxed);
+        jobs.insert(
+            id,
+            TranscodeJob {
+                id,
+                format,
+                state: TranscodeState::Queued,
+                progress: 0.0,
+            },
+        );
+        drop(jobs);
+        Ok(Self {
+            queue: queue.clone(),
+            id,
+            format,
+        })
+    }
+
+    fn update(&self, f: impl FnOnce(&mut TranscodeJob)) {
+        if let Some(job) = self.queue.lock_jobs().get_mut(&self.id) {
+            f(job);
+        }
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        self.queue.lock_jobs().remove(&self.id);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{env, io, process::Stdio};
+
+use log::warn;
+use tokio::{fs, io::AsyncWriteExt, process::Command};
+
+use super::{AudioSource, AudioSourceError};
+use crate::{
+    config::{self, TtsEngine},
+    graphql::GraphQLError,
+};
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum TtsError {
+    #[error("Voice model must be configured to use piper")]
+    NoVoiceModel,
+    #[error("Failed to run the synthesizer: {0}")]
+    RunFailed(io::Error),
+    #[error("Synthesizer failed: {0}")]
+    SynthesisFailed(String),
+    #[error("Unable to read the synthesized speech: {0}")]
+    ReadFailed(io::Error),
+    #[error("Unable to decode the synthesized speech: {0}")]
+    DecodeFailed(AudioSourceError),
+}
+
+impl GraphQLError for TtsError {}
+
+/// Speech synthesis using an external program (espeak-ng or piper).
+#[derive(Clone)]
+pub struct Tts {
+    config: config::Tts,
+}
+
+impl Tts {
+    pub fn new(config: config::Tts) -> Self {
+        Self { config }
+    }
+
+    /// Returns the spoken `text` which can be played using the secondary sink.
+    pub async fn synthesize(&self, text: &str) -> Result<AudioSource, TtsError> {
+        // Synthesizers can't write a proper WAVE header to stdout as the length is unknown.
+        let wav_path = env::temp_dir().join(format!(
+            "{}-tts-{}.wav",
+            env!("CARGO_PKG_NAME"),
+            uuid::Uuid::new_v4()
+        ));
+        let mut command = match self.config.engine {
+            TtsEngine::EspeakNg => {
+                let mut command = Command::new("espeak-ng");
+                command.arg("--stdin").arg("-w").arg(&wav_path);
+                if let Some(voice) = &self.config.voice {
+                    command.args(["-v", voice]);
+                }
+                command
+            }
+            TtsEngine::Piper => {
+                let mut command = Command::new("piper");
+                command
+                    .arg("--model")
+                    .arg(self.config.voice.as_ref().ok_or(TtsError::NoVoiceModel)?)
+                    .arg("--output_file")
+                    .arg(&wav_path);
+                command
+            }
+        };
+        // Text is passed using stdin, so it's never treated as an option.
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(TtsError::RunFailed)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .await
+                .map_err(TtsError::RunFailed)?;
+        }
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(TtsError::RunFailed)?;
+
+        let result = if output.status.success() {
+            fs::read(&wav_path)
+                .await
+                .map_err(TtsError::ReadFailed)
+                .and_then(|wav| AudioSource::wav_unbuffered(wav).map_err(TtsError::DecodeFailed))
+        } else {
+            Err(TtsError::SynthesisFailed(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        };
+        if let Err(e) = fs::remove_file(&wav_path).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {e}", wav_path.to_string_lossy());
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use async_graphql::SimpleObject;
+use claxon::FlacReader;
+use serde::Serialize;
+
+/// How many peaks are calculated for a recording regardless of its duration.
+const PEAKS_COUNT: u64 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WaveformError {
+    #[error("Unable to open the file: {0}")]
+    OpenFile(io::Error),
+    #[error("FLAC decode failed: {0}")]
+    DecodeFlac(claxon::Error),
+    #[error("There is no audio")]
+    NoAudio,
+}
+
+/// Minimum and maximum sample values of the consecutive parts of a recording,
+/// so a seek bar can be drawn without decoding the audio. Channels are combined.
+#[derive(Clone, Serialize, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Waveform {
+    /// Duration of audio represented by each peak.
+    pub peak_duration_ms: f64,
+    /// Values are in range -1..1.
+    pub min: Vec<f32>,
+    /// Values are in range -1..1.
+    pub max: Vec<f32>,
+}
+
+impl Waveform {
+    /// Decodes **whole** FLAC file, so it can take a long time.
+    pub fn analyze_flac(flac_file: &Path) -> Result<Self, WaveformError> {
+        let mut reader = FlacReader::new(BufReader::new(
+            File::open(flac_file).map_err(WaveformError::OpenFile)?,
+        ))
+        .map_err(WaveformError::DecodeFlac)?;
+        let streaminfo = reader.streaminfo();
+        let total_frames = streaminfo
+            .samples
+            .filter(|samples| *samples != 0)
+            .ok_or(WaveformError::NoAudio)?;
+        let frames_per_peak = total_frames.div_ceil(PEAKS_COUNT);
+        let samples_per_peak = frames_per_peak * streaminfo.channels as u64;
+        let scale = 1.0 / (1_i64 << (streaminfo.bits_per_sample - 1)) as f32;
+
+        let peaks_count = total_frames.div_ceil(frames_per_peak) as usize;
+        let mut waveform = Self {
+            peak_duration_ms: frames_per_peak as f64 * 1000.0 / streaminfo.sample_rate as f64,
+            min: Vec::with_capacity(peaks_count),
+            max: Vec::with_capacity(peaks_count),
+        };
+        let (mut min, mut max) = (0.0_f32, 0.0_f32);
+        let mut peak_samples = 0;
+        for sample in reader.samples() {
+            let sample = sample.map_err(WaveformError::DecodeFlac)? as f32 * scale;
+            min = min.min(sample);
+            max = max.max(sample);
+            peak_samples += 1;
+            if peak_samples == samples_per_peak {
+                waveform.push(&mut min, &mut max);
+                peak_samples = 0;
+            }
+        }
+        if peak_samples != 0 {
+            waveform.push(&mut min, &mut max);
+        }
+        Ok(waveform)
+    }
+
+    /// Compact representation to store in the database.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.min.len() * 8);
+        bytes.extend(self.peak_duration_ms.to_le_bytes());
+        for (min, max) in self.min.iter().zip(&self.max) {
+            bytes.extend(min.to_le_bytes());
+            bytes.extend(max.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Returns [None] if `bytes` are not made by [Self::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (duration, peaks) = bytes.split_first_chunk::<8>()?;
+        if peaks.len() % 8 != 0 {
+            return None;
+        }
+        let read = |bytes: &[u8]| f32::from_le_bytes(bytes.try_into().expect("4 bytes"));
+        let (min, max) = peaks
+            .chunks_exact(8)
+            .map(|peak| (read(&peak[..4]), read(&peak[4..])))
+            .unzip();
+        Some(Self {
+            peak_duration_ms: f64::from_le_bytes(*duration),
+            min,
+            max,
+        })
+    }
+
+    /// Finish the current peak and reset the values.
+    fn push(&mut self, min: &mut f32, max: &mut f32) {
+        self.min.push(std::mem::take(min));
+        self.max.push(std::mem::take(max));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        let waveform = Waveform {
+            peak_duration_ms: 12.5,
+            min: vec![-1.0, -0.25, 0.0],
+            max: vec![1.0, 0.5, 0.0],
+        };
+        let bytes = waveform.to_bytes();
+        assert_eq!(bytes.len(), 8 + 3 * 8);
+        let decoded = Waveform::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.peak_duration_ms, waveform.peak_duration_ms);
+        assert_eq!(decoded.min, waveform.min);
+        assert_eq!(decoded.max, waveform.max);
+    }
+
+    #[test]
+    fn empty_peaks() {
+        let waveform = Waveform {
+            peak_duration_ms: 1.0,
+            min: Vec::new(),
+            max: Vec::new(),
+        };
+        let decoded = Waveform::from_bytes(&waveform.to_bytes()).unwrap();
+        assert!(decoded.min.is_empty() && decoded.max.is_empty());
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        assert!(Waveform::from_bytes(&[]).is_none());
+        assert!(Waveform::from_bytes(&[0; 7]).is_none());
+        // Incomplete peak.
+        assert!(Waveform::from_bytes(&[0; 12]).is_none());
+    }
+}
@@ -0,0 +1,548 @@
+use std::{
+    cmp::Ordering,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset};
+use futures::{pin_mut, StreamExt};
+use log::{error, info, warn};
+use tokio::{join, select};
+
+use crate::{
+    audio::tts::Tts,
+    bluetooth::{Bluetooth, DeviceHolder},
+    calendar::{Calendar, CalendarEvent},
+    config::{self, AutomationAction, AutomationRule, AutomationTrigger, Scene},
+    core::{timezone, Broadcaster, ShutdownNotify},
+    device::{
+        description::LoungeTempMonitor,
+        display::Display,
+        led::StatusLed,
+        mi_temp_monitor::MiTempMonitor,
+        piano::{Piano, StopRecorderParams},
+    },
+    graphql::GraphQLError,
+    integrations::mqtt::MqttIntegration,
+    prefs::PreferencesStorage,
+    weather::WeatherMonitor,
+    GlobalEvent,
+};
+
+/// How often to try to connect to the temperature monitor if it's unavailable.
+const TEMP_MONITOR_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Calendar events can be changed at any time, so the next trigger time is recalculated.
+const CALENDAR_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum AutomationError {
+    #[error("rule \"{0}\" is not found")]
+    RuleNotFound(String),
+    #[error("scene \"{0}\" is not found")]
+    SceneNotFound(String),
+    #[error("{0} action(s) of the scene failed, see the logs")]
+    SceneActionsFailed(usize),
+    #[error("neither webhook rule nor scene \"{0}\" is found")]
+    WebhookNotFound(String),
+}
+
+impl GraphQLError for AutomationError {}
+
+#[derive(SimpleObject)]
+pub struct AutomationRuleStatus {
+    name: String,
+    enabled: bool,
+}
+
+/// Rule which will be triggered by a calendar event.
+#[derive(SimpleObject)]
+pub struct CalendarTrigger {
+    rule: String,
+    event: CalendarEvent,
+    at: DateTime<FixedOffset>,
+}
+
+struct Rule {
+    config: AutomationRule,
+    enabled: AtomicBool,
+}
+
+/// Services which are used to evaluate the conditions and to perform the actions.
+pub struct AutomationDependencies {
+    pub piano: Piano,
+    pub bluetooth: Bluetooth,
+    pub lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+    pub tts: Tts,
+    pub prefs: PreferencesStorage,
+    pub display: Display,
+    pub status_led: Option<StatusLed>,
+    pub weather: Option<WeatherMonitor>,
+    pub location: Option<config::Location>,
+    pub calendar: Option<Calendar>,
+    pub mqtt: Option<MqttIntegration>,
+    pub event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+/// Evaluates the configured rules against the piano events and the lounge temperature.
+#[derive(Clone)]
+pub struct Automation {
+    rules: Arc<Vec<Rule>>,
+    scenes: Arc<Vec<Scene>>,
+    piano: Piano,
+    bluetooth: Bluetooth,
+    lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+    tts: Tts,
+    prefs: PreferencesStorage,
+    display: Display,
+    /// If the LED is not configured, it will be [None].
+    status_led: Option<StatusLed>,
+    /// If weather configuration is not passed, it will be [None].
+    weather: Option<WeatherMonitor>,
+    /// Used by the sunrise / sunset triggers.
+    location: Option<config::Location>,
+    /// If calendar configuration is not passed, it will be [None].
+    calendar: Option<Calendar>,
+    /// If MQTT integration is not configured, it will be [None].
+    mqtt: Option<MqttIntegration>,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+impl Automation {
+    pub fn new(
+        rules: Vec<AutomationRule>,
+        scenes: Vec<Scene>,
+        dependencies: AutomationDependencies,
+    ) -> Self {
+        let AutomationDependencies {
+            piano,
+            bluetooth,
+            lounge_temp_monitor,
+            tts,
+            prefs,
+            display,
+            status_led,
+            weather,
+            location,
+            calendar,
+            mqtt,
+            event_broadcaster,
+        } = dependencies;
+        let rules = rules
+            .into_iter()
+            .map(|config| Rule {
+                enabled: AtomicBool::new(!config.disabled),
+                config,
+            })
+            .collect();
+        Self {
+            rules: Arc::new(rules),
+            scenes: Arc::new(scenes),
+            piano,
+            bluetooth,
+            lounge_temp_monitor,
+            tts,
+            prefs,
+            display,
+            status_led,
+            weather,
+            location,
+            calendar,
+            mqtt,
+            event_broadcaster,
+        }
+    }
+
+    pub fn rules(&self) -> Vec<AutomationRuleStatus> {
+        self.rules
+            .iter()
+            .map(|rule| AutomationRuleStatus {
+                name: rule.config.name.clone(),
+                enabled: rule.enabled.load(atomic::Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// The state is not persisted: it's reset to the configured one on restart.
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), AutomationError> {
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| rule.config.name == name)
+            .ok_or_else(|| AutomationError::RuleNotFound(name.to_string()))?;
+        rule.enabled.store(enabled, atomic::Ordering::Relaxed);
+        info!(
+            "Automation rule \"{name}\" {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        Ok(())
+    }
+
+    pub fn scene_names(&self) -> Vec<String> {
+        self.scenes.iter().map(|scene| scene.name.clone()).collect()
+    }
+
+    /// Perform all actions of the scene, even if some of them fail.
+    pub async fn activate_scene(&self, name: &str) -> Result<(), AutomationError> {
+        let scene = self
+            .scenes
+            .iter()
+            .find(|scene| scene.name == name)
+            .ok_or_else(|| AutomationError::SceneNotFound(name.to_string()))?;
+        info!("Activating scene \"{name}\"");
+        let mut failed = 0;
+        for action in &scene.actions {
+            if let Err(e) = self.perform(action).await {
+                error!("Failed to perform an action of the scene \"{name}\": {e}");
+                failed += 1;
+            }
+        }
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err(AutomationError::SceneActionsFailed(failed))
+        }
+    }
+
+    /// The nearest trigger of the enabled rules by the upcoming calendar events.
+    pub async fn next_calendar_trigger(&self) -> Option<CalendarTrigger> {
+        let events = self.calendar.as_ref()?.upcoming().await;
+        let now = timezone::now();
+        self.rules
+            .iter()
+            .filter(|rule| rule.enabled.load(atomic::Ordering::Relaxed))
+            .flat_map(|rule| {
+                events.iter().filter_map(move |event| {
+                    calendar_trigger_time(&rule.config.when, event).map(|at| (rule, event, at))
+                })
+            })
+            .filter(|(_, _, at)| *at > now)
+            .min_by_key(|(_, _, at)| *at)
+            .map(|(rule, event, at)| CalendarTrigger {
+                rule: rule.config.name.clone(),
+                event: event.clone(),
+                at,
+            })
+    }
+
+    /// Trigger the rules with the webhook `name`. If there are no such rules,
+    /// activate the scene with this name.
+    pub async fn fire_webhook(&self, name: &str) -> Result<(), AutomationError> {
+        let is_webhook = |trigger: &AutomationTrigger| matches!(trigger, AutomationTrigger::Webhook(webhook) if webhook == name);
+        if self.rules.iter().any(|rule| is_webhook(&rule.config.when)) {
+            self.trigger(is_webhook);
+            Ok(())
+        } else if self.scenes.iter().any(|scene| scene.name == name) {
+            self.activate_scene(name).await
+        } else {
+            Err(AutomationError::WebhookNotFound(name.to_string()))
+        }
+    }
+
+    /// Returns on shutdown. The temperature is watched only if any rule depends on it.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let watch_temperature = self.rules.iter().any(|rule| {
+            matches!(
+                rule.config.when,
+                AutomationTrigger::LoungeTemperatureBelow(_)
+                    | AutomationTrigger::LoungeTemperatureAbove(_)
+                    | AutomationTrigger::OutdoorDeltaBelow(_)
+                    | AutomationTrigger::OutdoorDeltaAbove(_)
+            )
+        });
+        let temperature_rules = async {
+            if watch_temperature {
+                self.handle_temperature().await;
+            }
+        };
+        let piano_rules = self.handle_piano_events(shutdown_notify.clone());
+        let presence_rules = self.handle_presence(shutdown_notify.clone());
+        let time_rules = self.handle_time();
+        let calendar_rules = async {
+            let has_calendar_rules = self
+                .rules
+                .iter()
+                .any(|rule| matches!(rule.config.when, AutomationTrigger::CalendarEvent { .. }));
+            if let (Some(calendar), true) = (&self.calendar, has_calendar_rules) {
+                self.handle_calendar(calendar).await;
+            }
+        };
+        select! {
+            _ = async {
+                join!(piano_rules, presence_rules, temperature_rules, time_rules, calendar_rules)
+            } => {}
+            _ = shutdown_notify.notified() => {}
+        }
+    }
+
+    async fn handle_piano_events(&self, shutdown_notify: ShutdownNotify) {
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify)
+            .await;
+        pin_mut!(piano_events);
+        while let Some(event) = piano_events.next().await {
+            self.trigger(
+                |trigger| matches!(trigger, AutomationTrigger::PianoEvent(e) if *e == event),
+            );
+        }
+    }
+
+    async fn handle_presence(&self, shutdown_notify: ShutdownNotify) {
+        let global_events = self
+            .event_broadcaster
+            .recv_continuously(shutdown_notify)
+            .await;
+        pin_mut!(global_events);
+        while let Some(event) = global_events.next().await {
+            let GlobalEvent::PresenceChanged(presence) = event.payload else {
+                continue;
+            };
+            self.trigger(|trigger| match trigger {
+                AutomationTrigger::PersonArrived(name) => presence.home && *name == presence.name,
+                AutomationTrigger::PersonLeft(name) => !presence.home && *name == presence.name,
+                _ => false,
+            });
+        }
+    }
+
+    /// Returns when there are no more times to wait for.
+    async fn handle_time(&self) {
+        loop {
+            let now = timezone::now();
+            let next_time = |trigger: &AutomationTrigger| match *trigger {
+                AutomationTrigger::At(time) => {
+                    timezone::next_daily_time(time, self.location.as_ref(), now)
+                }
+                _ => None,
+            };
+            let Some(at) = self
+                .rules
+                .iter()
+                .filter_map(|rule| next_time(&rule.config.when))
+                .min()
+            else {
+                return;
+            };
+            if let Ok(duration) = (at - now).to_std() {
+                tokio::time::sleep(duration).await;
+            }
+            self.trigger(|trigger| next_time(trigger) == Some(at));
+        }
+    }
+
+    /// Rules are triggered when their time is between the checks. Returns never.
+    async fn handle_calendar(&self, calendar: &Calendar) {
+        let mut last_check = timezone::now();
+        loop {
+            let next_at = calendar
+                .upcoming()
+                .await
+                .iter()
+                .flat_map(|event| {
+                    self.rules
+                        .iter()
+                        .filter_map(|rule| calendar_trigger_time(&rule.config.when, event))
+                })
+                .filter(|at| *at > last_check)
+                .min();
+            let delay = match next_at.map(|at| (at - last_check).to_std()) {
+                Some(Ok(until_next)) => until_next.min(CALENDAR_RECHECK_INTERVAL),
+                _ => CALENDAR_RECHECK_INTERVAL,
+            };
+            tokio::time::sleep(delay).await;
+
+            let now = timezone::now();
+            let events = calendar.upcoming().await;
+            self.trigger(|trigger| {
+                events.iter().any(|event| {
+                    calendar_trigger_time(trigger, event)
+                        .is_some_and(|at| at > last_check && at <= now)
+                })
+            });
+            last_check = now;
+        }
+    }
+
+    /// Rules are triggered only when the temperature (or the difference with the outdoor one)
+    /// crosses the threshold. Returns never.
+    async fn handle_temperature(&self) {
+        let (mut last_temperature, mut last_delta) = (None, None);
+        loop {
+            let connected_monitor = self
+                .bluetooth
+                .ensure_connected_and_healthy(Arc::clone(&self.lounge_temp_monitor))
+                .await;
+            let data_notify = match connected_monitor {
+                Ok(monitor) => monitor
+                    .read()
+                    .await
+                    .get_connected()
+                    .map(|monitor| monitor.data_notify())
+                    .ok(),
+                Err(_) => None,
+            };
+            if let Some((shared_data, notify)) = data_notify {
+                loop {
+                    notify.notified().await;
+                    // Device is no longer available.
+                    let Some(data) = *shared_data.lock().await else {
+                        break;
+                    };
+                    let temperature = Some(data.temperature());
+                    let delta = match &self.weather {
+                        Some(weather) => weather
+                            .current()
+                            .await
+                            .map(|outdoor| data.temperature() - outdoor.temperature_celsius),
+                        None => None,
+                    };
+                    self.trigger(|trigger| match *trigger {
+                        AutomationTrigger::LoungeTemperatureBelow(threshold) => {
+                            crossed(last_temperature, temperature, threshold, Ordering::Less)
+                        }
+                        AutomationTrigger::LoungeTemperatureAbove(threshold) => {
+                            crossed(last_temperature, temperature, threshold, Ordering::Greater)
+                        }
+                        AutomationTrigger::OutdoorDeltaBelow(threshold) => {
+                            crossed(last_delta, delta, threshold, Ordering::Less)
+                        }
+                        AutomationTrigger::OutdoorDeltaAbove(threshold) => {
+                            crossed(last_delta, delta, threshold, Ordering::Greater)
+                        }
+                        _ => false,
+                    });
+                    (last_temperature, last_delta) = (temperature, delta);
+                }
+            }
+            tokio::time::sleep(TEMP_MONITOR_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Perform actions of the enabled rules whose trigger matches.
+    fn trigger(&self, matches: impl Fn(&AutomationTrigger) -> bool) {
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.enabled.load(atomic::Ordering::Relaxed) || !matches(&rule.config.when) {
+                continue;
+            }
+            info!("Automation rule \"{}\" triggered", rule.config.name);
+            // Don't block the events handling as actions can take a while.
+            let self_clone = self.clone();
+            tokio::spawn(async move {
+                let rule = &self_clone.rules[index].config;
+                for action in &rule.then {
+                    if let Err(e) = self_clone.perform(action).await {
+                        error!(
+                            "Failed to perform an action of the automation rule \"{}\": {e}",
+                            rule.name
+                        );
+                    }
+                }
+            });
+        }
+    }
+
+    async fn perform(&self, action: &AutomationAction) -> Result<(), String> {
+        match action {
+            AutomationAction::Notify(message) => {
+                self.event_broadcaster.send(GlobalEvent::Notification {
+                    message: message.clone(),
+                });
+                Ok(())
+            }
+            AutomationAction::PlaySound(sound) => {
+                self.piano.play_sound(*sound).await;
+                Ok(())
+            }
+            AutomationAction::Announce(text) => {
+                let speech = self.tts.synthesize(text).await.map_err(|e| e.to_string())?;
+                self.piano
+                    .play_secondary(speech)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            AutomationAction::MqttPublish {
+                topic,
+                payload,
+                retain,
+            } => match &self.mqtt {
+                Some(mqtt) => mqtt
+                    .publish(topic, payload.clone(), *retain)
+                    .await
+                    .map_err(|e| e.to_string()),
+                None => {
+                    warn!("MQTT integration is not configured, {topic} is not published");
+                    Ok(())
+                }
+            },
+            AutomationAction::StartRecording => {
+                self.piano.record().await.map_err(|e| e.to_string())
+            }
+            AutomationAction::StopRecording => self
+                .piano
+                .stop_recorder(StopRecorderParams {
+                    play_feedback: true,
+                })
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            AutomationAction::SetHotspotHandling(enabled) => self
+                .prefs
+                .set_hotspot_handling_enabled(*enabled, &self.event_broadcaster)
+                .await
+                .map_err(|e| e.to_string()),
+            AutomationAction::SetSoundsVolume(volume) => self
+                .prefs
+                .set_sounds_volume(*volume, &self.event_broadcaster)
+                .await
+                .map_err(|e| e.to_string()),
+            AutomationAction::SetStatusLed(enabled) => {
+                match &self.status_led {
+                    Some(status_led) => status_led.set_enabled(*enabled),
+                    None => warn!("Status LED is not configured, it's not changed"),
+                }
+                Ok(())
+            }
+            AutomationAction::SetDisplayPower(on) => {
+                self.display.set_power(*on).await.map_err(|e| e.to_string())
+            }
+            // Boxed as scenes perform the actions too. They can't be nested, it's validated.
+            AutomationAction::ActivateScene(name) => Box::pin(self.activate_scene(name))
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// When the `trigger` fires because of the calendar `event`.
+/// [None] if it's not a calendar trigger or the event doesn't match.
+fn calendar_trigger_time(
+    trigger: &AutomationTrigger,
+    event: &CalendarEvent,
+) -> Option<DateTime<FixedOffset>> {
+    match trigger {
+        AutomationTrigger::CalendarEvent {
+            summary,
+            minutes_before,
+        } if event.matches(summary) => {
+            Some(event.start - chrono::Duration::minutes(*minutes_before))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `current` moved beyond `threshold` in the `direction` since `previous`.
+fn crossed(
+    previous: Option<f32>,
+    current: Option<f32>,
+    threshold: f32,
+    direction: Ordering,
+) -> bool {
+    let is_beyond = |value: f32| value.partial_cmp(&threshold) == Some(direction);
+    current.is_some_and(is_beyond) && !previous.is_some_and(is_beyond)
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::HashMap,
     fmt::{self, Display, Formatter},
     marker::PhantomData,
     mem,
@@ -8,21 +8,31 @@ use std::{
 };
 
 use anyhow::anyhow;
+use async_graphql::{value, Value};
 use bluez_async::{
     AdapterInfo, BluetoothError, BluetoothEvent, BluetoothSession, DeviceEvent, DeviceId,
     DeviceInfo, MacAddress,
 };
-use futures::StreamExt;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
 use log::{error, info, warn};
-use tokio::{sync::RwLock, task::AbortHandle};
+use tokio::{
+    sync::{Mutex, RwLock},
+    task::AbortHandle,
+};
 use uuid::Uuid;
 
 use crate::{
     config,
-    dbus::DBus,
-    device::{BluetoothDevice, DeviceDescription},
+    core::{
+        metrics::{self, Counter},
+        panic,
+        supervisor::Supervised,
+        Broadcaster,
+    },
+    dbus::{BluetoothDeviceChange, DBus, MediaTrack},
+    device::{plugin::DevicePlugin, BluetoothDevice, DeviceDescription},
     graphql::GraphQLError,
-    App, SharedRwLock,
+    App, SharedMutex, SharedRwLock, Subsystem,
 };
 
 pub type DeviceHolder<T, D> = SharedRwLock<Device<T, D>>;
@@ -115,14 +125,19 @@ impl<T: BluetoothDevice, D: DeviceDescription> Display for Device<T, D> {
 pub struct Bluetooth {
     session: BluetoothSession,
     config: config::Bluetooth,
+    backoff: config::Backoff,
     adapter: Option<AdapterInfo>,
 }
 
 impl Bluetooth {
-    pub async fn new(session: BluetoothSession, config: config::Bluetooth) -> anyhow::Result<Self> {
+    pub async fn new(
+        session: BluetoothSession,
+        config: config::Bluetooth,
+        backoff: config::Backoff,
+    ) -> anyhow::Result<Self> {
         // If the server started on system boot, Bluetooth adapters may not be available yet.
         info!("Waiting for adapters...");
-        let adapters = wait_for_adapters(&session).await?;
+        let adapters = wait_for_adapters(&session, &backoff.bluetooth_adapter_wait).await?;
 
         let adapter = if let Some(adapter_name) = config.adapter_name.as_deref() {
             let adapter = adapters
@@ -138,10 +153,27 @@ impl Bluetooth {
         Ok(Self {
             session,
             config,
+            backoff,
             adapter,
         })
     }
 
+    /// MAC addresses of the devices which are connected or seen by the discovery (have RSSI).
+    pub async fn nearby_devices(&self) -> Result<Vec<MacAddress>, BluetoothError> {
+        Ok(self
+            .session
+            .get_devices()
+            .await?
+            .into_iter()
+            .filter(|device| device.connected || device.rssi.is_some())
+            .map(|device| device.mac_address)
+            .collect())
+    }
+
+    pub async fn disconnect(&self, id: &DeviceId) -> Result<(), BluetoothError> {
+        self.session.disconnect(id).await
+    }
+
     /// If `self.adapter` is [Some], wait until it will be powered,
     /// otherwise wait for ANY adapter to be turned on.
     pub async fn wait_until_powered(&self) -> Result<(), BluetoothError> {
@@ -152,7 +184,8 @@ impl Bluetooth {
                 .map(|adapter| format!("adapter {}", adapter.name))
                 .unwrap_or("any adapter".to_string())
         );
-        backoff::future::retry(config::backoff::bluetooth_adapter_wait(), || async {
+        let policy = self.backoff.bluetooth_adapter_wait.exponential();
+        backoff::future::retry(policy, || async {
             let adapters = if let Some(adapter) = &self.adapter {
                 self.session
                     .get_adapter_info(&adapter.id)
@@ -263,21 +296,22 @@ impl Bluetooth {
             let short_device_info = device_short_info(&found_device);
             info!("Connecting to {short_device_info}...");
 
-            let result =
-                backoff::future::retry(config::backoff::bluetooth_device_connect(), || async {
-                    T::connect(found_device.clone(), &self.session)
-                        .await
-                        .map_err(|err| {
-                            warn!("Got error \"{err}\" while connecting; retrying...");
-                            backoff::Error::transient(err)
-                        })
-                })
-                .await;
+            let policy = self.backoff.bluetooth_device_connect.exponential();
+            let result = backoff::future::retry(policy, || async {
+                T::connect(found_device.clone(), &self.session)
+                    .await
+                    .map_err(|err| {
+                        warn!("Got error \"{err}\" while connecting; retrying...");
+                        backoff::Error::transient(err)
+                    })
+            })
+            .await;
 
             match result {
                 Ok(device_result) => {
                     *device.write().await = Device::Connected(device_result, PhantomData);
-                    info!("Connected successfully");
+                    metrics::increment(Counter::BluetoothReconnects);
+                    info!(device = D::name(), event = "connected"; "Connected successfully");
                 }
                 Err(e) => {
                     *device.write().await = Device::NotConnected(mac_address);
@@ -328,7 +362,7 @@ impl Bluetooth {
                 error!("Failed to disconnect: {err}");
                 err
             })?;
-            info!("Disconnected successfully");
+            info!(device = D::name(), event = "disconnected"; "Disconnected successfully");
         } else {
             info!("Ignoring disconnect request for {device_write}");
         }
@@ -409,55 +443,197 @@ impl Bluetooth {
     }
 }
 
-#[derive(strum::Display)]
+/// Makes a Bluetooth device available as [DevicePlugin].
+pub struct BluetoothDevicePlugin<T: BluetoothDevice, D: DeviceDescription> {
+    name: &'static str,
+    bluetooth: Bluetooth,
+    device: DeviceHolder<T, D>,
+}
+
+impl<T: BluetoothDevice, D: DeviceDescription> BluetoothDevicePlugin<T, D> {
+    pub fn new(name: &'static str, bluetooth: Bluetooth, device: DeviceHolder<T, D>) -> Self {
+        Self {
+            name,
+            bluetooth,
+            device,
+        }
+    }
+}
+
+impl<T: BluetoothDevice, D: DeviceDescription> DevicePlugin for BluetoothDevicePlugin<T, D> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn status(&self) -> BoxFuture<'_, Value> {
+        async {
+            let device = self.device.read().await;
+            value!({
+                "connected": matches!(*device, Device::Connected(_, _)),
+                "description": device.to_string(),
+            })
+        }
+        .boxed()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        async {
+            // Error will be logged by the method.
+            let _ = self.bluetooth.disconnect(Arc::clone(&self.device)).await;
+        }
+        .boxed()
+    }
+}
+
+#[derive(Clone, Copy, strum::Display, async_graphql::Enum)]
 pub enum MediaControlCommand {
+    Play,
     Pause,
+    Next,
+    Previous,
 }
 
 #[derive(Clone)]
 pub struct A2DPSourceHandler {
-    /// Currently connected devices which support A2DP source.
-    connected_devices: SharedRwLock<HashSet<DeviceId>>,
+    /// Currently connected devices which support A2DP source, along with their short info.
+    connected_devices: SharedRwLock<HashMap<DeviceId, String>>,
 }
 
 impl A2DPSourceHandler {
     pub async fn new(session: &BluetoothSession) -> Result<Self, BluetoothError> {
-        let connected_devices: HashSet<_> = session
+        Ok(Self {
+            connected_devices: Arc::new(RwLock::new(Self::find_connected(session).await?)),
+        })
+    }
+
+    /// Read the connected devices again, as the connection changes are missed while
+    /// the event handler is not running (e.g. during the startup).
+    /// Returns `true` if they are changed.
+    pub async fn sync(&self, session: &BluetoothSession) -> Result<bool, BluetoothError> {
+        let found = Self::find_connected(session).await?;
+        let mut connected_devices = self.connected_devices.write().await;
+        if *connected_devices == found {
+            return Ok(false);
+        }
+        let mut names: Vec<_> = found.values().cloned().collect();
+        names.sort();
+        info!(
+            "Connected A2DP sources changed while not tracked, now they are: [{}]",
+            names.join(", ")
+        );
+        *connected_devices = found;
+        Ok(true)
+    }
+
+    async fn find_connected(
+        session: &BluetoothSession,
+    ) -> Result<HashMap<DeviceId, String>, BluetoothError> {
+        Ok(session
             .get_devices()
             .await?
             .into_iter()
             .filter(|device| device.connected && Self::has_a2dp_source(device))
-            .map(|device| device.id)
-            .collect();
-        Ok(Self {
-            connected_devices: Arc::new(RwLock::new(connected_devices)),
-        })
+            .map(|device| (device.id.clone(), device_short_info(&device)))
+            .collect())
     }
 
     pub async fn has_connected(&self) -> bool {
         !self.connected_devices.read().await.is_empty()
     }
 
+    /// Names (or MAC addresses) of the connected devices.
+    pub async fn connected_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .connected_devices
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Disconnect all connected devices, so the audio device is not in use anymore.
+    /// Returns names of the disconnected devices or the last error
+    /// (other devices are disconnected anyway).
+    pub async fn disconnect_all(
+        &self,
+        bluetooth: &Bluetooth,
+    ) -> Result<Vec<String>, BluetoothError> {
+        let mut connected_devices = self.connected_devices.write().await;
+        let mut disconnected = Vec::new();
+        let mut result = Ok(());
+        for (device_id, name) in connected_devices.clone() {
+            match bluetooth.disconnect(&device_id).await {
+                Ok(()) => {
+                    info!("A2DP source disconnected on request: {name}");
+                    connected_devices.remove(&device_id);
+                    disconnected.push(name);
+                }
+                Err(e) => {
+                    error!("Failed to disconnect A2DP source {name}: {e}");
+                    result = Err(e);
+                }
+            }
+        }
+        result.map(|_| disconnected)
+    }
+
     /// Send a command to the all connected devices with the A2DP source support.
-    pub async fn send_media_control_command(&self, dbus: &DBus, command: MediaControlCommand) {
-        for device_id in self.connected_devices.read().await.iter() {
-            match dbus.bluetooth_media_control_proxy(device_id).await {
-                Ok(proxy) => {
-                    let result = match command {
-                        MediaControlCommand::Pause => proxy.pause().await,
+    /// Returns the last error if the command failed for any of them.
+    pub async fn send_media_control_command(
+        &self,
+        dbus: &DBus,
+        command: MediaControlCommand,
+    ) -> zbus::Result<()> {
+        let mut result = Ok(());
+        for device_id in self.connected_devices.read().await.keys() {
+            match dbus.bluetooth_media_controller(device_id).await {
+                Ok(controller) => {
+                    let interface = controller.interface();
+                    let sent = match command {
+                        MediaControlCommand::Play => controller.play().await,
+                        MediaControlCommand::Pause => controller.pause().await,
+                        MediaControlCommand::Next => controller.next().await,
+                        MediaControlCommand::Previous => controller.previous().await,
                     };
-                    if let Err(e) = result {
-                        error!(
-                            "Failed to send {command} Media Control \
-                            command to device {device_id}: {e}"
-                        );
-                    } else {
-                        info!("{command} Media Control command sent to device {device_id}");
+                    match sent {
+                        Ok(()) => {
+                            info!("{command} {interface} command sent to device {device_id}")
+                        }
+                        Err(e) => {
+                            error!(
+                                "Failed to send {command} {interface} \
+                                command to device {device_id}: {e}"
+                            );
+                            result = Err(e);
+                        }
                     }
                 }
-                Err(e) => error!("Failed to make Media Control proxy for device {device_id}: {e}"),
+                Err(e) => {
+                    error!("Failed to make media controller for device {device_id}: {e}");
+                    result = Err(e);
+                }
             }
         }
+        result
+    }
+
+    /// Returns metadata of the first track which is reported by the connected devices.
+    pub async fn current_track(&self, dbus: &DBus) -> Option<MediaTrack> {
+        for device_id in self.connected_devices.read().await.keys() {
+            let result = match dbus.bluetooth_media_controller(device_id).await {
+                Ok(controller) => controller.track().await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(Some(track)) => return Some(track),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to get the current track of device {device_id}: {e}"),
+            }
+        }
+        None
     }
 
     /// Returns `true` if A2DP source device connected / disconnected.
@@ -469,12 +645,19 @@ impl A2DPSourceHandler {
                     .connected_devices
                     .write()
                     .await
-                    .insert(device.id.clone())
+                    .insert(device.id.clone(), device_short_info(device))
+                    .is_none()
             {
                 info!("A2DP source connected: {}", device_short_info(device));
                 updated = true;
             }
-        } else if self.connected_devices.write().await.remove(&device.id) {
+        } else if self
+            .connected_devices
+            .write()
+            .await
+            .remove(&device.id)
+            .is_some()
+        {
             info!("A2DP source disconnected: {}", device_short_info(device));
             updated = true;
         }
@@ -507,59 +690,125 @@ impl A2DPSourceHandler {
     }
 }
 
-/// Handle all events from all adapters.
-pub async fn spawn_global_event_handler(
+/// Handles all events from all adapters.
+pub struct GlobalEventHandler {
+    session: BluetoothSession,
+    app: App,
+    abort_handle: SharedMutex<AbortHandle>,
+}
+
+impl GlobalEventHandler {
+    pub async fn spawn(session: BluetoothSession, app: App) -> Result<Self, BluetoothError> {
+        let abort_handle = spawn_global_event_loop(session.clone(), app.clone()).await?;
+        Ok(Self {
+            session,
+            app,
+            abort_handle: Arc::new(Mutex::new(abort_handle)),
+        })
+    }
+}
+
+impl Supervised for GlobalEventHandler {
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::BluetoothEventHandler
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        async { !self.abort_handle.lock().await.is_finished() }.boxed()
+    }
+
+    fn restart(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        async {
+            let mut abort_handle = self.abort_handle.lock().await;
+            abort_handle.abort();
+            *abort_handle = spawn_global_event_loop(self.session.clone(), self.app.clone()).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+async fn spawn_global_event_loop(
     session: BluetoothSession,
     app: App,
 ) -> Result<AbortHandle, BluetoothError> {
     let mut event_stream = session.event_stream().await?;
-    Ok(tokio::spawn(async move {
+    // Panic finishes the task, so it's respawned by the supervisor.
+    Ok(tokio::spawn(panic::supervised_task(async move {
         info!("Global event handler started");
+        // Devices could be connected or disconnected before subscribing to the events.
+        match app.a2dp_source_handler.sync(&session).await {
+            Ok(true) => app.piano.update_audio_io().await,
+            Ok(false) => {}
+            Err(e) => error!("Failed to check the connected A2DP sources: {e}"),
+        }
         while let Some(event) = event_stream.next().await {
             handle_event(event, &session, &app).await
         }
         error!("Event stream of the global handler is closed");
-    })
+    }))
     .abort_handle())
 }
 
 async fn handle_event(event: BluetoothEvent, session: &BluetoothSession, app: &App) {
-    if let BluetoothEvent::Device { id, event } = event {
-        match session.get_device_info(&id).await {
-            Ok(device) => {
-                if let DeviceEvent::Connected { connected } = event {
-                    if app
-                        .a2dp_source_handler
-                        .handle_connection_change(&device, connected)
-                        .await
-                    {
-                        // If A2DP source connected, audio device may become busy and piano can't
-                        // use this device no more.
-                        // If A2DP source disconnected, piano should take it for use again.
-                        app.piano.update_audio_io().await;
-                    }
+    // Other changes (like RSSI) are frequent and don't require the device info.
+    let BluetoothEvent::Device {
+        id,
+        event: DeviceEvent::Connected { connected },
+    } = event
+    else {
+        return;
+    };
+    let device = match session.get_device_info(&id).await {
+        Ok(device) => device,
+        Err(e) => {
+            error!("Failed to get info about handled device with ID {id}: {e}");
+            return;
+        }
+    };
 
-                    if let Some(hotspot) = &app.hotspot {
-                        if app.prefs.read().await.hotspot_handling_enabled
-                            && hotspot.is_hotspot(&device)
-                        {
-                            if connected {
-                                hotspot.disconnect_from_wifi().await
-                            } else {
-                                hotspot.connect_to_wifi().await
-                            };
-                        }
-                    }
-                }
-            }
-            Err(e) => error!("Failed to get info about handled device with ID {id}: {e}"),
+    if app
+        .a2dp_source_handler
+        .handle_connection_change(&device, connected)
+        .await
+    {
+        // If A2DP source connected, audio device may become busy and piano can't
+        // use this device no more.
+        // If A2DP source disconnected, piano should take it for use again.
+        app.piano.update_audio_io().await;
+    }
+
+    if let Some(hotspot) = &app.hotspot {
+        if app.prefs.read().await.hotspot_handling_enabled && hotspot.is_hotspot(&device) {
+            if connected {
+                hotspot.disconnect_from_wifi().await
+            } else {
+                hotspot.connect_to_wifi().await
+            };
         }
     }
 }
 
+/// Broadcast changes of the Bluetooth devices properties until the message bus stream closes.
+pub async fn forward_device_changes(
+    dbus: DBus,
+    broadcaster: Broadcaster<BluetoothDeviceChange>,
+) -> zbus::Result<()> {
+    let mut changes = dbus.bluetooth_device_changes().await?.boxed();
+    while let Some(change) = changes.next().await {
+        broadcaster.send(change);
+    }
+    Err(zbus::Error::Failure(
+        "device properties stream closed".to_string(),
+    ))
+}
+
 /// Wait until ANY (may be not all) adapter is available and then return a list of them.
-async fn wait_for_adapters(session: &BluetoothSession) -> Result<Vec<AdapterInfo>, BluetoothError> {
-    backoff::future::retry(config::backoff::bluetooth_adapter_wait(), || async {
+async fn wait_for_adapters(
+    session: &BluetoothSession,
+    policy: &config::BackoffPolicy,
+) -> Result<Vec<AdapterInfo>, BluetoothError> {
+    backoff::future::retry(policy.exponential(), || async {
         match session.get_adapters().await {
             Ok(adapters) => {
                 if adapters.is_empty() {
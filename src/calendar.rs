@@ -0,0 +1,445 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use async_graphql::SimpleObject;
+use chrono::{
+    DateTime, Datelike, Days, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use log::{info, warn};
+use tokio::{process::Command, select, sync::Mutex};
+
+use crate::{
+    config,
+    core::{curl_config::CurlConfig, timezone, ShutdownNotify},
+    SharedMutex,
+};
+
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// How far ahead the recurring events are expanded.
+const RECURRENCE_HORIZON_DAYS: u64 = 60;
+
+#[derive(Clone, PartialEq, SimpleObject)]
+pub struct CalendarEvent {
+    pub summary: String,
+    pub start: DateTime<FixedOffset>,
+    /// Null if the event doesn't have the end time.
+    pub end: Option<DateTime<FixedOffset>>,
+}
+
+impl CalendarEvent {
+    /// Whether the summary contains `text` (case-insensitive).
+    pub fn matches(&self, text: &str) -> bool {
+        self.summary.to_lowercase().contains(&text.to_lowercase())
+    }
+}
+
+/// Periodically fetches the configured ICS calendar. Daily and weekly recurring events
+/// are expanded, other recurrence rules use only the first occurrence.
+#[derive(Clone)]
+pub struct Calendar {
+    config: config::Calendar,
+    /// Sorted by the start time.
+    events: SharedMutex<Vec<CalendarEvent>>,
+}
+
+impl Calendar {
+    pub fn new(config: config::Calendar) -> Self {
+        Self {
+            config,
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Events which are not finished yet, including the ongoing ones.
+    pub async fn upcoming(&self) -> Vec<CalendarEvent> {
+        let now = timezone::now();
+        self.events
+            .lock()
+            .await
+            .iter()
+            .filter(|event| event.end.unwrap_or(event.start) > now)
+            .cloned()
+            .collect()
+    }
+
+    /// Update the events periodically. Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        loop {
+            let delay = match self.fetch().await {
+                Ok(mut events) => {
+                    info!("Calendar updated: {} events", events.len());
+                    events.sort_by_key(|event| event.start);
+                    *self.events.lock().await = events;
+                    Duration::from_secs(self.config.refresh_interval_mins * 60)
+                }
+                Err(e) => {
+                    warn!("Failed to fetch the calendar: {e}");
+                    RETRY_DELAY
+                }
+            };
+            select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown_notify.notified() => break,
+            }
+        }
+    }
+
+    async fn fetch(&self) -> Result<Vec<CalendarEvent>, String> {
+        // Address is secret.
+        let output = CurlConfig::default()
+            .option("url", &self.config.url)
+            .output(Command::new("curl").args(["--fail", "--silent", "--show-error", "--location"]))
+            .await
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(parse_events(
+            &String::from_utf8_lossy(&output.stdout),
+            timezone::now(),
+        ))
+    }
+}
+
+/// How a date-time value of the calendar is interpreted.
+#[derive(Clone, Copy)]
+enum Zone {
+    Utc,
+    /// `TZID` parameter.
+    Named(chrono_tz::Tz),
+    /// Local time in the configured timezone.
+    Floating,
+}
+
+/// `RRULE` with the daily or weekly frequency.
+struct Recurrence {
+    weekly: bool,
+    interval: u64,
+    count: Option<u64>,
+    until: Option<DateTime<FixedOffset>>,
+    /// Only for the weekly rules. If it's empty, the weekday of the start is used.
+    weekdays: Vec<Weekday>,
+}
+
+/// Events without the summary or a valid start time are skipped. Occurrences of the recurring
+/// events which finished before `now` or start after [RECURRENCE_HORIZON_DAYS] are skipped.
+fn parse_events(ics: &str, now: DateTime<FixedOffset>) -> Vec<CalendarEvent> {
+    // Long lines are folded: continuation lines start with a space or a tab.
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let (mut in_event, mut summary, mut start, mut end, mut recurrence) =
+        (false, None, None, None, None);
+    // Depth of the components inside the event (e.g. VALARM), their properties are skipped.
+    let mut nested = 0_usize;
+    for line in lines {
+        let Some((name_and_params, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut name_and_params = name_and_params.split(';');
+        let name = name_and_params.next().unwrap_or_default();
+        let tzid = name_and_params.find_map(|param| param.strip_prefix("TZID="));
+        match (name, value) {
+            ("BEGIN", "VEVENT") if !in_event => {
+                (in_event, nested) = (true, 0);
+                (summary, start, end, recurrence) = (None, None, None, None);
+            }
+            ("BEGIN", _) if in_event => nested += 1,
+            ("END", _) if in_event && nested > 0 => nested -= 1,
+            ("END", "VEVENT") if in_event => {
+                in_event = false;
+                let (Some(summary), Some(start)) = (summary.take(), start.take()) else {
+                    continue;
+                };
+                let end = end.take();
+                match recurrence.take() {
+                    Some(recurrence) => {
+                        events.extend(occurrences(&summary, start, end, &recurrence, now))
+                    }
+                    None => {
+                        if let Some(start) = resolve(start) {
+                            events.push(CalendarEvent {
+                                summary,
+                                start,
+                                end: end.and_then(resolve),
+                            });
+                        }
+                    }
+                }
+            }
+            _ if !in_event || nested > 0 => {}
+            ("SUMMARY", _) => summary = Some(unescape(value)),
+            ("DTSTART", _) => start = parse_datetime(value, tzid),
+            ("DTEND", _) => end = parse_datetime(value, tzid),
+            ("RRULE", _) => recurrence = parse_recurrence(value),
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Returns [None] if the frequency is not supported.
+fn parse_recurrence(rule: &str) -> Option<Recurrence> {
+    let mut recurrence = Recurrence {
+        weekly: false,
+        interval: 1,
+        count: None,
+        until: None,
+        weekdays: Vec::new(),
+    };
+    let mut frequency = None;
+    for part in rule.split(';') {
+        let Some((name, value)) = part.split_once('=') else {
+            continue;
+        };
+        match name {
+            "FREQ" => frequency = Some(value),
+            "INTERVAL" => recurrence.interval = value.parse().ok().filter(|&n| n != 0)?,
+            "COUNT" => recurrence.count = Some(value.parse().ok()?),
+            "UNTIL" => recurrence.until = Some(resolve(parse_datetime(value, None)?)?),
+            "BYDAY" => {
+                recurrence.weekdays = value
+                    .split(',')
+                    .map(|day| match day {
+                        "MO" => Some(Weekday::Mon),
+                        "TU" => Some(Weekday::Tue),
+                        "WE" => Some(Weekday::Wed),
+                        "TH" => Some(Weekday::Thu),
+                        "FR" => Some(Weekday::Fri),
+                        "SA" => Some(Weekday::Sat),
+                        "SU" => Some(Weekday::Sun),
+                        // E.g. "1MO" (first Monday) of the monthly rules.
+                        _ => None,
+                    })
+                    .collect::<Option<_>>()?
+            }
+            _ => {}
+        }
+    }
+    match frequency? {
+        "DAILY" => Some(recurrence),
+        "WEEKLY" => {
+            recurrence.weekly = true;
+            Some(recurrence)
+        }
+        _ => None,
+    }
+}
+
+/// Occurrences keep the wall clock time of the start (in its zone) and the duration.
+fn occurrences(
+    summary: &str,
+    start: (NaiveDateTime, Zone),
+    end: Option<(NaiveDateTime, Zone)>,
+    recurrence: &Recurrence,
+    now: DateTime<FixedOffset>,
+) -> Vec<CalendarEvent> {
+    let (first, zone) = start;
+    let duration = end.map(|(end, _)| end - first);
+    let horizon = now + Days::new(RECURRENCE_HORIZON_DAYS);
+    let first_week = first.date() - Days::new(first.weekday().num_days_from_monday().into());
+
+    let mut events = Vec::new();
+    let mut count = 0;
+    for date in first.date().iter_days() {
+        let matches = if recurrence.weekly {
+            let week = (date - first_week).num_days() / 7;
+            let weekday_matches = if recurrence.weekdays.is_empty() {
+                date.weekday() == first.weekday()
+            } else {
+                recurrence.weekdays.contains(&date.weekday())
+            };
+            weekday_matches && week as u64 % recurrence.interval == 0
+        } else {
+            (date - first.date()).num_days() as u64 % recurrence.interval == 0
+        };
+        if !matches {
+            continue;
+        }
+        if recurrence.count.is_some_and(|max| count >= max) {
+            break;
+        }
+        count += 1;
+
+        let naive = date.and_time(first.time());
+        // Time doesn't exist in the DST gap.
+        let Some(start) = resolve((naive, zone)) else {
+            continue;
+        };
+        if start > horizon || recurrence.until.is_some_and(|until| start > until) {
+            break;
+        }
+        let end = duration.and_then(|duration| resolve((naive + duration, zone)));
+        if end.unwrap_or(start) > now {
+            events.push(CalendarEvent {
+                summary: summary.to_string(),
+                start,
+                end,
+            });
+        }
+    }
+    events
+}
+
+/// Supports the UTC (`...Z`), zoned (`TZID` parameter), floating and date-only values.
+fn parse_datetime(value: &str, tzid: Option<&str>) -> Option<(NaiveDateTime, Zone)> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((naive, Zone::Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y%m%d").map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .ok()?;
+    let zone = tzid
+        .and_then(|tzid| chrono_tz::Tz::from_str(tzid).ok())
+        .map_or(Zone::Floating, Zone::Named);
+    Some((naive, zone))
+}
+
+fn resolve((naive, zone): (NaiveDateTime, Zone)) -> Option<DateTime<FixedOffset>> {
+    match zone {
+        Zone::Utc => Some(timezone::localize(Utc.from_utc_datetime(&naive))),
+        Zone::Named(tz) => tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(timezone::localize),
+        Zone::Floating => timezone::from_local(naive),
+    }
+}
+
+/// Escapes are processed in a single pass, so `\\n` is a backslash followed by `n`.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            // Backslash, comma and semicolon.
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32) -> DateTime<FixedOffset> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0)
+            .unwrap()
+            .fixed_offset()
+    }
+
+    fn calendar(event: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n{event}END:VEVENT\r\nEND:VCALENDAR\r\n")
+    }
+
+    #[test]
+    fn single_event() {
+        let ics = calendar(
+            "SUMMARY:Piano\r\n  lesson\r\nDTSTART:20240105T100000Z\r\n\
+             DTEND;TZID=Europe/Berlin:20240105T120000\r\n",
+        );
+        let events = parse_events(&ics, utc(2024, 1, 1, 0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "Piano lesson");
+        assert_eq!(events[0].start, utc(2024, 1, 5, 10));
+        assert_eq!(events[0].end, Some(utc(2024, 1, 5, 11)));
+    }
+
+    #[test]
+    fn incomplete_event() {
+        assert!(parse_events(&calendar("SUMMARY:No start\r\n"), utc(2024, 1, 1, 0)).is_empty());
+    }
+
+    #[test]
+    fn alarm_summary() {
+        let ics = calendar(
+            "SUMMARY:Lesson\r\nDTSTART:20240105T100000Z\r\n\
+             BEGIN:VALARM\r\nSUMMARY:Reminder\r\nEND:VALARM\r\n",
+        );
+        assert_eq!(parse_events(&ics, utc(2024, 1, 1, 0))[0].summary, "Lesson");
+    }
+
+    #[test]
+    fn daily_count() {
+        let ics = calendar(
+            "SUMMARY:Practice\r\nDTSTART:20240101T180000Z\r\nDTEND:20240101T190000Z\r\n\
+             RRULE:FREQ=DAILY;INTERVAL=2;COUNT=3\r\n",
+        );
+        let starts: Vec<_> = parse_events(&ics, utc(2023, 12, 1, 0))
+            .into_iter()
+            .map(|event| event.start)
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                utc(2024, 1, 1, 18),
+                utc(2024, 1, 3, 18),
+                utc(2024, 1, 5, 18)
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_until() {
+        // 2024-01-01 is Monday.
+        let ics = calendar(
+            "SUMMARY:Lesson\r\nDTSTART:20240101T100000Z\r\n\
+             RRULE:FREQ=WEEKLY;BYDAY=MO,TH;UNTIL=20240111T100000Z\r\n",
+        );
+        let starts: Vec<_> = parse_events(&ics, utc(2023, 12, 1, 0))
+            .into_iter()
+            .map(|event| event.start)
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                utc(2024, 1, 1, 10),
+                utc(2024, 1, 4, 10),
+                utc(2024, 1, 8, 10),
+                utc(2024, 1, 11, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn unbounded_recurrence() {
+        let ics = calendar(
+            "SUMMARY:Lesson\r\nDTSTART:20200101T100000Z\r\nDTEND:20200101T110000Z\r\n\
+             RRULE:FREQ=WEEKLY\r\n",
+        );
+        let now = utc(2024, 1, 1, 0);
+        let events = parse_events(&ics, now);
+        // Past occurrences are skipped, the future ones are limited by the horizon.
+        // 2020-01-01 is Wednesday.
+        assert_eq!(events.len(), 9);
+        assert_eq!(events[0].start, utc(2024, 1, 3, 10));
+    }
+
+    #[test]
+    fn unsupported_recurrence() {
+        let ics = calendar("SUMMARY:Lesson\r\nDTSTART:20240101T100000Z\r\nRRULE:FREQ=MONTHLY\r\n");
+        assert_eq!(parse_events(&ics, utc(2023, 12, 1, 0)).len(), 1);
+    }
+
+    #[test]
+    fn unescape_text() {
+        assert_eq!(unescape("a\\, b\\; c\\nd"), "a, b; c\nd");
+        assert_eq!(unescape("C:\\\\new"), "C:\\new");
+    }
+}
@@ -1,17 +1,26 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::anyhow;
+use chrono::NaiveTime;
 use figment::{
-    providers::{Env, Format, Yaml},
+    providers::{self, Env, Format, Yaml},
     Figment,
 };
 use log::LevelFilter;
 use serde::Deserialize;
 use serde_valid::Validate;
 
-use crate::files::{AssetsDir, DataDir};
+use crate::{
+    core::i18n::Locale,
+    device::piano::PianoEvent,
+    files::{self, AssetsDir, BaseDir, DataDir, Sound},
+};
 
-const YAML_FILE_LOCATION: &str = concat!("/etc/", env!("CARGO_PKG_NAME"), ".yaml");
+pub(crate) const YAML_FILE_LOCATION: &str = concat!("/etc/", env!("CARGO_PKG_NAME"), ".yaml");
 const ENV_PREFIX: &str = "HOMIE_";
 
 // TODO: make it cheap for cloning using `Arc`.
@@ -21,17 +30,129 @@ pub struct Config {
     pub server_address: String,
     pub server_port: u16,
     pub log_level: LevelFilter,
+    /// How many recent log records to keep in memory for the `logs` query.
+    #[validate(minimum = 1)]
+    pub log_buffer_capacity: usize,
+    pub log_output: LogOutput,
+    pub log_format: LogFormat,
+    /// Write logs to this file in addition to [LogOutput].
+    #[validate]
+    pub log_file: Option<LogFile>,
+    /// Forward WARN and ERROR records to a remote syslog server.
+    pub syslog: Option<Syslog>,
+    /// Capacity of the event channels. If a subscriber can't keep up with the events,
+    /// the oldest ones will be lost for it.
+    #[validate(minimum = 1)]
+    pub broadcaster_capacity: usize,
     #[validate]
     pub assets_dir: AssetsDir,
+    /// Load all sounds on the startup instead of the first use.
+    pub preload_sounds: bool,
+    /// Reload the changed sounds and the recording cover image without restarting.
+    pub watch_assets: bool,
+    /// Caching of the static site.
+    pub site: Site,
     #[validate]
     pub data_dir: DataDir,
+    /// Maximum time to wait until all shutdown steps will be performed.
+    #[validate(minimum = 1)]
+    pub shutdown_timeout_secs: u64,
+    /// Language of the human-readable strings.
+    pub locale: Locale,
+    /// Used to format dates. If [None], the system timezone will be used.
+    pub timezone: Option<chrono_tz::Tz>,
     /// Token to access the REST API endpoints.
     /// Set to [None] if authentication is not required.
     pub access_token: Option<String>,
+    /// Grants access to the admin operations (e.g. editing the configuration) in addition to
+    /// the regular API. If [None], they are available only to the local clients.
+    pub admin_token: Option<String>,
+    /// Whether to register the `org.homie.Home1` service on the system bus.
+    pub dbus_service: bool,
+    /// systemd units which can be restarted using the API.
+    pub manageable_units: Vec<String>,
+    #[validate]
+    pub backoff: Backoff,
     #[validate]
     pub bluetooth: Bluetooth,
     /// Information about a hosting device to which the Raspberry Pi connects to.
     pub hotspot: Option<Hotspot>,
+    /// Output-only sound card (e.g. USB DAC feeding room speakers).
+    #[validate]
+    pub monitor_output: Option<MonitorOutput>,
+    /// Snapcast server to play the recordings on the multi-room speakers.
+    #[validate]
+    pub snapcast: Option<Snapcast>,
+    /// Microphone to record the voice memos.
+    #[validate]
+    pub voice_memo: Option<VoiceMemo>,
+    pub udev: Udev,
+    #[validate]
+    pub backup: Backup,
+    /// Mirror the saved recordings to this target.
+    pub recording_upload: Option<BackupTargetKind>,
+    /// USB drive to export the recordings and backups to when it's plugged in.
+    #[validate]
+    pub usb_storage: Option<UsbStorage>,
+    /// Required to use the sunrise / sunset times (see [DailyTime]) and the outdoor weather.
+    #[validate]
+    pub location: Option<Location>,
+    /// HDMI display power schedule.
+    pub display: Display,
+    /// Daily period when the status LED is off and the push notifications are silent.
+    pub quiet_hours: Option<QuietHours>,
+    /// Status LED driven through `/sys/class/leds`.
+    pub led: Option<Led>,
+    /// Battery monitoring using UPower (e.g. UPS HAT).
+    #[validate]
+    pub power: Option<Power>,
+    /// Sample rate conversion of the played audio if it doesn't match the output device.
+    /// If [None], the conversion is left to the audio library.
+    pub resample_quality: Option<ResampleQuality>,
+    #[validate]
+    pub transcode: Transcode,
+    /// Speech synthesis of the announcements.
+    pub tts: Tts,
+    /// Audio clips sent using `POST /api/announce`.
+    #[validate]
+    pub announce: Announce,
+    #[validate]
+    pub disk_watchdog: DiskWatchdog,
+    /// SQLite database with the recording index and history of the events.
+    #[validate]
+    pub storage: Storage,
+    /// Self-update of the server binary.
+    pub updater: Option<Updater>,
+    /// Outdoor weather for the comparison with the lounge temperature.
+    #[validate]
+    pub weather: Option<Weather>,
+    /// ICS calendar which events can trigger the automation rules.
+    #[validate]
+    pub calendar: Option<Calendar>,
+    /// Home / away detection of the people using their phones.
+    #[validate]
+    pub presence: Option<Presence>,
+    /// MQTT broker to publish the state to (using the Home Assistant discovery).
+    pub mqtt: Option<Mqtt>,
+    /// Rules which react to the events (see [AutomationRule]).
+    pub automation: Automation,
+    /// Named groups of actions which can be activated at once.
+    pub scenes: Vec<Scene>,
+    /// Run the Lua scripts from the `scripts` data subdirectory.
+    pub scripting: bool,
+    /// Push notifications using ntfy.
+    pub ntfy: Option<Ntfy>,
+    /// Bot to control the server and receive notifications using Telegram.
+    pub telegram: Option<Telegram>,
+    /// HomeKit bridge with the lounge sensors and the piano recording switch.
+    #[validate]
+    pub homekit: Option<HomeKit>,
+    /// DLNA media server with the piano recordings.
+    #[validate]
+    pub dlna: Option<Dlna>,
+    /// Gracefully power off the system every day at this time (`HH:MM`).
+    #[serde(deserialize_with = "deserialize::time_of_day")]
+    pub daily_poweroff_at: Option<NaiveTime>,
     #[validate]
     pub piano: Piano,
 }
@@ -42,16 +163,195 @@ impl Default for Config {
             server_address: "0.0.0.0".to_string(),
             server_port: 80,
             log_level: LevelFilter::Info,
+            log_buffer_capacity: 500,
+            log_output: LogOutput::Auto,
+            log_format: LogFormat::Text,
+            log_file: None,
+            syslog: None,
+            broadcaster_capacity: 10,
             assets_dir: AssetsDir::unset(),
+            preload_sounds: false,
+            watch_assets: false,
+            site: Site::default(),
             data_dir: Path::new(concat!("/var/lib/", env!("CARGO_PKG_NAME"))).into(),
+            shutdown_timeout_secs: 10,
+            locale: Locale::default(),
+            timezone: None,
             access_token: None,
+            admin_token: None,
+            dbus_service: false,
+            manageable_units: vec![
+                "bluetooth.service".to_string(),
+                concat!(env!("CARGO_PKG_NAME"), ".service").to_string(),
+            ],
+            backoff: Backoff::default(),
             bluetooth: Bluetooth::default(),
             hotspot: None,
+            monitor_output: None,
+            snapcast: None,
+            voice_memo: None,
+            udev: Udev::default(),
+            backup: Backup::default(),
+            recording_upload: None,
+            usb_storage: None,
+            location: None,
+            display: Display::default(),
+            quiet_hours: None,
+            led: None,
+            power: None,
+            resample_quality: Some(ResampleQuality::Balanced),
+            transcode: Transcode::default(),
+            tts: Tts::default(),
+            announce: Announce::default(),
+            disk_watchdog: DiskWatchdog::default(),
+            storage: Storage::default(),
+            updater: None,
+            weather: None,
+            calendar: None,
+            presence: None,
+            mqtt: None,
+            automation: Automation::default(),
+            scenes: Vec::new(),
+            scripting: false,
+            ntfy: None,
+            telegram: None,
+            homekit: None,
+            dlna: None,
+            daily_poweroff_at: None,
             piano: Piano::default(),
         }
     }
 }
 
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    /// Journal if systemd-journald is running, otherwise stderr.
+    Auto,
+    Journal,
+    Stderr,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleQuality {
+    Fast,
+    Balanced,
+    High,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// Module, level and the record key-values (like `event` or `device`) are passed as
+    /// separate journal fields. Lines written to stderr or file are JSON objects.
+    Json,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct LogFile {
+    pub path: PathBuf,
+    /// When the file exceeds this size, it will be renamed to `<path>.1`
+    /// (replacing the previous one) and a new file will be created.
+    #[validate(minimum = 1)]
+    pub max_size_kib: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Syslog {
+    /// Host and port of the UDP listener, e.g. `logs.lan:514`.
+    pub address: String,
+}
+
+/// Retry policies of the operations which may fail temporarily.
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct Backoff {
+    /// Used for waiting until an adapter will be available or powered on.
+    #[validate]
+    pub bluetooth_adapter_wait: BackoffPolicy,
+    /// Used when trying to connect to device.
+    #[validate]
+    pub bluetooth_device_connect: BackoffPolicy,
+    /// We need to wait, for example, after a Bluetooth A2DP source is disconnected:
+    /// supported output stream configurations become available only in some time.
+    #[validate]
+    pub audio_output_stream_wait: BackoffPolicy,
+    /// Used to recreate the device events monitor after it stopped.
+    #[validate]
+    pub udev_monitor_restart: BackoffPolicy,
+    /// Used to upload a recording to [Config::recording_upload].
+    #[validate]
+    pub recording_upload: BackoffPolicy,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            bluetooth_adapter_wait: BackoffPolicy {
+                initial_interval_ms: 100,
+                multiplier: 1.5,
+                max_interval_ms: 500,
+                max_elapsed_time_ms: None, // Wait forever.
+            },
+            bluetooth_device_connect: BackoffPolicy {
+                initial_interval_ms: 1000,
+                multiplier: 1.5,
+                max_interval_ms: 5000,
+                max_elapsed_time_ms: Some(30_000),
+            },
+            audio_output_stream_wait: BackoffPolicy {
+                initial_interval_ms: 100,
+                multiplier: 5.0,
+                max_interval_ms: 1000,
+                max_elapsed_time_ms: Some(8000),
+            },
+            udev_monitor_restart: BackoffPolicy {
+                initial_interval_ms: 1000,
+                multiplier: 2.0,
+                max_interval_ms: 60_000,
+                max_elapsed_time_ms: None, // Retry forever.
+            },
+            recording_upload: BackoffPolicy {
+                initial_interval_ms: 30_000,
+                multiplier: 2.0,
+                max_interval_ms: 30 * 60 * 1000,
+                max_elapsed_time_ms: Some(6 * 60 * 60 * 1000),
+            },
+        }
+    }
+}
+
+/// Exponential backoff: interval between retries is multiplied by `multiplier`
+/// every time until it reaches `max_interval_ms`.
+#[derive(Clone, Deserialize, Validate)]
+#[validate(custom = validator::backoff_intervals)]
+pub struct BackoffPolicy {
+    #[validate(minimum = 1)]
+    pub initial_interval_ms: u64,
+    #[validate(minimum = 1.0)]
+    pub multiplier: f64,
+    #[validate(minimum = 1)]
+    pub max_interval_ms: u64,
+    /// Stop retrying after this time. Set to [None] to retry forever.
+    pub max_elapsed_time_ms: Option<u64>,
+}
+
+impl BackoffPolicy {
+    pub fn exponential(&self) -> backoff::ExponentialBackoff {
+        backoff::ExponentialBackoff {
+            initial_interval: Duration::from_millis(self.initial_interval_ms),
+            multiplier: self.multiplier,
+            max_interval: Duration::from_millis(self.max_interval_ms),
+            max_elapsed_time: self.max_elapsed_time_ms.map(Duration::from_millis),
+            randomization_factor: 0.0,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Validate)]
 #[serde(default)]
 pub struct Bluetooth {
@@ -82,6 +382,665 @@ pub struct Hotspot {
     pub bluetooth_mac_address: String,
 }
 
+#[derive(Clone, Default, Deserialize, Validate)]
+#[serde(default)]
+pub struct Backup {
+    /// Public keys of `age` (`age1...`). If not empty, backups are encrypted to these recipients.
+    pub age_recipients: Vec<String>,
+    /// Include the server configuration. Make sure it's safe to store the secrets with a backup.
+    pub include_config: bool,
+    /// Scheduled backups contain only the recordings which are new or changed since
+    /// the previous one (plus the preferences), instead of the full archive.
+    pub incremental: bool,
+    /// Make a backup every day at this time (`HH:MM`) and upload it to [Self::targets].
+    #[serde(deserialize_with = "deserialize::time_of_day")]
+    pub daily_at: Option<NaiveTime>,
+    /// Directory where the scheduled backup is written before uploading. Must be outside of
+    /// the data directory. If [None], the system temporary directory is used.
+    pub staging_dir: Option<PathBuf>,
+    #[validate]
+    pub targets: Vec<BackupTarget>,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct BackupTarget {
+    /// Used in the logs and the upload status.
+    pub name: String,
+    /// Older backups are removed from the target after upload.
+    #[validate(minimum = 1)]
+    pub keep_last: u16,
+    #[serde(flatten)]
+    pub kind: BackupTargetKind,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupTargetKind {
+    /// S3-compatible storage. Requests are signed by curl (7.75.0 or newer is required).
+    S3 {
+        /// E.g. `https://s3.eu-central-1.amazonaws.com`.
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        /// Prepended to the object names, e.g. `homie/`.
+        #[serde(default)]
+        prefix: String,
+    },
+    Webdav {
+        /// URL of the directory.
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    /// Destination directory of rsync, e.g. `user@host:backups` (SSH keys must be set up).
+    Rsync { destination: String },
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Display {
+    /// Turn the display off every day at this time.
+    pub sleep_at: Option<DailyTime>,
+    /// Turn the display on every day at this time.
+    pub wake_at: Option<DailyTime>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct QuietHours {
+    pub from: DailyTime,
+    /// Can be earlier than `from`, then the period crosses midnight.
+    pub until: DailyTime,
+}
+
+/// Time of the day: either `HH:MM` or `sunrise` / `sunset` with an optional offset in minutes,
+/// e.g. `sunset-30`. Sun-based times require [Location].
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DailyTime {
+    Clock(NaiveTime),
+    Sun { event: SunEvent, offset_mins: i64 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+impl TryFrom<String> for DailyTime {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let sun_time = [("sunrise", SunEvent::Sunrise), ("sunset", SunEvent::Sunset)]
+            .into_iter()
+            .find_map(|(name, event)| value.strip_prefix(name).map(|offset| (event, offset)));
+        let Some((event, offset)) = sun_time else {
+            return NaiveTime::parse_from_str(&value, "%H:%M")
+                .map(Self::Clock)
+                .map_err(|e| format!("invalid time {value}: {e}"));
+        };
+        let offset_mins = match offset {
+            "" => 0,
+            // Explicit sign is required, so "sunrise30" is not accepted.
+            _ if offset.starts_with(['+', '-']) => offset
+                .parse()
+                .map_err(|_| format!("invalid offset of {value}, expected minutes"))?,
+            _ => return Err(format!("invalid time {value}")),
+        };
+        Ok(Self::Sun { event, offset_mins })
+    }
+}
+
+impl DailyTime {
+    pub fn is_sun_based(&self) -> bool {
+        matches!(self, Self::Sun { .. })
+    }
+}
+
+/// Coordinates used to calculate the sunrise and sunset times and to fetch the outdoor weather.
+#[derive(Clone, Copy, Deserialize, Validate)]
+pub struct Location {
+    #[validate(minimum = -90.0)]
+    #[validate(maximum = 90.0)]
+    pub latitude: f64,
+    #[validate(minimum = -180.0)]
+    #[validate(maximum = 180.0)]
+    pub longitude: f64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Led {
+    /// Names of the LEDs (see `/sys/class/leds`) which form the color channels.
+    /// Channels which are not set are ignored, so a single LED can be used.
+    #[serde(default)]
+    pub red: Option<String>,
+    #[serde(default)]
+    pub green: Option<String>,
+    #[serde(default)]
+    pub blue: Option<String>,
+    #[serde(default)]
+    pub patterns: LedPatterns,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct LedPatterns {
+    pub recording: LedPattern,
+    /// A device which can play audio through the piano is connected via Bluetooth.
+    pub bluetooth_audio: LedPattern,
+    /// Shown for a few seconds after a subsystem failure.
+    pub error: LedPattern,
+}
+
+impl Default for LedPatterns {
+    fn default() -> Self {
+        Self {
+            recording: LedPattern {
+                color: LedColor::Red,
+                blink_interval_ms: Some(500),
+            },
+            bluetooth_audio: LedPattern {
+                color: LedColor::Blue,
+                blink_interval_ms: None,
+            },
+            error: LedPattern {
+                color: LedColor::Red,
+                blink_interval_ms: Some(100),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct LedPattern {
+    pub color: LedColor,
+    /// If [None], the LED is constantly on.
+    #[serde(default)]
+    pub blink_interval_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedColor {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Cyan,
+    Magenta,
+    White,
+}
+
+impl LedColor {
+    /// Returns whether the red, green and blue channels are on.
+    pub fn channels(self) -> (bool, bool, bool) {
+        match self {
+            Self::Red => (true, false, false),
+            Self::Green => (false, true, false),
+            Self::Blue => (false, false, true),
+            Self::Yellow => (true, true, false),
+            Self::Cyan => (false, true, true),
+            Self::Magenta => (true, false, true),
+            Self::White => (true, true, true),
+        }
+    }
+}
+
+/// The connection is plain TCP (TLS is not supported), so the broker
+/// should be in the local network or reached through a tunnel.
+#[derive(Clone, Deserialize)]
+pub struct Mqtt {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Prefix of the state and command topics. It's also used as the client identifier.
+    pub base_topic: String,
+    /// Home Assistant uses `homeassistant` by default.
+    pub discovery_prefix: String,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Automation {
+    pub rules: Vec<AutomationRule>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Scene {
+    /// Used to activate the scene using GraphQL or [AutomationAction::ActivateScene].
+    pub name: String,
+    /// Performed sequentially. Scenes can't activate other scenes.
+    pub actions: Vec<AutomationAction>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AutomationRule {
+    /// Used in the logs and to toggle the rule using GraphQL.
+    pub name: String,
+    /// The rule can be enabled later using GraphQL.
+    #[serde(default)]
+    pub disabled: bool,
+    pub when: AutomationTrigger,
+    /// Actions are performed sequentially.
+    pub then: Vec<AutomationAction>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationTrigger {
+    PianoEvent(PianoEvent),
+    /// Triggered when the lounge temperature (°C) drops below the value.
+    LoungeTemperatureBelow(f32),
+    /// Triggered when the lounge temperature (°C) rises above the value.
+    LoungeTemperatureAbove(f32),
+    /// Triggered when the difference between the lounge and outdoor temperatures
+    /// (lounge minus outdoor, °C) drops below the value. Requires [Weather].
+    OutdoorDeltaBelow(f32),
+    OutdoorDeltaAbove(f32),
+    /// Triggered every day at the time.
+    At(DailyTime),
+    /// Triggered before the start of the [Calendar] events which summary contains the text.
+    CalendarEvent {
+        summary: String,
+        #[serde(default)]
+        minutes_before: i64,
+    },
+    /// Triggered by the `POST /api/trigger/<NAME>` request.
+    Webhook(String),
+    /// Person with the given name came home (see [Presence]).
+    PersonArrived(String),
+    PersonLeft(String),
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationAction {
+    /// Send the global notification event with the message.
+    Notify(String),
+    /// Play a sound using the piano.
+    PlaySound(Sound),
+    /// Speak the text using the piano.
+    Announce(String),
+    /// Requires the MQTT integration to be configured.
+    MqttPublish {
+        topic: String,
+        payload: String,
+        #[serde(default)]
+        retain: bool,
+    },
+    StartRecording,
+    StopRecording,
+    /// Enable or disable the hotspot handling preference.
+    SetHotspotHandling(bool),
+    /// Change the volume of the secondary sounds preference (`1.0` is the original volume).
+    SetSoundsVolume(f32),
+    /// Turn the status LED on or off (it's still off during the quiet hours).
+    /// Requires [Led] to be configured.
+    SetStatusLed(bool),
+    /// Turn the HDMI display on or off.
+    SetDisplayPower(bool),
+    /// Perform the actions of the [Scene] with the given name.
+    ActivateScene(String),
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Telegram {
+    /// Bot token received from @BotFather.
+    pub token: String,
+    /// Commands are accepted only from these chats and notifications are sent to all of them.
+    pub chat_ids: Vec<i64>,
+    pub notifications: Vec<NotificationKind>,
+}
+
+/// Notifications which are pushed using Telegram or ntfy.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// Messages of the automation rules and scripts.
+    Notification,
+    NewRecordingSaved,
+    DiskSpaceLow,
+    BackupUploaded,
+    BackupUploadFailed,
+    UsbOffloadFinished,
+}
+
+impl NotificationKind {
+    /// Used if the priority is not configured.
+    pub fn default_ntfy_priority(&self) -> NtfyPriority {
+        match self {
+            Self::DiskSpaceLow | Self::BackupUploadFailed => NtfyPriority::High,
+            Self::BackupUploaded | Self::UsbOffloadFinished => NtfyPriority::Low,
+            Self::Notification | Self::NewRecordingSaved => NtfyPriority::Default,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Ntfy {
+    /// Base URL of the server, e.g. `https://ntfy.sh` or a self-hosted one.
+    pub server_url: String,
+    /// Used for the notifications which don't override the topic.
+    pub topic: String,
+    pub auth: Option<NtfyAuth>,
+    /// Only these notifications are sent.
+    pub notifications: HashMap<NotificationKind, NtfyNotification>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NtfyAuth {
+    AccessToken(String),
+    Basic { username: String, password: String },
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NtfyNotification {
+    /// If [None], [NotificationKind::default_ntfy_priority] is used.
+    pub priority: Option<NtfyPriority>,
+    pub topic: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize, strum::AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NtfyPriority {
+    Min,
+    Low,
+    Default,
+    High,
+    Urgent,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct HomeKit {
+    /// Name of the bridge shown in the Home app.
+    #[validate(min_length = 1)]
+    pub name: String,
+    /// Setup code which is entered on pairing.
+    #[validate(
+        pattern = r"^\d{3}-\d{2}-\d{3}$",
+        message = "must be in format XXX-XX-XXX"
+    )]
+    pub pin: String,
+    pub port: u16,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct Dlna {
+    /// Name shown on the TVs and streamers. Server identifier is derived from it.
+    #[validate(min_length = 1)]
+    pub friendly_name: String,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct Power {
+    /// Power off the system if it's running on battery and the charge drops to this value.
+    #[validate(minimum = 0.0)]
+    #[validate(maximum = 100.0)]
+    pub shutdown_percentage: f64,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct MonitorOutput {
+    #[validate(
+        min_length = 1,
+        message = "must be set (you can find it in /proc/asound/cards)"
+    )]
+    pub device_id: String,
+    #[validate(
+        min_length = 1,
+        message = "must be set (run 'aplay --list-pcms' to view available)"
+    )]
+    pub alsa_plugin: String,
+    /// Route the recordings playback to this device while it's plugged in.
+    pub auto_switch: bool,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct Snapcast {
+    /// `host:port` of the `tcp` stream source (in the server mode).
+    #[validate(min_length = 1)]
+    pub address: String,
+    /// Must match the sample format of the stream source (`sampleformat` parameter).
+    #[validate(minimum = 8000)]
+    pub sample_rate: u32,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct VoiceMemo {
+    #[validate(
+        min_length = 1,
+        message = "must be set (you can find it in /proc/asound/cards)"
+    )]
+    pub device_id: String,
+    #[validate(
+        min_length = 1,
+        message = "must be set (run 'arecord --list-pcms' to view available)"
+    )]
+    pub alsa_plugin: String,
+    /// When this number is exceeded, the oldest memos are removed.
+    #[validate(minimum = 1)]
+    pub max_memos: u16,
+    #[validate]
+    pub recorder: Recorder,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Udev {
+    /// Reactions to the device events which don't require a dedicated device implementation.
+    pub rules: Vec<UdevRule>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct UdevRule {
+    /// Used in the logs and passed with [UdevRuleAction::BroadcastEvent].
+    pub name: String,
+    pub subsystem: String,
+    /// Action of the event (e.g. `add` or `remove`). If [None], any action matches.
+    #[serde(default)]
+    pub event_type: Option<String>,
+    /// udev properties which must have the exact values (see `udevadm info`).
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+    /// sysfs attributes which must have the exact values.
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+    pub action: UdevRuleAction,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UdevRuleAction {
+    /// Send the global event with the rule name.
+    BroadcastEvent,
+    /// Play a sound using the piano.
+    PlaySound(Sound),
+    /// Run an executable with arguments. Event properties are passed as environment variables.
+    /// The command is not empty.
+    RunProgram(Vec<String>),
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct UsbStorage {
+    /// UUID of the file system (see `lsblk --fs`).
+    #[validate(custom = validator::fs_uuid)]
+    pub fs_uuid: String,
+    /// When the limit is reached, the oldest recordings are removed from the drive.
+    #[validate(minimum = 1)]
+    pub max_recordings: u16,
+    #[validate(minimum = 1)]
+    pub max_backups: u16,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Site {
+    /// URL path prefixes of the files having a content hash in the name (e.g. `/assets/`).
+    /// They never change, so clients can cache them forever.
+    pub immutable_prefixes: Vec<String>,
+    /// Serve `<file>.br` or `<file>.gz` instead of a file if it exists
+    /// and the client accepts the encoding.
+    pub precompressed: bool,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct Transcode {
+    /// How many conversions can run at once.
+    #[validate(minimum = 1)]
+    pub max_parallel_jobs: usize,
+    /// How many conversions can wait for a free slot. Further requests are rejected.
+    pub max_queued_jobs: usize,
+}
+
+impl Default for Transcode {
+    fn default() -> Self {
+        Self {
+            max_parallel_jobs: 1,
+            max_queued_jobs: 4,
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Tts {
+    pub engine: TtsEngine,
+    /// Voice of espeak-ng (e.g. `en-us`) or path to the piper model (required for piper).
+    /// If [None], the default espeak-ng voice is used.
+    pub voice: Option<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TtsEngine {
+    #[default]
+    EspeakNg,
+    Piper,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct Announce {
+    /// Each sample will be multiplied by this value. Unlike the speech,
+    /// the volume of sounds is not applied.
+    #[validate(minimum = 0.0)]
+    pub volume: f32,
+    /// Larger clips are rejected.
+    #[validate(minimum = 1)]
+    pub max_clip_kib: usize,
+}
+
+impl Default for Announce {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            max_clip_kib: 2048,
+        }
+    }
+}
+
+/// Fetched for the [Location], which is required.
+#[derive(Clone, Deserialize, Validate)]
+pub struct Weather {
+    #[validate(minimum = 1)]
+    pub update_interval_mins: u64,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct Calendar {
+    /// URL of the ICS file, e.g. the secret address of a Google calendar.
+    pub url: String,
+    #[validate(minimum = 1)]
+    pub refresh_interval_mins: u64,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct Presence {
+    pub people: Vec<Person>,
+    #[validate(minimum = 1)]
+    pub check_interval_secs: u64,
+    /// Person is considered away if none of their devices is seen for this time.
+    pub away_after_secs: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Person {
+    pub name: String,
+    /// MAC address of the phone in the local network (Wi-Fi).
+    #[serde(default)]
+    pub wifi_mac_address: Option<String>,
+    /// Address of the phone which is connected or discovered by the Bluetooth adapter.
+    #[serde(default)]
+    pub bluetooth_mac_address: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Updater {
+    pub source: UpdateSource,
+    /// Releases must be signed by the corresponding private key.
+    #[serde(deserialize_with = "deserialize::ed25519_public_key")]
+    pub public_key: ed25519_dalek::VerifyingKey,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateSource {
+    /// URL of a JSON file with the `version`, binary `url` and its `signature`.
+    ManifestUrl(String),
+    /// `owner/name` of a GitHub repository.
+    GithubRepo(String),
+}
+
+/// Free space monitoring of the data directory.
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct DiskWatchdog {
+    /// New recordings are refused when free space drops below this value.
+    pub min_free_mib: u64,
+    #[validate(minimum = 1)]
+    pub check_interval_secs: u64,
+    /// Remove the oldest recordings (except the newest one) until there is enough free space,
+    /// without waiting for `piano.max_recordings` to be reached.
+    pub cleanup_recordings: bool,
+}
+
+impl Default for DiskWatchdog {
+    fn default() -> Self {
+        Self {
+            min_free_mib: 256,
+            check_interval_secs: 60,
+            cleanup_recordings: false,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct Storage {
+    /// How often to save the lounge sensor data.
+    #[validate(minimum = 1)]
+    pub sensor_history_interval_mins: u64,
+    /// Sensor history, event and audit records older than this are removed.
+    #[validate(minimum = 1)]
+    pub retention_days: u32,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            sensor_history_interval_mins: 10,
+            retention_days: 365,
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Validate)]
 #[serde(default)]
 pub struct Piano {
@@ -101,8 +1060,23 @@ pub struct Piano {
     /// Recorder will be automatically stopped and a recording saved when this limit is reached.
     #[validate(minimum = 1)]
     pub max_recording_duration_secs: u32,
+    /// How long a recording requested with `waitForDevice` waits for the recorder.
+    #[validate(minimum = 1)]
+    pub record_device_wait_secs: u32,
     #[validate]
     pub recorder: Recorder,
+    /// Output for the feedback sounds while the piano's audio device is not available
+    /// (e.g. it's held by an A2DP source). If [None], sounds are skipped.
+    pub sounds_fallback_output: Option<SoundsFallbackOutput>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SoundsFallbackOutput {
+    /// See [Config::monitor_output].
+    Monitor,
+    /// See [Config::snapcast].
+    Snapcast,
 }
 
 impl Default for Piano {
@@ -117,7 +1091,9 @@ impl Default for Piano {
             alsa_plugin: "plughw".to_string(),
             max_recordings: 20,
             max_recording_duration_secs: 3600,
+            record_device_wait_secs: 300,
             recorder: Recorder::default(),
+            sounds_fallback_output: None,
         }
     }
 }
@@ -131,6 +1107,12 @@ pub struct Recorder {
     pub sample_rate: cpal::SampleRate,
     #[validate(maximum = 8)]
     pub flac_compression_level: u32,
+    /// Skip the silence at the start and at the end of the recordings.
+    #[validate]
+    pub trim_silence: Option<TrimSilence>,
+    /// Stop recording if there is no sound for a long time.
+    #[validate]
+    pub stop_on_silence: Option<StopOnSilence>,
 }
 
 impl Default for Recorder {
@@ -139,62 +1121,148 @@ impl Default for Recorder {
             channels: 2,                           // Stereo
             sample_rate: cpal::SampleRate(48_000), // 48 kHz
             flac_compression_level: 8,             // Maximum compression
+            trim_silence: None,
+            stop_on_silence: None,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct TrimSilence {
+    /// Audio with the peak level below this value is considered as silence.
+    #[validate(maximum = 0.0)]
+    pub threshold_dbfs: f32,
+}
+
+impl Default for TrimSilence {
+    fn default() -> Self {
+        Self {
+            threshold_dbfs: -50.0,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct StopOnSilence {
+    /// Audio with the peak level below this value is considered as silence.
+    #[validate(maximum = 0.0)]
+    pub threshold_dbfs: f32,
+    #[validate(minimum = 1)]
+    pub after_mins: u32,
+}
+
+impl Default for StopOnSilence {
+    fn default() -> Self {
+        Self {
+            threshold_dbfs: -50.0,
+            after_mins: 10,
         }
     }
 }
 
 impl Config {
+    /// The overlay edited using the API (see [crate::config_editor::ConfigEditor])
+    /// is merged on top of the configuration file.
     pub fn new() -> anyhow::Result<Self> {
-        let config: Self = Figment::new()
-            .merge(Yaml::file(YAML_FILE_LOCATION))
-            .merge(Env::prefixed(ENV_PREFIX))
-            .extract()?;
+        // Overlay is stored in the data directory, which is configurable itself.
+        let data_dir = Self::with_overlay_unchecked(Yaml::string(""))?.data_dir;
+        Self::with_overlay(Yaml::file(&*data_dir.path(files::Data::ConfigOverlay)))
+    }
+
+    /// Build and validate the configuration with `overlay` merged on top of the file.
+    /// Environment variables still have the highest priority.
+    pub fn with_overlay(overlay: providers::Data<Yaml>) -> anyhow::Result<Self> {
+        let config = Self::with_overlay_unchecked(overlay)?;
         config
             .validate()
             // Try pretty-printed YAML format instead of compacted JSON.
             .map_err(|err| anyhow!(serde_yaml::to_string(&err).unwrap_or(err.to_string())))?;
-        Ok(config)
-    }
-}
-
-pub mod backoff {
-    use std::time::Duration;
-
-    type ExponentialBackoff = backoff::exponential::ExponentialBackoff<backoff::SystemClock>;
-
-    /// Used for waiting until an adapter will be available or powered on.
-    pub fn bluetooth_adapter_wait() -> ExponentialBackoff {
-        ExponentialBackoff {
-            initial_interval: Duration::from_millis(100),
-            max_interval: Duration::from_millis(500),
-            max_elapsed_time: None, // Wait forever.
-            randomization_factor: 0.0,
-            ..Default::default()
+        for scene in &config.scenes {
+            if scene
+                .actions
+                .iter()
+                .any(|action| matches!(action, AutomationAction::ActivateScene(_)))
+            {
+                return Err(anyhow!(
+                    "scene {} must not activate other scenes",
+                    scene.name
+                ));
+            }
+        }
+        for rule in &config.automation.rules {
+            for action in &rule.then {
+                if let AutomationAction::ActivateScene(name) = action {
+                    if !config.scenes.iter().any(|scene| scene.name == *name) {
+                        return Err(anyhow!(
+                            "automation rule {} activates unknown scene {name}",
+                            rule.name
+                        ));
+                    }
+                }
+            }
+        }
+        if config.location.is_none() && config.daily_times().any(|time| time.is_sun_based()) {
+            return Err(anyhow!(
+                "location must be set to use the sunrise / sunset times"
+            ));
+        }
+        if config.location.is_none() && config.weather.is_some() {
+            return Err(anyhow!("location must be set to fetch the outdoor weather"));
+        }
+        if let Some(staging_dir) = &config.backup.staging_dir {
+            if staging_dir.starts_with(config.data_dir.root()) {
+                return Err(anyhow!(
+                    "backup staging directory must be outside of the data directory"
+                ));
+            }
         }
+        let has_negative_volume = config
+            .automation
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.then)
+            .chain(config.scenes.iter().flat_map(|scene| &scene.actions))
+            .any(|action| matches!(action, AutomationAction::SetSoundsVolume(volume) if *volume < 0.0));
+        if has_negative_volume {
+            return Err(anyhow!("sounds volume of the actions must not be negative"));
+        }
+        for rule in &config.udev.rules {
+            if matches!(&rule.action, UdevRuleAction::RunProgram(command) if command.is_empty()) {
+                return Err(anyhow!("udev rule \"{}\" has an empty command", rule.name));
+            }
+        }
+        Ok(config)
     }
 
-    /// Used when trying to connect to device.
-    pub fn bluetooth_device_connect() -> ExponentialBackoff {
-        ExponentialBackoff {
-            initial_interval: Duration::from_secs(1),
-            max_interval: Duration::from_secs(5),
-            max_elapsed_time: Some(Duration::from_secs(30)),
-            randomization_factor: 0.0,
-            ..Default::default()
-        }
+    fn with_overlay_unchecked(overlay: providers::Data<Yaml>) -> anyhow::Result<Self> {
+        Ok(Figment::new()
+            .merge(Yaml::file(YAML_FILE_LOCATION))
+            .merge(overlay)
+            .merge(Env::prefixed(ENV_PREFIX))
+            .extract()?)
     }
 
-    /// We need to wait, for example, after a Bluetooth A2DP source is disconnected:
-    /// supported output stream configurations become available only in some time.
-    pub fn audio_output_stream_wait() -> ExponentialBackoff {
-        ExponentialBackoff {
-            initial_interval: Duration::from_millis(100),
-            multiplier: 5.0,
-            max_interval: Duration::from_secs(1),
-            max_elapsed_time: Some(Duration::from_secs(8)),
-            randomization_factor: 0.0,
-            ..Default::default()
-        }
+    /// All configured times of the day which can depend on the location.
+    fn daily_times(&self) -> impl Iterator<Item = DailyTime> + '_ {
+        let rule_times = self
+            .automation
+            .rules
+            .iter()
+            .filter_map(|rule| match rule.when {
+                AutomationTrigger::At(time) => Some(time),
+                _ => None,
+            });
+        let quiet_hours = self
+            .quiet_hours
+            .into_iter()
+            .flat_map(|quiet_hours| [quiet_hours.from, quiet_hours.until]);
+        [self.display.sleep_at, self.display.wake_at]
+            .into_iter()
+            .flatten()
+            .chain(quiet_hours)
+            .chain(rule_times)
     }
 }
 
@@ -212,10 +1280,49 @@ mod validator {
             .map(|_| ())
             .map_err(|e| Error::Custom(e.to_string()))
     }
+
+    /// Formats differ between the file systems (e.g. `1234-ABCD` for FAT),
+    /// but all of them consist of the hexadecimal digits and dashes.
+    pub fn fs_uuid(val: &str) -> Result<(), Error> {
+        if !val.chars().any(|ch| ch.is_ascii_hexdigit())
+            || !val.chars().all(|ch| ch.is_ascii_hexdigit() || ch == '-')
+        {
+            return Err(Error::Custom(format!(
+                "\"{val}\" is not a file system UUID (see \"lsblk --fs\")"
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn backoff_intervals(val: &super::BackoffPolicy) -> Result<(), Error> {
+        if val.max_interval_ms < val.initial_interval_ms {
+            return Err(Error::Custom(
+                "max_interval_ms must not be less than initial_interval_ms".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 mod deserialize {
-    use serde::{Deserialize, Deserializer};
+    use base64::{prelude::BASE64_STANDARD, Engine};
+    use chrono::NaiveTime;
+    use ed25519_dalek::VerifyingKey;
+    use serde::{de, Deserialize, Deserializer};
+
+    /// Takes Base64 of the raw 32-byte key.
+    pub fn ed25519_public_key<'de, D>(deserializer: D) -> Result<VerifyingKey, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = BASE64_STANDARD
+            .decode(String::deserialize(deserializer)?.trim())
+            .map_err(de::Error::custom)?;
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| de::Error::custom("public key must be 32 bytes long"))?;
+        VerifyingKey::from_bytes(&bytes).map_err(de::Error::custom)
+    }
 
     pub fn sample_rate<'de, D>(deserializer: D) -> Result<cpal::SampleRate, D::Error>
     where
@@ -223,4 +1330,13 @@ mod deserialize {
     {
         u32::deserialize(deserializer).map(cpal::SampleRate)
     }
+
+    pub fn time_of_day<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|time| NaiveTime::parse_from_str(&time, "%H:%M").map_err(de::Error::custom))
+            .transpose()
+    }
 }
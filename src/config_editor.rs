@@ -0,0 +1,217 @@
+use std::{io, path::PathBuf, sync::Arc};
+
+use figment::providers::{Format, Yaml};
+use log::info;
+use serde_yaml::{Mapping, Value};
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+    config::Config,
+    dbus::{DBus, UnitControlError},
+    graphql::GraphQLError,
+    updater::SERVICE_UNIT,
+};
+
+/// Parts of the configuration which can be changed remotely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, async_graphql::Enum)]
+pub enum ConfigKey {
+    /// `bluetooth.lounge_temp_mac_address`: the lounge sensor.
+    BluetoothLoungeTempMacAddress,
+    /// `hotspot.bluetooth_mac_address`: the hotspot phone.
+    HotspotBluetoothMacAddress,
+    /// `presence.people`: phones which are tracked using Wi-Fi and Bluetooth.
+    PresencePeople,
+    /// `automation.rules`, including the webhook and time triggers.
+    AutomationRules,
+    Scenes,
+    /// `backup.daily_at`.
+    BackupDailyAt,
+    DailyPoweroffAt,
+}
+
+impl ConfigKey {
+    /// Path of the value in the YAML file.
+    fn path(&self) -> &'static [&'static str] {
+        match self {
+            Self::BluetoothLoungeTempMacAddress => &["bluetooth", "lounge_temp_mac_address"],
+            Self::HotspotBluetoothMacAddress => &["hotspot", "bluetooth_mac_address"],
+            Self::PresencePeople => &["presence", "people"],
+            Self::AutomationRules => &["automation", "rules"],
+            Self::Scenes => &["scenes"],
+            Self::BackupDailyAt => &["backup", "daily_at"],
+            Self::DailyPoweroffAt => &["daily_poweroff_at"],
+        }
+    }
+}
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConfigEditError {
+    #[error("Configuration is invalid: {0}")]
+    InvalidConfig(String),
+    #[error("Failed to read the overlay: {0}")]
+    ReadFailed(String),
+    #[error("Failed to save the overlay: {0}")]
+    SaveFailed(io::Error),
+    #[error("Failed to restart the service: {0}")]
+    RestartFailed(UnitControlError),
+}
+
+impl GraphQLError for ConfigEditError {}
+
+/// Edits the overlay which is merged on top of the configuration file (see [Config::new]).
+/// Changes take effect after the server restarts.
+#[derive(Clone)]
+pub struct ConfigEditor {
+    overlay_path: PathBuf,
+    dbus: DBus,
+    /// Serializes the modifications of the overlay.
+    lock: Arc<Mutex<()>>,
+}
+
+impl ConfigEditor {
+    pub fn new(overlay_path: PathBuf, dbus: DBus) -> Self {
+        Self {
+            overlay_path,
+            dbus,
+            lock: Arc::default(),
+        }
+    }
+
+    /// Returns [serde_json::Value::Null] if nothing is overridden.
+    pub async fn overlay(&self) -> Result<serde_json::Value, ConfigEditError> {
+        let overlay = self.read().await?;
+        serde_json::to_value(overlay).map_err(|e| ConfigEditError::ReadFailed(e.to_string()))
+    }
+
+    /// Override the value of `key`. If `value` is null, the value from the configuration file
+    /// is used again. The whole configuration is validated before saving.
+    pub async fn set(
+        &self,
+        key: ConfigKey,
+        value: serde_json::Value,
+    ) -> Result<(), ConfigEditError> {
+        let _lock = self.lock.lock().await;
+        let mut overlay = self.read().await?;
+        let value = match value {
+            serde_json::Value::Null => None,
+            value => Some(
+                serde_yaml::to_value(value)
+                    .map_err(|e| ConfigEditError::InvalidConfig(e.to_string()))?,
+            ),
+        };
+        set_path(&mut overlay, key.path(), value);
+
+        let yaml = serde_yaml::to_string(&overlay)
+            .map_err(|e| ConfigEditError::InvalidConfig(e.to_string()))?;
+        Config::with_overlay(Yaml::string(&yaml))
+            .map_err(|e| ConfigEditError::InvalidConfig(e.to_string()))?;
+
+        // Write atomically, so the server can always start.
+        let temp_path = self.overlay_path.with_extension("yaml.tmp");
+        fs::write(&temp_path, yaml)
+            .await
+            .map_err(ConfigEditError::SaveFailed)?;
+        fs::rename(&temp_path, &self.overlay_path)
+            .await
+            .map_err(ConfigEditError::SaveFailed)?;
+        info!("Configuration overlay updated");
+        Ok(())
+    }
+
+    /// Restart the server, so the overlay is applied.
+    pub async fn reload(&self) -> Result<(), ConfigEditError> {
+        self.dbus
+            .restart_unit(SERVICE_UNIT, &[SERVICE_UNIT.to_string()])
+            .await
+            .map_err(ConfigEditError::RestartFailed)
+    }
+
+    async fn read(&self) -> Result<Value, ConfigEditError> {
+        match fs::read_to_string(&self.overlay_path).await {
+            Ok(content) => serde_yaml::from_str(&content)
+                .map_err(|e| ConfigEditError::ReadFailed(e.to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Value::Null),
+            Err(e) => Err(ConfigEditError::ReadFailed(e.to_string())),
+        }
+    }
+}
+
+/// Set or remove (if `value` is [None]) the nested value. Empty mappings are removed.
+fn set_path(root: &mut Value, path: &[&str], value: Option<Value>) {
+    let Some((key, rest)) = path.split_first() else {
+        return;
+    };
+    if !root.is_mapping() {
+        *root = Value::Mapping(Mapping::new());
+    }
+    let Some(mapping) = root.as_mapping_mut() else {
+        return;
+    };
+    let key = Value::from(*key);
+    if rest.is_empty() {
+        match value {
+            Some(value) => {
+                mapping.insert(key, value);
+            }
+            None => {
+                mapping.remove(&key);
+            }
+        }
+        return;
+    }
+    let child = mapping.entry(key.clone()).or_insert(Value::Null);
+    set_path(child, rest, value);
+    if child.as_mapping().is_some_and(Mapping::is_empty) {
+        mapping.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn set_nested() {
+        let mut root = Value::Null;
+        set_path(
+            &mut root,
+            &["backup", "daily_at"],
+            Some(Value::from("03:00")),
+        );
+        assert_eq!(root, yaml("backup: {daily_at: '03:00'}"));
+
+        let mut root = yaml("backup: {incremental: true}\nscenes: []");
+        set_path(
+            &mut root,
+            &["backup", "daily_at"],
+            Some(Value::from("03:00")),
+        );
+        assert_eq!(
+            root,
+            yaml("backup: {incremental: true, daily_at: '03:00'}\nscenes: []")
+        );
+    }
+
+    #[test]
+    fn replace_scalar_parent() {
+        let mut root = yaml("presence: null");
+        set_path(&mut root, &["presence", "people"], Some(yaml("[]")));
+        assert_eq!(root, yaml("presence: {people: []}"));
+    }
+
+    #[test]
+    fn remove_empty_mappings() {
+        let mut root = yaml("backup: {daily_at: '03:00'}\nscenes: []");
+        set_path(&mut root, &["backup", "daily_at"], None);
+        assert_eq!(root, yaml("scenes: []"));
+
+        // Removing an absent value doesn't leave the empty parents.
+        set_path(&mut root, &["hotspot", "bluetooth_mac_address"], None);
+        assert_eq!(root, yaml("scenes: []"));
+    }
+}
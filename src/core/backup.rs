@@ -0,0 +1,463 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    os::{fd::OwnedFd, unix::net::UnixStream},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::web;
+use async_stream::stream;
+use claxon::FlacReader;
+use futures::{join, pin_mut, Stream, StreamExt};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncWriteExt, task};
+
+use super::stdout_reader::StdoutReader;
+use crate::{
+    audio::recorder::RECORDING_EXTENSION,
+    config::{self, YAML_FILE_LOCATION},
+    files::{BaseDir, Data, DataDir},
+    prefs::Preferences,
+};
+
+const ARCHIVE_EXTENSION: &str = ".tar";
+/// Appended to [ARCHIVE_EXTENSION] if the archive is encrypted.
+const ENCRYPTED_EXTENSION: &str = ".age";
+const ARCHIVE_MIME_TYPE: &str = "application/x-tar";
+const ENCRYPTED_MIME_TYPE: &str = "application/octet-stream";
+/// Name of [Manifest] inside an incremental archive.
+const MANIFEST_NAME: &str = "manifest.json";
+/// Name of the server configuration inside an archive.
+const CONFIG_NAME: &str = "config.yaml";
+/// Beginning of the files encrypted by `age`.
+const ENCRYPTED_HEADER: &[u8] = b"age-encryption.org/";
+/// Appended to the database path to make a temporary consistent copy of it.
+const DATABASE_SNAPSHOT_SUFFIX: &str = ".snapshot";
+
+/// Makes tar archives of the data directory (except the temporary files) and optionally
+/// the server configuration. Archives are encrypted using `age` if it's configured.
+/// Paths inside the archives are relative to the data directory.
+#[derive(Clone)]
+pub struct Backup {
+    config: config::Backup,
+    data_dir: DataDir,
+}
+
+impl Backup {
+    pub fn new(config: config::Backup, data_dir: DataDir) -> Self {
+        Self { config, data_dir }
+    }
+
+    pub fn extension(&self) -> String {
+        if self.config.age_recipients.is_empty() {
+            ARCHIVE_EXTENSION.to_string()
+        } else {
+            format!("{ARCHIVE_EXTENSION}{ENCRYPTED_EXTENSION}")
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        if self.config.age_recipients.is_empty() {
+            ARCHIVE_MIME_TYPE
+        } else {
+            ENCRYPTED_MIME_TYPE
+        }
+    }
+
+    /// Whether the file name has the extension of an archive (encrypted or not).
+    pub fn is_archive_name(name: &str) -> bool {
+        name.ends_with(ARCHIVE_EXTENSION)
+            || name.ends_with(&format!("{ARCHIVE_EXTENSION}{ENCRYPTED_EXTENSION}"))
+    }
+
+    /// Write a full archive to `output`.
+    pub async fn write(&self, output: File) -> io::Result<()> {
+        let (config, data_dir) = (self.config.clone(), self.data_dir.clone());
+        task::spawn_blocking(move || {
+            write_encrypted(&config.age_recipients, output.into(), |writer| {
+                write_full(&data_dir, config.include_config, writer)
+            })
+        })
+        .await?
+    }
+
+    /// Stream a full archive. If writing fails, the stream ends with the error.
+    pub async fn stream(&self) -> io::Result<impl Stream<Item = io::Result<web::Bytes>>> {
+        let (reader, writer) = UnixStream::pair()?;
+        let self_clone = self.clone();
+        let writing =
+            task::spawn(async move { self_clone.write(File::from(OwnedFd::from(writer))).await });
+        let reader = tokio::fs::File::from_std(File::from(OwnedFd::from(reader)));
+        let chunks = StdoutReader::new(reader).stream().await;
+        Ok(stream! {
+            pin_mut!(chunks);
+            while let Some(chunk) = chunks.next().await {
+                yield chunk;
+            }
+            match writing.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => yield Err(e),
+                Err(e) => yield Err(e.into()),
+            }
+        })
+    }
+
+    /// Read the whole archive and check its content. Returns an error only if the archive
+    /// can't be received, problems with the archive itself are listed in the result.
+    pub async fn verify<S, E>(&self, archive: S) -> io::Result<BackupVerification>
+    where
+        S: Stream<Item = Result<web::Bytes, E>>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (reader, writer) = UnixStream::pair()?;
+        let prefs_name = file_name(&self.data_dir.path(Data::Preferences));
+        let verifying =
+            task::spawn_blocking(move || verify(File::from(OwnedFd::from(reader)), &prefs_name));
+        let writing = async move {
+            let mut writer = tokio::fs::File::from_std(File::from(OwnedFd::from(writer)));
+            pin_mut!(archive);
+            while let Some(chunk) = archive.next().await {
+                writer.write_all(&chunk.map_err(io::Error::other)?).await?;
+            }
+            writer.flush().await
+        };
+        let (verification, written) = join!(verifying, writing);
+        written?;
+        Ok(verification?)
+    }
+
+    /// Write an archive with the recordings which are new or changed since the last committed
+    /// incremental backup, the preferences, the database and the manifest of all recordings.
+    pub async fn write_incremental(&self, output: File) -> io::Result<IncrementalBackup> {
+        let (config, data_dir) = (self.config.clone(), self.data_dir.clone());
+        task::spawn_blocking(move || {
+            write_encrypted(&config.age_recipients, output.into(), |writer| {
+                write_incremental(&data_dir, writer)
+            })
+        })
+        .await?
+    }
+}
+
+/// All recordings at the time of an incremental backup. Recordings which are not listed in the
+/// manifest of the latest archive were removed, so they must be skipped on restore.
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    /// By the file name.
+    recordings: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    size: u64,
+    /// Seconds since the Unix epoch. Used to skip hashing of the unchanged files.
+    modified_secs: u64,
+    /// Lowercase hex.
+    sha256: String,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupVerification {
+    /// Number of the files and directories in the archive.
+    pub entries: usize,
+    pub recordings: usize,
+    pub has_preferences: bool,
+    pub has_config: bool,
+    /// Whether it's made by [Backup::write_incremental].
+    pub incremental: bool,
+    /// Problems found in the archive. It's valid if there are no errors.
+    pub errors: Vec<String>,
+}
+
+/// Made by [Backup::write_incremental].
+pub struct IncrementalBackup {
+    manifest: Manifest,
+    manifest_path: PathBuf,
+    /// Number of the packaged recordings.
+    pub changed_recordings: usize,
+}
+
+impl IncrementalBackup {
+    /// Must be called after the archive is stored, so the next backup includes
+    /// only the subsequent changes.
+    pub async fn commit(self) -> io::Result<()> {
+        let manifest = serde_json::to_vec(&self.manifest).map_err(io::Error::other)?;
+        tokio::fs::write(self.manifest_path, manifest).await
+    }
+}
+
+/// Pass the writer to `write` directly or through `age` if there are recipients.
+/// `write` must drop the writer when it's done.
+fn write_encrypted<T>(
+    age_recipients: &[String],
+    output: OwnedFd,
+    write: impl FnOnce(Box<dyn Write>) -> io::Result<T>,
+) -> io::Result<T> {
+    if age_recipients.is_empty() {
+        return write(Box::new(File::from(output)));
+    }
+    let mut age = Command::new("age");
+    for recipient in age_recipients {
+        age.args(["--recipient", recipient]);
+    }
+    let mut child = age
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(output))
+        .spawn()?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| io::Error::other("age input is not captured"))?;
+    let result = write(Box::new(stdin));
+    let status = child.wait()?;
+    let value = result?;
+    if !status.success() {
+        return Err(io::Error::other(format!("age exited with {status}")));
+    }
+    Ok(value)
+}
+
+fn write_full(data_dir: &DataDir, include_config: bool, writer: Box<dyn Write>) -> io::Result<()> {
+    let recordings_dir = data_dir.path(Data::PianoRecordings);
+    let transcodes_dir = data_dir.path(Data::Transcodes);
+    let database_name = file_name(&data_dir.path(Data::Database));
+    let mut builder = tar::Builder::new(writer);
+    for entry in fs::read_dir(data_dir.root())? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = file_name(&path);
+        // Database is added as a snapshot, its live files (including `-wal` and `-shm`) are
+        // skipped.
+        if path == *transcodes_dir
+            || name
+                .to_string_lossy()
+                .starts_with(&*database_name.to_string_lossy())
+        {
+            continue;
+        } else if path == *recordings_dir {
+            builder.append_dir(&name, &path)?;
+            for recording in recording_names(&path)? {
+                builder.append_path_with_name(path.join(&recording), name.join(&recording))?;
+            }
+        } else if entry.file_type()?.is_dir() {
+            builder.append_dir_all(&name, &path)?;
+        } else {
+            builder.append_path_with_name(&path, &name)?;
+        }
+    }
+    append_database(data_dir, &mut builder)?;
+    if include_config && Path::new(YAML_FILE_LOCATION).is_file() {
+        builder.append_path_with_name(YAML_FILE_LOCATION, CONFIG_NAME)?;
+    }
+    // Closes the input of the encryption.
+    drop(builder.into_inner()?);
+    Ok(())
+}
+
+fn write_incremental(data_dir: &DataDir, writer: Box<dyn Write>) -> io::Result<IncrementalBackup> {
+    let prefs_path = data_dir.path(Data::Preferences);
+    let recordings_dir = data_dir.path(Data::PianoRecordings);
+    let manifest_path = data_dir.path(Data::BackupManifest).to_path_buf();
+    let previous: Manifest = match fs::read(&manifest_path) {
+        Ok(manifest) => serde_json::from_slice(&manifest).map_err(io::Error::other)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Manifest::default(),
+        Err(e) => return Err(e),
+    };
+    let mut manifest = Manifest::default();
+    let mut changed = Vec::new();
+    for name in recording_names(&recordings_dir)? {
+        let path = recordings_dir.join(&name);
+        let metadata = fs::metadata(&path)?;
+        let modified_secs = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let previous_entry = previous.recordings.get(&name);
+        let sha256 = match previous_entry {
+            Some(previous_entry)
+                if previous_entry.size == metadata.len()
+                    && previous_entry.modified_secs == modified_secs =>
+            {
+                previous_entry.sha256.clone()
+            }
+            _ => sha256(&path)?,
+        };
+        if previous_entry.map_or(true, |previous_entry| previous_entry.sha256 != sha256) {
+            changed.push(name.clone());
+        }
+        manifest.recordings.insert(
+            name,
+            ManifestEntry {
+                size: metadata.len(),
+                modified_secs,
+                sha256,
+            },
+        );
+    }
+
+    let mut builder = tar::Builder::new(writer);
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(io::Error::other)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()),
+    );
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+    if prefs_path.is_file() {
+        builder.append_path_with_name(&*prefs_path, file_name(&prefs_path))?;
+    }
+    append_database(data_dir, &mut builder)?;
+    for name in &changed {
+        builder.append_path_with_name(
+            recordings_dir.join(name),
+            file_name(&recordings_dir).join(name),
+        )?;
+    }
+    // Closes the input of the encryption.
+    drop(builder.into_inner()?);
+
+    Ok(IncrementalBackup {
+        manifest,
+        manifest_path,
+        changed_recordings: changed.len(),
+    })
+}
+
+/// Add a consistent copy of the database. The live file can't be copied as is,
+/// because the changes are written to it (and to the WAL file) concurrently.
+fn append_database<W: Write>(data_dir: &DataDir, builder: &mut tar::Builder<W>) -> io::Result<()> {
+    let database_path = data_dir.path(Data::Database);
+    if !database_path.is_file() {
+        return Ok(());
+    }
+    let snapshot_path = PathBuf::from(format!(
+        "{}{DATABASE_SNAPSHOT_SUFFIX}",
+        database_path.to_string_lossy()
+    ));
+    // Left by an interrupted backup, VACUUM INTO requires a non-existent file.
+    let _ = fs::remove_file(&snapshot_path);
+    let result = Connection::open(&*database_path)
+        .and_then(|connection| {
+            connection.execute(
+                "VACUUM INTO ?1",
+                params![snapshot_path.to_string_lossy().as_ref()],
+            )
+        })
+        .map_err(io::Error::other)
+        .and_then(|_| builder.append_path_with_name(&snapshot_path, file_name(&database_path)));
+    let _ = fs::remove_file(&snapshot_path);
+    result
+}
+
+fn verify(archive: impl Read, prefs_name: &Path) -> BackupVerification {
+    let mut archive = BufReader::new(archive);
+    let verification = verify_entries(&mut archive, prefs_name);
+    // Consume the rest (e.g. padding or data after a broken entry), so the sender doesn't fail.
+    let _ = io::copy(&mut archive, &mut io::sink());
+    verification
+}
+
+fn verify_entries(archive: &mut BufReader<impl Read>, prefs_name: &Path) -> BackupVerification {
+    let mut verification = BackupVerification::default();
+    if archive
+        .fill_buf()
+        .is_ok_and(|beginning| beginning.starts_with(ENCRYPTED_HEADER))
+    {
+        verification
+            .errors
+            .push("archive is encrypted, decrypt it using age before verification".to_string());
+        return verification;
+    }
+
+    let mut archive = tar::Archive::new(archive);
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            verification.errors.push(format!("invalid archive: {e}"));
+            return verification;
+        }
+    };
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                verification.errors.push(format!("corrupted archive: {e}"));
+                break;
+            }
+        };
+        verification.entries += 1;
+        let path = entry
+            .path()
+            .map(|path| path.into_owned())
+            .unwrap_or_default();
+        let result = if path == prefs_name {
+            verification.has_preferences = true;
+            serde_yaml::from_reader::<_, Preferences>(&mut entry)
+                .map(|_| ())
+                .map_err(|e| format!("invalid preferences: {e}"))
+        } else if path == Path::new(CONFIG_NAME) {
+            verification.has_config = true;
+            Ok(())
+        } else if path == Path::new(MANIFEST_NAME) {
+            verification.incremental = true;
+            serde_json::from_reader::<_, Manifest>(&mut entry)
+                .map(|_| ())
+                .map_err(|e| format!("invalid manifest: {e}"))
+        } else if path.to_string_lossy().ends_with(RECORDING_EXTENSION) {
+            verification.recordings += 1;
+            FlacReader::new(&mut entry)
+                .map(|_| ())
+                .map_err(|e| format!("invalid FLAC header: {e}"))
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            verification
+                .errors
+                .push(format!("{}: {e}", path.to_string_lossy()));
+        }
+        // Read the rest to make sure that the entry is not truncated.
+        if let Err(e) = io::copy(&mut entry, &mut io::sink()) {
+            verification
+                .errors
+                .push(format!("{}: {e}", path.to_string_lossy()));
+            break;
+        }
+    }
+    verification
+}
+
+/// Returns file names of the saved recordings. The unsaved recording and foreign files are skipped.
+fn recording_names(recordings_dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(recordings_dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name
+            .strip_suffix(RECORDING_EXTENSION)
+            .is_some_and(|basename| basename.parse::<u64>().is_ok())
+        {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn file_name(path: &Path) -> PathBuf {
+    path.file_name().map(PathBuf::from).unwrap_or_default()
+}
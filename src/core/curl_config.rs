@@ -0,0 +1,79 @@
+use std::{
+    io,
+    process::{Output, Stdio},
+};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// curl options which are passed to its stdin (`--config -`) instead of the command line,
+/// as the arguments of a process are visible to all users of the system.
+/// Use it for the options containing secrets.
+#[derive(Default)]
+pub struct CurlConfig(String);
+
+impl CurlConfig {
+    /// Long option name without the leading dashes (e.g. `user`).
+    pub fn option(mut self, name: &str, value: &str) -> Self {
+        let mut quoted = String::with_capacity(value.len());
+        for ch in value.chars() {
+            match ch {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                _ => quoted.push(ch),
+            }
+        }
+        self.0 += &format!("{name} = \"{quoted}\"\n");
+        self
+    }
+
+    /// Run the curl `command` with the options. Its stdin is occupied by them.
+    pub async fn output(&self, command: &mut Command) -> io::Result<Output> {
+        let mut child = command
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin.write_all(self.0.as_bytes()).await?;
+        // Closed, so curl stops reading the options.
+        drop(stdin);
+        child.wait_with_output().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_value() {
+        let config = CurlConfig::default().option("user", "name:secret");
+        assert_eq!(config.0, "user = \"name:secret\"\n");
+    }
+
+    #[test]
+    fn escaped_quotes_and_backslashes() {
+        let config = CurlConfig::default().option("header", r#"X-Key: a"b\c"#);
+        assert_eq!(config.0, "header = \"X-Key: a\\\"b\\\\c\"\n");
+    }
+
+    #[test]
+    fn escaped_control_characters() {
+        // Otherwise a newline would start a new option.
+        let config = CurlConfig::default().option("user", "a\nurl = evil\r\tb");
+        assert_eq!(config.0, "user = \"a\\nurl = evil\\r\\tb\"\n");
+        assert_eq!(config.0.lines().count(), 1);
+    }
+
+    #[test]
+    fn multiple_options() {
+        let config = CurlConfig::default()
+            .option("url", "https://example.com")
+            .option("user", "a:b");
+        assert_eq!(config.0, "url = \"https://example.com\"\nuser = \"a:b\"\n");
+    }
+}
@@ -0,0 +1,146 @@
+use std::{fmt::Display, sync::OnceLock};
+
+use log::warn;
+use serde::Deserialize;
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Language of the human-readable strings returned by the API.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Ru,
+}
+
+/// Set the process-wide locale. Must be called once at startup,
+/// otherwise the default one will be used.
+pub fn init(locale: Locale) {
+    if LOCALE.set(locale).is_err() {
+        warn!("Locale is already initialized");
+    }
+}
+
+pub fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+impl Locale {
+    pub fn just_now(self) -> &'static str {
+        match self {
+            Self::En => "Just now",
+            Self::Ru => "Только что",
+        }
+    }
+
+    pub fn today_at(self, time: impl Display) -> String {
+        match self {
+            Self::En => format!("Today at {time}"),
+            Self::Ru => format!("Сегодня в {time}"),
+        }
+    }
+
+    pub fn yesterday_at(self, time: impl Display) -> String {
+        match self {
+            Self::En => format!("Yesterday at {time}"),
+            Self::Ru => format!("Вчера в {time}"),
+        }
+    }
+
+    /// Date of the current year. `month` starts from 1.
+    pub fn day_at(self, day: u32, month: u32, time: impl Display) -> String {
+        let month = self.month_name(month);
+        match self {
+            Self::En => format!("{month} {day} at {time}"),
+            Self::Ru => format!("{day} {month} в {time}"),
+        }
+    }
+
+    /// Date of a non-current year. `month` starts from 1.
+    pub fn full_date_at(self, day: u32, month: u32, year: i32, time: impl Display) -> String {
+        let month = self.month_name(month);
+        match self {
+            Self::En => format!("{day} {month} {year} at {time}"),
+            Self::Ru => format!("{day} {month} {year} г. в {time}"),
+        }
+    }
+
+    fn month_name(self, month: u32) -> &'static str {
+        const EN: [&str; 12] = [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ];
+        // Genitive case, as the month always goes after the day.
+        const RU: [&str; 12] = [
+            "января",
+            "февраля",
+            "марта",
+            "апреля",
+            "мая",
+            "июня",
+            "июля",
+            "августа",
+            "сентября",
+            "октября",
+            "ноября",
+            "декабря",
+        ];
+        let names = match self {
+            Self::En => &EN,
+            Self::Ru => &RU,
+        };
+        names[(month as usize - 1) % names.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_locale() {
+        // Not initialized in the tests.
+        assert!(locale() == Locale::En);
+    }
+
+    #[test]
+    fn deserialize() {
+        let locale: Locale = serde_yaml::from_str("ru").unwrap();
+        assert!(locale == Locale::Ru);
+        assert!(serde_yaml::from_str::<Locale>("Ru").is_err());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(Locale::En.day_at(5, 3, "10:00"), "March 5 at 10:00");
+        assert_eq!(Locale::Ru.day_at(5, 3, "10:00"), "5 марта в 10:00");
+        assert_eq!(
+            Locale::En.full_date_at(31, 12, 2023, "23:59"),
+            "31 December 2023 at 23:59"
+        );
+        assert_eq!(
+            Locale::Ru.full_date_at(1, 1, 2024, "00:00"),
+            "1 января 2024 г. в 00:00"
+        );
+        assert_eq!(Locale::Ru.yesterday_at("9:30"), "Вчера в 9:30");
+    }
+
+    #[test]
+    fn month_names() {
+        assert_eq!(Locale::En.month_name(1), "January");
+        assert_eq!(Locale::Ru.month_name(12), "декабря");
+        // Out of range months wrap instead of panicking.
+        assert_eq!(Locale::En.month_name(13), "January");
+    }
+}
@@ -1,20 +1,122 @@
-use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::{ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Local};
+use log::{
+    kv::{self, Key, Source, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
 use systemd_journal_logger::JournalLog;
+use tokio::sync::broadcast;
+
+use crate::config::{self, Config, LogFormat, LogOutput};
 
 /// Max verbosity level for a module and all its nested children.
-const MODULES_MAX_LEVEL: [(&str, Level); 1] = [
-    ("zbus::connection", Level::Warn), // Prints a lot of raw information.
+/// These are the initial values which can be overridden at runtime.
+const MODULES_MAX_LEVEL: [(&str, LevelFilter); 1] = [
+    ("zbus::connection", LevelFilter::Warn), // Prints a lot of raw information.
 ];
+/// Capacity of the channel used to stream new records.
+const LOG_STREAM_CAPACITY: usize = 100;
+const JOURNAL_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+/// Records less severe than this are not forwarded to syslog.
+const SYSLOG_MIN_LEVEL: Level = Level::Warn;
+/// "System daemons" facility.
+const SYSLOG_FACILITY: u8 = 3;
+/// Records are not forwarded for this time after resolving the syslog address or sending failed,
+/// so an unavailable DNS server doesn't block every logging call.
+const SYSLOG_RETRY_INTERVAL: Duration = Duration::from_secs(30);
 
-pub struct AppLogger(JournalLog);
+static LOG_BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+static LOG_LEVELS: RwLock<LogLevels> = RwLock::new(LogLevels {
+    default: LogLevelFilter::Info,
+    modules: Vec::new(),
+});
+
+pub struct AppLogger {
+    /// If [None], records are written to stderr.
+    journal: Option<JournalLog>,
+    file: Option<Mutex<RotatingFile>>,
+    syslog: Option<SyslogForwarder>,
+    format: LogFormat,
+}
 
 impl AppLogger {
-    pub fn install(level_filter: LevelFilter) -> anyhow::Result<()> {
-        let logger = Box::new(Self(JournalLog::new()?));
+    pub fn install(config: &Config) -> anyhow::Result<()> {
+        let _ = LOG_BUFFER.set(LogBuffer::new(config.log_buffer_capacity));
+        let use_journal = match config.log_output {
+            LogOutput::Auto => Path::new(JOURNAL_SOCKET_PATH).exists(),
+            LogOutput::Journal => true,
+            LogOutput::Stderr => false,
+        };
+        let logger = Box::new(Self {
+            journal: use_journal.then(JournalLog::new).transpose()?,
+            file: config
+                .log_file
+                .as_ref()
+                .map(RotatingFile::open)
+                .transpose()?
+                .map(Mutex::new),
+            syslog: config.syslog.as_ref().map(SyslogForwarder::new),
+            format: config.log_format,
+        });
         log::set_boxed_logger(logger)?;
-        log::set_max_level(level_filter);
+
+        let mut levels = LOG_LEVELS.write().unwrap_or_else(|err| err.into_inner());
+        levels.default = config.log_level.into();
+        levels.modules = MODULES_MAX_LEVEL
+            .into_iter()
+            .map(|(module, level)| ModuleLogLevel {
+                module: module.to_string(),
+                level: level.into(),
+            })
+            .collect();
+        levels.apply_max_level();
         Ok(())
     }
+
+    /// Returns [None] if the logger is not installed.
+    pub fn buffer() -> Option<&'static LogBuffer> {
+        LOG_BUFFER.get()
+    }
+
+    pub fn levels() -> LogLevels {
+        LOG_LEVELS
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Change the max verbosity level of `module` and all its nested children.
+    /// If `module` is [None], the default level will be changed.
+    pub fn set_level(module: Option<String>, level: LogLevelFilter) -> LogLevels {
+        let mut levels = LOG_LEVELS.write().unwrap_or_else(|err| err.into_inner());
+        match module {
+            Some(module) => match levels.modules.iter_mut().find(|item| item.module == module) {
+                Some(item) => item.level = level,
+                None => levels.modules.push(ModuleLogLevel { module, level }),
+            },
+            None => levels.default = level,
+        }
+        levels.apply_max_level();
+        levels.clone()
+    }
+
+    /// Remove the override of `module`, so the default level will be used for it.
+    pub fn reset_level(module: &str) -> LogLevels {
+        let mut levels = LOG_LEVELS.write().unwrap_or_else(|err| err.into_inner());
+        levels.modules.retain(|item| item.module != module);
+        levels.apply_max_level();
+        levels.clone()
+    }
 }
 
 impl Log for AppLogger {
@@ -23,56 +125,461 @@ impl Log for AppLogger {
     }
 
     fn log(&self, record: &Record) {
-        if is_blacklisted(record) {
+        if !is_enabled(record) {
             return;
         }
-        let result = self.0.journal_send(
-            &record
-                .to_builder()
-                .args(format_args!(
-                    "{}{}",
-                    record
-                        .module_path()
-                        .map(make_message_prefix)
-                        .unwrap_or_default(),
+        if let Some(buffer) = LOG_BUFFER.get() {
+            buffer.push(record);
+        }
+        let message_prefix = record
+            .module_path()
+            .map(make_message_prefix)
+            .unwrap_or_default();
+
+        if let Some(journal) = &self.journal {
+            let result = match self.format {
+                LogFormat::Text => journal.journal_send(
+                    &record
+                        .to_builder()
+                        .args(format_args!("{message_prefix}{}", record.args()))
+                        .build(),
+                ),
+                LogFormat::Json => {
+                    let fields = [
+                        (
+                            "module",
+                            record.module_path().and_then(relative_module_path),
+                        ),
+                        ("level", Some(record.level().as_str())),
+                    ];
+                    let key_values = ChainedSource(record.key_values(), &fields);
+                    journal.journal_send(&record.to_builder().key_values(&key_values).build())
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Unable to send a log to the journal: {e}");
+                println!("{}", record.args());
+            }
+        }
+
+        if self.journal.is_none() || self.file.is_some() {
+            let line = match self.format {
+                LogFormat::Text => format!(
+                    "{} {:<5} {message_prefix}{}\n",
+                    Local::now().format("%F %T%.3f"),
+                    record.level(),
                     record.args()
-                ))
-                .build(),
+                ),
+                LogFormat::Json => format_json(record) + "\n",
+            };
+            if self.journal.is_none() {
+                eprint!("{line}");
+            }
+            if let Some(file) = &self.file {
+                let result = file
+                    .lock()
+                    .map_err(|_| io::Error::other("file lock is poisoned"))
+                    .and_then(|mut file| file.append(line.as_bytes()));
+                if let Err(e) = result {
+                    eprintln!("Unable to write a log to the file: {e}");
+                }
+            }
+        }
+
+        if let Some(syslog) = &self.syslog {
+            if record.level() <= SYSLOG_MIN_LEVEL {
+                if let Err(e) = syslog.send(record, &message_prefix) {
+                    eprintln!("Unable to forward a log to syslog: {e}");
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(Ok(mut file)) = self.file.as_ref().map(Mutex::lock) {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Log file which is moved to `<path>.1` (replacing the previous one) when it exceeds the size.
+struct RotatingFile {
+    path: PathBuf,
+    max_size_bytes: u64,
+    file: File,
+    size_bytes: u64,
+}
+
+impl RotatingFile {
+    fn open(config: &config::LogFile) -> io::Result<Self> {
+        let file = Self::open_append(&config.path)?;
+        Ok(Self {
+            path: config.path.clone(),
+            max_size_bytes: config.max_size_kib * 1024,
+            size_bytes: file.metadata()?.len(),
+            file,
+        })
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.size_bytes + data.len() as u64 > self.max_size_bytes {
+            let mut rotated_path = self.path.clone().into_os_string();
+            rotated_path.push(".1");
+            fs::rename(&self.path, rotated_path)?;
+            self.file = Self::open_append(&self.path)?;
+            self.size_bytes = 0;
+        }
+        self.file.write_all(data)?;
+        self.size_bytes += data.len() as u64;
+        Ok(())
+    }
+
+    fn open_append(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+}
+
+/// Sends records to a remote syslog server over UDP using the RFC 5424 format.
+struct SyslogForwarder {
+    address: String,
+    hostname: String,
+    connection: Mutex<SyslogConnection>,
+}
+
+#[derive(Default)]
+struct SyslogConnection {
+    /// If [None], the address is resolved on the next record.
+    socket: Option<UdpSocket>,
+    /// Set when resolution or sending failed: no attempts are made until this time.
+    retry_at: Option<Instant>,
+}
+
+impl SyslogForwarder {
+    /// The address is resolved lazily, so an unavailable DNS server doesn't abort the startup.
+    fn new(config: &config::Syslog) -> Self {
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|hostname| hostname.trim().to_string())
+            .unwrap_or_default();
+        Self {
+            address: config.address.clone(),
+            hostname: if hostname.is_empty() {
+                "-".to_string()
+            } else {
+                hostname
+            },
+            connection: Mutex::default(),
+        }
+    }
+
+    fn connect(&self) -> io::Result<UdpSocket> {
+        let address = self
+            .address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::other("syslog address is not resolved"))?;
+        let socket = if address.is_ipv4() {
+            UdpSocket::bind("0.0.0.0:0")
+        } else {
+            UdpSocket::bind("[::]:0")
+        }?;
+        socket.connect(address)?;
+        // Sending must not block the logging thread.
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    fn send(&self, record: &Record, message_prefix: &str) -> io::Result<()> {
+        let severity = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        let message = format!(
+            "<{}>1 {} {} {} {} - - {message_prefix}{}",
+            SYSLOG_FACILITY * 8 + severity,
+            Local::now().to_rfc3339(),
+            self.hostname,
+            env!("CARGO_PKG_NAME"),
+            std::process::id(),
+            record.args()
         );
-        if let Err(e) = result {
-            eprintln!("Unable to send a log to the journal: {e}");
-            println!("{}", record.args());
+
+        let mut connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let socket = match connection.socket.take() {
+            Some(socket) => socket,
+            None if connection.retry_at.is_some_and(|at| Instant::now() < at) => return Ok(()),
+            None => self.connect().inspect_err(|_| {
+                connection.retry_at = Some(Instant::now() + SYSLOG_RETRY_INTERVAL);
+            })?,
+        };
+        match socket.send(message.as_bytes()) {
+            Ok(_) => {}
+            // The send buffer is full, so the record is dropped.
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => {
+                // The address may have changed, so it is resolved again after the interval.
+                connection.retry_at = Some(Instant::now() + SYSLOG_RETRY_INTERVAL);
+                return Err(e);
+            }
         }
+        connection.socket = Some(socket);
+        Ok(())
     }
+}
 
-    fn flush(&self) {}
+/// Mirror of [LevelFilter] to expose it via GraphQL.
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum LogLevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
 
-fn is_blacklisted(record: &Record) -> bool {
-    if let Some(module_path) = record.module_path() {
-        let max_level = MODULES_MAX_LEVEL
-            .into_iter()
-            .find(|(path, _)| {
-                module_path == *path || module_path.starts_with(&(path.to_string() + "::"))
+impl From<LevelFilter> for LogLevelFilter {
+    fn from(level: LevelFilter) -> Self {
+        match level {
+            LevelFilter::Off => Self::Off,
+            LevelFilter::Error => Self::Error,
+            LevelFilter::Warn => Self::Warn,
+            LevelFilter::Info => Self::Info,
+            LevelFilter::Debug => Self::Debug,
+            LevelFilter::Trace => Self::Trace,
+        }
+    }
+}
+
+impl From<LogLevelFilter> for LevelFilter {
+    fn from(level: LogLevelFilter) -> Self {
+        match level {
+            LogLevelFilter::Off => Self::Off,
+            LogLevelFilter::Error => Self::Error,
+            LogLevelFilter::Warn => Self::Warn,
+            LogLevelFilter::Info => Self::Info,
+            LogLevelFilter::Debug => Self::Debug,
+            LogLevelFilter::Trace => Self::Trace,
+        }
+    }
+}
+
+#[derive(Clone, SimpleObject)]
+pub struct ModuleLogLevel {
+    /// Full module path, e.g. `homie_home::bluetooth` or `zbus::connection`.
+    module: String,
+    level: LogLevelFilter,
+}
+
+#[derive(Clone, SimpleObject)]
+pub struct LogLevels {
+    /// Used for the modules without overrides.
+    default: LogLevelFilter,
+    modules: Vec<ModuleLogLevel>,
+}
+
+impl LogLevels {
+    /// Returns the level of the most specific module override or the default one.
+    fn level_of(&self, module_path: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|item| {
+                module_path == item.module || module_path.starts_with(&(item.module.clone() + "::"))
             })
-            .map(|(_, level)| level);
-        if let Some(max_level) = max_level {
-            return record.level() > max_level;
+            .max_by_key(|item| item.module.len())
+            .map(|item| item.level)
+            .unwrap_or(self.default)
+            .into()
+    }
+
+    /// Records above the global max level are filtered out before reaching the logger,
+    /// so it must be not less than any of the levels.
+    fn apply_max_level(&self) {
+        let max_level = self
+            .modules
+            .iter()
+            .map(|item| LevelFilter::from(item.level))
+            .chain([self.default.into()])
+            .max()
+            .unwrap_or(LevelFilter::Off);
+        log::set_max_level(max_level);
+    }
+}
+
+/// Mirror of [Level] to expose it via GraphQL.
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => Self::Error,
+            Level::Warn => Self::Warn,
+            Level::Info => Self::Info,
+            Level::Debug => Self::Debug,
+            Level::Trace => Self::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Self::Error,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+#[derive(Clone, SimpleObject)]
+pub struct LogRecord {
+    timestamp: DateTime<Local>,
+    level: LogLevel,
+    /// Module path relative to the crate root. [None] for the crate root itself.
+    module: Option<String>,
+    message: String,
+}
+
+impl LogRecord {
+    /// Returns `true` if the record is at least as severe as `min_level`.
+    pub fn is_at_least(&self, min_level: LogLevel) -> bool {
+        Level::from(self.level) <= Level::from(min_level)
+    }
+}
+
+/// Bounded buffer of the most recent records.
+pub struct LogBuffer {
+    records: Mutex<VecDeque<LogRecord>>,
+    capacity: usize,
+    // Not using [super::Broadcaster], because it logs lost messages.
+    sender: broadcast::Sender<LogRecord>,
+}
+
+impl LogBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            sender: broadcast::Sender::new(LOG_STREAM_CAPACITY),
+        }
+    }
+
+    fn push(&self, record: &Record) {
+        let record = LogRecord {
+            timestamp: Local::now(),
+            level: record.level().into(),
+            module: record
+                .module_path()
+                .and_then(relative_module_path)
+                .map(str::to_string),
+            message: record.args().to_string(),
+        };
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() == self.capacity {
+                records.pop_front();
+            }
+            records.push_back(record.clone());
         }
+        // Ignore if there is no receivers.
+        let _ = self.sender.send(record);
+    }
+
+    /// Returns up to `limit` most recent records, ordered from the oldest to the newest.
+    pub fn recent(&self, limit: usize, min_level: LogLevel) -> Vec<LogRecord> {
+        let Ok(records) = self.records.lock() else {
+            return Vec::new();
+        };
+        let mut recent: Vec<_> = records
+            .iter()
+            .rev()
+            .filter(|record| record.is_at_least(min_level))
+            .take(limit)
+            .cloned()
+            .collect();
+        recent.reverse();
+        recent
+    }
+
+    /// Receiver of the new records.
+    pub fn subscribe(&self) -> broadcast::Receiver<LogRecord> {
+        self.sender.subscribe()
+    }
+}
+
+fn is_enabled(record: &Record) -> bool {
+    let levels = LOG_LEVELS.read().unwrap_or_else(|err| err.into_inner());
+    let max_level = record
+        .module_path()
+        .map(|module_path| levels.level_of(module_path))
+        .unwrap_or(levels.default.into());
+    record.level() <= max_level
+}
+
+/// Returns a JSON object with the record fields and key-values.
+fn format_json(record: &Record) -> String {
+    struct Visitor(serde_json::Map<String, serde_json::Value>);
+
+    impl<'kvs> VisitSource<'kvs> for Visitor {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.insert(key.to_string(), value.to_string().into());
+            Ok(())
+        }
+    }
+
+    let mut visitor = Visitor(serde_json::Map::new());
+    // Key-values are visited first, so they can't override the record fields.
+    let _ = record.key_values().visit(&mut visitor);
+    let mut fields = visitor.0;
+    fields.insert("timestamp".into(), Local::now().to_rfc3339().into());
+    fields.insert("level".into(), record.level().as_str().into());
+    fields.insert(
+        "module".into(),
+        record.module_path().and_then(relative_module_path).into(),
+    );
+    fields.insert("message".into(), record.args().to_string().into());
+    serde_json::Value::Object(fields).to_string()
+}
+
+/// Visits key-values of both sources.
+struct ChainedSource<'a>(&'a dyn Source, &'a dyn Source);
+
+impl Source for ChainedSource<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), kv::Error> {
+        self.0.visit(visitor)?;
+        self.1.visit(visitor)
     }
-    false
 }
 
 fn make_message_prefix(module_path: &str) -> String {
+    relative_module_path(module_path)
+        .map(|path| format!("<{path}> "))
+        .unwrap_or_default()
+}
+
+/// Returns [None] for the crate root. Paths of other crates are returned as is.
+fn relative_module_path(module_path: &str) -> Option<&str> {
     let crate_name: &'static str = env!("CARGO_CRATE_NAME");
     if module_path == crate_name {
-        String::new()
+        None
     } else {
-        format!(
-            "<{}> ",
+        Some(
             module_path
-                .strip_prefix(&(crate_name.to_string() + "::"))
-                .unwrap_or(module_path)
+                .strip_prefix(crate_name)
+                .and_then(|path| path.strip_prefix("::"))
+                .unwrap_or(module_path),
         )
     }
 }
@@ -0,0 +1,103 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{self, AtomicI64, AtomicU64},
+};
+
+use async_graphql::{Enum, SimpleObject};
+use strum::{EnumCount, EnumIter, IntoEnumIterator};
+
+static COUNTERS: [AtomicU64; Counter::COUNT] = [const { AtomicU64::new(0) }; Counter::COUNT];
+static GAUGES: [AtomicI64; Gauge::COUNT] = [const { AtomicI64::new(0) }; Gauge::COUNT];
+
+/// Monotonically increasing value.
+#[derive(Clone, Copy, strum::Display, EnumIter, EnumCount)]
+#[strum(serialize_all = "snake_case")]
+pub enum Counter {
+    GraphqlRequests,
+    RecordingsMade,
+    BluetoothReconnects,
+    PlayerErrors,
+}
+
+/// Value that can go up and down.
+#[derive(Clone, Copy, strum::Display, EnumIter, EnumCount)]
+#[strum(serialize_all = "snake_case")]
+pub enum Gauge {
+    BroadcastCapacity,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+#[derive(SimpleObject)]
+pub struct Metric {
+    name: String,
+    kind: MetricKind,
+    description: &'static str,
+    value: i64,
+}
+
+impl Counter {
+    fn description(self) -> &'static str {
+        match self {
+            Self::GraphqlRequests => "Handled GraphQL requests (excluding subscriptions).",
+            Self::RecordingsMade => "Piano recordings that have been preserved.",
+            Self::BluetoothReconnects => "Successful (re)connections to the Bluetooth devices.",
+            Self::PlayerErrors => "Failed calls to the audio player.",
+        }
+    }
+}
+
+impl Gauge {
+    fn description(self) -> &'static str {
+        match self {
+            Self::BroadcastCapacity => "Capacity of the event channels.",
+        }
+    }
+}
+
+pub fn increment(counter: Counter) {
+    COUNTERS[counter as usize].fetch_add(1, atomic::Ordering::Relaxed);
+}
+
+pub fn set(gauge: Gauge, value: i64) {
+    GAUGES[gauge as usize].store(value, atomic::Ordering::Relaxed);
+}
+
+/// Returns current values of all metrics.
+pub fn snapshot() -> Vec<Metric> {
+    let counters = Counter::iter().map(|counter| Metric {
+        name: counter.to_string(),
+        kind: MetricKind::Counter,
+        description: counter.description(),
+        value: COUNTERS[counter as usize].load(atomic::Ordering::Relaxed) as i64,
+    });
+    let gauges = Gauge::iter().map(|gauge| Metric {
+        name: gauge.to_string(),
+        kind: MetricKind::Gauge,
+        description: gauge.description(),
+        value: GAUGES[gauge as usize].load(atomic::Ordering::Relaxed),
+    });
+    counters.chain(gauges).collect()
+}
+
+/// Metrics in the Prometheus text-based exposition format.
+pub fn prometheus_text() -> String {
+    let mut text = String::new();
+    for metric in snapshot() {
+        let (name, kind) = match metric.kind {
+            MetricKind::Counter => (format!("homie_{}_total", metric.name), "counter"),
+            MetricKind::Gauge => (format!("homie_{}", metric.name), "gauge"),
+        };
+        // Writing to a string never fails.
+        let _ = write!(
+            text,
+            "# HELP {name} {}\n# TYPE {name} {kind}\n{name} {}\n",
+            metric.description, metric.value
+        );
+    }
+    text
+}
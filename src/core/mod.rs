@@ -1,18 +1,28 @@
+pub mod backup;
+pub mod curl_config;
+pub mod i18n;
 pub mod logger;
+pub mod metrics;
+pub mod panic;
+pub mod shutdown;
 pub mod stdout_reader;
+pub mod sun;
+pub mod supervisor;
+pub mod task;
+pub mod timezone;
 
 use std::{
-    fmt::Display,
     io,
     sync::{
-        atomic::{self, AtomicBool},
+        atomic::{self, AtomicBool, AtomicU64},
         Arc,
     },
     time::Duration,
 };
 
+use async_graphql::SimpleObject;
 use async_stream::stream;
-use chrono::{DateTime, Datelike, Days, TimeDelta, TimeZone, Utc};
+use chrono::{DateTime, Datelike, Days, Local, TimeDelta, TimeZone};
 use futures::{Stream, StreamExt};
 use log::{error, info};
 use tokio::{
@@ -21,7 +31,12 @@ use tokio::{
     sync::{broadcast, Notify},
 };
 
-use crate::GlobalEvent;
+use crate::{
+    dbus::BluetoothDeviceChange,
+    device::{piano::PianoEvent, power::PowerStatus},
+    udev::HotplugEvent,
+    GlobalEvent, GlobalEventKind,
+};
 
 #[derive(Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
 pub enum SortOrder {
@@ -29,23 +44,62 @@ pub enum SortOrder {
     Descending,
 }
 
-const BROADCASTER_CHANNEL_CAPACITY: usize = 10;
+/// Envelope of a broadcasted value.
+#[derive(Clone, SimpleObject)]
+#[graphql(
+    concrete(name = "GlobalEventEnvelope", params(GlobalEventKind)),
+    concrete(name = "GlobalEventDetailsEnvelope", params(GlobalEvent)),
+    concrete(name = "PianoEventEnvelope", params(PianoEvent)),
+    concrete(name = "HotplugEventEnvelope", params(HotplugEvent)),
+    concrete(name = "BluetoothDeviceChangeEnvelope", params(BluetoothDeviceChange)),
+    concrete(name = "PowerStatusEnvelope", params(PowerStatus))
+)]
+pub struct Event<T> {
+    /// Sequence number which is unique within a channel.
+    pub id: u64,
+    /// When the event was sent.
+    pub timestamp: DateTime<Local>,
+    pub payload: T,
+}
 
 #[derive(Clone)]
-pub struct Broadcaster<T>(broadcast::Sender<T>);
+pub struct Broadcaster<T> {
+    sender: broadcast::Sender<Event<T>>,
+    /// Channel name used to identify the broadcaster in metrics.
+    name: &'static str,
+    /// Total number of messages which were skipped by the lagged receivers.
+    lagged_messages: Arc<AtomicU64>,
+    /// Identifier of the next event.
+    next_id: Arc<AtomicU64>,
+}
 
 impl<T: Clone> Broadcaster<T> {
-    pub fn send(&self, value: T) {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            sender: broadcast::Sender::new(capacity),
+            name,
+            lagged_messages: Arc::default(),
+            next_id: Arc::default(),
+        }
+    }
+
+    pub fn send(&self, payload: T) {
+        let event = Event {
+            id: self.next_id.fetch_add(1, atomic::Ordering::Relaxed),
+            timestamp: Local::now(),
+            payload,
+        };
         // Ignore if there is no receivers.
-        let _ = self.0.send(value);
+        let _ = self.sender.send(event);
     }
 
     /// Stream will close if there is no more self instances or at server shutdown.
     pub async fn recv_continuously(
         &self,
         shutdown_notify: ShutdownNotify,
-    ) -> impl Stream<Item = T> {
-        let mut receiver = self.0.subscribe();
+    ) -> impl Stream<Item = Event<T>> {
+        let mut receiver = self.sender.subscribe();
+        let (name, lagged_messages) = (self.name, Arc::clone(&self.lagged_messages));
         stream! {
             loop {
                 select! {
@@ -53,8 +107,9 @@ impl<T: Clone> Broadcaster<T> {
                         Ok(value) => yield value,
                         Err(broadcast::error::RecvError::Closed) => break,
                         Err(broadcast::error::RecvError::Lagged(messages_count)) => {
-                            // Increase BROADCASTER_CHANNEL_CAPACITY if you are see this error.
-                            error!("{messages_count} broadcast message(s) was lost");
+                            // Increase the broadcaster capacity if you are see this error.
+                            error!("{messages_count} message(s) of channel \"{name}\" was lost");
+                            lagged_messages.fetch_add(messages_count, atomic::Ordering::Relaxed);
                         }
                     },
                     _ = shutdown_notify.notified() => break,
@@ -64,26 +119,31 @@ impl<T: Clone> Broadcaster<T> {
     }
 }
 
+impl<T> Broadcaster<T> {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn lagged_messages(&self) -> u64 {
+        self.lagged_messages.load(atomic::Ordering::Relaxed)
+    }
+}
+
 impl<T: Clone + PartialEq> Broadcaster<T> {
     /// Wait until **at least one** of the given values will be received or shutdown triggered.
     pub async fn wait_for(&self, any_of: &[T], shutdown_notify: ShutdownNotify) {
         self.recv_continuously(shutdown_notify)
             .await
-            .any(|recv_val| async move { any_of.contains(&recv_val) })
+            .any(|event| async move { any_of.contains(&event.payload) })
             .await;
     }
 }
 
-impl<T> Default for Broadcaster<T> {
-    fn default() -> Self {
-        Self(broadcast::Sender::new(BROADCASTER_CHANNEL_CAPACITY))
-    }
-}
-
 #[derive(Clone)]
 pub struct ShutdownNotify {
     notify: Arc<Notify>,
     triggered: Arc<AtomicBool>,
+    event_broadcaster: Broadcaster<GlobalEvent>,
 }
 
 impl ShutdownNotify {
@@ -95,6 +155,7 @@ impl ShutdownNotify {
         let this = Self {
             notify: Arc::default(),
             triggered: Arc::default(),
+            event_broadcaster,
         };
         let this_half = this.clone();
 
@@ -103,13 +164,20 @@ impl ShutdownNotify {
                 _ = sigint.recv() => shutdown_info("SIGINT"),
                 _ = sigterm.recv() => shutdown_info("SIGTERM"),
             }
-            event_broadcaster.send(GlobalEvent::Shutdown);
-            this_half.triggered.store(true, atomic::Ordering::Relaxed);
-            this_half.notify.notify_waiters();
+            this_half.trigger();
         });
         Ok(this)
     }
 
+    /// Notify about shutdown without receiving a signal. Does nothing if it's already triggered.
+    pub fn trigger(&self) {
+        if self.triggered.swap(true, atomic::Ordering::Relaxed) {
+            return;
+        }
+        self.event_broadcaster.send(GlobalEvent::Shutdown);
+        self.notify.notify_waiters();
+    }
+
     /// Wait for shutdown or return immediately if it has been triggered.
     pub async fn notified(&self) {
         if self.is_triggered() {
@@ -147,33 +215,30 @@ pub struct HumanDateParams {
     pub filename_safe: bool,
 }
 
-pub fn human_date_ago<Tz>(datetime: DateTime<Tz>, params: HumanDateParams) -> String
-where
-    Tz: TimeZone,
-    Tz::Offset: Copy + Display,
-{
+/// `datetime` will be converted to the configured timezone.
+pub fn human_date_ago<Tz: TimeZone>(datetime: DateTime<Tz>, params: HumanDateParams) -> String {
     const JUST_NOW_THRESHOLD: TimeDelta = TimeDelta::seconds(60);
-    let now = Utc::now().with_timezone(&datetime.timezone());
+    let locale = i18n::locale();
+    let (datetime, now) = (timezone::localize(datetime), timezone::now());
     if now - datetime < JUST_NOW_THRESHOLD {
-        return "Just now".to_string();
+        return locale.just_now().to_string();
     }
 
     let (date, now_date) = (Date::from(datetime), Date::from(now));
     let time = datetime.format(if params.filename_safe { "%H-%M" } else { "%R" });
     if date == now_date {
-        return format!("Today at {time}");
+        return locale.today_at(time);
     }
 
     let yesterday = Date::from(now - Days::new(1));
     if date == yesterday {
-        return format!("Yesterday at {time}");
+        return locale.yesterday_at(time);
     }
 
-    let month = datetime.format("%B");
     if date.year == now_date.year {
-        format!("{month} {} at {time}", date.day)
+        locale.day_at(date.day, date.month, time)
     } else {
-        format!("{} {month} {} at {time}", date.day, date.year)
+        locale.full_date_at(date.day, date.month, date.year, time)
     }
 }
 
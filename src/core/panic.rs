@@ -0,0 +1,92 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    panic::{self, PanicHookInfo},
+    sync::atomic::{self, AtomicBool},
+    thread,
+};
+
+use log::error;
+
+use super::ShutdownNotify;
+
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static SUPERVISED_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+tokio::task_local! {
+    static SUPERVISED_TASK: ();
+}
+
+/// Replace the default panic hook with the one that logs a panic and triggers shutdown,
+/// so an in-progress recording will be preserved and the server exits with an error.
+/// Panics of the supervised code (see [SupervisedThread] and [supervised_task]) are only
+/// logged, as the supervisor restarts the failed subsystem.
+pub fn install_hook(shutdown_notify: ShutdownNotify) {
+    panic::set_hook(Box::new(move |info| {
+        let supervised = is_supervised();
+        error!(
+            "Thread \"{}\" panicked{}: {}. {}",
+            thread::current().name().unwrap_or("<unnamed>"),
+            info.location()
+                .map(|location| format!(" at {location}"))
+                .unwrap_or_default(),
+            panic_message(info),
+            if supervised {
+                "Subsystem will be restarted"
+            } else {
+                "Shutting down..."
+            }
+        );
+        if !supervised {
+            PANICKED.store(true, atomic::Ordering::Relaxed);
+            shutdown_notify.trigger();
+        }
+    }));
+}
+
+/// Marks the current thread as supervised while it's alive. The caller must make sure
+/// that the panic is caught (e.g. by the thread end) and the subsystem is restarted.
+pub struct SupervisedThread {
+    was_supervised: bool,
+}
+
+impl SupervisedThread {
+    pub fn enter() -> Self {
+        Self {
+            was_supervised: SUPERVISED_THREAD.replace(true),
+        }
+    }
+}
+
+impl Drop for SupervisedThread {
+    /// Called on panic too, as the thread can be reused (e.g. by the blocking pool).
+    fn drop(&mut self) {
+        SUPERVISED_THREAD.set(self.was_supervised);
+    }
+}
+
+/// The same as [SupervisedThread], but for a task which can move between the threads.
+pub async fn supervised_task<F: Future>(future: F) -> F::Output {
+    SUPERVISED_TASK.scope((), future).await
+}
+
+fn is_supervised() -> bool {
+    SUPERVISED_THREAD.get() || SUPERVISED_TASK.try_with(|_| ()).is_ok()
+}
+
+/// Returns `true` if a panic occurred in any thread after installing the hook.
+pub fn has_panicked() -> bool {
+    PANICKED.load(atomic::Ordering::Relaxed)
+}
+
+fn panic_message<'a>(info: &'a PanicHookInfo) -> &'a str {
+    let payload = info.payload();
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown payload")
+}
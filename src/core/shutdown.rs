@@ -0,0 +1,60 @@
+use std::{future::Future, time::Duration};
+
+use futures::{future::BoxFuture, FutureExt};
+use log::{error, info};
+use tokio::time::{self, Instant};
+
+/// Performs the shutdown steps one by one in the order they were added.
+pub struct ShutdownCoordinator {
+    timeout: Duration,
+    steps: Vec<(&'static str, BoxFuture<'static, ()>)>,
+}
+
+impl ShutdownCoordinator {
+    /// `timeout` limits the total time of all steps.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn step<F>(mut self, name: &'static str, future: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.steps.push((name, future.boxed()));
+        self
+    }
+
+    /// If the timeout is reached, the running step will be interrupted
+    /// and the remaining ones will be skipped.
+    pub async fn run(self) {
+        info!("Shutting down...");
+        let deadline = Instant::now() + self.timeout;
+        let mut steps = self.steps.into_iter();
+
+        let mut timed_out_step = None;
+        for (name, step) in steps.by_ref() {
+            info!("Shutdown step: {name}");
+            if time::timeout_at(deadline, step).await.is_err() {
+                timed_out_step = Some(name);
+                break;
+            }
+        }
+
+        if let Some(timed_out_step) = timed_out_step {
+            let skipped_steps: Vec<_> = steps.map(|(name, _)| name).collect();
+            error!(
+                "Shutdown step \"{timed_out_step}\" did not finish in time{}",
+                if skipped_steps.is_empty() {
+                    String::new()
+                } else {
+                    format!(", skipped steps: {}", skipped_steps.join(", "))
+                }
+            );
+        } else {
+            info!("Shutdown completed");
+        }
+    }
+}
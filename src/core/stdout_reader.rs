@@ -3,21 +3,22 @@ use std::io;
 use actix_web::web;
 use async_stream::stream;
 use futures::Stream;
-use tokio::{io::AsyncReadExt, process::ChildStdout};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 type BytesResult = io::Result<web::Bytes>;
 
 const BUFFER_SIZE: usize = 8 * 1024;
 
-pub struct StdoutReader {
-    child_stdout: ChildStdout,
+/// Streams the output of a child process or a pipe.
+pub struct StdoutReader<R> {
+    stdout: R,
     buf: [u8; BUFFER_SIZE],
 }
 
-impl StdoutReader {
-    pub fn new(child_stdout: ChildStdout) -> Self {
+impl<R: AsyncRead + Unpin> StdoutReader<R> {
+    pub fn new(stdout: R) -> Self {
         Self {
-            child_stdout,
+            stdout,
             buf: [0; BUFFER_SIZE],
         }
     }
@@ -25,7 +26,7 @@ impl StdoutReader {
     pub async fn stream(mut self) -> impl Stream<Item = BytesResult> {
         stream! {
             loop {
-                match self.child_stdout.read(&mut self.buf).await {
+                match self.stdout.read(&mut self.buf).await {
                     Ok(len) => {
                         if len == 0 {
                             break
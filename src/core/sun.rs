@@ -0,0 +1,123 @@
+//! Sunrise and sunset times using the sunrise equation
+//! (accuracy is about a minute, which is enough for scheduling).
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::config::{Location, SunEvent};
+
+/// Julian day of 2000-01-01 12:00 UTC.
+const J2000: f64 = 2_451_545.0;
+/// Julian day of the Unix epoch.
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2_440_587.5;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+/// Axial tilt of the Earth.
+const OBLIQUITY_DEG: f64 = 23.4397;
+/// Sun altitude at the sunrise / sunset: the atmospheric refraction and the solar disc size.
+const HORIZON_ALTITUDE_DEG: f64 = -0.833;
+
+/// Returns [None] if there is no such event on the `date` (polar day or night).
+pub fn time(event: SunEvent, date: NaiveDate, location: &Location) -> Option<DateTime<Utc>> {
+    let j2000_date = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let days = (date - j2000_date).num_days() as f64;
+    // Mean solar time at the longitude.
+    let mean_solar_time = days - location.longitude / 360.0;
+
+    let mean_anomaly = (357.5291 + 0.985_600_28 * mean_solar_time).rem_euclid(360.0);
+    let anomaly_rad = mean_anomaly.to_radians();
+    let equation_of_center = 1.9148 * anomaly_rad.sin()
+        + 0.02 * (2.0 * anomaly_rad).sin()
+        + 0.0003 * (3.0 * anomaly_rad).sin();
+    let ecliptic_longitude_rad = (mean_anomaly + equation_of_center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean_solar_time + 0.0053 * anomaly_rad.sin()
+        - 0.0069 * (2.0 * ecliptic_longitude_rad).sin();
+
+    let declination_sin = ecliptic_longitude_rad.sin() * OBLIQUITY_DEG.to_radians().sin();
+    let declination_cos = declination_sin.asin().cos();
+    let latitude_rad = location.latitude.to_radians();
+    let hour_angle_cos = (HORIZON_ALTITUDE_DEG.to_radians().sin()
+        - latitude_rad.sin() * declination_sin)
+        / (latitude_rad.cos() * declination_cos);
+    if !(-1.0..=1.0).contains(&hour_angle_cos) {
+        return None;
+    }
+    let half_day = hour_angle_cos.acos().to_degrees() / 360.0;
+
+    let julian_day = match event {
+        SunEvent::Sunrise => transit - half_day,
+        SunEvent::Sunset => transit + half_day,
+    };
+    let timestamp = (julian_day - UNIX_EPOCH_JULIAN_DAY) * SECONDS_PER_DAY;
+    DateTime::from_timestamp(timestamp.round() as i64, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.006,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6496,
+        longitude: 18.956,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Published times are rounded to minutes, the equation is accurate to about a minute.
+    fn assert_near(actual: Option<DateTime<Utc>>, expected: (i32, u32, u32, u32, u32)) {
+        let (year, month, day, hour, minute) = expected;
+        let expected = Utc
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap();
+        let actual = actual.expect("sun event is expected");
+        assert!(
+            (actual - expected).num_seconds().abs() <= 120,
+            "{actual} is not near {expected}"
+        );
+    }
+
+    #[test]
+    fn summer_solstice() {
+        let day = date(2024, 6, 21);
+        assert_near(time(SunEvent::Sunrise, day, &LONDON), (2024, 6, 21, 3, 43));
+        assert_near(time(SunEvent::Sunset, day, &LONDON), (2024, 6, 21, 20, 21));
+    }
+
+    #[test]
+    fn winter_solstice() {
+        let day = date(2024, 12, 21);
+        assert_near(time(SunEvent::Sunrise, day, &LONDON), (2024, 12, 21, 8, 4));
+        assert_near(time(SunEvent::Sunset, day, &LONDON), (2024, 12, 21, 15, 53));
+    }
+
+    #[test]
+    fn western_longitude() {
+        // Sunset is after midnight UTC.
+        let day = date(2024, 6, 20);
+        assert_near(
+            time(SunEvent::Sunrise, day, &NEW_YORK),
+            (2024, 6, 20, 9, 25),
+        );
+        assert_near(time(SunEvent::Sunset, day, &NEW_YORK), (2024, 6, 21, 0, 31));
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        for day in [date(2024, 6, 21), date(2024, 12, 21)] {
+            assert_eq!(time(SunEvent::Sunrise, day, &TROMSO), None);
+            assert_eq!(time(SunEvent::Sunset, day, &TROMSO), None);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use backoff::backoff::Backoff;
+use futures::future::BoxFuture;
+use log::{error, info, warn};
+use tokio::select;
+
+use super::{Broadcaster, ShutdownNotify};
+use crate::{GlobalEvent, Subsystem};
+
+/// Interval between the liveness checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// If a local subsystem worked longer than this, the restart delay is reset to the initial one.
+const LOCAL_STABLE_RUN: Duration = Duration::from_secs(60);
+
+pub trait Supervised: Send + Sync {
+    fn subsystem(&self) -> Subsystem;
+
+    /// Returns `false` if the subsystem stopped working and it should be restarted.
+    fn is_alive(&self) -> BoxFuture<'_, bool>;
+
+    fn restart(&self) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
+/// Watches for the key subsystems and restarts them if they are failed.
+#[derive(Clone)]
+pub struct Supervisor {
+    watched: Vec<Arc<dyn Supervised>>,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+    shutdown_notify: ShutdownNotify,
+}
+
+impl Supervisor {
+    pub fn new(
+        event_broadcaster: Broadcaster<GlobalEvent>,
+        shutdown_notify: ShutdownNotify,
+    ) -> Self {
+        Self {
+            watched: Vec::new(),
+            event_broadcaster,
+            shutdown_notify,
+        }
+    }
+
+    pub fn watch(mut self, subsystem: impl Supervised + 'static) -> Self {
+        self.watched.push(Arc::new(subsystem));
+        self
+    }
+
+    /// Periodically check liveness of the watched subsystems until shutdown.
+    pub async fn run(self) {
+        loop {
+            select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                _ = self.shutdown_notify.notified() => break,
+            }
+            for watched in &self.watched {
+                if watched.is_alive().await {
+                    continue;
+                }
+                let subsystem = watched.subsystem();
+                warn!("Subsystem {subsystem} is not alive. Restarting...");
+                match watched.restart().await {
+                    Ok(()) => self.notify_restarted(subsystem),
+                    Err(e) => error!("Failed to restart subsystem {subsystem}: {e}"),
+                }
+            }
+        }
+    }
+
+    /// Runs a subsystem in place (e.g. on a thread which it's bound to). `run` is called again
+    /// (with `true` passed) every time the subsystem stops or fails until shutdown.
+    /// Delays between the restarts are taken from `restart_backoff`.
+    ///
+    /// Returns an error if the subsystem failed to start the first time
+    /// or if `restart_backoff` gave up.
+    pub async fn run_local<F, Fut, E>(
+        &self,
+        subsystem: Subsystem,
+        mut restart_backoff: backoff::ExponentialBackoff,
+        mut run: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(bool) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        let mut restarted = false;
+        loop {
+            let started_at = Instant::now();
+            let result = run(restarted).await;
+            if self.shutdown_notify.is_triggered() {
+                return result;
+            }
+            match &result {
+                Err(_) if !restarted => return result,
+                Err(e) => error!("Subsystem {subsystem} failed: {e}"),
+                Ok(()) => warn!("Subsystem {subsystem} stopped"),
+            }
+
+            if started_at.elapsed() >= LOCAL_STABLE_RUN {
+                restart_backoff.reset();
+            }
+            let Some(delay) = restart_backoff.next_backoff() else {
+                error!("Giving up restarting subsystem {subsystem}");
+                return result;
+            };
+            info!(
+                "Restarting subsystem {subsystem} in {} ms...",
+                delay.as_millis()
+            );
+            select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.shutdown_notify.notified() => return Ok(()),
+            }
+            self.notify_restarted(subsystem);
+            restarted = true;
+        }
+    }
+
+    fn notify_restarted(&self, subsystem: Subsystem) {
+        info!("Subsystem {subsystem} restarted");
+        self.event_broadcaster
+            .send(GlobalEvent::SubsystemRestarted { name: subsystem });
+    }
+}
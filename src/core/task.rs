@@ -0,0 +1,131 @@
+use std::{collections::HashMap, fmt::Display, future::Future, sync::Arc};
+
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Local};
+use log::{error, warn};
+use tokio::{runtime::Handle, select, task::JoinHandle};
+
+use super::ShutdownNotify;
+use crate::SharedRwLock;
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum TaskState {
+    Running,
+    Finished,
+    Failed,
+    /// Task has been interrupted because of the server shutdown.
+    Cancelled,
+}
+
+#[derive(Clone, SimpleObject)]
+pub struct TaskStatus {
+    name: String,
+    state: TaskState,
+    /// How many times the task has been spawned.
+    runs: u32,
+    /// When the latest run started.
+    started_at: DateTime<Local>,
+    /// [None] if the latest run is still in process.
+    finished_at: Option<DateTime<Local>>,
+    /// Error of the latest failed run. It's kept even if the next runs succeed.
+    last_error: Option<String>,
+}
+
+/// Output of a background task. Used to determine whether the task failed.
+pub trait TaskOutput {
+    fn error(&self) -> Option<String>;
+}
+
+impl TaskOutput for () {
+    fn error(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<T, E: Display> TaskOutput for Result<T, E> {
+    fn error(&self) -> Option<String> {
+        self.as_ref().err().map(|err| err.to_string())
+    }
+}
+
+/// Registry of the named background jobs.
+#[derive(Clone)]
+pub struct TaskManager {
+    tasks: SharedRwLock<HashMap<String, TaskStatus>>,
+    shutdown_notify: ShutdownNotify,
+    /// Runtime the manager is created in. Tasks are spawned on it even if `spawn` is called
+    /// from another runtime (e.g. by the udev monitor thread).
+    runtime: Handle,
+}
+
+impl TaskManager {
+    /// Must be called within the main runtime.
+    pub fn new(shutdown_notify: ShutdownNotify) -> Self {
+        Self {
+            tasks: Arc::default(),
+            shutdown_notify,
+            runtime: Handle::current(),
+        }
+    }
+
+    /// Spawn `future` as a background task which will be cancelled at server shutdown.
+    /// If a task with the same name has been spawned before, its status will be reused.
+    pub fn spawn<F>(&self, name: impl Into<String>, future: F) -> JoinHandle<()>
+    where
+        F: Future + Send + 'static,
+        F::Output: TaskOutput,
+    {
+        let name = name.into();
+        let tasks = Arc::clone(&self.tasks);
+        let shutdown_notify = self.shutdown_notify.clone();
+
+        self.runtime.spawn(async move {
+            tasks
+                .write()
+                .await
+                .entry(name.clone())
+                .and_modify(|status| {
+                    status.state = TaskState::Running;
+                    status.runs += 1;
+                    status.started_at = Local::now();
+                    status.finished_at = None;
+                })
+                .or_insert_with(|| TaskStatus {
+                    name: name.clone(),
+                    state: TaskState::Running,
+                    runs: 1,
+                    started_at: Local::now(),
+                    finished_at: None,
+                    last_error: None,
+                });
+
+            let (state, error) = select! {
+                output = future => match output.error() {
+                    Some(err) => (TaskState::Failed, Some(err)),
+                    None => (TaskState::Finished, None),
+                },
+                _ = shutdown_notify.notified() => (TaskState::Cancelled, None),
+            };
+            match (&state, &error) {
+                (TaskState::Failed, Some(err)) => error!("Task \"{name}\" failed: {err}"),
+                (TaskState::Cancelled, _) => warn!("Task \"{name}\" cancelled"),
+                _ => {}
+            }
+
+            if let Some(status) = tasks.write().await.get_mut(&name) {
+                status.state = state;
+                status.finished_at = Some(Local::now());
+                if error.is_some() {
+                    status.last_error = error;
+                }
+            }
+        })
+    }
+
+    /// Returns statuses of all tasks that have been spawned, ordered by name.
+    pub async fn list(&self) -> Vec<TaskStatus> {
+        let mut tasks: Vec<_> = self.tasks.read().await.values().cloned().collect();
+        tasks.sort_by(|a, b| a.name.cmp(&b.name));
+        tasks
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Days, FixedOffset, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use log::warn;
+
+use super::sun;
+use crate::config::{DailyTime, Location, QuietHours};
+
+/// How many days to look ahead for the sunrise / sunset (it can be absent during the polar night).
+const SUN_LOOKAHEAD_DAYS: usize = 366;
+
+static TIMEZONE: OnceLock<chrono_tz::Tz> = OnceLock::new();
+
+/// Set the process-wide timezone. If it's not initialized,
+/// the system one ([Local]) will be used.
+pub fn init(timezone: chrono_tz::Tz) {
+    if TIMEZONE.set(timezone).is_err() {
+        warn!("Timezone is already initialized");
+    }
+}
+
+/// Convert `datetime` to the configured timezone.
+pub fn localize<Tz: TimeZone>(datetime: DateTime<Tz>) -> DateTime<FixedOffset> {
+    match TIMEZONE.get() {
+        Some(timezone) => datetime.with_timezone(timezone).fixed_offset(),
+        None => datetime.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+/// Interpret `datetime` as the time in the configured timezone.
+/// Returns the earliest one if it's ambiguous and [None] if it doesn't exist (DST gap).
+pub fn from_local(datetime: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+    match TIMEZONE.get() {
+        Some(timezone) => timezone
+            .from_local_datetime(&datetime)
+            .earliest()
+            .map(|datetime| datetime.fixed_offset()),
+        None => Local
+            .from_local_datetime(&datetime)
+            .earliest()
+            .map(|datetime| datetime.fixed_offset()),
+    }
+}
+
+/// Current time in the configured timezone.
+pub fn now() -> DateTime<FixedOffset> {
+    localize(Utc::now())
+}
+
+/// The nearest moment after `after` when the clock shows `time`.
+pub fn next_time_of_day(
+    time: NaiveTime,
+    after: DateTime<FixedOffset>,
+) -> Option<DateTime<FixedOffset>> {
+    let mut next = after
+        .offset()
+        .from_local_datetime(&after.date_naive().and_time(time))
+        .single()?;
+    while next <= after {
+        next = next.checked_add_days(Days::new(1))?;
+    }
+    Some(next)
+}
+
+/// The nearest moment after `after` of the daily `time`. Sun-based times
+/// are [None] if the `location` is not passed.
+pub fn next_daily_time(
+    time: DailyTime,
+    location: Option<&Location>,
+    after: DateTime<FixedOffset>,
+) -> Option<DateTime<FixedOffset>> {
+    match time {
+        DailyTime::Clock(time) => next_time_of_day(time, after),
+        DailyTime::Sun { event, offset_mins } => {
+            let location = location?;
+            let offset = chrono::Duration::minutes(offset_mins);
+            // Start from the previous day as the offset can move the time to the next one.
+            after
+                .date_naive()
+                .pred_opt()?
+                .iter_days()
+                .take(SUN_LOOKAHEAD_DAYS)
+                .filter_map(|date| sun::time(event, date, location))
+                .map(|at| localize(at + offset))
+                .find(|at| *at > after)
+        }
+    }
+}
+
+/// Whether `at` is inside the quiet hours. If the period can't be calculated, it's not quiet.
+pub fn is_quiet(
+    quiet_hours: &QuietHours,
+    location: Option<&Location>,
+    at: DateTime<FixedOffset>,
+) -> bool {
+    let next_from = next_daily_time(quiet_hours.from, location, at);
+    let next_until = next_daily_time(quiet_hours.until, location, at);
+    // The period ends before the next one begins.
+    matches!((next_from, next_until), (Some(from), Some(until)) if until < from)
+}
@@ -0,0 +1,350 @@
+mod network;
+mod service;
+
+use std::collections::HashMap;
+
+use async_graphql::SimpleObject;
+use futures::{Stream, StreamExt};
+use log::info;
+use zbus::{
+    proxy,
+    zvariant::{OwnedFd, OwnedObjectPath, OwnedValue},
+    Connection, MatchRule, MessageStream, MessageType, Result,
+};
+
+use crate::{device::piano::Piano, graphql::GraphQLError};
+pub use network::NetworkInfo;
+use service::HomeService;
+
+/// See [specification](https://bluez.github.io/bluez/doc/org.bluez.MediaControl.rst) for
+/// reference. It's deprecated in favor of `MediaPlayer`, but some hosts don't expose a player.
+#[proxy(default_service = "org.bluez", interface = "org.bluez.MediaControl1")]
+trait BluetoothMediaControl {
+    async fn play(&self) -> Result<()>;
+    async fn pause(&self) -> Result<()>;
+    async fn next(&self) -> Result<()>;
+    async fn previous(&self) -> Result<()>;
+
+    /// Path of the `MediaPlayer` object. Missing if there is no player.
+    #[zbus(property)]
+    fn player(&self) -> Result<OwnedObjectPath>;
+}
+
+/// See [specification](https://bluez.github.io/bluez/doc/org.bluez.MediaPlayer.rst)
+/// for reference.
+#[proxy(default_service = "org.bluez", interface = "org.bluez.MediaPlayer1")]
+trait BluetoothMediaPlayer {
+    async fn play(&self) -> Result<()>;
+    async fn pause(&self) -> Result<()>;
+    async fn next(&self) -> Result<()>;
+    async fn previous(&self) -> Result<()>;
+
+    #[zbus(property)]
+    fn track(&self) -> Result<HashMap<String, OwnedValue>>;
+}
+
+/// See [documentation](https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.login1.html)
+/// for reference.
+#[proxy(
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1",
+    interface = "org.freedesktop.login1.Manager"
+)]
+trait LoginManager {
+    fn power_off(&self, interactive: bool) -> Result<()>;
+    fn reboot(&self, interactive: bool) -> Result<()>;
+
+    /// Lock is held until the returned descriptor is closed.
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> Result<OwnedFd>;
+
+    /// Sent with `true` before the system shutdown and with `false` if it's cancelled.
+    #[zbus(signal)]
+    fn prepare_for_shutdown(&self, start: bool) -> Result<()>;
+}
+
+/// See [documentation](https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.systemd1.html)
+/// for reference.
+#[proxy(
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1",
+    interface = "org.freedesktop.systemd1.Manager"
+)]
+trait SystemdManager {
+    /// Returns path of the queued job.
+    fn restart_unit(&self, name: &str, mode: &str) -> Result<OwnedObjectPath>;
+}
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum UnitControlError {
+    #[error("Unit is not in the list of the manageable ones")]
+    NotAllowed,
+    #[error("D-Bus call failed: {0}")]
+    DBus(#[from] zbus::Error),
+}
+
+impl GraphQLError for UnitControlError {}
+
+/// Metadata of the track which is playing on a Bluetooth device.
+#[derive(Clone, Default, SimpleObject)]
+pub struct MediaTrack {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration_ms: Option<u32>,
+}
+
+impl MediaTrack {
+    fn new(mut properties: HashMap<String, OwnedValue>) -> Self {
+        let mut string = |name| {
+            properties
+                .remove(name)
+                .and_then(|value| String::try_from(value).ok())
+                .filter(|value| !value.is_empty())
+        };
+        Self {
+            title: string("Title"),
+            artist: string("Artist"),
+            album: string("Album"),
+            duration_ms: properties
+                .remove("Duration")
+                .and_then(|value| u32::try_from(value).ok()),
+        }
+    }
+}
+
+/// Composite battery of the system (e.g. UPS HAT). See
+/// [documentation](https://upower.freedesktop.org/docs/Device.html) for reference.
+#[proxy(
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice",
+    interface = "org.freedesktop.UPower.Device"
+)]
+trait UPowerDevice {
+    #[zbus(property)]
+    fn is_present(&self) -> Result<bool>;
+    #[zbus(property)]
+    fn percentage(&self) -> Result<f64>;
+    /// 1 - charging, 2 - discharging, 4 - fully charged, 6 - pending discharge, etc.
+    #[zbus(property)]
+    fn state(&self) -> Result<u32>;
+    /// Seconds. Zero if unknown.
+    #[zbus(property)]
+    fn time_to_empty(&self) -> Result<i64>;
+}
+
+/// Changed properties of a Bluetooth device reported by BlueZ.
+/// Properties which are not changed are [None].
+#[derive(Clone, PartialEq, Eq, SimpleObject)]
+pub struct BluetoothDeviceChange {
+    mac_address: String,
+    connected: Option<bool>,
+    /// Received signal strength indicator in dBm. Reported while discovering.
+    rssi: Option<i16>,
+    /// Battery charge (from 0 to 100) if the device supports the Battery profile.
+    battery_percentage: Option<u8>,
+}
+
+impl BluetoothDeviceChange {
+    /// Returns [None] if it's not a device object or there are no properties of interest.
+    fn new(path: &str, interface: &str, mut changed: HashMap<String, OwnedValue>) -> Option<Self> {
+        // Path has the format `/org/bluez/<ADAPTER>/dev_XX_XX_XX_XX_XX_XX`.
+        let mac_address = path
+            .rsplit('/')
+            .next()?
+            .strip_prefix("dev_")?
+            .replace('_', ":");
+        let mut change = Self {
+            mac_address,
+            connected: None,
+            rssi: None,
+            battery_percentage: None,
+        };
+        match interface {
+            "org.bluez.Device1" => {
+                change.connected = changed
+                    .remove("Connected")
+                    .and_then(|value| bool::try_from(value).ok());
+                change.rssi = changed
+                    .remove("RSSI")
+                    .and_then(|value| i16::try_from(value).ok());
+            }
+            "org.bluez.Battery1" => {
+                change.battery_percentage = changed
+                    .remove("Percentage")
+                    .and_then(|value| u8::try_from(value).ok());
+            }
+            _ => return None,
+        }
+        if change.connected.is_none()
+            && change.rssi.is_none()
+            && change.battery_percentage.is_none()
+        {
+            return None;
+        }
+        Some(change)
+    }
+}
+
+/// Controls the media playback on a Bluetooth device. Prefers `MediaPlayer` if the device
+/// exposes it, otherwise falls back to `MediaControl`.
+pub enum MediaController {
+    Player(BluetoothMediaPlayerProxy<'static>),
+    Control(BluetoothMediaControlProxy<'static>),
+}
+
+impl MediaController {
+    pub async fn play(&self) -> Result<()> {
+        match self {
+            Self::Player(proxy) => proxy.play().await,
+            Self::Control(proxy) => proxy.play().await,
+        }
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        match self {
+            Self::Player(proxy) => proxy.pause().await,
+            Self::Control(proxy) => proxy.pause().await,
+        }
+    }
+
+    pub async fn next(&self) -> Result<()> {
+        match self {
+            Self::Player(proxy) => proxy.next().await,
+            Self::Control(proxy) => proxy.next().await,
+        }
+    }
+
+    pub async fn previous(&self) -> Result<()> {
+        match self {
+            Self::Player(proxy) => proxy.previous().await,
+            Self::Control(proxy) => proxy.previous().await,
+        }
+    }
+
+    /// Returns [None] if the metadata is not available (`MediaControl` doesn't provide it).
+    pub async fn track(&self) -> Result<Option<MediaTrack>> {
+        match self {
+            Self::Player(proxy) => proxy
+                .track()
+                .await
+                .map(|track| Some(MediaTrack::new(track))),
+            Self::Control(_) => Ok(None),
+        }
+    }
+
+    pub fn interface(&self) -> &'static str {
+        match self {
+            Self::Player(_) => "MediaPlayer",
+            Self::Control(_) => "MediaControl",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DBus {
+    system_connection: Connection,
+}
+
+impl DBus {
+    pub async fn new() -> Result<Self> {
+        Connection::system()
+            .await
+            .map(|system_connection| Self { system_connection })
+    }
+
+    pub async fn bluetooth_media_controller(
+        &self,
+        device_id: &bluez_async::DeviceId,
+    ) -> Result<MediaController> {
+        let control_proxy = BluetoothMediaControlProxy::builder(&self.system_connection)
+            .path(format!("/org/bluez/{device_id}"))?
+            .build()
+            .await?;
+        // Property is missing if the device has no player at the moment.
+        let Ok(player_path) = control_proxy.player().await else {
+            return Ok(MediaController::Control(control_proxy));
+        };
+        BluetoothMediaPlayerProxy::builder(&self.system_connection)
+            .path(player_path)?
+            .build()
+            .await
+            .map(MediaController::Player)
+    }
+
+    /// Receive changes of the Bluetooth devices properties (connection state, RSSI and battery)
+    /// as they are signaled, without requesting the whole device information.
+    pub async fn bluetooth_device_changes(
+        &self,
+    ) -> Result<impl Stream<Item = BluetoothDeviceChange>> {
+        let rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender("org.bluez")?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .path_namespace("/org/bluez")?
+            .build();
+        let stream = MessageStream::for_match_rule(rule, &self.system_connection, None).await?;
+        Ok(stream.filter_map(|message| async move {
+            let message = message.ok()?;
+            let path = message.header().path()?.to_string();
+            let (interface, changed, _invalidated): (
+                String,
+                HashMap<String, OwnedValue>,
+                Vec<String>,
+            ) = message.body().deserialize().ok()?;
+            BluetoothDeviceChange::new(&path, &interface, changed)
+        }))
+    }
+
+    /// Export the local control service on the system bus.
+    pub async fn serve(&self, piano: Piano) -> Result<()> {
+        self.system_connection
+            .object_server()
+            .at(service::OBJECT_PATH, HomeService { piano })
+            .await?;
+        self.system_connection
+            .request_name(service::SERVICE_NAME)
+            .await?;
+        info!("D-Bus service {} registered", service::SERVICE_NAME);
+        Ok(())
+    }
+
+    /// Restart `unit` if it's listed in `allowed_units`.
+    pub async fn restart_unit(
+        &self,
+        unit: &str,
+        allowed_units: &[String],
+    ) -> std::result::Result<(), UnitControlError> {
+        if !allowed_units.iter().any(|allowed| allowed == unit) {
+            return Err(UnitControlError::NotAllowed);
+        }
+        SystemdManagerProxy::new(&self.system_connection)
+            .await?
+            .restart_unit(unit, "replace")
+            .await?;
+        info!("Restart of unit {unit} queued");
+        Ok(())
+    }
+
+    pub async fn upower_device_proxy(&self) -> Result<UPowerDeviceProxy> {
+        UPowerDeviceProxy::new(&self.system_connection).await
+    }
+
+    pub async fn login_manager_proxy(&self) -> Result<LoginManagerProxy> {
+        LoginManagerProxy::new(&self.system_connection).await
+    }
+}
+
+/// Returns `false` if the stream is closed before `PrepareForShutdown(start)` received.
+pub async fn wait_for_prepare_for_shutdown(
+    stream: &mut PrepareForShutdownStream<'_>,
+    start: bool,
+) -> Result<bool> {
+    while let Some(signal) = stream.next().await {
+        if signal.args()?.start == start {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use async_graphql::{Enum, SimpleObject};
+use tokio::fs;
+use zbus::{
+    proxy,
+    zvariant::{OwnedObjectPath, OwnedValue},
+    Result,
+};
+
+use super::DBus;
+
+/// NetworkManager device types of interest.
+const DEVICE_TYPE_ETHERNET: u32 = 1;
+const DEVICE_TYPE_WIFI: u32 = 2;
+const DEVICE_TYPE_LOOPBACK: u32 = 32;
+/// NetworkManager device state when it's fully connected.
+const DEVICE_STATE_ACTIVATED: u32 = 100;
+/// Placeholder of the object path properties which are not set.
+const NO_OBJECT_PATH: &str = "/";
+
+/// See [documentation](https://networkmanager.dev/docs/api/latest/spec.html) for reference.
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager",
+    interface = "org.freedesktop.NetworkManager"
+)]
+trait NetworkManager {
+    fn get_devices(&self) -> Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.Device"
+)]
+trait NetworkManagerDevice {
+    #[zbus(property)]
+    fn interface(&self) -> Result<String>;
+    #[zbus(property)]
+    fn hw_address(&self) -> Result<String>;
+    #[zbus(property)]
+    fn device_type(&self) -> Result<u32>;
+    #[zbus(property)]
+    fn state(&self) -> Result<u32>;
+    #[zbus(property)]
+    fn ip4_config(&self) -> Result<OwnedObjectPath>;
+    #[zbus(property)]
+    fn ip6_config(&self) -> Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.Device.Wireless"
+)]
+trait NetworkManagerWireless {
+    #[zbus(property)]
+    fn active_access_point(&self) -> Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.AccessPoint"
+)]
+trait NetworkManagerAccessPoint {
+    #[zbus(property)]
+    fn ssid(&self) -> Result<Vec<u8>>;
+}
+
+/// Both `IP4Config` and `IP6Config` have the same property.
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.IP4Config"
+)]
+trait NetworkManagerIp4Config {
+    #[zbus(property)]
+    fn address_data(&self) -> Result<Vec<HashMap<String, OwnedValue>>>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.IP6Config"
+)]
+trait NetworkManagerIp6Config {
+    #[zbus(property)]
+    fn address_data(&self) -> Result<Vec<HashMap<String, OwnedValue>>>;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum NetworkInterfaceKind {
+    Ethernet,
+    Wifi,
+    Other,
+}
+
+#[derive(SimpleObject)]
+pub struct NetworkInterface {
+    name: String,
+    kind: NetworkInterfaceKind,
+    mac_address: String,
+    /// Whether the interface is connected.
+    is_up: bool,
+    /// Addresses in the CIDR notation (e.g. `192.168.1.2/24`).
+    ip_addresses: Vec<String>,
+    /// Set if it's a connected Wi-Fi interface.
+    ssid: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct NetworkInfo {
+    hostname: String,
+    /// Loopback interface is excluded.
+    interfaces: Vec<NetworkInterface>,
+}
+
+impl DBus {
+    /// Get the network interfaces from NetworkManager.
+    pub async fn network_info(&self) -> Result<NetworkInfo> {
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .await
+            .map(|hostname| hostname.trim().to_string())
+            .unwrap_or_default();
+        let devices = NetworkManagerProxy::new(&self.system_connection)
+            .await?
+            .get_devices()
+            .await?;
+
+        let mut interfaces = Vec::with_capacity(devices.len());
+        for device_path in devices {
+            let device = NetworkManagerDeviceProxy::builder(&self.system_connection)
+                .path(device_path.clone())?
+                .build()
+                .await?;
+            let kind = match device.device_type().await? {
+                DEVICE_TYPE_LOOPBACK => continue,
+                DEVICE_TYPE_ETHERNET => NetworkInterfaceKind::Ethernet,
+                DEVICE_TYPE_WIFI => NetworkInterfaceKind::Wifi,
+                _ => NetworkInterfaceKind::Other,
+            };
+            let is_up = device.state().await? == DEVICE_STATE_ACTIVATED;
+
+            let mut ip_addresses = Vec::new();
+            let ip4_config = device.ip4_config().await?;
+            if ip4_config.as_str() != NO_OBJECT_PATH {
+                let addresses = NetworkManagerIp4ConfigProxy::builder(&self.system_connection)
+                    .path(ip4_config)?
+                    .build()
+                    .await?
+                    .address_data()
+                    .await?;
+                ip_addresses.extend(addresses.into_iter().filter_map(cidr_address));
+            }
+            let ip6_config = device.ip6_config().await?;
+            if ip6_config.as_str() != NO_OBJECT_PATH {
+                let addresses = NetworkManagerIp6ConfigProxy::builder(&self.system_connection)
+                    .path(ip6_config)?
+                    .build()
+                    .await?
+                    .address_data()
+                    .await?;
+                ip_addresses.extend(addresses.into_iter().filter_map(cidr_address));
+            }
+
+            let ssid = if kind == NetworkInterfaceKind::Wifi && is_up {
+                self.wifi_ssid(device_path).await?
+            } else {
+                None
+            };
+            interfaces.push(NetworkInterface {
+                name: device.interface().await?,
+                kind,
+                mac_address: device.hw_address().await?,
+                is_up,
+                ip_addresses,
+                ssid,
+            });
+        }
+        Ok(NetworkInfo {
+            hostname,
+            interfaces,
+        })
+    }
+
+    async fn wifi_ssid(&self, device_path: OwnedObjectPath) -> Result<Option<String>> {
+        let access_point = NetworkManagerWirelessProxy::builder(&self.system_connection)
+            .path(device_path)?
+            .build()
+            .await?
+            .active_access_point()
+            .await?;
+        if access_point.as_str() == NO_OBJECT_PATH {
+            return Ok(None);
+        }
+        let ssid = NetworkManagerAccessPointProxy::builder(&self.system_connection)
+            .path(access_point)?
+            .build()
+            .await?
+            .ssid()
+            .await?;
+        Ok(Some(String::from_utf8_lossy(&ssid).into_owned()))
+    }
+}
+
+/// Convert an entry of the `AddressData` property.
+fn cidr_address(mut address_data: HashMap<String, OwnedValue>) -> Option<String> {
+    let address = String::try_from(address_data.remove("address")?).ok()?;
+    let prefix = u32::try_from(address_data.remove("prefix")?).ok()?;
+    Some(format!("{address}/{prefix}"))
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use zbus::{fdo, interface, zvariant::Value};
+
+use crate::{
+    device::piano::{Piano, StopRecorderParams},
+    graphql::GraphQLError,
+};
+
+pub const SERVICE_NAME: &str = "org.homie.Home1";
+pub const OBJECT_PATH: &str = "/org/homie/Home1";
+
+/// Local control of the server on the system bus. Unlike the HTTP API, it doesn't require
+/// a token: access is restricted by the D-Bus policy (see README).
+pub(super) struct HomeService {
+    pub(super) piano: Piano,
+}
+
+#[interface(name = "org.homie.Home1")]
+impl HomeService {
+    async fn start_recording(&self) -> fdo::Result<()> {
+        self.piano.record().await.map_err(to_fdo_error)
+    }
+
+    /// Returns identifier of the saved recording.
+    async fn stop_recording(&self) -> fdo::Result<i64> {
+        self.piano
+            .stop_recorder(StopRecorderParams {
+                play_feedback: true,
+            })
+            .await
+            .map(|recording| recording.id())
+            .map_err(to_fdo_error)
+    }
+
+    /// Play the recording on the preferred output.
+    async fn play_recording(&self, id: i64) -> fdo::Result<()> {
+        self.piano
+            .play_recording(id, None)
+            .await
+            .map_err(to_fdo_error)
+    }
+
+    async fn get_status(&self) -> fdo::Result<HashMap<&'static str, Value<'static>>> {
+        let status = self
+            .piano
+            .status()
+            .await
+            .map_err(|err| fdo::Error::Failed(err.to_string()))?;
+        Ok(HashMap::from([
+            ("Connected", status.connected.into()),
+            ("HasPlayer", status.has_player.into()),
+            ("HasRecorder", status.has_recorder.into()),
+            ("IsRecording", status.is_recording.into()),
+            ("Output", status.output.to_string().into()),
+        ]))
+    }
+}
+
+fn to_fdo_error(err: impl GraphQLError) -> fdo::Error {
+    // Keep the error code to let scripts handle it.
+    fdo::Error::Failed(format!("{}: {err}", err.as_ref()))
+}
@@ -0,0 +1,100 @@
+use std::io;
+
+use log::{error, info};
+use tokio::{process::Command, select};
+
+use crate::{
+    config,
+    core::{timezone, ShutdownNotify},
+    graphql::GraphQLError,
+};
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum DisplayError {
+    #[error("Failed to run vcgencmd: {0}")]
+    RunFailed(io::Error),
+    #[error("vcgencmd failed: {0}")]
+    CommandFailed(String),
+    #[error("Unexpected vcgencmd output: {0}")]
+    UnexpectedOutput(String),
+}
+
+impl GraphQLError for DisplayError {}
+
+/// Power of the attached HDMI display, controlled using `vcgencmd`
+/// (KMS driver must be in the `fkms` mode or the firmware must drive the display).
+#[derive(Clone)]
+pub struct Display {
+    config: config::Display,
+    location: Option<config::Location>,
+}
+
+impl Display {
+    pub fn new(config: config::Display, location: Option<config::Location>) -> Self {
+        Self { config, location }
+    }
+
+    pub async fn is_on(&self) -> Result<bool, DisplayError> {
+        let output = vcgencmd(&["display_power"]).await?;
+        // Output is "display_power=<0 or 1>".
+        match output.trim().strip_prefix("display_power=") {
+            Some("0") => Ok(false),
+            Some("1") => Ok(true),
+            _ => Err(DisplayError::UnexpectedOutput(output)),
+        }
+    }
+
+    pub async fn set_power(&self, on: bool) -> Result<(), DisplayError> {
+        vcgencmd(&["display_power", if on { "1" } else { "0" }]).await?;
+        info!("Display turned {}", if on { "on" } else { "off" });
+        Ok(())
+    }
+
+    /// Turn the display off and on at the configured times. Returns on shutdown
+    /// or immediately if the schedule is not configured.
+    pub async fn follow_schedule(self, shutdown_notify: ShutdownNotify) {
+        loop {
+            let now = timezone::now();
+            let next_sleep = self
+                .config
+                .sleep_at
+                .and_then(|time| timezone::next_daily_time(time, self.location.as_ref(), now));
+            let next_wake = self
+                .config
+                .wake_at
+                .and_then(|time| timezone::next_daily_time(time, self.location.as_ref(), now));
+            let (at, on) = match (next_sleep, next_wake) {
+                (Some(sleep), Some(wake)) if wake < sleep => (wake, true),
+                (Some(sleep), _) => (sleep, false),
+                (None, Some(wake)) => (wake, true),
+                (None, None) => return,
+            };
+            let Ok(duration) = (at - now).to_std() else {
+                continue;
+            };
+            select! {
+                _ = tokio::time::sleep(duration) => {}
+                _ = shutdown_notify.notified() => return,
+            }
+            if let Err(e) = self.set_power(on).await {
+                error!("Failed to change the display power: {e}");
+            }
+        }
+    }
+}
+
+/// Returns stdout.
+async fn vcgencmd(args: &[&str]) -> Result<String, DisplayError> {
+    let output = Command::new("vcgencmd")
+        .args(args)
+        .output()
+        .await
+        .map_err(DisplayError::RunFailed)?;
+    if !output.status.success() {
+        return Err(DisplayError::CommandFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
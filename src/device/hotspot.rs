@@ -1,9 +1,10 @@
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use log::{error, info, warn};
 use tokio::{process::Command, task::JoinHandle};
 
-use crate::{config, SharedMutex};
+use crate::{config, core::task::TaskManager, SharedMutex};
 
 #[derive(strum::Display)]
 enum NetworkManagerAction {
@@ -14,20 +15,20 @@ enum NetworkManagerAction {
 #[derive(Clone)]
 pub struct Hotspot {
     config: config::Hotspot,
+    tasks: TaskManager,
     /// [JoinHandle] to the already running `nmcli` command.
     running_nmcli: SharedMutex<Option<JoinHandle<()>>>,
 }
 
-impl From<config::Hotspot> for Hotspot {
-    fn from(config: config::Hotspot) -> Self {
+impl Hotspot {
+    pub fn new(config: config::Hotspot, tasks: TaskManager) -> Self {
         Self {
             config,
+            tasks,
             running_nmcli: Arc::default(),
         }
     }
-}
 
-impl Hotspot {
     /// Check if a Bluetooth device is the hotspot device.
     pub fn is_hotspot(&self, bluetooth_device: &bluez_async::DeviceInfo) -> bool {
         bluetooth_device.mac_address
@@ -60,6 +61,7 @@ impl Hotspot {
 
         let running_nmcli = Arc::clone(&self.running_nmcli);
         let connection = self.config.connection.clone();
+        let tasks = self.tasks.clone();
         tokio::spawn(async move {
             let mut running_nmcli = running_nmcli.lock().await;
             let should_wait = running_nmcli
@@ -75,45 +77,45 @@ impl Hotspot {
                     );
                 }
             }
-            *running_nmcli = Some(spawn_nmcli(action, connection));
+            *running_nmcli = Some(spawn_nmcli(&tasks, action, connection));
         });
     }
 }
 
 // TODO: check the current connection state using neli-wifi before proceeding.
-fn spawn_nmcli(action: NetworkManagerAction, connection: String) -> JoinHandle<()> {
-    tokio::spawn(async move {
+fn spawn_nmcli(
+    tasks: &TaskManager,
+    action: NetworkManagerAction,
+    connection: String,
+) -> JoinHandle<()> {
+    tasks.spawn("nmcli", async move {
         let action_str = action.to_string();
         info!(
             "Performing NetworkManager {} action for connection {}...",
             action_str.to_uppercase(),
             connection
         );
-        let result = Command::new("nmcli")
+        let output = Command::new("nmcli")
             .args(["connection", &action_str.to_lowercase(), &connection])
             .output()
-            .await;
+            .await
+            .map_err(|err| anyhow!("failed to run nmcli: {err}"))?;
 
-        match result {
-            Ok(output) => {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                if !output.status.success() {
-                    error!(
-                        "Action {} failed{}",
-                        action_str.to_uppercase(),
-                        if stderr.is_empty() {
-                            "".to_string()
-                        } else {
-                            format!(": {stderr}")
-                        }
-                    );
-                    return;
-                } else if !stderr.is_empty() {
-                    warn!("NetworkManager produced error output: {stderr}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            return Err(anyhow!(
+                "action {} failed{}",
+                action_str.to_uppercase(),
+                if stderr.is_empty() {
+                    "".to_string()
+                } else {
+                    format!(": {stderr}")
                 }
-                info!("Action {} succeed", action_str.to_uppercase());
-            }
-            Err(e) => error!("Failed to run nmcli: {e}"),
-        };
+            ));
+        } else if !stderr.is_empty() {
+            warn!("NetworkManager produced error output: {stderr}");
+        }
+        info!("Action {} succeed", action_str.to_uppercase());
+        Ok(())
     })
 }
@@ -0,0 +1,259 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use async_graphql::Value;
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
+use log::{error, info};
+use tokio::{
+    fs, select,
+    sync::{Mutex, Notify},
+    time::Instant,
+};
+
+use super::{piano::Piano, plugin::DevicePlugin};
+use crate::{
+    bluetooth::A2DPSourceHandler,
+    config::{self, LedPattern, Location, QuietHours},
+    core::{timezone, Broadcaster, ShutdownNotify},
+    dbus::BluetoothDeviceChange,
+    GlobalEvent, SharedMutex,
+};
+
+const LEDS_DIR: &str = "/sys/class/leds";
+/// How long the error pattern is shown after a failure.
+const ERROR_PATTERN_DURATION: Duration = Duration::from_secs(5);
+
+/// If several states are active, the one with the highest priority is shown.
+#[derive(Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "kebab-case")]
+enum LedState {
+    Off,
+    BluetoothAudio,
+    Recording,
+    Error,
+}
+
+enum Trigger {
+    StateChanged,
+    Failure,
+}
+
+/// Reflects the server state using the LEDs from `/sys/class/leds`
+/// (e.g. the Raspberry Pi ACT LED or a GPIO-attached RGB LED set up by the `gpio-led` overlay).
+/// The LEDs are off during the quiet hours.
+#[derive(Clone)]
+pub struct StatusLed {
+    config: config::Led,
+    quiet_hours: Option<QuietHours>,
+    location: Option<Location>,
+    piano: Piano,
+    a2dp_source_handler: A2DPSourceHandler,
+    state: SharedMutex<LedState>,
+    /// If `false`, the LEDs are off regardless of the state (changed by the automation actions).
+    enabled: Arc<AtomicBool>,
+    enabled_changed: Arc<Notify>,
+}
+
+impl StatusLed {
+    pub fn new(
+        config: config::Led,
+        quiet_hours: Option<QuietHours>,
+        location: Option<Location>,
+        piano: Piano,
+        a2dp_source_handler: A2DPSourceHandler,
+    ) -> Self {
+        Self {
+            config,
+            quiet_hours,
+            location,
+            piano,
+            a2dp_source_handler,
+            state: Arc::new(Mutex::new(LedState::Off)),
+            enabled: Arc::new(AtomicBool::new(true)),
+            enabled_changed: Arc::default(),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.enabled_changed.notify_one();
+        info!(
+            "Status LED {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+
+    /// Follow the events which affect the state until shutdown.
+    pub async fn run(
+        self,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+        bluetooth_device_broadcaster: Broadcaster<BluetoothDeviceChange>,
+        shutdown_notify: ShutdownNotify,
+    ) {
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await
+            .map(|_| Trigger::StateChanged);
+        let global_events = event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await
+            .filter_map(|event| async move {
+                matches!(event.payload, GlobalEvent::SubsystemRestarted { .. })
+                    .then_some(Trigger::Failure)
+            });
+        let bluetooth_changes = bluetooth_device_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await
+            .map(|_| Trigger::StateChanged);
+        let mut triggers = stream::select_all([
+            piano_events.boxed(),
+            global_events.boxed(),
+            bluetooth_changes.boxed(),
+        ]);
+
+        let mut error_until = None;
+        self.update(false).await;
+        loop {
+            select! {
+                trigger = triggers.next() => match trigger {
+                    Some(Trigger::Failure) => {
+                        error_until = Some(Instant::now() + ERROR_PATTERN_DURATION);
+                    }
+                    Some(Trigger::StateChanged) => {}
+                    None => break,
+                },
+                _ = async {
+                    match error_until {
+                        Some(until) => tokio::time::sleep_until(until).await,
+                        None => std::future::pending().await,
+                    }
+                } => error_until = None,
+                _ = async {
+                    match self.next_quiet_hours_change() {
+                        Some(at) => {
+                            if let Ok(duration) = (at - timezone::now()).to_std() {
+                                tokio::time::sleep(duration).await;
+                            }
+                        }
+                        None => std::future::pending().await,
+                    }
+                } => {}
+                _ = self.enabled_changed.notified() => {}
+            }
+            self.update(error_until.is_some()).await;
+        }
+    }
+
+    /// When the quiet hours begin or end next time.
+    fn next_quiet_hours_change(&self) -> Option<DateTime<FixedOffset>> {
+        let quiet_hours = self.quiet_hours?;
+        let now = timezone::now();
+        [quiet_hours.from, quiet_hours.until]
+            .into_iter()
+            .filter_map(|time| timezone::next_daily_time(time, self.location.as_ref(), now))
+            .min()
+    }
+
+    async fn update(&self, has_error: bool) {
+        let is_quiet = self.quiet_hours.is_some_and(|quiet_hours| {
+            timezone::is_quiet(&quiet_hours, self.location.as_ref(), timezone::now())
+        });
+        let new_state = if is_quiet || !self.enabled.load(Ordering::Relaxed) {
+            LedState::Off
+        } else if has_error {
+            LedState::Error
+        } else if self
+            .piano
+            .status()
+            .await
+            .is_ok_and(|status| status.is_recording)
+        {
+            LedState::Recording
+        } else if self.a2dp_source_handler.has_connected().await {
+            LedState::BluetoothAudio
+        } else {
+            LedState::Off
+        };
+        let mut state = self.state.lock().await;
+        if *state != new_state {
+            *state = new_state;
+            self.apply(new_state).await;
+        }
+    }
+
+    async fn apply(&self, state: LedState) {
+        let patterns = &self.config.patterns;
+        let pattern = match state {
+            LedState::Off => None,
+            LedState::BluetoothAudio => Some(patterns.bluetooth_audio),
+            LedState::Recording => Some(patterns.recording),
+            LedState::Error => Some(patterns.error),
+        };
+        let (red, green, blue) =
+            pattern.map_or((false, false, false), |pattern| pattern.color.channels());
+        for (led, is_on) in [
+            (&self.config.red, red),
+            (&self.config.green, green),
+            (&self.config.blue, blue),
+        ] {
+            let Some(led) = led else {
+                continue;
+            };
+            let pattern = pattern.filter(|_| is_on);
+            if let Err(e) = set_led(led, pattern).await {
+                error!("Failed to control LED {led}: {e}");
+            }
+        }
+    }
+}
+
+/// Turn on the LED with the pattern or turn it off if it's [None].
+async fn set_led(name: &str, pattern: Option<LedPattern>) -> std::io::Result<()> {
+    let dir = PathBuf::from(LEDS_DIR).join(name);
+    match pattern.and_then(|pattern| pattern.blink_interval_ms) {
+        // Kernel blinks the LED by itself.
+        Some(interval_ms) => {
+            fs::write(dir.join("trigger"), "timer").await?;
+            fs::write(dir.join("delay_on"), interval_ms.to_string()).await?;
+            fs::write(dir.join("delay_off"), interval_ms.to_string()).await
+        }
+        None => {
+            fs::write(dir.join("trigger"), "none").await?;
+            let brightness = if pattern.is_some() {
+                fs::read_to_string(dir.join("max_brightness")).await?
+            } else {
+                "0".to_string()
+            };
+            fs::write(dir.join("brightness"), brightness.trim()).await
+        }
+    }
+}
+
+impl DevicePlugin for StatusLed {
+    fn name(&self) -> &'static str {
+        "status-led"
+    }
+
+    fn status(&self) -> BoxFuture<'_, Value> {
+        async { Value::String(self.state.lock().await.to_string()) }.boxed()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        async {
+            *self.state.lock().await = LedState::Off;
+            self.apply(LedState::Off).await;
+            info!("Status LED turned off");
+        }
+        .boxed()
+    }
+}
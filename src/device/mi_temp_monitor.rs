@@ -155,7 +155,17 @@ pub struct Data {
 }
 
 impl Data {
-    fn battery_percents(&self) -> u8 {
+    /// In Celsius.
+    pub fn temperature(&self) -> f32 {
+        self.temp_celsius
+    }
+
+    /// In percents.
+    pub fn humidity(&self) -> u8 {
+        self.humidity_percents
+    }
+
+    pub fn battery_percents(&self) -> u8 {
         ((self.voltage - BATTERY_VOLTAGE_ALIGN) * 100.0).clamp(0.0, 100.0) as _
     }
 }
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use async_graphql::{SimpleObject, Value};
+use futures::{
+    future::{BoxFuture, LocalBoxFuture},
+    FutureExt,
+};
+use log::{error, info};
+
+use super::plugin::DevicePlugin;
+use crate::{core::Broadcaster, GlobalEvent, SharedRwLock};
+
+/// Pattern of the ALSA raw MIDI device names (`midiC<CARD>D<DEVICE>`).
+const RAWMIDI_SYSNAME_PATTERN: &str = "midiC*";
+
+#[derive(Clone, SimpleObject)]
+pub struct MidiController {
+    /// Device node to read the raw MIDI messages from, e.g. `/dev/snd/midiC1D0`.
+    devnode: String,
+    /// Model name reported by the device (if present).
+    name: Option<String>,
+    #[graphql(skip)]
+    devpath: String,
+}
+
+impl MidiController {
+    fn new(device: &tokio_udev::Device) -> Option<Self> {
+        let property = |name| {
+            device
+                .property_value(name)
+                .map(|value| value.to_string_lossy().replace('_', " "))
+        };
+        Some(Self {
+            devnode: device.devnode()?.to_string_lossy().into_owned(),
+            name: property("ID_MODEL_FROM_DATABASE").or_else(|| property("ID_MODEL")),
+            devpath: device.devpath().to_string_lossy().into_owned(),
+        })
+    }
+}
+
+/// Tracks plugged in class-compliant MIDI controllers (they are exposed as ALSA raw MIDI devices).
+#[derive(Clone)]
+pub struct MidiControllers {
+    controllers: SharedRwLock<Vec<MidiController>>,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+impl MidiControllers {
+    pub fn new(event_broadcaster: Broadcaster<GlobalEvent>) -> Self {
+        Self {
+            controllers: Arc::default(),
+            event_broadcaster,
+        }
+    }
+
+    pub async fn list(&self) -> Vec<MidiController> {
+        self.controllers.read().await.clone()
+    }
+
+    fn scan() -> Vec<MidiController> {
+        let mut enumerator = match tokio_udev::Enumerator::new() {
+            Ok(enumerator) => enumerator,
+            Err(e) => {
+                error!("Failed to set up the udev MIDI scanner: {e}");
+                return Vec::new();
+            }
+        };
+        let match_result = enumerator
+            .match_subsystem("sound")
+            .and_then(|_| enumerator.match_sysname(RAWMIDI_SYSNAME_PATTERN));
+        if let Err(e) = match_result {
+            error!("Failed to apply filters to the udev MIDI scanner: {e}");
+            return Vec::new();
+        }
+        match enumerator.scan_devices() {
+            Ok(devices) => devices
+                .filter_map(|device| MidiController::new(&device))
+                .collect(),
+            Err(e) => {
+                error!("Failed to scan /sys for the MIDI controllers: {e}");
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl DevicePlugin for MidiControllers {
+    fn name(&self) -> &'static str {
+        "midi-controllers"
+    }
+
+    fn init(&self) -> BoxFuture<'_, ()> {
+        async {
+            let controllers = Self::scan();
+            if !controllers.is_empty() {
+                info!("Found {} MIDI controller(s)", controllers.len());
+            }
+            *self.controllers.write().await = controllers;
+        }
+        .boxed()
+    }
+
+    fn udev_subsystems(&self) -> &'static [&'static str] {
+        &["sound"]
+    }
+
+    fn handle_udev_event<'a>(&'a self, event: &'a tokio_udev::Event) -> LocalBoxFuture<'a, ()> {
+        async {
+            if !event.sysname().to_string_lossy().starts_with("midiC") {
+                return;
+            }
+            let devpath = event.devpath().to_string_lossy();
+            let mut controllers = self.controllers.write().await;
+            match event.event_type() {
+                tokio_udev::EventType::Add => {
+                    let Some(controller) = MidiController::new(event) else {
+                        return;
+                    };
+                    info!(
+                        device = "midi-controller", event = "connected";
+                        "MIDI controller connected: {}",
+                        controller.name.as_deref().unwrap_or(&controller.devnode)
+                    );
+                    controllers.retain(|item| item.devpath != devpath);
+                    controllers.push(controller);
+                }
+                tokio_udev::EventType::Remove => {
+                    let count = controllers.len();
+                    controllers.retain(|item| item.devpath != devpath);
+                    if controllers.len() == count {
+                        return;
+                    }
+                    info!(device = "midi-controller", event = "removed"; "MIDI controller removed");
+                }
+                _ => return,
+            }
+            self.event_broadcaster
+                .send(GlobalEvent::MidiControllersChanged);
+        }
+        .boxed_local()
+    }
+
+    fn resync(&self) -> BoxFuture<'_, ()> {
+        async {
+            let controllers = Self::scan();
+            let mut current = self.controllers.write().await;
+            let changed = controllers.len() != current.len()
+                || controllers.iter().any(|controller| {
+                    !current
+                        .iter()
+                        .any(|item| item.devpath == controller.devpath)
+                });
+            *current = controllers;
+            if changed {
+                self.event_broadcaster
+                    .send(GlobalEvent::MidiControllersChanged);
+            }
+        }
+        .boxed()
+    }
+
+    fn status(&self) -> BoxFuture<'_, Value> {
+        async {
+            async_graphql::to_value(
+                self.list()
+                    .await
+                    .into_iter()
+                    .map(|controller| controller.devnode)
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap_or_default()
+        }
+        .boxed()
+    }
+}
@@ -1,10 +1,20 @@
 pub mod description;
+pub mod display;
 pub mod hotspot;
+pub mod led;
 pub mod mi_temp_monitor;
+pub mod midi;
+pub mod monitor_output;
 pub mod piano;
+pub mod plugin;
+pub mod power;
+pub mod snapcast_output;
+pub mod usb_storage;
+pub mod voice_memo;
 
 use bluez_async::{BluetoothError, BluetoothSession, DeviceInfo};
-use std::{fmt::Debug, future::Future};
+use log::error;
+use std::{ffi::OsString, fmt::Debug, future::Future};
 
 pub trait DeviceDescription: Send + Sync + 'static {
     fn name() -> &'static str;
@@ -63,3 +73,31 @@ pub trait BluetoothDevice: Sized + Send + Sync + Debug {
         }
     }
 }
+
+/// Returns devpath of the sound card with the `card_id` identifier (see /proc/asound/cards).
+pub fn find_sound_card_devpath(card_id: &str) -> Option<OsString> {
+    let mut enumerator = match tokio_udev::Enumerator::new() {
+        Ok(enumerator) => enumerator,
+        Err(e) => {
+            error!("Failed to set up the udev sound card scanner: {e}");
+            return None;
+        }
+    };
+
+    let match_result = enumerator
+        .match_subsystem("sound")
+        .and_then(|_| enumerator.match_is_initialized())
+        .and_then(|_| enumerator.match_attribute("id", card_id));
+
+    if let Err(e) = match_result {
+        error!("Failed to apply filters to the udev sound card scanner: {e}");
+    } else {
+        match enumerator.scan_devices() {
+            Ok(mut devices) => {
+                return devices.next().map(|device| device.devpath().to_os_string());
+            }
+            Err(e) => error!("Failed to scan /sys for the sound card: {e}"),
+        }
+    }
+    None
+}
@@ -0,0 +1,232 @@
+use std::{ffi::OsString, sync::Arc};
+
+use anyhow::anyhow;
+use async_graphql::{value, Value};
+use cpal::traits::DeviceTrait;
+use futures::{
+    future::{BoxFuture, LocalBoxFuture},
+    FutureExt,
+};
+use log::{error, info};
+use tokio::sync::watch;
+
+use super::{
+    piano::{AudioError, AudioResult},
+    plugin::DevicePlugin,
+};
+use crate::{
+    audio::{
+        self,
+        player::{Player, PlayerError, PlayerOutput},
+        AudioObject,
+    },
+    config,
+    core::task::TaskManager,
+    SharedMutex,
+};
+
+/// Output-only sound card which is used to play the audio while the piano stays free.
+#[derive(Clone)]
+pub struct MonitorOutput {
+    config: config::MonitorOutput,
+    output_stream_wait: config::BackoffPolicy,
+    resample_quality: Option<config::ResampleQuality>,
+    tasks: TaskManager,
+    /// If the device is not connected, it will be [None].
+    inner: SharedMutex<Option<Inner>>,
+    /// Whether the player is ready to use.
+    available_tx: Arc<watch::Sender<bool>>,
+}
+
+struct Inner {
+    devpath: OsString,
+    /// Set to [None] if player initialization failed or is in process.
+    player: Option<Player>,
+}
+
+impl MonitorOutput {
+    pub fn new(
+        config: config::MonitorOutput,
+        output_stream_wait: config::BackoffPolicy,
+        resample_quality: Option<config::ResampleQuality>,
+        tasks: TaskManager,
+    ) -> Self {
+        Self {
+            config,
+            output_stream_wait,
+            resample_quality,
+            tasks,
+            inner: Arc::default(),
+            available_tx: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Receive changes of the player availability (the device is plugged in or removed).
+    pub fn subscribe_availability(&self) -> watch::Receiver<bool> {
+        self.available_tx.subscribe()
+    }
+
+    /// Whether recordings must be played on this device while it's available.
+    pub fn auto_switch(&self) -> bool {
+        self.config.auto_switch
+    }
+
+    pub fn is_available(&self) -> bool {
+        *self.available_tx.borrow()
+    }
+
+    fn set_available(&self, available: bool) {
+        self.available_tx.send_if_modified(|current| {
+            let modified = *current != available;
+            *current = available;
+            modified
+        });
+    }
+
+    pub async fn call_player<T, F>(&self, f: F) -> AudioResult<T, PlayerError>
+    where
+        F: FnOnce(&mut Player) -> BoxFuture<Result<T, PlayerError>>,
+    {
+        let mut inner_lock = self.inner.lock().await;
+        let player = inner_lock
+            .as_mut()
+            .ok_or(AudioError::MonitorNotConnected)?
+            .player
+            .as_mut()
+            .ok_or(AudioError::NotInitialized(AudioObject::Player))?;
+        f(player).await.map_err(AudioError::Error)
+    }
+
+    async fn connect(&self, devpath: OsString) {
+        *self.inner.lock().await = Some(Inner {
+            devpath,
+            player: None,
+        });
+        self.set_available(false);
+        info!(device = "monitor-output", event = "connected"; "Monitor output connected");
+
+        let self_clone = self.clone();
+        self.tasks.spawn("monitor-player-init", async move {
+            self_clone.init_player().await
+        });
+    }
+
+    async fn init_player(&self) -> anyhow::Result<()> {
+        // Sound server may keep the device busy just after it's plugged in.
+        let (device, stream_config) =
+            backoff::future::retry(self.output_stream_wait.exponential(), || async {
+                let device = audio::find_device(&self.config.alsa_plugin, &self.config.device_id)
+                    .ok_or_else(|| {
+                    backoff::Error::transient(anyhow!("audio device is not found"))
+                })?;
+                device
+                    .default_output_config()
+                    .map(|stream_config| (device, stream_config))
+                    .map_err(|err| backoff::Error::transient(anyhow!(err)))
+            })
+            .await?;
+        info!(
+            "Monitor output stream format: {}",
+            audio::stream_info(&stream_config)
+        );
+
+        let player = Player::new(
+            PlayerOutput::Device(device, stream_config),
+            self.resample_quality,
+        )
+        .await
+        .map_err(|err| anyhow!("player initialization failed: {err}"))?;
+        match self.inner.lock().await.as_mut() {
+            Some(inner) => {
+                inner.player = Some(player);
+                self.set_available(true);
+            }
+            None => info!("Monitor output removed while initializing the player"),
+        }
+        Ok(())
+    }
+}
+
+impl DevicePlugin for MonitorOutput {
+    fn name(&self) -> &'static str {
+        "monitor-output"
+    }
+
+    fn init(&self) -> BoxFuture<'_, ()> {
+        async {
+            if let Some(devpath) = super::find_sound_card_devpath(&self.config.device_id) {
+                self.connect(devpath).await;
+            }
+        }
+        .boxed()
+    }
+
+    fn udev_subsystems(&self) -> &'static [&'static str] {
+        &["sound"]
+    }
+
+    fn handle_udev_event<'a>(&'a self, event: &'a tokio_udev::Event) -> LocalBoxFuture<'a, ()> {
+        async {
+            match event.event_type() {
+                tokio_udev::EventType::Add => {
+                    let id_matches = event
+                        .attribute_value("id")
+                        .map(|id| id.to_string_lossy() == self.config.device_id)
+                        .unwrap_or(false);
+                    if !id_matches {
+                        return;
+                    }
+                    if event.is_initialized() {
+                        self.connect(event.devpath().to_os_string()).await;
+                    } else {
+                        error!("Udev device found, but it's not initialized");
+                    }
+                }
+                tokio_udev::EventType::Remove => {
+                    let mut inner = self.inner.lock().await;
+                    let devpath_matches = inner
+                        .as_ref()
+                        .map(|inner| event.devpath() == inner.devpath)
+                        .unwrap_or(false);
+                    if devpath_matches {
+                        *inner = None;
+                        self.set_available(false);
+                        info!(device = "monitor-output", event = "removed"; "Monitor output removed");
+                    }
+                }
+                _ => {}
+            }
+        }
+        .boxed_local()
+    }
+
+    fn resync(&self) -> BoxFuture<'_, ()> {
+        async {
+            let devpath = super::find_sound_card_devpath(&self.config.device_id);
+            let mut inner = self.inner.lock().await;
+            if devpath == inner.as_ref().map(|inner| inner.devpath.clone()) {
+                return;
+            }
+            if inner.take().is_some() {
+                self.set_available(false);
+                info!("Monitor output removed while device events were not monitored");
+            }
+            drop(inner);
+            if let Some(devpath) = devpath {
+                self.connect(devpath).await;
+            }
+        }
+        .boxed()
+    }
+
+    fn status(&self) -> BoxFuture<'_, Value> {
+        async {
+            let inner = self.inner.lock().await;
+            value!({
+                "connected": inner.is_some(),
+                "hasPlayer": inner.as_ref().is_some_and(|inner| inner.player.is_some()),
+            })
+        }
+        .boxed()
+    }
+}
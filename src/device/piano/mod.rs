@@ -1,28 +1,59 @@
 pub mod recordings;
 
-use std::{ffi::OsString, fmt::Display, path::Path, sync::Arc, time::Duration};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fmt::Display,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use async_graphql::SimpleObject;
+use anyhow::{anyhow, bail};
+use async_graphql::{SimpleObject, Value};
 use async_stream::stream;
-use cpal::traits::{DeviceTrait, HostTrait};
-use futures::{executor, future::BoxFuture, FutureExt, Stream, StreamExt};
+use cpal::traits::DeviceTrait;
+use futures::{
+    future::{self, BoxFuture, LocalBoxFuture},
+    pin_mut, FutureExt, Stream, StreamExt,
+};
 use log::{error, info, warn};
-use tokio::{fs, select};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, select, sync::MutexGuard};
 
 use crate::{
     audio::{
         self,
-        player::{PlaybackPosition, PlaybackProperties, Player, PlayerError, SeekTo},
-        recorder::{self, RecordError, RecordParams, Recorder},
-        AudioObject, AudioSource, AudioSourceError, AudioSourceProperties, SoundLibrary,
+        envelope::SkipControl,
+        player::{
+            LoopRegion, PlaybackPosition, PlaybackProperties, Player, PlayerError, PlayerOutput,
+            SeekTo,
+        },
+        recorder::{self, RecordError, RecordParams, Recorder, RecorderStatus},
+        router::{FanoutSource, OutputRoute, OutputTarget},
+        AudioObject, AudioOutput, AudioSource, AudioSourceError, AudioSourceProperties,
+        SoundLibrary, StreamFormat,
+    },
+    bluetooth::{A2DPSourceHandler, MediaControlCommand},
+    config::{self, Config, SoundsFallbackOutput},
+    core::{
+        metrics::{self, Counter},
+        supervisor::Supervised,
+        task::TaskManager,
+        Broadcaster, ShutdownNotify, SortOrder,
+    },
+    dbus::{self, DBus},
+    device::{
+        self, monitor_output::MonitorOutput, plugin::DevicePlugin, snapcast_output::SnapcastOutput,
     },
-    bluetooth::A2DPSourceHandler,
-    config::{self, Config},
-    core::{Broadcaster, ShutdownNotify},
     files::{self, Asset, AssetsDir, BaseDir, Sound},
     graphql::GraphQLError,
     prefs::PreferencesStorage,
-    SharedMutex,
+    storage::Storage,
+    SharedMutex, Subsystem,
 };
 use recordings::{Recording, RecordingStorage, RecordingStorageError};
 
@@ -36,6 +67,10 @@ use recordings::{Recording, RecordingStorage, RecordingStorageError};
 /// it will not be picked up.
 const FIND_AUDIO_DEVICE_DELAY: Duration = Duration::from_millis(500);
 const PLAY_RECORDING_FADE_IN: Duration = Duration::from_millis(300);
+/// How often to check whether the players have switched to the queued recording.
+const QUEUE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How many played recordings to remember for [Piano::skip_previous].
+const MAX_QUEUE_HISTORY: usize = 100;
 
 pub enum HandledPianoEvent {
     Add,
@@ -52,15 +87,21 @@ pub struct InitParams {
 pub enum AudioError<E> {
     #[error("Piano is not connected")]
     PianoNotConnected,
+    #[error("Monitor output is not connected")]
+    MonitorNotConnected,
+    #[error("Snapcast output is not configured")]
+    SnapcastNotConfigured,
     #[error("{0} is not initialized")]
     NotInitialized(AudioObject),
+    #[error("Audio device is in use by {}", _0.join(", "))]
+    AudioBlocked(Vec<String>),
     #[error(transparent)]
     Error(E),
 }
 
 impl<E: Display> GraphQLError for AudioError<E> {}
 
-type AudioResult<T, E> = Result<T, AudioError<E>>;
+pub type AudioResult<T, E> = Result<T, AudioError<E>>;
 
 pub struct StopRecorderParams {
     /// Whether to play a sound or log the result.
@@ -80,6 +121,10 @@ pub enum RecordControlError {
     PreserveRecordingError(RecordingStorageError),
     #[error("Unable to check recorder status: {0}")]
     CheckStatusFailed(RecordingStorageError),
+    #[error("Not enough free disk space ({0} MiB left)")]
+    DiskFull(u64),
+    #[error("Recording is already waiting for the audio device")]
+    AlreadyWaiting,
     #[error(transparent)]
     Error(AudioError<RecordError>),
 }
@@ -91,38 +136,121 @@ impl GraphQLError for RecordControlError {}
 pub enum PlayRecordingError {
     #[error("Unable to get a recording: {0}")]
     GetRecording(RecordingStorageError),
+    #[error("There are no recordings")]
+    NoRecordings,
     #[error("Unable to make an audio source: {0}")]
     MakeAudioSource(AudioSourceError),
+    #[error("Loop region must be within the recording and its end must be after the start")]
+    InvalidLoopRegion,
+    #[error("There is no playback session to resume")]
+    NoPlaybackSession,
+    #[error("Queue is empty")]
+    QueueEmpty,
+    #[error("There is no previously played recording in the queue history")]
+    NoPreviousRecording,
     #[error(transparent)]
     Error(AudioError<PlayerError>),
 }
 
 impl GraphQLError for PlayRecordingError {}
 
-#[derive(SimpleObject)]
+#[derive(SimpleObject, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PianoStatus {
     /// Is piano plugged in.
-    connected: bool,
+    pub connected: bool,
     /// Whether player is available.
-    has_player: bool,
+    pub has_player: bool,
     /// Whether recorder is available.
-    has_recorder: bool,
+    pub has_recorder: bool,
     /// Is audio recording in process.
-    is_recording: bool,
+    pub is_recording: bool,
+    /// Whether the captured audio is discarded until the recorder is resumed.
+    pub is_recording_paused: bool,
+    /// Details of the active recording. It's not updated while recording,
+    /// so query the status periodically to track them.
+    pub recorder: Option<RecorderStatus>,
+    /// Where the recordings are playing now.
+    pub output: AudioOutput,
+    /// Outputs which play the same recording along with `output`.
+    pub mirrored_outputs: Vec<AudioOutput>,
+    /// Output set by the user. If [None], it's chosen automatically.
+    pub output_override: Option<AudioOutput>,
+    /// Connected A2DP sources which hold the audio device, so the player and recorder
+    /// are not available. Empty if the audio device is not blocked.
+    pub audio_blocked_by: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct PianoAudioInfo {
+    /// ALSA name of the piano device. Null if the piano is not connected
+    /// or the device is in use by an A2DP source.
+    pub device_name: Option<String>,
+    /// Device which plays the recordings (it can be set in the preferences).
+    /// Null if the player is not initialized.
+    pub output_device_name: Option<String>,
+    pub output_stream: Option<StreamFormat>,
+    /// Null if the recorder is not initialized.
+    pub input_stream: Option<StreamFormat>,
+}
+
+/// Result of [Piano::reinit_audio]. Errors are null if the initialization succeeded.
+#[derive(SimpleObject)]
+pub struct AudioReinitResult {
+    pub success: bool,
+    pub player_error: Option<String>,
+    /// It's null if the recorder is kept as it's recording.
+    pub recorder_error: Option<String>,
 }
 
 #[derive(Default, SimpleObject)]
 pub struct PianoPlaybackStatus {
     /// Is some recording playing now.
-    is_playing: bool,
-    /// [None] if there was no played recording _since piano connected_.
-    last_played_recording: Option<Recording>,
+    pub is_playing: bool,
+    /// [None] if there was no played recording _since piano connected_
+    /// (use `resumeLastSession` to restore it).
+    pub last_played_recording: Option<Recording>,
     /// [None] if there is no playing (or paused) recording.
-    position: Option<PlaybackPosition>,
+    pub position: Option<PlaybackPosition>,
+    /// Recordings to play after the current one (see `enqueueRecording`).
+    pub queue: Vec<Recording>,
+}
+
+/// Recordings to play one after another (see [Piano::enqueue_recording]).
+#[derive(Default)]
+struct PlaybackQueue {
+    upcoming: VecDeque<i64>,
+    /// Recordings played before the current one, the last is the most recent.
+    history: Vec<i64>,
+    /// Set if the first upcoming recording is already appended to the players,
+    /// so there is no gap between recordings.
+    appended: Option<SkipControl>,
+    /// Whether the task which advances the queue is running.
+    is_advancing: bool,
+}
+
+impl PlaybackQueue {
+    fn push_history(&mut self, id: i64) {
+        if self.history.len() == MAX_QUEUE_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(id);
+    }
+
+    /// Must be called when the players start another recording.
+    fn discard_appended(&mut self) {
+        if let Some(appended) = self.appended.take() {
+            appended.skip();
+        }
+    }
 }
 
 // ATTENTION: do not forget to check the `status_update` method when you add a new event.
-#[derive(Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+#[derive(Clone, Copy, PartialEq, Eq, async_graphql::Enum, Deserialize, strum::AsRefStr)]
+// The same names as in the GraphQL schema, so they can be used in the automation rules
+// and passed to the scripts.
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum PianoEvent {
     PianoConnected,
     PianoRemoved,
@@ -136,25 +264,66 @@ pub enum PianoEvent {
     PlayerPlay,
     PlayerPause,
     PlayerSeek,
+    /// Playback is moved to another output.
+    OutputChanged,
+    /// Recordings are added to or removed from the playback queue.
+    QueueChanged,
 
     RecordStart,
+    RecorderPaused,
+    RecorderResumed,
+    /// Recording is requested while the recorder is not available,
+    /// so it will start as soon as the audio device becomes available.
+    RecordWaitingForDevice,
+    /// Audio device is not available within `piano.record_device_wait_secs`.
+    RecordWaitTimedOut,
     /// Triggered before stopping the recorder automatically
     /// as the recording duration limit is reached.
     RecordingLengthLimitReached,
+    /// Triggered before stopping the recorder automatically as there is no sound
+    /// for the time set in `piano.recorder.stop_on_silence`.
+    ProlongedSilenceDetected,
+    /// Triggered after [PianoEvent::NewRecordingSaved] if the recorder
+    /// is stopped not by the user.
+    RecorderStoppedAutomatically,
     NewRecordingSaved,
     OldRecordingsRemoved,
+    /// Recording is uploaded or deleted using the file manager.
+    RecordingsModified,
+    /// Loudness of the new recording is measured.
+    RecordingAnalyzed,
+    /// Free space dropped below the configured threshold, new recordings are refused.
+    DiskSpaceLow,
 }
 
 #[derive(Clone)]
 pub struct Piano {
     config: config::Piano,
+    backoff: config::Backoff,
+    resample_quality: Option<config::ResampleQuality>,
+    disk_watchdog: config::DiskWatchdog,
     assets: AssetsDir,
     prefs: PreferencesStorage,
+    /// Used to save the play statistics of the recordings.
+    storage: Storage,
 
     sounds: SoundLibrary,
     shutdown_notify: ShutdownNotify,
+    tasks: TaskManager,
     /// Used to check whether an audio device is in use by a Bluetooth device.
     a2dp_source_handler: A2DPSourceHandler,
+    dbus: DBus,
+    /// [None] if it's not configured.
+    monitor_output: Option<MonitorOutput>,
+    /// [None] if it's not configured.
+    snapcast_output: Option<SnapcastOutput>,
+    /// Outputs of the latest played recording. Playback control is applied to all of them.
+    active_route: SharedMutex<OutputRoute>,
+    /// Output chosen by the user, which takes precedence over the automatic routing.
+    output_override: SharedMutex<Option<AudioOutput>>,
+    /// Whether a recording waits for the recorder to become available.
+    record_waiting: Arc<AtomicBool>,
+    queue: SharedMutex<PlaybackQueue>,
 
     pub event_broadcaster: Broadcaster<PianoEvent>,
     /// If the piano is not connected, it will be [None].
@@ -162,40 +331,107 @@ pub struct Piano {
     pub recording_storage: RecordingStorage,
 }
 
+/// Shared services which are used by the [Piano].
+pub struct PianoDependencies {
+    pub prefs: PreferencesStorage,
+    pub storage: Storage,
+    pub sounds: SoundLibrary,
+    pub shutdown_notify: ShutdownNotify,
+    pub tasks: TaskManager,
+    pub a2dp_source_handler: A2DPSourceHandler,
+    pub dbus: DBus,
+    pub monitor_output: Option<MonitorOutput>,
+}
+
 impl Piano {
-    pub fn new(
-        config: &Config,
-        prefs: PreferencesStorage,
-        sounds: SoundLibrary,
-        shutdown_notify: ShutdownNotify,
-        a2dp_source_handler: A2DPSourceHandler,
-    ) -> Self {
+    pub fn new(config: &Config, dependencies: PianoDependencies) -> Self {
+        let PianoDependencies {
+            prefs,
+            storage,
+            sounds,
+            shutdown_notify,
+            tasks,
+            a2dp_source_handler,
+            dbus,
+            monitor_output,
+        } = dependencies;
         Self {
             config: config.piano.clone(),
+            backoff: config.backoff.clone(),
+            resample_quality: config.resample_quality,
+            disk_watchdog: config.disk_watchdog.clone(),
             assets: config.assets_dir.clone(),
             prefs,
+            storage,
             sounds,
             shutdown_notify,
+            tasks: tasks.clone(),
             a2dp_source_handler,
-            event_broadcaster: Broadcaster::default(),
+            dbus,
+            monitor_output,
+            snapcast_output: config.snapcast.clone().map(|snapcast_config| {
+                SnapcastOutput::new(snapcast_config, config.resample_quality)
+            }),
+            active_route: Arc::default(),
+            output_override: Arc::default(),
+            record_waiting: Arc::default(),
+            queue: Arc::default(),
+            event_broadcaster: Broadcaster::new("piano", config.broadcaster_capacity),
             inner: Arc::default(),
             recording_storage: RecordingStorage::new(
                 &config.data_dir.path(files::Data::PianoRecordings),
                 config.piano.max_recordings,
+                tasks,
             ),
         }
     }
 
-    async fn status(&self) -> Result<PianoStatus, RecordingStorageError> {
-        let connected = self.inner.lock().await.is_some();
+    pub async fn status(&self) -> Result<PianoStatus, RecordingStorageError> {
+        let (connected, device_released) = match self.inner.lock().await.as_ref() {
+            Some(inner) => (true, inner.device.is_none()),
+            None => (false, false),
+        };
+        let active_route = self.active_route.lock().await.clone();
         Ok(PianoStatus {
             connected,
             has_player: self.has_initialized(AudioObject::Player).await,
             has_recorder: self.has_initialized(AudioObject::Recorder).await,
             is_recording: self.recording_storage.is_recording().await?,
+            is_recording_paused: self
+                .call_recorder(|recorder| async { Ok(recorder.is_paused()) }.boxed())
+                .await
+                .unwrap_or_default(),
+            recorder: self
+                .call_recorder(|recorder| async { Ok(recorder.status().await) }.boxed())
+                .await
+                .ok()
+                .flatten(),
+            output: active_route.primary(),
+            mirrored_outputs: active_route.mirrors().collect(),
+            output_override: *self.output_override.lock().await,
+            audio_blocked_by: self.audio_blocked_by(device_released).await,
         })
     }
 
+    /// Devices and stream formats which are in use now.
+    pub async fn audio_info(&self) -> PianoAudioInfo {
+        let inner_lock = self.inner.lock().await;
+        let inner = inner_lock.as_ref();
+        let player_device = inner
+            .and_then(|inner| inner.player.as_ref())
+            .and_then(Player::device);
+        PianoAudioInfo {
+            device_name: inner
+                .and_then(|inner| inner.device.as_ref())
+                .and_then(|device| device.name().ok()),
+            output_device_name: player_device.map(|(name, _)| name.clone()),
+            output_stream: player_device.map(|(_, format)| format.clone()),
+            input_stream: inner
+                .and_then(|inner| inner.recorder.as_ref())
+                .map(Recorder::stream_format),
+        }
+    }
+
     /// Continuously receive the current piano status.
     pub async fn status_update(
         self,
@@ -208,13 +444,21 @@ impl Piano {
         stream! {
             yield self.status().await;
             while let Some(event) = event_stream.next().await {
-                match event {
+                match event.payload {
                     // These events don't affect the piano status.
                     PianoEvent::RecordingLengthLimitReached
+                    | PianoEvent::ProlongedSilenceDetected
+                    | PianoEvent::RecorderStoppedAutomatically
                     | PianoEvent::OldRecordingsRemoved
+                    | PianoEvent::RecordingsModified
+                    | PianoEvent::RecordingAnalyzed
+                    | PianoEvent::DiskSpaceLow
+                    | PianoEvent::RecordWaitingForDevice
+                    | PianoEvent::RecordWaitTimedOut
                     | PianoEvent::PlayerPlay
                     | PianoEvent::PlayerPause
-                    | PianoEvent::PlayerSeek => {}
+                    | PianoEvent::PlayerSeek
+                    | PianoEvent::QueueChanged => {}
                     _ => yield self.status().await,
                 }
             }
@@ -232,39 +476,13 @@ impl Piano {
     ) -> impl Stream<Item = Result<PianoPlaybackStatus, PlayerError>> {
         stream! {
             loop {
-                let player_result = self
-                    .call_player(|player| {
-                        async { Ok((player.is_playing().await?, player.position().await?)) }.boxed()
-                    })
-                    .await;
-                let last_played_recording = self
-                    .inner
-                    .lock()
-                    .await
-                    .as_ref()
-                    .and_then(|inner| inner.last_played_recording.clone());
-                let status_result = match player_result {
-                    Ok((is_playing, position)) => Ok(PianoPlaybackStatus {
-                        is_playing,
-                        last_played_recording,
-                        position,
-                    }),
-                    Err(e) => match e {
-                        AudioError::PianoNotConnected | AudioError::NotInitialized(_) => {
-                            Ok(PianoPlaybackStatus {
-                                last_played_recording,
-                                ..Default::default()
-                            })
-                        }
-                        AudioError::Error(e) => Err(e),
-                    },
-                };
+                let status_result = self.playback_status().await;
                 let (update_continuously, events_to_wait) = status_result
                     .as_ref()
                     .ok()
                     .map(|status| {
                         if status.position.is_none() {
-                            return (false, vec![PianoEvent::PlayerPlay]);
+                            return (false, vec![PianoEvent::PlayerPlay, PianoEvent::QueueChanged]);
                         }
 
                         let mut events = vec![
@@ -272,6 +490,8 @@ impl Piano {
                             PianoEvent::AudioReleased,
                             PianoEvent::PlayerPlay,
                             PianoEvent::PlayerSeek,
+                            PianoEvent::OutputChanged,
+                            PianoEvent::QueueChanged,
                         ];
                         if status.is_playing {
                             events.push(PianoEvent::PlayerPause);
@@ -304,8 +524,63 @@ impl Piano {
         }
     }
 
+    /// Unavailable player is not an error: the status just has no position.
+    pub async fn playback_status(&self) -> Result<PianoPlaybackStatus, PlayerError> {
+        let player_result = self
+            .call_player(|player| {
+                async { Ok((player.is_playing().await?, player.position().await?)) }.boxed()
+            })
+            .await;
+        let last_played_recording = self
+            .inner
+            .lock()
+            .await
+            .as_ref()
+            .and_then(|inner| inner.last_played_recording.clone());
+        let upcoming = self.queue.lock().await.upcoming.clone();
+        let queue = future::join_all(upcoming.iter().map(|id| self.recording_storage.get(*id)))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        match player_result {
+            Ok((is_playing, position)) => Ok(PianoPlaybackStatus {
+                is_playing,
+                last_played_recording,
+                position,
+                queue,
+            }),
+            Err(e) => match e {
+                AudioError::PianoNotConnected
+                | AudioError::MonitorNotConnected
+                | AudioError::SnapcastNotConfigured
+                | AudioError::NotInitialized(_) => Ok(PianoPlaybackStatus {
+                    last_played_recording,
+                    queue,
+                    ..Default::default()
+                }),
+                AudioError::Error(e) => Err(e),
+            },
+        }
+    }
+
+    /// Whether new recordings are refused as free disk space is low.
+    /// It's not low if free space can't be checked.
+    pub fn is_disk_space_low(&self) -> bool {
+        self.recording_storage
+            .free_space_mib()
+            .is_ok_and(|free_mib| free_mib < self.disk_watchdog.min_free_mib)
+    }
+
     /// Start recording to the new temporary file.
     pub async fn record(&self) -> Result<(), RecordControlError> {
+        match self.recording_storage.free_space_mib() {
+            Ok(free_mib) if free_mib < self.disk_watchdog.min_free_mib => {
+                return Err(RecordControlError::DiskFull(free_mib))
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Unable to check free disk space: {e}"),
+        }
         let out_path = self
             .recording_storage
             .prepare_new()
@@ -327,6 +602,8 @@ impl Piano {
             amplitude_scale: prefs_lock.piano.record_amplitude_scale,
             artist: prefs_lock.piano.recordings_artist.clone(),
             front_cover_jpeg,
+            trim_silence: prefs_lock.piano.trim_silence,
+            on_prolonged_silence: Some(self.get_prolonged_silence_callback()),
         };
         drop(prefs_lock);
 
@@ -353,22 +630,72 @@ impl Piano {
         }
     }
 
+    /// Same as [Piano::record], but if the recorder is not available (e.g. the audio device
+    /// is held by an A2DP source), recording starts as soon as it becomes available.
+    /// Returns `false` if the start is postponed.
+    pub async fn record_when_available(&self) -> Result<bool, RecordControlError> {
+        match self.record().await {
+            Ok(()) => Ok(true),
+            Err(RecordControlError::Error(
+                AudioError::NotInitialized(_) | AudioError::AudioBlocked(_),
+            )) => {
+                if self.record_waiting.swap(true, Ordering::Relaxed) {
+                    return Err(RecordControlError::AlreadyWaiting);
+                }
+                info!("Recording will start when the audio device becomes available");
+                self.event_broadcaster
+                    .send(PianoEvent::RecordWaitingForDevice);
+                self.tasks
+                    .spawn("piano-record-wait", self.clone().record_after_wait());
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn record_after_wait(self) {
+        let events = self
+            .event_broadcaster
+            .recv_continuously(self.shutdown_notify.clone())
+            .await;
+        pin_mut!(events);
+        // Recorder may be initialized before subscribing to the events.
+        let wait = async {
+            if self.has_initialized(AudioObject::Recorder).await {
+                return true;
+            }
+            while let Some(event) = events.next().await {
+                if event.payload == PianoEvent::RecorderInitialized {
+                    return true;
+                }
+            }
+            // Shutting down.
+            false
+        };
+        let timeout = Duration::from_secs(self.config.record_device_wait_secs.into());
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(true) => {
+                if let Err(e) = self.record().await {
+                    error!("Failed to start the postponed recording: {e}");
+                }
+            }
+            Ok(false) => {}
+            Err(_) => {
+                warn!("Audio device is not available in time, recording is cancelled");
+                self.event_broadcaster.send(PianoEvent::RecordWaitTimedOut);
+            }
+        }
+        self.record_waiting.store(false, Ordering::Relaxed);
+    }
+
     /// Used to stop a running recorder when the recording duration limit is reached.
     fn get_recorder_timepoint_handler(&self) -> recorder::TimepointHandler {
         let piano = self.clone();
         let callback = async move {
             warn!("Recording length limit reached. Recorder will be stopped");
             piano
-                .event_broadcaster
-                .send(PianoEvent::RecordingLengthLimitReached);
-            let result = piano
-                .stop_recorder(StopRecorderParams {
-                    play_feedback: true,
-                })
+                .stop_recorder_automatically(PianoEvent::RecordingLengthLimitReached)
                 .await;
-            if let Err(e) = result {
-                error!("Failed to stop the recorder properly: {e}");
-            }
         };
         recorder::TimepointHandler {
             at: Duration::from_secs(self.config.max_recording_duration_secs as u64),
@@ -376,6 +703,60 @@ impl Piano {
         }
     }
 
+    /// Used to stop a running recorder if there is no sound for a long time.
+    fn get_prolonged_silence_callback(&self) -> recorder::TimepointCallback {
+        let piano = self.clone();
+        let callback = async move {
+            warn!("No sound for a long time. Recorder will be stopped");
+            piano
+                .stop_recorder_automatically(PianoEvent::ProlongedSilenceDetected)
+                .await;
+        };
+        Box::new(|| callback.boxed())
+    }
+
+    /// Stop the recorder and save the recording as if the user did it.
+    /// `reason` is sent before stopping.
+    async fn stop_recorder_automatically(&self, reason: PianoEvent) {
+        self.event_broadcaster.send(reason);
+        let result = self
+            .stop_recorder(StopRecorderParams {
+                play_feedback: true,
+            })
+            .await;
+        match result {
+            Ok(recording) => {
+                info!("Recording {recording} is stopped automatically");
+                self.event_broadcaster
+                    .send(PianoEvent::RecorderStoppedAutomatically);
+            }
+            Err(e) => error!("Failed to stop the recorder properly: {e}"),
+        }
+    }
+
+    /// Interrupt the recording without splitting it into several files.
+    /// Returns `false` if the recorder is already paused.
+    pub async fn pause_recorder(&self) -> AudioResult<bool, RecordError> {
+        let paused = self
+            .call_recorder(|recorder| async { recorder.pause() }.boxed())
+            .await?;
+        if paused {
+            self.event_broadcaster.send(PianoEvent::RecorderPaused);
+        }
+        Ok(paused)
+    }
+
+    /// Returns `false` if the recorder is not paused.
+    pub async fn resume_recorder(&self) -> AudioResult<bool, RecordError> {
+        let resumed = self
+            .call_recorder(|recorder| async { recorder.resume() }.boxed())
+            .await?;
+        if resumed {
+            self.event_broadcaster.send(PianoEvent::RecorderResumed);
+        }
+        Ok(resumed)
+    }
+
     /// Stop recorder and preserve a new recording.
     pub async fn stop_recorder(
         &self,
@@ -409,8 +790,13 @@ impl Piano {
             .await
             .map_err(RecordControlError::PreserveRecordingError)
             .and_then(|path| path.ok_or(RecordControlError::NotRecording));
-        if preserve_result.is_ok() {
+        if let Ok(recording) = &preserve_result {
+            metrics::increment(Counter::RecordingsMade);
             self.event_broadcaster.send(PianoEvent::NewRecordingSaved);
+            let (recording, storage) = (recording.clone(), self.storage.clone());
+            self.tasks.spawn("recording-waveform-analysis", async move {
+                recording.waveform(&storage).await
+            });
         }
         if params.play_feedback {
             self.play_sound(if recorder_succeed && preserve_result.is_ok() {
@@ -421,7 +807,10 @@ impl Piano {
             .await;
         } else {
             match &preserve_result {
-                Ok(recording) => info!("New recording preserved: {recording}"),
+                Ok(recording) => info!(
+                    device = "piano", event = "recording_preserved";
+                    "New recording preserved: {recording}"
+                ),
                 Err(e) => error!("Failed to preserve a new recording: {e}"),
             }
         }
@@ -429,38 +818,401 @@ impl Piano {
     }
 
     /// Executing this method can take a long time as it _decodes_ entire recording.
-    pub async fn play_recording(&self, id: i64) -> Result<(), PlayRecordingError> {
+    /// If `output` is [None], the preferred one is used.
+    pub async fn play_recording(
+        &self,
+        id: i64,
+        output: Option<AudioOutput>,
+    ) -> Result<(), PlayRecordingError> {
+        let output = match output {
+            Some(output) => output,
+            None => self.preferred_output().await,
+        };
+        self.play_recording_on(id, output.into()).await
+    }
+
+    /// Play the newest recording on the preferred output. Returns its identifier.
+    pub async fn play_last_recording(&self) -> Result<i64, PlayRecordingError> {
+        let id = self
+            .recording_storage
+            .list(SortOrder::Descending)
+            .await
+            .map_err(PlayRecordingError::GetRecording)?
+            .first()
+            .ok_or(PlayRecordingError::NoRecordings)?
+            .id();
+        self.play_recording(id, None).await.map(|_| id)
+    }
+
+    /// Play the recording on all outputs of `route` simultaneously. If a mirror output
+    /// fails to start, it's skipped. Executing this method can take a long time as well.
+    /// The queue is kept, it continues after this recording.
+    pub async fn play_recording_on(
+        &self,
+        id: i64,
+        route: OutputRoute,
+    ) -> Result<(), PlayRecordingError> {
+        let mut queue = self.queue.lock().await;
+        self.play_recording_locked(id, route, &mut queue, true)
+            .await
+    }
+
+    /// If `remember_current` is set, the current recording is added
+    /// to the queue history, so it can be played again using [Self::skip_previous].
+    async fn play_recording_locked(
+        &self,
+        id: i64,
+        mut route: OutputRoute,
+        queue: &mut PlaybackQueue,
+        remember_current: bool,
+    ) -> Result<(), PlayRecordingError> {
         let recording = self
             .recording_storage
             .get(id)
             .await
             .map_err(PlayRecordingError::GetRecording)?;
-        // User should be able to seek:
-        // `rodio` doesn't support it for FLAC and for buffered decoders.
-        let source = AudioSource::flac_decoded_unbuffered(&recording.flac_path)
+        // Recording is decoded only once for all outputs.
+        let fanout = FanoutSource::decode_flac(&recording.flac_path)
             .map_err(PlayRecordingError::MakeAudioSource)?;
-        let props = PlaybackProperties {
-            source_props: AudioSourceProperties {
-                fade_in: Some(PLAY_RECORDING_FADE_IN),
+        let gain = self.recording_gain(&recording).await;
+
+        let mut active_route = self.active_route.lock().await;
+        for target in route.targets().to_vec() {
+            // User should be able to seek:
+            // `rodio` doesn't support it for FLAC and for buffered decoders.
+            let source = fanout
+                .source()
+                .map_err(PlayRecordingError::MakeAudioSource)?;
+            let props = PlaybackProperties {
+                volume: target.volume * gain,
+                source_props: AudioSourceProperties {
+                    fade_in: Some(PLAY_RECORDING_FADE_IN),
+                    ..Default::default()
+                },
+                output: target.output,
                 ..Default::default()
-            },
-            ..Default::default()
-        };
-        self.call_player(|player| async { player.play(source, props).await }.boxed())
-            .await
-            .map_err(PlayRecordingError::Error)?;
+            };
+            let result = self
+                .call_player_on(target.output, |player| {
+                    async { player.play(source, props).await }.boxed()
+                })
+                .await;
+            match result {
+                Ok(()) => {}
+                Err(e) if target.output == route.primary() => {
+                    return Err(PlayRecordingError::Error(e))
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to play the recording on the {} output: {e}",
+                        target.output
+                    );
+                    route.remove_mirror(target.output);
+                }
+            }
+        }
+        // Only one recording can be playing at a time.
+        for previous in active_route.targets().to_vec() {
+            if !route.contains(previous.output) {
+                let _ = self
+                    .call_player_on(previous.output, |player| {
+                        async { player.pause().await }.boxed()
+                    })
+                    .await;
+            }
+        }
+        *active_route = route;
+        drop(active_route);
+        // Players dropped it when started the new recording.
+        queue.discard_appended();
 
-        if let Some(inner) = self.inner.lock().await.as_mut() {
-            inner.last_played_recording = Some(recording);
+        self.storage.add_play(recording.id()).await;
+        let previous = self
+            .inner
+            .lock()
+            .await
+            .as_mut()
+            .and_then(|inner| inner.last_played_recording.replace(recording));
+        if let Some(previous) = previous.filter(|previous| remember_current && previous.id() != id)
+        {
+            queue.push_history(previous.id());
         }
         self.event_broadcaster.send(PianoEvent::PlayerPlay);
         self.play_sound(Sound::Play).await;
         Ok(())
     }
 
+    /// Add the recording to the end of the queue. If nothing is playing, it starts right away
+    /// on the outputs of the last played recording. Recordings are switched without a gap,
+    /// transition between them is set by the `queue_transition_ms` preference.
+    pub async fn enqueue_recording(&self, id: i64) -> Result<(), PlayRecordingError> {
+        // Check it now, because the queue is played in the background.
+        self.recording_storage
+            .get(id)
+            .await
+            .map_err(PlayRecordingError::GetRecording)?;
+        let mut queue = self.queue.lock().await;
+        let is_playing = self
+            .call_player(|player| async { player.position().await }.boxed())
+            .await
+            .is_ok_and(|position| position.is_some());
+        if queue.upcoming.is_empty() && !is_playing {
+            let route = self.active_route.lock().await.clone();
+            return self
+                .play_recording_locked(id, route, &mut queue, true)
+                .await;
+        }
+        queue.upcoming.push_back(id);
+        self.spawn_queue_advancing(&mut queue);
+        self.event_broadcaster.send(PianoEvent::QueueChanged);
+        Ok(())
+    }
+
+    /// Returns the number of removed recordings.
+    pub async fn clear_queue(&self) -> usize {
+        let mut queue = self.queue.lock().await;
+        queue.discard_appended();
+        let removed = queue.upcoming.len();
+        queue.upcoming.clear();
+        if removed != 0 {
+            self.event_broadcaster.send(PianoEvent::QueueChanged);
+        }
+        removed
+    }
+
+    /// Play the next recording from the queue right away. Returns its identifier.
+    pub async fn skip_next(&self) -> Result<i64, PlayRecordingError> {
+        let mut queue = self.queue.lock().await;
+        let id = *queue
+            .upcoming
+            .front()
+            .ok_or(PlayRecordingError::QueueEmpty)?;
+        let route = self.active_route.lock().await.clone();
+        self.play_recording_locked(id, route, &mut queue, true)
+            .await?;
+        queue.upcoming.pop_front();
+        self.event_broadcaster.send(PianoEvent::QueueChanged);
+        Ok(id)
+    }
+
+    /// Play the previous recording from the queue history. The current one is placed
+    /// at the beginning of the queue. Returns identifier of the played recording.
+    pub async fn skip_previous(&self) -> Result<i64, PlayRecordingError> {
+        let mut queue = self.queue.lock().await;
+        let id = *queue
+            .history
+            .last()
+            .ok_or(PlayRecordingError::NoPreviousRecording)?;
+        let current = self
+            .inner
+            .lock()
+            .await
+            .as_ref()
+            .and_then(|inner| inner.last_played_recording.as_ref().map(Recording::id));
+        let route = self.active_route.lock().await.clone();
+        self.play_recording_locked(id, route, &mut queue, false)
+            .await?;
+        queue.history.pop();
+        if let Some(current) = current {
+            queue.upcoming.push_front(current);
+            self.spawn_queue_advancing(&mut queue);
+        }
+        self.event_broadcaster.send(PianoEvent::QueueChanged);
+        Ok(id)
+    }
+
+    fn spawn_queue_advancing(&self, queue: &mut PlaybackQueue) {
+        if queue.is_advancing {
+            return;
+        }
+        queue.is_advancing = true;
+        let self_clone = self.clone();
+        self.tasks.spawn("piano-playback-queue", async move {
+            self_clone.advance_queue().await
+        });
+    }
+
+    /// Keeps the queue going until it's empty: appends the next recording to the players
+    /// and tracks when they switch to it.
+    async fn advance_queue(&self) {
+        loop {
+            select! {
+                _ = tokio::time::sleep(QUEUE_CHECK_INTERVAL) => {}
+                _ = self.shutdown_notify.notified() => break,
+            }
+            let mut queue = self.queue.lock().await;
+            let Some(&next) = queue.upcoming.front() else {
+                queue.is_advancing = false;
+                break;
+            };
+            if queue
+                .appended
+                .as_ref()
+                .is_some_and(SkipControl::has_started)
+            {
+                queue.upcoming.pop_front();
+                queue.appended = None;
+                self.switch_to_queued(next, &mut queue).await;
+                continue;
+            }
+
+            let position = self
+                .call_player(|player| async { player.position().await }.boxed())
+                .await;
+            let result = match position {
+                // Nothing is playing (or paused), so start the next one right away.
+                // If it's appended, then the players were recreated and it's lost.
+                Ok(None) => {
+                    let route = self.active_route.lock().await.clone();
+                    self.play_recording_locked(next, route, &mut queue, true)
+                        .await
+                        .map(|_| {
+                            queue.upcoming.pop_front();
+                            self.event_broadcaster.send(PianoEvent::QueueChanged);
+                        })
+                }
+                Ok(Some(_)) if queue.appended.is_some() => continue,
+                Ok(Some(_)) => self.append_to_players(next).await.map(|appended| {
+                    queue.appended = Some(appended);
+                }),
+                // Wait until the player becomes available.
+                Err(_) => continue,
+            };
+            if let Err(e) = result {
+                error!("Failed to play the queued recording {next}, skipping it: {e}");
+                queue.upcoming.pop_front();
+                self.event_broadcaster.send(PianoEvent::QueueChanged);
+            }
+        }
+    }
+
+    /// Called when the players have switched to the appended recording on their own.
+    async fn switch_to_queued(&self, id: i64, queue: &mut PlaybackQueue) {
+        let recording = match self.recording_storage.get(id).await {
+            Ok(recording) => recording,
+            Err(e) => return error!("Failed to get the queued recording {id}: {e}"),
+        };
+        self.storage.add_play(id).await;
+        let previous = self
+            .inner
+            .lock()
+            .await
+            .as_mut()
+            .and_then(|inner| inner.last_played_recording.replace(recording));
+        if let Some(previous) = previous {
+            queue.push_history(previous.id());
+        }
+        self.event_broadcaster.send(PianoEvent::PlayerPlay);
+        self.event_broadcaster.send(PianoEvent::QueueChanged);
+    }
+
+    /// Append the recording after the current one on all outputs of the active route.
+    /// Returned control tells when the players switch to it.
+    async fn append_to_players(&self, id: i64) -> Result<SkipControl, PlayRecordingError> {
+        let recording = self
+            .recording_storage
+            .get(id)
+            .await
+            .map_err(PlayRecordingError::GetRecording)?;
+        let fanout = FanoutSource::decode_flac(&recording.flac_path)
+            .map_err(PlayRecordingError::MakeAudioSource)?;
+        let gain = self.recording_gain(&recording).await;
+        let transition =
+            Duration::from_millis(self.prefs.read().await.audio.queue_transition_ms.into());
+
+        let appended = SkipControl::default();
+        let route = self.active_route.lock().await;
+        for target in route.targets() {
+            let source = fanout
+                .source()
+                .map_err(PlayRecordingError::MakeAudioSource)?;
+            let props = PlaybackProperties {
+                volume: target.volume * gain,
+                source_props: AudioSourceProperties {
+                    skip: Some(appended.clone()),
+                    ..Default::default()
+                },
+                output: target.output,
+                ..Default::default()
+            };
+            let result = self
+                .call_player_on(target.output, |player| {
+                    async move { player.enqueue(source, props, transition).await }.boxed()
+                })
+                .await;
+            match result {
+                Ok(()) => {}
+                Err(e) if target.output == route.primary() => {
+                    // It could be appended to the mirrors.
+                    appended.skip();
+                    return Err(PlayRecordingError::Error(e));
+                }
+                Err(e) => warn!(
+                    "Failed to enqueue the recording on the {} output: {e}",
+                    target.output
+                ),
+            }
+        }
+        Ok(appended)
+    }
+
+    /// Start playing the recording on the preferred output from `position`.
+    pub async fn play_recording_from(
+        &self,
+        id: i64,
+        position: Duration,
+    ) -> Result<(), PlayRecordingError> {
+        self.play_recording(id, None).await?;
+        self.seek_player(SeekTo::Position(position))
+            .await
+            .map(|_| ())
+            .map_err(PlayRecordingError::Error)
+    }
+
+    /// Play the region of the recording repeatedly (e.g. to practice a passage).
+    /// If the recording is not playing, it's started on the preferred output.
+    pub async fn set_loop_region(
+        &self,
+        id: i64,
+        region: LoopRegion,
+    ) -> Result<(), PlayRecordingError> {
+        let recording = self
+            .recording_storage
+            .get(id)
+            .await
+            .map_err(PlayRecordingError::GetRecording)?;
+        if region.from >= region.to || region.to > recording.duration() {
+            return Err(PlayRecordingError::InvalidLoopRegion);
+        }
+        let set_region = || {
+            self.control_players(move |player| {
+                async move { player.set_loop_region(region).await }.boxed()
+            })
+        };
+        let is_last_played = self.inner.lock().await.as_ref().is_some_and(|inner| {
+            inner
+                .last_played_recording
+                .as_ref()
+                .is_some_and(|recording| recording.id() == id)
+        });
+        // Region can't be set if the playback has finished.
+        if !is_last_played || !set_region().await.map_err(PlayRecordingError::Error)? {
+            self.play_recording(id, None).await?;
+            set_region().await.map_err(PlayRecordingError::Error)?;
+        }
+        self.event_broadcaster.send(PianoEvent::PlayerSeek);
+        Ok(())
+    }
+
+    /// Returns `false` if there was no loop region.
+    pub async fn clear_loop_region(&self) -> AudioResult<bool, PlayerError> {
+        self.control_players(|player| async { player.clear_loop_region().await }.boxed())
+            .await
+    }
+
     /// Returns `false` if there is no playing (or paused) audio.
     pub async fn seek_player(&self, to: SeekTo) -> AudioResult<bool, PlayerError> {
-        self.call_player(|player| async move { player.seek(to).await }.boxed())
+        self.control_players(|player| async move { player.seek(to).await }.boxed())
             .await
             .inspect(|&success| {
                 if success {
@@ -471,7 +1223,7 @@ impl Piano {
 
     pub async fn resume_player(&self) -> AudioResult<bool, PlayerError> {
         let resumed = self
-            .call_player(|player| async { player.resume().await }.boxed())
+            .control_players(|player| async { player.resume().await }.boxed())
             .await?;
         if resumed {
             self.event_broadcaster.send(PianoEvent::PlayerPlay);
@@ -482,7 +1234,7 @@ impl Piano {
 
     pub async fn pause_player(&self) -> AudioResult<bool, PlayerError> {
         let paused = self
-            .call_player(|player| async { player.pause().await }.boxed())
+            .control_players(|player| async { player.pause().await }.boxed())
             .await?;
         if paused {
             self.event_broadcaster.send(PianoEvent::PlayerPause);
@@ -491,39 +1243,341 @@ impl Piano {
         Ok(paused)
     }
 
-    /// Play `sound` using the secondary sink.
-    async fn play_sound(&self, sound: Sound) {
-        if !self.has_initialized(AudioObject::Player).await {
+    /// Pass [None] to choose the output automatically. Returns the active output.
+    pub async fn set_output_override(&self, output: Option<AudioOutput>) -> AudioOutput {
+        *self.output_override.lock().await = output;
+        self.route_output().await
+    }
+
+    /// Output for the recordings when it's not passed explicitly.
+    async fn preferred_output(&self) -> AudioOutput {
+        if let Some(output) = *self.output_override.lock().await {
+            return output;
+        }
+        match &self.monitor_output {
+            Some(monitor_output)
+                if monitor_output.auto_switch() && monitor_output.is_available() =>
+            {
+                AudioOutput::Monitor
+            }
+            _ => AudioOutput::Piano,
+        }
+    }
+
+    /// Move the playing (or paused) recording to the preferred output.
+    /// Playback on several outputs is chosen explicitly, so it's not moved.
+    /// Returns the active (primary) output.
+    async fn route_output(&self) -> AudioOutput {
+        let target = self.preferred_output().await;
+        let mut active_route = self.active_route.lock().await;
+        let previous = active_route.targets()[0];
+        if previous.output == target || active_route.mirrors().next().is_some() {
+            return previous.output;
+        }
+        *active_route = OutputTarget {
+            output: target,
+            volume: previous.volume,
+        }
+        .into();
+        let previous = previous.output;
+        info!(device = "piano", event = "output_changed"; "Playback output changed to {target}");
+        self.event_broadcaster.send(PianoEvent::OutputChanged);
+
+        // The previous output may be already unplugged, then there is nothing to move.
+        let playback = self
+            .call_player_on(previous, |player| {
+                async {
+                    let is_playing = player.is_playing().await?;
+                    let position = player.position().await?;
+                    player.pause().await?;
+                    Ok((is_playing, position))
+                }
+                .boxed()
+            })
+            .await;
+        let recording = self
+            .inner
+            .lock()
+            .await
+            .as_ref()
+            .and_then(|inner| inner.last_played_recording.clone());
+        if let (Ok((is_playing, Some(position))), Some(recording)) = (playback, recording) {
+            let volume = active_route.targets()[0].volume;
+            if let Err(e) = self
+                .continue_playback(target, volume, &recording, position.current, is_playing)
+                .await
+            {
+                error!("Failed to move the playback to the new output: {e}");
+            }
+        }
+        target
+    }
+
+    async fn continue_playback(
+        &self,
+        output: AudioOutput,
+        volume: f32,
+        recording: &Recording,
+        position: Duration,
+        is_playing: bool,
+    ) -> Result<(), PlayRecordingError> {
+        let source = AudioSource::flac_decoded_unbuffered(&recording.flac_path)
+            .map_err(PlayRecordingError::MakeAudioSource)?;
+        let props = PlaybackProperties {
+            volume: volume * self.recording_gain(recording).await,
+            output,
+            ..Default::default()
+        };
+        self.call_player_on(output, |player| {
+            async move {
+                player.play(source, props).await?;
+                player.seek(SeekTo::Position(position)).await?;
+                if !is_playing {
+                    player.pause().await?;
+                }
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+        .map_err(PlayRecordingError::Error)
+    }
+
+    /// Samples multiplier which brings the recording to the reference loudness
+    /// if the normalization is enabled.
+    async fn recording_gain(&self, recording: &Recording) -> f32 {
+        if !self.prefs.read().await.audio.normalize_loudness {
+            return 1.0;
+        }
+        recording
+            .loudness()
+            .map_or(1.0, |loudness| loudness.playback_gain())
+    }
+
+    /// Hold a logind delay lock to preserve an active recording before the system goes down.
+    pub async fn inhibit_system_shutdown(self) -> zbus::Result<()> {
+        let login_manager = self.dbus.login_manager_proxy().await?;
+        let mut prepare_for_shutdown = login_manager.receive_prepare_for_shutdown().await?;
+        loop {
+            let inhibitor = login_manager
+                .inhibit(
+                    "shutdown",
+                    env!("CARGO_PKG_NAME"),
+                    "Preserve the piano recording",
+                    "delay",
+                )
+                .await?;
+            if !dbus::wait_for_prepare_for_shutdown(&mut prepare_for_shutdown, true).await? {
+                return Ok(());
+            }
+            info!("System is going down. Finishing the recording...");
+            // Result will be logged by the method.
+            let _ = self
+                .stop_recorder(StopRecorderParams {
+                    play_feedback: false,
+                })
+                .await;
+            drop(inhibitor);
+
+            // Take the lock again if the shutdown is cancelled.
+            if !dbus::wait_for_prepare_for_shutdown(&mut prepare_for_shutdown, false).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Periodically check free space of the recordings directory.
+    pub async fn watch_disk_space(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.disk_watchdog.check_interval_secs));
+        let mut was_low = false;
+        loop {
+            select! {
+                _ = interval.tick() => {}
+                _ = self.shutdown_notify.notified() => break,
+            }
+            let mut free_mib = match self.recording_storage.free_space_mib() {
+                Ok(free_mib) => free_mib,
+                Err(e) => {
+                    error!("Unable to check free disk space: {e}");
+                    continue;
+                }
+            };
+            if self.disk_watchdog.cleanup_recordings {
+                let mut removed = false;
+                while free_mib < self.disk_watchdog.min_free_mib {
+                    match self.recording_storage.remove_oldest().await {
+                        Ok(true) => removed = true,
+                        Ok(false) => break,
+                        Err(e) => {
+                            error!("Failed to remove the oldest recording: {e}");
+                            break;
+                        }
+                    }
+                    match self.recording_storage.free_space_mib() {
+                        Ok(new_free_mib) => free_mib = new_free_mib,
+                        Err(_) => break,
+                    }
+                }
+                if removed {
+                    self.event_broadcaster
+                        .send(PianoEvent::OldRecordingsRemoved);
+                }
+            }
+
+            let is_low = free_mib < self.disk_watchdog.min_free_mib;
+            if is_low && !was_low {
+                warn!("Free disk space dropped to {free_mib} MiB, new recordings are refused");
+                self.event_broadcaster.send(PianoEvent::DiskSpaceLow);
+            } else if !is_low && was_low {
+                info!("Free disk space restored ({free_mib} MiB)");
+            }
+            was_low = is_low;
+        }
+    }
+
+    /// Follow plugging in and removing of the monitor output to route the playback.
+    pub async fn route_output_continuously(self) {
+        let Some(monitor_output) = self.monitor_output.clone() else {
             return;
+        };
+        let mut availability = monitor_output.subscribe_availability();
+        loop {
+            select! {
+                result = availability.changed() => {
+                    if result.is_err() {
+                        break;
+                    }
+                    self.route_output().await;
+                }
+                _ = self.shutdown_notify.notified() => break,
+            }
         }
-        let source = self.sounds.get(sound);
+    }
+
+    /// Play `sound` using the secondary sink. While the player is not initialized
+    /// (e.g. the audio device is held by an A2DP source), the fallback output is used.
+    pub async fn play_sound(&self, sound: Sound) {
+        let output = if self.has_initialized(AudioObject::Player).await {
+            AudioOutput::Piano
+        } else {
+            match self.config.sounds_fallback_output {
+                Some(SoundsFallbackOutput::Monitor) => AudioOutput::Monitor,
+                Some(SoundsFallbackOutput::Snapcast) => AudioOutput::Snapcast,
+                None => return,
+            }
+        };
+        let source = match self.sounds.get(sound).await {
+            Ok(source) => source,
+            Err(e) => return error!("Failed to load sound \"{sound}\": {e}"),
+        };
+        let volume = self.prefs.read().await.piano.sounds_volume;
+        if let Err(e) = self.play_secondary_on(output, source, volume).await {
+            warn!("Failed to play sound \"{sound}\" on the {output} output: {e}");
+        }
+    }
+
+    /// Play `source` (e.g. a voice announcement) along with the recordings
+    /// using the secondary sink. The volume of sounds is applied.
+    pub async fn play_secondary(&self, source: AudioSource) -> AudioResult<(), PlayerError> {
+        let volume = self.prefs.read().await.piano.sounds_volume;
+        self.play_secondary_with_volume(source, volume).await
+    }
+
+    /// Same as [Piano::play_secondary], but with the given `volume`.
+    pub async fn play_secondary_with_volume(
+        &self,
+        source: AudioSource,
+        volume: f32,
+    ) -> AudioResult<(), PlayerError> {
+        self.play_secondary_on(AudioOutput::Piano, source, volume)
+            .await
+    }
+
+    async fn play_secondary_on(
+        &self,
+        output: AudioOutput,
+        source: AudioSource,
+        volume: f32,
+    ) -> AudioResult<(), PlayerError> {
         let props = PlaybackProperties {
             secondary: true,
-            volume: self.prefs.read().await.piano.sounds_volume,
+            volume,
+            output,
             ..Default::default()
         };
-        let result = self
-            .call_player(|player| async { player.play(source, props).await }.boxed())
-            .await;
-        if let Err(e) = result {
-            warn!("Failed to play sound \"{sound}\": {e}");
-        }
+        self.call_player_on(output, |player| {
+            async { player.play(source, props).await }.boxed()
+        })
+        .await
     }
 
+    /// Call the player of the active (primary) output.
     async fn call_player<T, F>(&self, f: F) -> AudioResult<T, PlayerError>
+    where
+        F: FnOnce(&mut Player) -> BoxFuture<Result<T, PlayerError>>,
+    {
+        let output = self.active_route.lock().await.primary();
+        self.call_player_on(output, f).await
+    }
+
+    /// Apply a playback control command to all active outputs.
+    /// Returns the result of the primary one, errors of the mirrors are only logged.
+    async fn control_players<T, F>(&self, f: F) -> AudioResult<T, PlayerError>
+    where
+        F: Fn(&mut Player) -> BoxFuture<Result<T, PlayerError>>,
+    {
+        let route = self.active_route.lock().await.clone();
+        let result = self.call_player_on(route.primary(), &f).await?;
+        for output in route.mirrors() {
+            if let Err(e) = self.call_player_on(output, &f).await {
+                warn!("Failed to control the playback on the {output} output: {e}");
+            }
+        }
+        Ok(result)
+    }
+
+    async fn call_player_on<T, F>(&self, output: AudioOutput, f: F) -> AudioResult<T, PlayerError>
     where
         // Using [BoxFuture] because of a problem with the closure
         // lifetimes when passing a reference in the parameters.
         F: FnOnce(&mut Player) -> BoxFuture<Result<T, PlayerError>>,
     {
+        match output {
+            AudioOutput::Piano => {}
+            AudioOutput::Monitor => {
+                return match &self.monitor_output {
+                    Some(monitor_output) => monitor_output.call_player(f).await,
+                    None => Err(AudioError::MonitorNotConnected),
+                }
+                .inspect_err(|_| metrics::increment(Counter::PlayerErrors));
+            }
+            AudioOutput::Snapcast => {
+                return match &self.snapcast_output {
+                    Some(snapcast_output) => snapcast_output.call_player(f).await,
+                    None => Err(AudioError::SnapcastNotConfigured),
+                }
+                .inspect_err(|_| metrics::increment(Counter::PlayerErrors));
+            }
+        }
         let mut inner_lock = self.inner.lock().await;
-        let player = inner_lock
-            .as_mut()
-            .ok_or(AudioError::PianoNotConnected)?
-            .player
-            .as_mut()
-            .ok_or(AudioError::NotInitialized(AudioObject::Player))?;
-        f(player).await.map_err(AudioError::Error)
+        let inner = inner_lock.as_mut().ok_or(AudioError::PianoNotConnected)?;
+        let device_released = inner.device.is_none();
+        let Some(player) = inner.player.as_mut() else {
+            return Err(self
+                .not_initialized(AudioObject::Player, device_released)
+                .await);
+        };
+        let result = f(player).await;
+        // Otherwise every call fails until the supervisor notices it.
+        if matches!(result, Err(PlayerError::StreamClosed)) && !player.is_alive() {
+            warn!("Playback thread is finished unexpectedly, recreating the player...");
+            inner.player = None;
+            self.init_audio_io(inner).await;
+        }
+        result
+            .inspect_err(|_| metrics::increment(Counter::PlayerErrors))
+            .map_err(AudioError::Error)
     }
 
     async fn call_recorder<T, F>(&self, f: F) -> AudioResult<T, RecordError>
@@ -531,15 +1585,39 @@ impl Piano {
         F: FnOnce(&mut Recorder) -> BoxFuture<Result<T, RecordError>>,
     {
         let mut inner_lock = self.inner.lock().await;
-        let recorder = inner_lock
-            .as_mut()
-            .ok_or(AudioError::PianoNotConnected)?
-            .recorder
-            .as_mut()
-            .ok_or(AudioError::NotInitialized(AudioObject::Recorder))?;
+        let inner = inner_lock.as_mut().ok_or(AudioError::PianoNotConnected)?;
+        let device_released = inner.device.is_none();
+        let Some(recorder) = inner.recorder.as_mut() else {
+            return Err(self
+                .not_initialized(AudioObject::Recorder, device_released)
+                .await);
+        };
         f(recorder).await.map_err(AudioError::Error)
     }
 
+    /// Tells whether `audio_object` is not available because of the connected A2DP sources.
+    async fn not_initialized<E>(
+        &self,
+        audio_object: AudioObject,
+        device_released: bool,
+    ) -> AudioError<E> {
+        let blocked_by = self.audio_blocked_by(device_released).await;
+        if blocked_by.is_empty() {
+            AudioError::NotInitialized(audio_object)
+        } else {
+            AudioError::AudioBlocked(blocked_by)
+        }
+    }
+
+    /// Names of the A2DP sources which hold the audio device if it's released.
+    async fn audio_blocked_by(&self, device_released: bool) -> Vec<String> {
+        if device_released {
+            self.a2dp_source_handler.connected_names().await
+        } else {
+            Vec::new()
+        }
+    }
+
     pub async fn handle_udev_event(&self, event: &tokio_udev::Event) -> Option<HandledPianoEvent> {
         if !event
             .subsystem()
@@ -575,21 +1653,56 @@ impl Piano {
                 .unwrap_or(false);
 
             if devpath_matches {
-                *inner = None;
-                self.event_broadcaster.send(PianoEvent::PianoRemoved);
-                info!("Piano removed");
-                drop(inner);
-                let _ = self
-                    .stop_recorder(StopRecorderParams {
-                        play_feedback: false,
-                    })
-                    .await;
+                self.remove(inner).await;
                 return Some(HandledPianoEvent::Remove);
             }
         }
         None
     }
 
+    async fn remove(&self, mut inner: MutexGuard<'_, Option<InnerInitialized>>) {
+        *inner = None;
+        self.event_broadcaster.send(PianoEvent::PianoRemoved);
+        info!(device = "piano", event = "removed"; "Piano removed");
+        drop(inner);
+        let _ = self
+            .stop_recorder(StopRecorderParams {
+                play_feedback: false,
+            })
+            .await;
+    }
+
+    /// Initialize or remove the piano if udev events were missed.
+    async fn resync(&self) {
+        let devpath = self.find_devpath();
+        let inner = self.inner.lock().await;
+        let current_devpath = inner.as_ref().map(|inner| inner.devpath.clone());
+        if devpath == current_devpath {
+            return;
+        }
+        if current_devpath.is_some() {
+            self.remove(inner).await;
+            self.pause_a2dp_playback().await;
+        } else {
+            drop(inner);
+        }
+        if let Some(devpath) = devpath {
+            let init_params = InitParams {
+                after_piano_connected: true,
+            };
+            self.init(devpath, init_params).await;
+        }
+    }
+
+    /// Pause playback because the output device removed.
+    async fn pause_a2dp_playback(&self) {
+        // Failures are logged by the handler, nothing else can be done.
+        let _ = self
+            .a2dp_source_handler
+            .send_media_control_command(&self.dbus, MediaControlCommand::Pause)
+            .await;
+    }
+
     pub async fn init(&self, devpath: OsString, params: InitParams) {
         let mut inner = self.inner.lock().await;
         if inner.is_some() {
@@ -601,12 +1714,12 @@ impl Piano {
             InnerInitialized::new(devpath, &self.assets.path(Asset::PianoRecordingCoverJPEG)).await,
         );
         self.event_broadcaster.send(PianoEvent::PianoConnected);
-        info!("Piano initialized");
+        info!(device = "piano", event = "connected"; "Piano initialized");
 
         if !self.a2dp_source_handler.has_connected().await {
             let self_clone = self.clone();
             // Using separate thread because of FIND_AUDIO_DEVICE_DELAY.
-            tokio::spawn(async move {
+            self.tasks.spawn("piano-audio-init", async move {
                 if params.after_piano_connected {
                     info!("Waiting before initializing the audio...");
                     tokio::time::sleep(FIND_AUDIO_DEVICE_DELAY).await;
@@ -627,7 +1740,12 @@ impl Piano {
         };
 
         if self.a2dp_source_handler.has_connected().await {
-            if inner.device.is_some() {
+            if inner.device.is_none() {
+                info!(
+                    "Audio device is not acquired as it's in use by {}",
+                    self.a2dp_source_handler.connected_names().await.join(", ")
+                );
+            } else {
                 inner.release_audio();
                 self.event_broadcaster.send(PianoEvent::AudioReleased);
                 info!("Audio device released");
@@ -663,8 +1781,20 @@ impl Piano {
         if inner.player.is_none() {
             let shared_inner = Arc::clone(&self.inner);
             let event_broadcaster = self.event_broadcaster.clone();
+            let output_stream_wait = self.backoff.audio_output_stream_wait.exponential();
+            let output_device = self.find_output_device().await;
+            let resample_quality = self.resample_quality;
             // It may take a long time retrying to get the output stream configuration.
-            tokio::spawn(async { Self::init_player(shared_inner, event_broadcaster).await });
+            self.tasks.spawn("player-init", async {
+                Self::init_player(
+                    shared_inner,
+                    event_broadcaster,
+                    output_stream_wait,
+                    output_device,
+                    resample_quality,
+                )
+                .await
+            });
         }
 
         if inner.recorder.is_none() {
@@ -682,35 +1812,43 @@ impl Piano {
         }
     }
 
+    /// If `output_device` is [None], the piano device is used.
     async fn init_player(
         inner: SharedMutex<Option<InnerInitialized>>,
         event_broadcaster: Broadcaster<PianoEvent>,
-    ) {
+        output_stream_wait: backoff::ExponentialBackoff,
+        output_device: Option<cpal::Device>,
+        resample_quality: Option<config::ResampleQuality>,
+    ) -> anyhow::Result<()> {
         info!("Retrieving the default output stream format...");
-        let result =
-            backoff::future::retry(config::backoff::audio_output_stream_wait(), || async {
-                let inner_lock = inner.lock().await;
-                inner_lock
-                    .as_ref()
-                    .and_then(|inner| {
-                        if inner.player.is_none() {
-                            inner.device.clone()
-                        } else {
-                            None
-                        }
-                    })
-                    // We don't need to proceed (by returning `None`) if:
-                    // 1. piano disconnected
-                    // 2. audio device is busy
-                    // 3. player initialized from another thread
-                    .map_or(Err(backoff::Error::permanent(None)), |device| {
-                        device
-                            .default_output_config()
-                            .map(|config| (inner_lock, device, config))
-                            .map_err(|err| backoff::Error::transient(Some(err)))
-                    })
-            })
-            .await;
+        let result = backoff::future::retry(output_stream_wait, || async {
+            let inner_lock = inner.lock().await;
+            inner_lock
+                .as_ref()
+                .and_then(|inner| {
+                    if inner.player.is_none() {
+                        // Piano device is still required to be set
+                        // even if the playback goes to another one.
+                        inner
+                            .device
+                            .clone()
+                            .map(|device| output_device.clone().unwrap_or(device))
+                    } else {
+                        None
+                    }
+                })
+                // We don't need to proceed (by returning `None`) if:
+                // 1. piano disconnected
+                // 2. audio device is busy
+                // 3. player initialized from another thread
+                .map_or(Err(backoff::Error::permanent(None)), |device| {
+                    device
+                        .default_output_config()
+                        .map(|config| (inner_lock, device, config))
+                        .map_err(|err| backoff::Error::transient(Some(err)))
+                })
+        })
+        .await;
 
         match result {
             Ok((mut inner_lock, device, default_stream_config)) => {
@@ -718,19 +1856,134 @@ impl Piano {
                     "Output stream format: {}",
                     audio::stream_info(&default_stream_config)
                 );
-                match Player::new(device, default_stream_config).await {
-                    Ok(player) => {
-                        // Unwrapping because inner checked in the backoff operation
-                        // and it can't be changed as inner is locked.
-                        inner_lock.as_mut().unwrap().player = Some(player);
-                        event_broadcaster.send(PianoEvent::PlayerInitialized);
-                    }
-                    Err(e) => error!("Player initialization failed: {e}"),
-                }
+                let player = Player::new(
+                    PlayerOutput::Device(device, default_stream_config),
+                    resample_quality,
+                )
+                .await
+                .map_err(|err| anyhow!("player initialization failed: {err}"))?;
+                // Unwrapping because inner checked in the backoff operation
+                // and it can't be changed as inner is locked.
+                inner_lock.as_mut().unwrap().player = Some(player);
+                event_broadcaster.send(PianoEvent::PlayerInitialized);
+            }
+            Err(Some(err)) => {
+                return Err(anyhow!("failed to get the default output format: {err}"))
             }
-            Err(Some(err)) => error!("Failed to get the default output format: {err}"),
             Err(None) => warn!("Player initialization skipped as it's not required anymore"),
         }
+        Ok(())
+    }
+
+    /// Find the audio device again and initialize the player and recorder, e.g. if
+    /// the player initialization gave up. Unlike on the piano connection, the player is
+    /// initialized before returning. Running recorder is kept.
+    pub async fn reinit_audio(&self) -> AudioResult<AudioReinitResult, anyhow::Error> {
+        let mut inner_lock = self.inner.lock().await;
+        let inner = inner_lock.as_mut().ok_or(AudioError::PianoNotConnected)?;
+        if self.a2dp_source_handler.has_connected().await {
+            return Err(AudioError::AudioBlocked(
+                self.a2dp_source_handler.connected_names().await,
+            ));
+        }
+        info!("Reinitializing the audio...");
+        let device = self
+            .find_audio_device()
+            .ok_or_else(|| AudioError::Error(anyhow!("audio device is not found")))?;
+        inner.device = Some(device.clone());
+        inner.player = None;
+        let is_recording = match &inner.recorder {
+            Some(recorder) => recorder.status().await.is_some(),
+            None => false,
+        };
+        let recorder_error = if is_recording {
+            None
+        } else {
+            inner.recorder = None;
+            match Recorder::new(
+                self.config.recorder.clone(),
+                device,
+                self.shutdown_notify.clone(),
+            ) {
+                Ok(recorder) => {
+                    inner.recorder = Some(recorder);
+                    self.event_broadcaster.send(PianoEvent::RecorderInitialized);
+                    None
+                }
+                Err(e) => Some(e.to_string()),
+            }
+        };
+        drop(inner_lock);
+
+        let result = Self::init_player(
+            Arc::clone(&self.inner),
+            self.event_broadcaster.clone(),
+            self.backoff.audio_output_stream_wait.exponential(),
+            self.find_output_device().await,
+            self.resample_quality,
+        )
+        .await;
+        let player_error = match result {
+            Ok(()) if self.has_initialized(AudioObject::Player).await => None,
+            Ok(()) => Some("initialization skipped as the audio device changed".to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        let success = player_error.is_none() && recorder_error.is_none();
+        if success {
+            info!("Audio reinitialized");
+        } else {
+            error!("Audio reinitialization failed");
+        }
+        Ok(AudioReinitResult {
+            success,
+            player_error,
+            recorder_error,
+        })
+    }
+
+    /// Drop the current player and initialize a new one.
+    async fn restart_player(&self) -> anyhow::Result<()> {
+        let mut inner_lock = self.inner.lock().await;
+        let inner = inner_lock
+            .as_mut()
+            .ok_or(anyhow!("piano is not connected"))?;
+        if inner.device.is_none() {
+            bail!("audio device is not set");
+        }
+        inner.player = None;
+        self.init_audio_io(inner).await;
+        Ok(())
+    }
+
+    /// Read the recordings cover image again (e.g. if it's changed).
+    pub async fn reload_recording_cover(&self) {
+        let cover = read_recording_cover(&self.assets.path(Asset::PianoRecordingCoverJPEG)).await;
+        if let Some(inner) = self.inner.lock().await.as_mut() {
+            inner.recording_cover_jpeg = cover;
+        }
+    }
+
+    /// Reinitialize the player to use the output device from the preferences.
+    pub async fn switch_output_device(&self) {
+        if !self.has_initialized(AudioObject::Player).await {
+            // Device will be picked up on the next initialization.
+            return;
+        }
+        match self.restart_player().await {
+            Ok(()) => info!("Switching the output device..."),
+            Err(e) => error!("Failed to switch the output device: {e}"),
+        }
+    }
+
+    /// Find the output device set in the preferences.
+    /// Returns [None] if it's not set or not found, so the piano should be used.
+    async fn find_output_device(&self) -> Option<cpal::Device> {
+        let name = self.prefs.read().await.audio.output_device.clone()?;
+        let device = audio::find_output_device(&name);
+        if device.is_none() {
+            warn!("Output device \"{name}\" is not found, using the piano instead");
+        }
+        device
     }
 
     async fn has_initialized(&self, audio_object: AudioObject) -> bool {
@@ -745,65 +1998,94 @@ impl Piano {
     }
 
     pub fn find_devpath(&self) -> Option<OsString> {
-        let mut enumerator = match tokio_udev::Enumerator::new() {
-            Ok(enumerator) => enumerator,
-            Err(e) => {
-                error!("Failed to set up the udev piano scanner: {e}");
-                return None;
-            }
-        };
+        device::find_sound_card_devpath(&self.config.device_id)
+    }
+
+    fn find_audio_device(&self) -> Option<cpal::Device> {
+        audio::find_device(&self.config.alsa_plugin, &self.config.device_id)
+    }
+}
 
-        let match_result = enumerator
-            .match_subsystem("sound")
-            .and_then(|_| enumerator.match_is_initialized())
-            .and_then(|_| enumerator.match_attribute("id", &self.config.device_id));
+impl DevicePlugin for Piano {
+    fn name(&self) -> &'static str {
+        "piano"
+    }
 
-        if let Err(e) = match_result {
-            error!("Failed to apply filters to the udev piano scanner: {e}");
-        } else {
-            match enumerator.scan_devices() {
-                Ok(mut devices) => {
-                    return devices.next().map(|device| device.devpath().to_os_string());
-                }
-                Err(e) => error!("Failed to scan /sys for the piano: {e}"),
+    fn init(&self) -> BoxFuture<'_, ()> {
+        async {
+            if let Some(devpath) = self.find_devpath() {
+                let init_params = InitParams {
+                    after_piano_connected: false,
+                };
+                Piano::init(self, devpath, init_params).await;
             }
         }
-        None
+        .boxed()
     }
 
-    fn find_audio_device(&self) -> Option<cpal::Device> {
-        let devices = match cpal::default_host().devices() {
-            Ok(devices) => devices,
-            Err(e) => {
-                error!("Failed to list the audio devices: {e}");
-                return None;
+    fn udev_subsystems(&self) -> &'static [&'static str] {
+        &["sound"]
+    }
+
+    fn handle_udev_event<'a>(&'a self, event: &'a tokio_udev::Event) -> LocalBoxFuture<'a, ()> {
+        async {
+            if let Some(HandledPianoEvent::Remove) = Piano::handle_udev_event(self, event).await {
+                self.pause_a2dp_playback().await;
             }
-        };
-        for device in devices {
-            match device.name() {
-                Ok(name) => {
-                    if name.starts_with(&format!(
-                        "{}:CARD={}",
-                        self.config.alsa_plugin, self.config.device_id
-                    )) {
-                        return Some(device);
-                    }
+        }
+        .boxed_local()
+    }
+
+    fn resync(&self) -> BoxFuture<'_, ()> {
+        Piano::resync(self).boxed()
+    }
+
+    fn status(&self) -> BoxFuture<'_, Value> {
+        async {
+            match Piano::status(self).await {
+                Ok(status) => async_graphql::to_value(status).unwrap_or_default(),
+                Err(e) => {
+                    error!("Failed to get the piano status: {e}");
+                    Value::Null
                 }
-                Err(e) => error!("Failed to get an audio device name: {e}"),
             }
         }
-        None
+        .boxed()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        async {
+            // Result will be logged by the method.
+            let _ = self
+                .stop_recorder(StopRecorderParams {
+                    play_feedback: false,
+                })
+                .await;
+        }
+        .boxed()
     }
 }
 
-impl Drop for Piano {
-    fn drop(&mut self) {
-        // Preserve recording (if recorder is active) on latest instance drop (at server shutdown).
-        if Arc::strong_count(&self.inner) == 1 {
-            let _ = executor::block_on(self.stop_recorder(StopRecorderParams {
-                play_feedback: false,
-            }));
+impl Supervised for Piano {
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Player
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        async {
+            // There is nothing to restart if the player is not initialized.
+            self.inner
+                .lock()
+                .await
+                .as_ref()
+                .and_then(|inner| inner.player.as_ref())
+                .map_or(true, Player::is_alive)
         }
+        .boxed()
+    }
+
+    fn restart(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.restart_player().boxed()
     }
 }
 
@@ -823,34 +2105,9 @@ struct InnerInitialized {
 
 impl InnerInitialized {
     async fn new(devpath: OsString, recording_cover_jpeg: &Path) -> Self {
-        let recording_cover_jpeg = match fs::try_exists(recording_cover_jpeg).await {
-            Ok(exists) => {
-                if exists {
-                    fs::read(recording_cover_jpeg)
-                        .await
-                        .inspect(|bytes| {
-                            info!("Recordings cover image loaded ({} kB)", bytes.len() / 1000);
-                        })
-                        .map_err(|e| {
-                            let path_str = recording_cover_jpeg.to_string_lossy();
-                            error!("Failed to read {path_str}: {e}")
-                        })
-                        .ok()
-                } else {
-                    None
-                }
-            }
-            Err(e) => {
-                error!(
-                    "Failed to check existence of {}: {e}",
-                    recording_cover_jpeg.to_string_lossy()
-                );
-                None
-            }
-        };
         Self {
             devpath,
-            recording_cover_jpeg,
+            recording_cover_jpeg: read_recording_cover(recording_cover_jpeg).await,
             last_played_recording: None,
             device: None,
             player: None,
@@ -864,3 +2121,32 @@ impl InnerInitialized {
         self.recorder = None;
     }
 }
+
+/// Returns [None] if the image doesn't exist or can't be read.
+async fn read_recording_cover(path: &Path) -> Option<Vec<u8>> {
+    match fs::try_exists(path).await {
+        Ok(exists) => {
+            if exists {
+                fs::read(path)
+                    .await
+                    .inspect(|bytes| {
+                        info!("Recordings cover image loaded ({} kB)", bytes.len() / 1000);
+                    })
+                    .map_err(|e| {
+                        let path_str = path.to_string_lossy();
+                        error!("Failed to read {path_str}: {e}")
+                    })
+                    .ok()
+            } else {
+                None
+            }
+        }
+        Err(e) => {
+            error!(
+                "Failed to check existence of {}: {e}",
+                path.to_string_lossy()
+            );
+            None
+        }
+    }
+}
@@ -1,23 +1,39 @@
 use std::{
     cmp,
     fmt::{self, Display, Formatter},
+    fs::File,
+    io::BufReader,
     path::{Path, PathBuf},
-    time::Duration,
+    time::{Duration, UNIX_EPOCH},
 };
 
-use async_graphql::{ComplexObject, SimpleObject};
-use chrono::DateTime;
+use anyhow::{anyhow, bail};
+use async_graphql::{ComplexObject, Context, SimpleObject};
+use chrono::{DateTime, FixedOffset};
+use claxon::FlacReader;
 use futures::future;
-use log::{error, info};
-use tokio::{fs, io};
+use log::{error, info, warn};
+use nix::sys::statvfs::statvfs;
+use tokio::{fs, io, task};
 
 use super::PianoEvent;
 use crate::{
-    audio::recorder::RECORDING_EXTENSION,
-    core::{human_date_ago, human_duration, Broadcaster, HumanDateParams, SortOrder},
+    audio::{
+        loudness::Loudness,
+        recorder::RECORDING_EXTENSION,
+        waveform::{Waveform, WaveformError},
+    },
+    core::{
+        human_date_ago, human_duration, task::TaskManager, Broadcaster, HumanDateParams, SortOrder,
+    },
     graphql::GraphQLError,
+    recording_upload::RecordingUploadStatus,
+    storage::{Bookmark, Storage, StorageError},
 };
 
+/// Appended to the file name of an unsaved recording which can't be salvaged.
+const CORRUPTED_SUFFIX: &str = ".corrupted";
+
 #[derive(Debug, strum::AsRefStr, thiserror::Error)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum RecordingStorageError {
@@ -25,27 +41,46 @@ pub enum RecordingStorageError {
     RecordingNotExists,
     #[error("Unable to read a recording: {0}")]
     FailedToRead(ReadRecordingError),
+    #[error("Unable to write the FLAC tag ({0})")]
+    FailedToWrite(metaflac::Error),
+    #[error("Title must not be blank")]
+    InvalidTitle,
     #[error("File system error ({0})")]
     FileSystemError(io::Error),
 }
 
 impl GraphQLError for RecordingStorageError {}
 
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum RecordingWaveformError {
+    #[error(transparent)]
+    StorageError(StorageError),
+    #[error("Unable to analyze the recording: {0}")]
+    AnalysisFailed(WaveformError),
+    #[error("Analysis task failed: {0}")]
+    TaskFailed(task::JoinError),
+}
+
+impl GraphQLError for RecordingWaveformError {}
+
 #[derive(Clone)]
 pub struct RecordingStorage {
     dir: PathBuf,
     max_recordings: u16,
+    tasks: TaskManager,
 }
 
 impl RecordingStorage {
-    pub(super) fn new(dir: &Path, max_recordings: u16) -> Self {
+    pub fn new(dir: &Path, max_recordings: u16, tasks: TaskManager) -> Self {
         Self {
             dir: dir.to_owned(),
             max_recordings,
+            tasks,
         }
     }
 
-    pub(super) async fn is_recording(&self) -> Result<bool, RecordingStorageError> {
+    pub async fn is_recording(&self) -> Result<bool, RecordingStorageError> {
         fs::try_exists(&self.unsaved_path())
             .await
             .map_err(RecordingStorageError::FileSystemError)
@@ -108,7 +143,7 @@ impl RecordingStorage {
 
     /// Returns path of the new file to create (it will **not** be created)
     /// or [None] if recording is already in process.
-    pub(super) async fn prepare_new(&self) -> Result<Option<PathBuf>, RecordingStorageError> {
+    pub async fn prepare_new(&self) -> Result<Option<PathBuf>, RecordingStorageError> {
         let path = self.unsaved_path();
         if fs::try_exists(&path)
             .await
@@ -120,10 +155,89 @@ impl RecordingStorage {
         }
     }
 
-    /// Returns [None] if recording is not in process.
+    /// Returns [None] if recording is not in process. Unlike [Self::save_new],
+    /// the old recordings are removed and the new one is analyzed in the background.
     pub(super) async fn preserve_new(
         &self,
         event_broadcaster: Broadcaster<PianoEvent>,
+    ) -> Result<Option<Recording>, RecordingStorageError> {
+        let Some(recording) = self.save_new().await? else {
+            return Ok(None);
+        };
+
+        let self_clone = self.clone();
+        let cleanup_event_broadcaster = event_broadcaster.clone();
+        self.tasks.spawn("old-recordings-cleanup", async move {
+            if self_clone.remove_old_if_limit_reached().await != 0 {
+                cleanup_event_broadcaster.send(PianoEvent::OldRecordingsRemoved);
+            }
+        });
+        let analyzed_path = recording.flac_path.clone();
+        self.tasks.spawn("recording-loudness-analysis", async move {
+            task::spawn_blocking(move || {
+                Loudness::analyze_flac(&analyzed_path)
+                    .and_then(|loudness| loudness.write_tag(&analyzed_path))
+            })
+            .await
+            .map_err(|e| anyhow!("analysis panicked: {e}"))??;
+            event_broadcaster.send(PianoEvent::RecordingAnalyzed);
+            anyhow::Ok(())
+        });
+        Ok(Some(recording))
+    }
+
+    /// Give the new recording its permanent name.
+    /// Returns [None] if recording is not in process.
+    pub async fn save_new(&self) -> Result<Option<Recording>, RecordingStorageError> {
+        self.save_new_as(chrono::Local::now().timestamp_millis())
+            .await
+    }
+
+    /// Save the new recording which is left if the server crashed while recording,
+    /// because it blocks the new recordings. Must be called before the recorder starts.
+    /// If the recording can't be salvaged, it's removed.
+    pub async fn recover_unsaved(&self) {
+        let path = self.unsaved_path();
+        let modified = match fs::metadata(&path).await {
+            Ok(metadata) => metadata.modified(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => Err(e),
+        };
+        warn!("Found an unsaved recording, probably the server crashed while recording");
+        let salvaged_path = path.clone();
+        let result = task::spawn_blocking(move || salvage(&salvaged_path))
+            .await
+            .map_err(|e| anyhow!("salvaging panicked: {e}"))
+            .and_then(|result| result);
+        if let Err(e) = result {
+            // Kept for the manual recovery, but out of the way of new recordings.
+            let mut corrupted_path = path.clone().into_os_string();
+            corrupted_path.push(CORRUPTED_SUFFIX);
+            error!(
+                "Unable to salvage the unsaved recording, moving it to {}: {e}",
+                corrupted_path.to_string_lossy()
+            );
+            if let Err(e) = fs::rename(&path, &corrupted_path).await {
+                error!("Failed to move {}: {e}", path.to_string_lossy());
+            }
+            return;
+        }
+        // Recording was stopped at the last modification.
+        let stopped_at = modified
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(chrono::Local::now().timestamp_millis(), |since_epoch| {
+                since_epoch.as_millis() as i64
+            });
+        match self.save_new_as(stopped_at).await {
+            Ok(_) => info!("Unsaved recording is salvaged"),
+            Err(e) => error!("Failed to save the salvaged recording: {e}"),
+        }
+    }
+
+    async fn save_new_as(
+        &self,
+        timestamp_millis: i64,
     ) -> Result<Option<Recording>, RecordingStorageError> {
         let path = self.unsaved_path();
         if !fs::try_exists(&path)
@@ -133,37 +247,99 @@ impl RecordingStorage {
             return Ok(None);
         }
 
-        let new_path = path
-            .parent()
-            .map(|dir| {
-                let mut path = dir.to_owned();
-                path.push(format!(
-                    "{}{RECORDING_EXTENSION}",
-                    chrono::Local::now().timestamp_millis()
-                ));
-                path
-            })
-            .ok_or(RecordingStorageError::FileSystemError(io::Error::other(
-                "incorrect parent directory",
-            )))?;
+        let new_path = self.path(&timestamp_millis.to_string());
         fs::rename(path, &new_path)
             .await
             .map_err(RecordingStorageError::FileSystemError)?;
         info!("New recording saved to {}", new_path.to_string_lossy());
-
-        let self_clone = self.clone();
-        tokio::spawn(async move {
-            if self_clone.remove_old_if_limit_reached().await != 0 {
-                event_broadcaster.send(PianoEvent::OldRecordingsRemoved);
-            }
-        });
         Recording::new(&new_path)
             .map(Some)
             .map_err(RecordingStorageError::FailedToRead)
     }
 
+    /// Move the `file` (e.g. an uploaded one) to the recordings, replacing the existing one
+    /// with the same ID. It must be on the same file system and be a valid FLAC file.
+    pub async fn import(
+        &self,
+        file: &Path,
+        recording_id: i64,
+    ) -> Result<Recording, RecordingStorageError> {
+        let tag = metaflac::Tag::read_from_path(file).map_err(|e| {
+            RecordingStorageError::FailedToRead(ReadRecordingError::ReadTagError(e))
+        })?;
+        if tag.get_streaminfo().is_none() {
+            return Err(RecordingStorageError::FailedToRead(
+                ReadRecordingError::NoStreamInfo,
+            ));
+        }
+        let path = self.path(&recording_id.to_string());
+        fs::rename(file, &path)
+            .await
+            .map_err(RecordingStorageError::FileSystemError)?;
+        info!("Recording imported to {}", path.to_string_lossy());
+        Recording::new(&path).map_err(RecordingStorageError::FailedToRead)
+    }
+
+    pub async fn remove(&self, recording_id: i64) -> Result<(), RecordingStorageError> {
+        let path = self.path(&recording_id.to_string());
+        match fs::remove_file(&path).await {
+            Ok(()) => {
+                info!("Recording {} removed", path.to_string_lossy());
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(RecordingStorageError::RecordingNotExists)
+            }
+            Err(e) => Err(RecordingStorageError::FileSystemError(e)),
+        }
+    }
+
+    /// Set the TITLE vorbis comment of the recording. Returns the updated recording.
+    pub async fn rename(
+        &self,
+        recording_id: i64,
+        title: &str,
+    ) -> Result<Recording, RecordingStorageError> {
+        let title = title.trim().to_string();
+        if title.is_empty() {
+            return Err(RecordingStorageError::InvalidTitle);
+        }
+        let recording = self.get(recording_id).await?;
+        let path = recording.flac_path.clone();
+        task::spawn_blocking(move || {
+            let mut tag = metaflac::Tag::read_from_path(&path)?;
+            tag.vorbis_comments_mut().set_title(vec![title]);
+            tag.save()
+        })
+        .await
+        .map_err(|e| RecordingStorageError::FileSystemError(io::Error::other(e)))?
+        .map_err(RecordingStorageError::FailedToWrite)?;
+        info!("Recording {recording} renamed");
+        self.get(recording_id).await
+    }
+
+    /// Free space of the file system which stores the recordings.
+    pub fn free_space_mib(&self) -> io::Result<u64> {
+        let stat = statvfs(&self.dir).map_err(io::Error::from)?;
+        Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64 / (1024 * 1024))
+    }
+
+    /// Remove the oldest recording, but only if it's not the single one.
+    /// Returns `false` if nothing is removed.
+    pub(super) async fn remove_oldest(&self) -> Result<bool, RecordingStorageError> {
+        let recordings = self.list(SortOrder::Ascending).await?;
+        let [oldest, _, ..] = recordings.as_slice() else {
+            return Ok(false);
+        };
+        fs::remove_file(&oldest.flac_path)
+            .await
+            .map_err(RecordingStorageError::FileSystemError)?;
+        info!("Old recording {oldest} removed to free the disk space");
+        Ok(true)
+    }
+
     /// Returns number of removed recordings.
-    async fn remove_old_if_limit_reached(&self) -> usize {
+    pub async fn remove_old_if_limit_reached(&self) -> usize {
         // List from the newest to the oldest.
         let old_recordings = match self.list(SortOrder::Descending).await {
             Ok(recordings) => recordings.into_iter().skip(self.max_recordings as usize),
@@ -198,6 +374,30 @@ impl RecordingStorage {
     }
 }
 
+/// Set the number of samples which is not written if the encoding is not finished.
+/// Returns an error if there is no audio in `flac_file`.
+fn salvage(flac_file: &Path) -> anyhow::Result<()> {
+    let mut reader = FlacReader::new(BufReader::new(File::open(flac_file)?))?;
+    let mut frames = reader.blocks();
+    let mut total_samples = 0;
+    let mut buffer = Vec::new();
+    // The last frame may be incomplete, so any error means the end.
+    while let Ok(Some(block)) = frames.read_next_or_eof(buffer) {
+        total_samples += u64::from(block.duration());
+        buffer = block.into_buffer();
+    }
+    if total_samples == 0 {
+        bail!("there is no audio");
+    }
+
+    let mut tag = metaflac::Tag::read_from_path(flac_file)?;
+    let mut stream_info = tag.get_streaminfo().cloned().unwrap_or_default();
+    stream_info.total_samples = total_samples;
+    tag.set_streaminfo(stream_info);
+    tag.save()?;
+    Ok(())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ReadRecordingError {
     #[error("Unable to read a FLAC tag ({0})")]
@@ -208,6 +408,14 @@ pub enum ReadRecordingError {
     InvalidFileName,
 }
 
+/// Ordering of the recordings by the play statistics.
+/// Never played recordings are placed at the end.
+#[derive(Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+pub enum RecordingSort {
+    RecentlyPlayed,
+    MostPlayed,
+}
+
 #[derive(Clone, SimpleObject)]
 #[graphql(complex, name = "PianoRecording")]
 pub struct Recording {
@@ -216,6 +424,11 @@ pub struct Recording {
     creation_time: DateTime<chrono::Local>,
     #[graphql(skip)]
     duration: Duration,
+    /// TITLE vorbis comment. Recorder sets it to the creation date,
+    /// and it can be changed using `renameRecording`.
+    title: Option<String>,
+    /// [None] if the recording is not analyzed yet.
+    loudness: Option<Loudness>,
 }
 
 impl Recording {
@@ -244,16 +457,72 @@ impl Recording {
             duration: Duration::from_millis(
                 stream_info.total_samples * 1000 / stream_info.sample_rate as u64,
             ),
+            title: tag
+                .vorbis_comments()
+                .and_then(|comments| comments.title()?.first().cloned()),
+            loudness: Loudness::from_tag(&tag),
         })
     }
 
-    fn id(&self) -> i64 {
+    pub fn id(&self) -> i64 {
         self.creation_time.timestamp_millis()
     }
 
     pub fn human_creation_date(&self, params: HumanDateParams) -> String {
         human_date_ago(self.creation_time, params)
     }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn loudness(&self) -> Option<Loudness> {
+        self.loudness
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Returns the cached waveform. If it's not cached yet,
+    /// the recording is analyzed, so it can take a long time.
+    pub async fn waveform(&self, storage: &Storage) -> Result<Waveform, RecordingWaveformError> {
+        if let Some(waveform) = storage
+            .waveform(self.id())
+            .await
+            .map_err(RecordingWaveformError::StorageError)?
+        {
+            return Ok(waveform);
+        }
+        let flac_path = self.flac_path.clone();
+        let waveform = task::spawn_blocking(move || Waveform::analyze_flac(&flac_path))
+            .await
+            .map_err(RecordingWaveformError::TaskFailed)?
+            .map_err(RecordingWaveformError::AnalysisFailed)?;
+        storage
+            .set_waveform(self.id(), &waveform)
+            .await
+            .map_err(RecordingWaveformError::StorageError)?;
+        Ok(waveform)
+    }
+
+    /// Title which can be used as the file name (without the extension).
+    /// If the title is not set, the creation date is used.
+    pub fn filename(&self) -> String {
+        match &self.title {
+            Some(title) => title
+                .chars()
+                .map(|char| match char {
+                    '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+                    char if char.is_control() => '-',
+                    char => char,
+                })
+                .collect(),
+            None => self.human_creation_date(HumanDateParams {
+                filename_safe: true,
+            }),
+        }
+    }
 }
 
 #[ComplexObject]
@@ -281,6 +550,59 @@ impl Recording {
     async fn api_endpoint(&self) -> String {
         format!("/api/piano/recording/{}", self.id())
     }
+
+    /// Peaks to draw a seek bar. They are calculated when a recording is saved,
+    /// the older recordings are analyzed on the first request.
+    #[graphql(name = "waveform")]
+    async fn waveform_gql(&self, ctx: &Context<'_>) -> async_graphql::Result<Waveform> {
+        self.waveform(ctx.data::<Storage>()?)
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Named positions ordered by the position (see `addBookmark`).
+    async fn bookmarks(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Bookmark>> {
+        ctx.data::<Storage>()?
+            .bookmarks(self.id())
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// How many times the recording was played.
+    async fn play_count(&self, ctx: &Context<'_>) -> async_graphql::Result<u32> {
+        Ok(ctx
+            .data::<Storage>()?
+            .play_stats(self.id())
+            .await
+            .map_err(GraphQLError::extend)?
+            .map(|stats| stats.play_count)
+            .unwrap_or_default())
+    }
+
+    /// Null if the recording has never been played.
+    async fn last_played_at(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<DateTime<FixedOffset>>> {
+        Ok(ctx
+            .data::<Storage>()?
+            .play_stats(self.id())
+            .await
+            .map_err(GraphQLError::extend)?
+            .map(|stats| stats.last_played_at))
+    }
+
+    /// Result of the last upload to the `recording_upload` target.
+    /// Null if uploading is disabled or it has not been finished yet.
+    async fn upload(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<RecordingUploadStatus>> {
+        ctx.data::<Storage>()?
+            .upload_status(self.id())
+            .await
+            .map_err(GraphQLError::extend)
+    }
 }
 
 impl Display for Recording {
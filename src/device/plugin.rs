@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use async_graphql::{Json, SimpleObject, Value};
+use futures::{
+    future::{self, BoxFuture, LocalBoxFuture},
+    FutureExt,
+};
+
+/// Device managed by [DeviceRegistry]. Register an implementation in `App::new`
+/// to make the device initialized, handle udev events, expose its status and
+/// shut it down gracefully.
+pub trait DevicePlugin: Send + Sync {
+    /// Unique identifier of the device.
+    fn name(&self) -> &'static str;
+
+    /// Called once at the server startup.
+    fn init(&self) -> BoxFuture<'_, ()> {
+        future::ready(()).boxed()
+    }
+
+    /// udev subsystems which events will be passed to [DevicePlugin::handle_udev_event].
+    fn udev_subsystems(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// It's a local future, because [tokio_udev::Event] can not be sent between threads.
+    fn handle_udev_event<'a>(&'a self, _event: &'a tokio_udev::Event) -> LocalBoxFuture<'a, ()> {
+        future::ready(()).boxed_local()
+    }
+
+    /// Called when udev events might be missed (e.g. the monitor was restarted),
+    /// so the device should check whether it was plugged in or removed.
+    fn resync(&self) -> BoxFuture<'_, ()> {
+        future::ready(()).boxed()
+    }
+
+    /// Arbitrary data describing the current device state.
+    fn status(&self) -> BoxFuture<'_, Value>;
+
+    /// Called once at the graceful shutdown.
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        future::ready(()).boxed()
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DeviceStatus {
+    name: &'static str,
+    status: Json<Value>,
+}
+
+#[derive(Clone, Default)]
+pub struct DeviceRegistry {
+    plugins: Vec<Arc<dyn DevicePlugin>>,
+}
+
+impl DeviceRegistry {
+    pub fn register(&mut self, plugin: impl DevicePlugin + 'static) {
+        self.plugins.push(Arc::new(plugin));
+    }
+
+    /// Initialize all devices in the order they were registered.
+    pub async fn init(&self) {
+        for plugin in &self.plugins {
+            plugin.init().await;
+        }
+    }
+
+    /// Returns subsystems of all devices without duplicates.
+    pub fn udev_subsystems(&self) -> Vec<&'static str> {
+        let mut subsystems: Vec<_> = self
+            .plugins
+            .iter()
+            .flat_map(|plugin| plugin.udev_subsystems())
+            .copied()
+            .collect();
+        subsystems.sort_unstable();
+        subsystems.dedup();
+        subsystems
+    }
+
+    /// Pass `event` to the devices which are interested in its subsystem.
+    pub async fn handle_udev_event(&self, event: &tokio_udev::Event) {
+        let Some(subsystem) = event.subsystem() else {
+            return;
+        };
+        for plugin in &self.plugins {
+            if plugin
+                .udev_subsystems()
+                .iter()
+                .any(|plugin_subsystem| subsystem == *plugin_subsystem)
+            {
+                plugin.handle_udev_event(event).await;
+            }
+        }
+    }
+
+    pub async fn resync(&self) {
+        for plugin in &self.plugins {
+            plugin.resync().await;
+        }
+    }
+
+    pub async fn statuses(&self) -> Vec<DeviceStatus> {
+        future::join_all(self.plugins.iter().map(|plugin| async {
+            DeviceStatus {
+                name: plugin.name(),
+                status: Json(plugin.status().await),
+            }
+        }))
+        .await
+    }
+
+    /// Shut down all devices in the order they were registered.
+    pub async fn shutdown(&self) {
+        for plugin in &self.plugins {
+            plugin.shutdown().await;
+        }
+    }
+}
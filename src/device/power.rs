@@ -0,0 +1,136 @@
+use std::sync::{
+    atomic::{self, AtomicBool},
+    Arc,
+};
+
+use async_graphql::SimpleObject;
+use futures::{stream, StreamExt};
+use log::{error, info, warn};
+
+use crate::{
+    config,
+    core::Broadcaster,
+    dbus::{DBus, UPowerDeviceProxy},
+    SharedRwLock,
+};
+
+/// UPower states meaning that the system is powered by the battery.
+const DISCHARGING_STATES: [u32; 2] = [2, 6];
+
+#[derive(Clone, Copy, PartialEq, SimpleObject)]
+pub struct PowerStatus {
+    /// Battery charge from 0.0 to 100.0.
+    percentage: f64,
+    on_battery: bool,
+    /// [None] if it's unknown or the battery is not discharging.
+    time_to_empty_secs: Option<i64>,
+}
+
+impl PowerStatus {
+    pub fn percentage(&self) -> f64 {
+        self.percentage
+    }
+
+    pub fn on_battery(&self) -> bool {
+        self.on_battery
+    }
+
+    /// Returns [None] if there is no battery.
+    async fn read(proxy: &UPowerDeviceProxy<'_>) -> zbus::Result<Option<Self>> {
+        if !proxy.is_present().await? {
+            return Ok(None);
+        }
+        let time_to_empty = proxy.time_to_empty().await?;
+        Ok(Some(Self {
+            percentage: proxy.percentage().await?,
+            on_battery: DISCHARGING_STATES.contains(&proxy.state().await?),
+            time_to_empty_secs: (time_to_empty > 0).then_some(time_to_empty),
+        }))
+    }
+}
+
+/// Monitors the system battery using UPower and powers off the system when it's running out.
+#[derive(Clone)]
+pub struct PowerMonitor {
+    config: config::Power,
+    dbus: DBus,
+    /// [None] if there is no battery or it's not read yet.
+    status: SharedRwLock<Option<PowerStatus>>,
+    shutdown_requested: Arc<AtomicBool>,
+    pub status_broadcaster: Broadcaster<PowerStatus>,
+}
+
+impl PowerMonitor {
+    pub fn new(config: config::Power, dbus: DBus, broadcaster_capacity: usize) -> Self {
+        Self {
+            config,
+            dbus,
+            status: Arc::default(),
+            shutdown_requested: Arc::default(),
+            status_broadcaster: Broadcaster::new("power", broadcaster_capacity),
+        }
+    }
+
+    pub async fn status(&self) -> Option<PowerStatus> {
+        *self.status.read().await
+    }
+
+    /// Follow the battery changes. Never returns on success.
+    pub async fn run(self) -> zbus::Result<()> {
+        let proxy = self.dbus.upower_device_proxy().await?;
+        let changes = stream::select_all([
+            proxy.receive_is_present_changed().await.map(|_| ()).boxed(),
+            proxy.receive_percentage_changed().await.map(|_| ()).boxed(),
+            proxy.receive_state_changed().await.map(|_| ()).boxed(),
+            proxy
+                .receive_time_to_empty_changed()
+                .await
+                .map(|_| ())
+                .boxed(),
+        ]);
+        // Read the initial status before waiting for the changes.
+        let mut updates = stream::once(async {}).chain(changes).boxed();
+        while let Some(()) = updates.next().await {
+            match PowerStatus::read(&proxy).await {
+                Ok(status) => self.update(status).await,
+                Err(e) => error!("Failed to read the battery status: {e}"),
+            }
+        }
+        Err(zbus::Error::Failure(
+            "battery changes stream closed".to_string(),
+        ))
+    }
+
+    async fn update(&self, status: Option<PowerStatus>) {
+        let mut current = self.status.write().await;
+        if *current == status {
+            return;
+        }
+        *current = status;
+        drop(current);
+        let Some(status) = status else {
+            info!("Battery is not present");
+            return;
+        };
+        self.status_broadcaster.send(status);
+
+        if status.on_battery
+            && status.percentage <= self.config.shutdown_percentage
+            && !self.shutdown_requested.swap(true, atomic::Ordering::SeqCst)
+        {
+            warn!(
+                "Battery charge dropped to {:.0}%. Powering off...",
+                status.percentage
+            );
+            let result = match self.dbus.login_manager_proxy().await {
+                Ok(proxy) => proxy.power_off(false).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Failed to power off: {e}");
+                self.shutdown_requested
+                    .store(false, atomic::Ordering::SeqCst);
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use log::info;
+
+use super::piano::{AudioError, AudioResult};
+use crate::{
+    audio::player::{Player, PlayerError, PlayerOutput},
+    config, SharedMutex,
+};
+
+/// Multi-room speakers fed by a Snapcast server. Audio is streamed to a `tcp` source of the
+/// server, which must be in the server mode (e.g. `tcp://0.0.0.0:4953?name=Piano&mode=server`).
+///
+/// Connection is established on the first use and re-established if it was broken.
+#[derive(Clone)]
+pub struct SnapcastOutput {
+    config: config::Snapcast,
+    resample_quality: Option<config::ResampleQuality>,
+    player: SharedMutex<Option<Player>>,
+}
+
+impl SnapcastOutput {
+    pub fn new(
+        config: config::Snapcast,
+        resample_quality: Option<config::ResampleQuality>,
+    ) -> Self {
+        Self {
+            config,
+            resample_quality,
+            player: Arc::default(),
+        }
+    }
+
+    pub async fn call_player<T, F>(&self, f: F) -> AudioResult<T, PlayerError>
+    where
+        F: FnOnce(&mut Player) -> BoxFuture<Result<T, PlayerError>>,
+    {
+        let mut player_lock = self.player.lock().await;
+        let player = match player_lock.take() {
+            Some(player) if player.is_alive() => player_lock.insert(player),
+            _ => {
+                let output = PlayerOutput::Network {
+                    address: self.config.address.clone(),
+                    sample_rate: self.config.sample_rate,
+                };
+                let player = Player::new(output, self.resample_quality)
+                    .await
+                    .map_err(AudioError::Error)?;
+                info!("Connected to the Snapcast server");
+                player_lock.insert(player)
+            }
+        };
+        f(player).await.map_err(AudioError::Error)
+    }
+}
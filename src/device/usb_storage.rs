@@ -0,0 +1,317 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Context};
+use async_graphql::{value, SimpleObject, Value};
+use chrono::Local;
+use futures::{
+    future::{self, BoxFuture, LocalBoxFuture},
+    FutureExt,
+};
+use log::{error, info, warn};
+use tokio::{fs, process::Command};
+use tokio_udev::EventType;
+
+use super::{piano::recordings::RecordingStorage, plugin::DevicePlugin};
+use crate::{
+    config,
+    core::{backup::Backup, task::TaskManager, Broadcaster, SortOrder},
+    GlobalEvent,
+};
+
+/// Directory on the drive where all the files are stored.
+const ROOT_DIR: &str = env!("CARGO_PKG_NAME");
+const RECORDINGS_DIR: &str = "recordings";
+const BACKUPS_DIR: &str = "backups";
+
+#[derive(Clone, Copy, PartialEq, Eq, SimpleObject)]
+pub struct OffloadProgress {
+    /// Number of the copied files (including the backup).
+    done: u32,
+    total: u32,
+}
+
+/// USB drive which is used to export the recordings and backups when plugged in.
+/// It's mounted only while the files are copying.
+#[derive(Clone)]
+pub struct UsbStorage {
+    config: config::UsbStorage,
+    backup: Backup,
+    recording_storage: RecordingStorage,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+    tasks: TaskManager,
+    offloading: Arc<AtomicBool>,
+}
+
+impl UsbStorage {
+    pub fn new(
+        config: config::UsbStorage,
+        backup: Backup,
+        recording_storage: RecordingStorage,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+        tasks: TaskManager,
+    ) -> Self {
+        Self {
+            config,
+            backup,
+            recording_storage,
+            event_broadcaster,
+            tasks,
+            offloading: Arc::default(),
+        }
+    }
+
+    fn is_configured_drive(&self, event: &tokio_udev::Event) -> bool {
+        event
+            .property_value("ID_FS_UUID")
+            .map(|uuid| uuid.eq_ignore_ascii_case(&self.config.fs_uuid))
+            .unwrap_or(false)
+    }
+
+    async fn offload(&self, block_device: &Path) -> anyhow::Result<()> {
+        let drive = MountedDrive::mount(block_device).await?;
+        info!("Drive mounted to {}", drive.mount_point.to_string_lossy());
+        let result = self.copy_files(&drive.mount_point.join(ROOT_DIR)).await;
+        if let Err(e) = drive.unmount().await {
+            error!("Failed to unmount the drive: {e}");
+        }
+        result
+    }
+
+    async fn copy_files(&self, root_dir: &Path) -> anyhow::Result<()> {
+        let (recordings_dir, backups_dir) =
+            (root_dir.join(RECORDINGS_DIR), root_dir.join(BACKUPS_DIR));
+        for dir in [&recordings_dir, &backups_dir] {
+            fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Unable to create {}", dir.to_string_lossy()))?;
+        }
+
+        let mut new_recordings = Vec::new();
+        for recording in self.recording_storage.list(SortOrder::Ascending).await? {
+            let Some(file_name) = recording.flac_path.file_name() else {
+                continue;
+            };
+            let target_path = recordings_dir.join(file_name);
+            if !fs::try_exists(&target_path).await? {
+                new_recordings.push((recording.flac_path, target_path));
+            }
+        }
+
+        let mut progress = OffloadProgress {
+            done: 0,
+            // Including the backup.
+            total: new_recordings.len() as u32 + 1,
+        };
+        self.event_broadcaster
+            .send(GlobalEvent::UsbOffloadProgress(progress));
+
+        for (source_path, target_path) in new_recordings {
+            fs::copy(&source_path, &target_path)
+                .await
+                .with_context(|| format!("Unable to copy {}", source_path.to_string_lossy()))?;
+            progress.done += 1;
+            self.event_broadcaster
+                .send(GlobalEvent::UsbOffloadProgress(progress));
+        }
+        remove_oldest(&recordings_dir, self.config.max_recordings as usize).await?;
+
+        let backup_path = backups_dir.join(format!(
+            "{}{}",
+            Local::now().format("%F_%H-%M-%S"),
+            self.backup.extension()
+        ));
+        make_backup(&self.backup, &backup_path).await?;
+        progress.done += 1;
+        self.event_broadcaster
+            .send(GlobalEvent::UsbOffloadProgress(progress));
+        remove_oldest(&backups_dir, self.config.max_backups as usize).await?;
+        Ok(())
+    }
+}
+
+impl DevicePlugin for UsbStorage {
+    fn name(&self) -> &'static str {
+        "usb-storage"
+    }
+
+    fn udev_subsystems(&self) -> &'static [&'static str] {
+        &["block"]
+    }
+
+    fn handle_udev_event<'a>(&'a self, event: &'a tokio_udev::Event) -> LocalBoxFuture<'a, ()> {
+        if event.event_type() != EventType::Add || !self.is_configured_drive(event) {
+            return future::ready(()).boxed_local();
+        }
+        let Some(block_device) = event.devnode().map(Path::to_path_buf) else {
+            error!("Drive plugged in, but it has no device node");
+            return future::ready(()).boxed_local();
+        };
+        if self.offloading.swap(true, atomic::Ordering::SeqCst) {
+            warn!("Offloading is already in process");
+            return future::ready(()).boxed_local();
+        }
+
+        info!(device = "usb-storage", event = "connected"; "Drive plugged in. Offloading files...");
+        let self_clone = self.clone();
+        self.tasks.spawn("usb-offload", async move {
+            let result = self_clone.offload(&block_device).await;
+            match &result {
+                Ok(()) => info!("Files offloaded to the drive"),
+                Err(e) => error!("Failed to offload files to the drive: {e:#}"),
+            }
+            self_clone
+                .event_broadcaster
+                .send(GlobalEvent::UsbOffloadFinished {
+                    success: result.is_ok(),
+                });
+            self_clone.offloading.store(false, atomic::Ordering::SeqCst);
+            result
+        });
+        future::ready(()).boxed_local()
+    }
+
+    fn status(&self) -> BoxFuture<'_, Value> {
+        future::ready(value!({
+            "offloading": self.offloading.load(atomic::Ordering::SeqCst),
+        }))
+        .boxed()
+    }
+}
+
+/// Unmounts the drive when dropped without calling [MountedDrive::unmount],
+/// e.g. if the offload task is cancelled on shutdown.
+struct MountedDrive {
+    block_device: PathBuf,
+    mount_point: PathBuf,
+    unmounted: bool,
+}
+
+impl MountedDrive {
+    async fn mount(block_device: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            block_device: block_device.to_path_buf(),
+            mount_point: mount(block_device).await?,
+            unmounted: false,
+        })
+    }
+
+    async fn unmount(mut self) -> anyhow::Result<()> {
+        // Don't retry in the destructor if it fails.
+        self.unmounted = true;
+        unmount(&self.block_device).await
+    }
+}
+
+impl Drop for MountedDrive {
+    fn drop(&mut self) {
+        if self.unmounted {
+            return;
+        }
+        warn!("Offloading is interrupted, unmounting the drive");
+        // Can't await here, and the runtime may be shutting down already.
+        let status = std::process::Command::new("udisksctl")
+            .arg("unmount")
+            .arg("--no-user-interaction")
+            .arg("--block-device")
+            .arg(&self.block_device)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => error!("Failed to unmount the drive: udisksctl exited with {status}"),
+            Err(e) => error!("Failed to unmount the drive: {e}"),
+        }
+    }
+}
+
+/// Returns the mount point.
+async fn mount(block_device: &Path) -> anyhow::Result<PathBuf> {
+    run_command(
+        Command::new("udisksctl")
+            .arg("mount")
+            .arg("--no-user-interaction")
+            .arg("--block-device")
+            .arg(block_device),
+    )
+    .await?;
+    let output = run_command(
+        Command::new("findmnt")
+            .args([
+                "--noheadings",
+                "--first-only",
+                "--output",
+                "TARGET",
+                "--source",
+            ])
+            .arg(block_device),
+    )
+    .await?;
+    let mount_point = output.trim();
+    if mount_point.is_empty() {
+        Err(anyhow!("mount point is not found"))
+    } else {
+        Ok(mount_point.into())
+    }
+}
+
+async fn unmount(block_device: &Path) -> anyhow::Result<()> {
+    run_command(
+        Command::new("udisksctl")
+            .arg("unmount")
+            .arg("--no-user-interaction")
+            .arg("--block-device")
+            .arg(block_device),
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn make_backup(backup: &Backup, path: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Unable to create {}", path.to_string_lossy()))?;
+    let result = backup.write(file).await;
+    if result.is_err() {
+        let _ = fs::remove_file(path).await;
+    }
+    result.with_context(|| "Unable to make a backup")
+}
+
+/// Keep only `max_files` newest files in `dir`. File names must start with a timestamp.
+async fn remove_oldest(dir: &Path, max_files: usize) -> anyhow::Result<()> {
+    let mut paths = Vec::new();
+    let mut read_dir = fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort_unstable();
+    for path in paths.iter().rev().skip(max_files) {
+        fs::remove_file(path)
+            .await
+            .with_context(|| format!("Unable to remove {}", path.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+/// Returns stdout of the command if it succeeded.
+async fn run_command(command: &mut Command) -> anyhow::Result<String> {
+    let output = command.stdin(Stdio::null()).output().await?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(anyhow!(
+            "{:?} failed: {}",
+            command.as_std().get_program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
@@ -0,0 +1,180 @@
+use std::{path::Path, sync::Arc};
+
+use async_graphql::Object;
+use log::{error, info};
+use tokio::fs;
+
+use super::piano::recordings::{Recording, RecordingStorage, RecordingStorageError};
+use crate::{
+    audio::{
+        self,
+        recorder::{RecordError, RecordParams, Recorder},
+    },
+    config,
+    core::{human_duration, task::TaskManager, HumanDateParams, ShutdownNotify, SortOrder},
+    graphql::GraphQLError,
+    SharedMutex,
+};
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum VoiceMemoError {
+    #[error("Voice memos are not configured")]
+    NotConfigured,
+    #[error("Microphone is not plugged in")]
+    DeviceNotFound,
+    #[error("Unable to initialize the recorder: {0}")]
+    InitFailed(anyhow::Error),
+    #[error("Already recording")]
+    AlreadyRecording,
+    #[error("Not recording")]
+    NotRecording,
+    #[error(transparent)]
+    StorageError(RecordingStorageError),
+    #[error("Recorder failed: {0}")]
+    RecordError(RecordError),
+}
+
+impl GraphQLError for VoiceMemoError {}
+
+pub struct VoiceMemo(pub Recording);
+
+#[Object]
+impl VoiceMemo {
+    async fn id(&self) -> i64 {
+        self.0.id()
+    }
+
+    async fn human_creation_date(&self) -> String {
+        self.0.human_creation_date(HumanDateParams {
+            filename_safe: false,
+        })
+    }
+
+    async fn human_duration(&self) -> String {
+        human_duration(self.0.duration())
+    }
+
+    async fn duration_ms(&self) -> u64 {
+        self.0.duration().as_millis() as u64
+    }
+
+    async fn api_endpoint(&self) -> String {
+        format!("/api/memos/{}", self.0.id())
+    }
+}
+
+/// Records a separate microphone (e.g. USB one) into the `memos` data subdirectory.
+/// Device is opened only while recording, so it doesn't depend on the piano
+/// and can be plugged in at any time.
+#[derive(Clone)]
+pub struct VoiceMemos {
+    config: config::VoiceMemo,
+    storage: RecordingStorage,
+    shutdown_notify: ShutdownNotify,
+    /// Set to [Some] if recording is in process.
+    recorder: SharedMutex<Option<Recorder>>,
+}
+
+impl VoiceMemos {
+    pub fn new(
+        config: config::VoiceMemo,
+        dir: &Path,
+        tasks: TaskManager,
+        shutdown_notify: ShutdownNotify,
+    ) -> Self {
+        Self {
+            storage: RecordingStorage::new(dir, config.max_memos, tasks),
+            config,
+            shutdown_notify,
+            recorder: Arc::default(),
+        }
+    }
+
+    /// Save the memo which is left if the server crashed while recording.
+    pub async fn recover_unsaved(&self) {
+        self.storage.recover_unsaved().await
+    }
+
+    pub async fn is_recording(&self) -> bool {
+        self.recorder.lock().await.is_some()
+    }
+
+    /// Returns memos from the newest to the oldest.
+    pub async fn list(&self) -> Result<Vec<VoiceMemo>, RecordingStorageError> {
+        let memos = self.storage.list(SortOrder::Descending).await?;
+        Ok(memos.into_iter().map(VoiceMemo).collect())
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Recording, RecordingStorageError> {
+        self.storage.get(id).await
+    }
+
+    pub async fn record(&self) -> Result<(), VoiceMemoError> {
+        let mut recorder_lock = self.recorder.lock().await;
+        if recorder_lock.is_some() {
+            return Err(VoiceMemoError::AlreadyRecording);
+        }
+        let device = audio::find_device(&self.config.alsa_plugin, &self.config.device_id)
+            .ok_or(VoiceMemoError::DeviceNotFound)?;
+        let mut recorder = Recorder::new(
+            self.config.recorder.clone(),
+            device,
+            self.shutdown_notify.clone(),
+        )
+        .map_err(VoiceMemoError::InitFailed)?;
+        let out_flac = self
+            .storage
+            .prepare_new()
+            .await
+            .map_err(VoiceMemoError::StorageError)?
+            .ok_or(VoiceMemoError::AlreadyRecording)?;
+        let params = RecordParams {
+            out_flac,
+            amplitude_scale: None,
+            artist: None,
+            front_cover_jpeg: None,
+            trim_silence: true,
+            on_prolonged_silence: None,
+        };
+        recorder
+            .start(params, None)
+            .await
+            .map_err(VoiceMemoError::RecordError)?;
+        *recorder_lock = Some(recorder);
+        info!("Voice memo recording started");
+        Ok(())
+    }
+
+    /// Stop recording and save the memo. The oldest memos are removed
+    /// if there are more than `max_memos`.
+    pub async fn stop(&self) -> Result<VoiceMemo, VoiceMemoError> {
+        let mut recorder = self
+            .recorder
+            .lock()
+            .await
+            .take()
+            .ok_or(VoiceMemoError::NotRecording)?;
+        // Try to save a memo even if recorder failed.
+        if let Err(e) = recorder.stop().await {
+            error!("Failed to stop the voice memo recorder: {e}");
+        }
+        let memo = self
+            .storage
+            .save_new()
+            .await
+            .map_err(VoiceMemoError::StorageError)?
+            .ok_or(VoiceMemoError::NotRecording)?;
+        self.storage.remove_old_if_limit_reached().await;
+        Ok(VoiceMemo(memo))
+    }
+
+    pub async fn remove(&self, id: i64) -> Result<(), RecordingStorageError> {
+        let memo = self.storage.get(id).await?;
+        fs::remove_file(&memo.flac_path)
+            .await
+            .map_err(RecordingStorageError::FileSystemError)?;
+        info!("Voice memo {memo} removed");
+        Ok(())
+    }
+}
@@ -1,32 +1,48 @@
-use std::{io, process::Stdio};
+use std::{fs::File, io, path::Path, time::Duration};
 
 use actix_files::NamedFile;
 use actix_web::{
     body::BodyStream,
     cookie::{Cookie, SameSite},
-    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound},
+    delete,
+    error::{
+        ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound,
+        ErrorPayloadTooLarge, ErrorServiceUnavailable, ErrorUnsupportedMediaType,
+    },
     get,
     http::header::{self, ContentDisposition, DispositionParam, DispositionType},
-    post, routes, web, HttpRequest, HttpResponse, Responder, Result,
+    post, put, routes, web, HttpMessage, HttpRequest, HttpResponse, Responder, Result,
 };
 use actix_web_httpauth::middleware::HttpAuthentication;
 use async_graphql::Schema;
 use async_graphql_actix_web::{GraphQLRequest, GraphQLSubscription};
 use log::error;
-use serde::Deserialize;
-use tokio::process::Command;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    audio::recorder::RECORDING_EXTENSION,
-    core::{stdout_reader::StdoutReader, HumanDateParams},
-    device::piano::recordings::RecordingStorageError,
-    files::{Asset, BaseDir},
+    audio::{
+        clip::{self, ClipError},
+        recorder::RECORDING_EXTENSION,
+        transcode::{TranscodeError, TranscodeFormat},
+    },
+    automation::AutomationError,
+    core::{
+        metrics::{self, Counter},
+        HumanDateParams,
+    },
+    device::piano::{recordings::RecordingStorageError, AudioError, StopRecorderParams},
+    file_manager::{FileManagerError, ManagedFolder},
+    files::{Asset, BaseDir, EmbeddedGraphiQL},
     graphql::GraphQLSchema,
-    rest::auth_validator,
+    integrations::dlna::{self, DlnaServer, DlnaService},
+    journal::Journal,
+    rest::{auth_validator, AdminAccess, RequestId},
+    summary::StatusSummary,
     App,
 };
 
-const BACKUP_MIME_TYPE: &str = "application/x-tar";
+/// Prometheus text-based exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
 
 #[get("/api/live")]
 pub async fn live() -> HttpResponse {
@@ -60,19 +76,32 @@ pub async fn graphql_playground(
         .unwrap_or(request_path)
         .trim_start_matches('/');
     let file = if file.is_empty() { "index.html" } else { file };
-    let fs_path = app.config.assets_dir.path(Asset::GraphiQL).join(file);
-
-    let mut response = NamedFile::open_async(&fs_path)
-        .await
-        .map_err(|err| {
-            if err.kind() == io::ErrorKind::NotFound {
-                ErrorNotFound(format!("file {file} not found"))
-            } else {
-                error!("Failed to open file {}: {err}", fs_path.to_string_lossy());
-                ErrorInternalServerError(format!("failed to open file {file}"))
-            }
-        })?
-        .into_response(&request);
+    let graphiql_dir = app.config.assets_dir.path(Asset::GraphiQL);
+
+    let mut response = if graphiql_dir.is_dir() {
+        let fs_path = graphiql_dir.join(file);
+        NamedFile::open_async(&fs_path)
+            .await
+            .map_err(|err| {
+                if err.kind() == io::ErrorKind::NotFound {
+                    ErrorNotFound(format!("file {file} not found"))
+                } else {
+                    error!("Failed to open file {}: {err}", fs_path.to_string_lossy());
+                    ErrorInternalServerError(format!("failed to open file {file}"))
+                }
+            })?
+            .into_response(&request)
+    } else {
+        let embedded = EmbeddedGraphiQL::get(file)
+            .ok_or_else(|| ErrorNotFound(format!("file {file} not found")))?;
+        let extension = Path::new(file)
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy();
+        HttpResponse::Ok()
+            .content_type(actix_files::file_extension_to_mime(&extension))
+            .body(embedded.data.into_owned())
+    };
 
     if let Some(auth_token) = query.auth_token.as_deref() {
         // Cookie is required for subscription,
@@ -87,8 +116,27 @@ pub async fn graphql_playground(
 }
 
 #[post("/api/graphql", wrap = "HttpAuthentication::with_fn(auth_validator)")]
-pub async fn graphql(request: GraphQLRequest, schema: web::Data<GraphQLSchema>) -> impl Responder {
-    web::Json(schema.execute(request.into_inner()).await)
+pub async fn graphql(
+    http_request: HttpRequest,
+    request: GraphQLRequest,
+    schema: web::Data<GraphQLSchema>,
+) -> impl Responder {
+    metrics::increment(Counter::GraphqlRequests);
+    let mut request = request.into_inner();
+    if let Some(admin_access) = http_request.extensions().get::<AdminAccess>() {
+        request = request.data(*admin_access);
+    }
+    let mut response = schema.execute(request).await;
+    if let Some(RequestId(request_id)) = http_request.extensions().get::<RequestId>() {
+        // Client can report it to find the related logs.
+        for error in &mut response.errors {
+            error
+                .extensions
+                .get_or_insert_with(Default::default)
+                .set("requestId", request_id.clone());
+        }
+    }
+    web::Json(response)
 }
 
 #[get(
@@ -111,50 +159,253 @@ pub async fn graphql_schema(schema: web::Data<GraphQLSchema>) -> HttpResponse {
         .body(schema.sdl())
 }
 
+/// Metrics in the Prometheus format.
+#[get("/api/metrics", wrap = "HttpAuthentication::with_fn(auth_validator)")]
+pub async fn metrics(app: web::Data<App>) -> HttpResponse {
+    let mut body = metrics::prometheus_text();
+    body.push_str(
+        "# HELP homie_broadcast_lagged_messages_total Events lost by the lagged subscribers.\n\
+        # TYPE homie_broadcast_lagged_messages_total counter\n",
+    );
+    for (channel, lagged_messages) in [
+        (
+            app.event_broadcaster.name(),
+            app.event_broadcaster.lagged_messages(),
+        ),
+        (
+            app.piano.event_broadcaster.name(),
+            app.piano.event_broadcaster.lagged_messages(),
+        ),
+        (
+            app.hotplug_broadcaster.name(),
+            app.hotplug_broadcaster.lagged_messages(),
+        ),
+        (
+            app.bluetooth_device_broadcaster.name(),
+            app.bluetooth_device_broadcaster.lagged_messages(),
+        ),
+    ]
+    .into_iter()
+    .chain(app.power.as_ref().map(|power| {
+        (
+            power.status_broadcaster.name(),
+            power.status_broadcaster.lagged_messages(),
+        )
+    })) {
+        body.push_str(&format!(
+            "homie_broadcast_lagged_messages_total{{channel=\"{channel}\"}} {lagged_messages}\n"
+        ));
+    }
+    HttpResponse::Ok()
+        .content_type(METRICS_CONTENT_TYPE)
+        .body(body)
+}
+
 #[post("/api/backup", wrap = "HttpAuthentication::with_fn(auth_validator)")]
-pub async fn backup() -> Result<HttpResponse> {
-    let mut child = Command::new("rpi-backup")
-        .stdout(Stdio::piped())
-        .stdin(Stdio::null())
-        .spawn()
-        .map_err(|err| {
-            error!("Failed to initiate the back up process: {err}");
-            err
+pub async fn backup(app: web::Data<App>) -> Result<HttpResponse> {
+    let stream = app.backup.stream().await.map_err(|err| {
+        error!("Failed to initiate the back up process: {err}");
+        err
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type(app.backup.mime_type())
+        .body(BodyStream::new(stream)))
+}
+
+/// Takes a backup archive (not encrypted) as the body and returns a summary of its content.
+#[post(
+    "/api/backup/verify",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn verify_backup(app: web::Data<App>, payload: web::Payload) -> Result<HttpResponse> {
+    let verification = app.backup.verify(payload).await.map_err(|err| {
+        error!("Failed to receive the backup: {err}");
+        err
+    })?;
+    Ok(HttpResponse::Ok().json(verification))
+}
+
+/// Takes a short WAVE or Ogg Opus clip as the body and plays it immediately
+/// using the piano along with the playing recording.
+#[post("/api/announce", wrap = "HttpAuthentication::with_fn(auth_validator)")]
+pub async fn announce(app: web::Data<App>, payload: web::Payload) -> Result<HttpResponse> {
+    let clip = payload
+        .to_bytes_limited(app.config.announce.max_clip_kib * 1024)
+        .await
+        .map_err(ErrorPayloadTooLarge)??;
+    let source = clip::decode(clip.into()).await.map_err(|err| match err {
+        ClipError::UnsupportedFormat => ErrorUnsupportedMediaType(err),
+        err => {
+            error!("Failed to decode the announcement: {err}");
+            ErrorBadRequest(err)
+        }
+    })?;
+    app.piano
+        .play_secondary_with_volume(source, app.config.announce.volume)
+        .await
+        .map_err(|err| match err {
+            AudioError::Error(err) => ErrorInternalServerError(err),
+            err => ErrorServiceUnavailable(err),
         })?;
+    Ok(HttpResponse::Ok().finish())
+}
 
-    if let Some(stdout) = child.stdout.take() {
-        let body = BodyStream::new(StdoutReader::new(stdout).stream().await);
-        return Ok(HttpResponse::Ok().content_type(BACKUP_MIME_TYPE).body(body));
-    } else {
-        error!("Failed to capture the backup output");
-        Err(ErrorInternalServerError("unable to capture the output"))
+/// Fails if the request is not made with [AdminAccess].
+fn require_admin(request: &HttpRequest) -> Result<()> {
+    if request.extensions().get::<AdminAccess>().is_none() {
+        return Err(ErrorForbidden("Admin access is required"));
     }
+    Ok(())
 }
 
 #[post("/api/poweroff", wrap = "HttpAuthentication::with_fn(auth_validator)")]
-pub async fn poweroff() -> Result<HttpResponse> {
-    let result = Command::new("systemctl")
-        .arg("poweroff")
-        .output()
-        .await
-        .map_err(|err| {
-            error!("Failed to initiate the power off: {err}");
-            err
-        })?;
+pub async fn poweroff(request: HttpRequest, app: web::Data<App>) -> Result<HttpResponse> {
+    require_admin(&request)?;
+    // Active recording will be preserved before the system goes down
+    // (see `Piano::inhibit_system_shutdown`).
+    let result = match app.dbus.login_manager_proxy().await {
+        Ok(proxy) => proxy.power_off(false).await,
+        Err(e) => Err(e),
+    };
+    result.map(|_| HttpResponse::Ok().finish()).map_err(|err| {
+        error!("Failed to power off: {err}");
+        ErrorInternalServerError(err)
+    })
+}
 
-    if result.status.success() {
-        Ok(HttpResponse::Ok().finish())
-    } else {
-        let output = String::from_utf8_lossy(if result.stderr.is_empty() {
-            &result.stdout
-        } else {
-            &result.stderr
-        });
-        error!("Failed to power off: {output}");
-        Err(ErrorInternalServerError(output.to_string()))
+#[post("/api/reboot", wrap = "HttpAuthentication::with_fn(auth_validator)")]
+pub async fn reboot(request: HttpRequest, app: web::Data<App>) -> Result<HttpResponse> {
+    require_admin(&request)?;
+    let result = match app.dbus.login_manager_proxy().await {
+        Ok(proxy) => proxy.reboot(false).await,
+        Err(e) => Err(e),
+    };
+    result.map(|_| HttpResponse::Ok().finish()).map_err(|err| {
+        error!("Failed to reboot: {err}");
+        ErrorInternalServerError(err)
+    })
+}
+
+/// Fire the automation rules with the webhook trigger or activate the scene with the `name`.
+#[post(
+    "/api/trigger/{name}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn trigger(name: web::Path<String>, app: web::Data<App>) -> Result<HttpResponse> {
+    app.automation
+        .fire_webhook(&name)
+        .await
+        .map(|_| HttpResponse::Ok().finish())
+        .map_err(|err| match err {
+            AutomationError::WebhookNotFound(_) => ErrorNotFound(err),
+            err => ErrorInternalServerError(err),
+        })
+}
+
+/// State of the piano, playback, lounge and alerts in one response for the polling dashboards.
+#[get(
+    "/api/status/summary",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn status_summary(app: web::Data<App>) -> HttpResponse {
+    HttpResponse::Ok().json(StatusSummary::collect(&app).await)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JournalFormat {
+    Md,
+    Json,
+}
+
+/// Practice history: recordings with their bookmarks grouped by day, plus the daily totals.
+#[get(
+    "/api/piano/journal.{format}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn practice_journal(
+    format: web::Path<JournalFormat>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let journal = Journal::collect(&app.storage).await.map_err(|e| {
+        error!("Failed to make the practice journal: {e}");
+        ErrorInternalServerError(e)
+    })?;
+    Ok(match format.into_inner() {
+        JournalFormat::Md => HttpResponse::Ok()
+            .content_type("text/markdown; charset=utf-8")
+            .body(journal.to_markdown()),
+        JournalFormat::Json => HttpResponse::Ok().json(journal),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ControlCommand {
+    StartRecord,
+    StopRecord,
+    PlayLast,
+    Pause,
+}
+
+/// Response of the control endpoint, simple enough for the tiny HTTP clients.
+#[derive(Serialize)]
+struct ControlResponse {
+    ok: bool,
+    /// Result description or the error.
+    message: String,
+}
+
+/// Piano control for the voice assistants and hardware buttons which can't use GraphQL.
+#[post(
+    "/api/control/{command}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn control(command: web::Path<ControlCommand>, app: web::Data<App>) -> HttpResponse {
+    let piano = &app.piano;
+    let result = match *command {
+        ControlCommand::StartRecord => piano
+            .record()
+            .await
+            .map(|_| "Recording started".to_string())
+            .map_err(|e| e.to_string()),
+        ControlCommand::StopRecord => piano
+            .stop_recorder(StopRecorderParams {
+                play_feedback: true,
+            })
+            .await
+            .map(|recording| format!("Recording {} saved", recording.id()))
+            .map_err(|e| e.to_string()),
+        ControlCommand::PlayLast => piano
+            .play_last_recording()
+            .await
+            .map(|id| format!("Playing recording {id}"))
+            .map_err(|e| e.to_string()),
+        ControlCommand::Pause => piano
+            .pause_player()
+            .await
+            .map(|paused| {
+                if paused {
+                    "Paused"
+                } else {
+                    "Nothing is playing"
+                }
+                .to_string()
+            })
+            .map_err(|e| e.to_string()),
+    };
+    match result {
+        Ok(message) => HttpResponse::Ok().json(ControlResponse { ok: true, message }),
+        Err(message) => HttpResponse::Conflict().json(ControlResponse { ok: false, message }),
     }
 }
 
+#[derive(Deserialize)]
+struct PianoRecordingQuery {
+    /// If not set, the original FLAC file is returned.
+    format: Option<TranscodeFormat>,
+}
+
 #[get(
     "/api/piano/recording/{id}",
     wrap = "HttpAuthentication::with_fn(auth_validator)"
@@ -162,6 +413,7 @@ pub async fn poweroff() -> Result<HttpResponse> {
 pub async fn piano_recording(
     request: HttpRequest,
     recording_id: web::Path<i64>,
+    query: web::Query<PianoRecordingQuery>,
     app: web::Data<App>,
 ) -> Result<HttpResponse> {
     let recording = app
@@ -173,21 +425,381 @@ pub async fn piano_recording(
             RecordingStorageError::RecordingNotExists => ErrorNotFound("recording does not exist"),
             err => ErrorInternalServerError(err),
         })?;
-    NamedFile::open_async(&recording.flac_path)
-        .await
-        .map(|file| {
-            file.set_content_disposition(ContentDisposition {
-                disposition: DispositionType::Attachment,
-                parameters: vec![DispositionParam::Filename(format!(
-                    "{}{RECORDING_EXTENSION}",
-                    recording.human_creation_date(HumanDateParams {
-                        filename_safe: true
-                    })
-                ))],
-            })
-            .into_response(&request)
+    let (file, extension) = match query.format {
+        Some(format) => (
+            transcoded_file(&app, &recording.flac_path, recording.duration(), format).await?,
+            format.extension(),
+        ),
+        None => (
+            NamedFile::open_async(&recording.flac_path)
+                .await
+                .map_err(ErrorInternalServerError)?,
+            RECORDING_EXTENSION,
+        ),
+    };
+    Ok(file
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}{extension}",
+                recording.filename()
+            ))],
+        })
+        .into_response(&request))
+}
+
+#[derive(Deserialize)]
+struct TranscodedRecordingQuery {
+    format: TranscodeFormat,
+}
+
+/// Unlike `/api/piano/recording/{id}?format=...`, the output is sent while converting,
+/// so there is no need to wait for the whole file. But the size is unknown
+/// and seeking is not supported.
+#[get(
+    "/api/piano/recording/{id}/transcoded",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn transcoded_piano_recording(
+    recording_id: web::Path<i64>,
+    query: web::Query<TranscodedRecordingQuery>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let recording = app
+        .piano
+        .recording_storage
+        .get(*recording_id)
+        .await
+        .map_err(|err| match err {
+            RecordingStorageError::RecordingNotExists => ErrorNotFound("recording does not exist"),
+            err => ErrorInternalServerError(err),
+        })?;
+    let stream = app
+        .transcoder
+        .stream(&recording.flac_path, query.format)
+        .await
+        .map_err(|err| transcode_error(&recording.flac_path, err))?;
+    Ok(HttpResponse::Ok()
+        .content_type(query.format.mime_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}{}",
+                recording.filename(),
+                query.format.extension()
+            ))],
+        })
+        .body(BodyStream::new(stream)))
+}
+
+/// Same as the `waveform` field of `PianoRecording`.
+#[get(
+    "/api/piano/recording/{id}/waveform",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn piano_recording_waveform(
+    recording_id: web::Path<i64>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let recording = app
+        .piano
+        .recording_storage
+        .get(*recording_id)
+        .await
+        .map_err(|err| match err {
+            RecordingStorageError::RecordingNotExists => ErrorNotFound("recording does not exist"),
+            err => ErrorInternalServerError(err),
+        })?;
+    let waveform = recording.waveform(&app.storage).await.map_err(|e| {
+        error!("Failed to get the waveform of recording {recording}: {e}");
+        ErrorInternalServerError(e)
+    })?;
+    Ok(HttpResponse::Ok().json(waveform))
+}
+
+#[get(
+    "/api/memos/{id}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn voice_memo(
+    request: HttpRequest,
+    memo_id: web::Path<i64>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let memo = app
+        .voice_memos
+        .as_ref()
+        .ok_or_else(|| ErrorNotFound("voice memos are not configured"))?
+        .get(*memo_id)
+        .await
+        .map_err(|err| match err {
+            RecordingStorageError::RecordingNotExists => ErrorNotFound("memo does not exist"),
+            err => ErrorInternalServerError(err),
+        })?;
+    let file = NamedFile::open_async(&memo.flac_path)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(file
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "memo {}{RECORDING_EXTENSION}",
+                memo.human_creation_date(HumanDateParams {
+                    filename_safe: true
+                })
+            ))],
         })
-        .map_err(ErrorInternalServerError)
+        .into_response(&request))
+}
+
+/// Convert the file using the queue. The converted file is unlinked
+/// just after opening, so it's removed when the response is sent.
+async fn transcoded_file(
+    app: &App,
+    flac_file: &Path,
+    duration: Duration,
+    format: TranscodeFormat,
+) -> Result<NamedFile> {
+    let path = app
+        .transcoder
+        .transcode(flac_file, duration, format)
+        .await
+        .map_err(|err| transcode_error(flac_file, err))?;
+    let file = File::open(&path);
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        error!("Failed to remove the converted file: {e}");
+    }
+    NamedFile::from_file(file?, &path).map_err(ErrorInternalServerError)
+}
+
+fn transcode_error(flac_file: &Path, err: TranscodeError) -> actix_web::Error {
+    match err {
+        TranscodeError::QueueFull => ErrorServiceUnavailable(err),
+        err => {
+            error!("Failed to convert {}: {err}", flac_file.to_string_lossy());
+            ErrorInternalServerError(err)
+        }
+    }
+}
+
+fn file_manager_error(err: FileManagerError) -> actix_web::Error {
+    match err {
+        FileManagerError::InvalidName | FileManagerError::InvalidRecording(_) => {
+            ErrorBadRequest(err)
+        }
+        FileManagerError::NotFound => ErrorNotFound(err),
+        FileManagerError::TooLarge => ErrorPayloadTooLarge(err),
+        FileManagerError::FileSystemError(_) => {
+            error!("File manager failed: {err}");
+            ErrorInternalServerError(err)
+        }
+    }
+}
+
+fn check_write_access(request: &HttpRequest, folder: ManagedFolder) -> Result<()> {
+    if folder.admin_writable() {
+        require_admin(request)?;
+    }
+    Ok(())
+}
+
+#[get(
+    "/api/files/{folder}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn list_files(
+    folder: web::Path<ManagedFolder>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let files = app
+        .file_manager
+        .list(*folder)
+        .await
+        .map_err(file_manager_error)?;
+    Ok(HttpResponse::Ok().json(files))
+}
+
+#[get(
+    "/api/files/{folder}/{name}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn download_file(
+    request: HttpRequest,
+    path: web::Path<(ManagedFolder, String)>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let (folder, name) = path.into_inner();
+    let path = app
+        .file_manager
+        .path(folder, &name)
+        .await
+        .map_err(file_manager_error)?;
+    Ok(NamedFile::open_async(&path)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(name)],
+        })
+        .into_response(&request))
+}
+
+/// Takes the file content as the body. Existing file is replaced.
+#[put(
+    "/api/files/{folder}/{name}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn upload_file(
+    request: HttpRequest,
+    path: web::Path<(ManagedFolder, String)>,
+    payload: web::Payload,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let (folder, name) = path.into_inner();
+    check_write_access(&request, folder)?;
+    app.file_manager
+        .upload(folder, &name, payload)
+        .await
+        .map_err(file_manager_error)?;
+    app.storage
+        .audit("upload_file", format!("{folder:?}/{name}"))
+        .await;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[delete(
+    "/api/files/{folder}/{name}",
+    wrap = "HttpAuthentication::with_fn(auth_validator)"
+)]
+pub async fn delete_file(
+    request: HttpRequest,
+    path: web::Path<(ManagedFolder, String)>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let (folder, name) = path.into_inner();
+    check_write_access(&request, folder)?;
+    app.file_manager
+        .delete(folder, &name)
+        .await
+        .map_err(file_manager_error)?;
+    app.storage
+        .audit("delete_file", format!("{folder:?}/{name}"))
+        .await;
+    Ok(HttpResponse::Ok().finish())
+}
+
+// DLNA endpoints don't require authorization, as the TVs and streamers can't pass it.
+// Instead, they are available only to the local network.
+
+fn dlna_server<'a>(request: &HttpRequest, app: &'a App) -> Result<&'a DlnaServer> {
+    let dlna = app
+        .dlna
+        .as_ref()
+        .ok_or_else(|| ErrorNotFound("DLNA server is not enabled"))?;
+    // Not the "realip" of the connection info, because the forwarding headers can be forged.
+    match request.peer_addr() {
+        Some(peer) if dlna::is_local_peer(peer.ip()) => Ok(dlna),
+        _ => Err(ErrorForbidden(
+            "DLNA server is available only to the local network",
+        )),
+    }
+}
+
+/// Address of this server which the client used.
+fn base_url(request: &HttpRequest) -> String {
+    format!("http://{}", request.connection_info().host())
+}
+
+#[get("/dlna/description.xml")]
+pub async fn dlna_description(request: HttpRequest, app: web::Data<App>) -> Result<HttpResponse> {
+    let description = dlna_server(&request, &app)?.device_description(&base_url(&request));
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_XML)
+        .body(description))
+}
+
+#[get("/dlna/scpd/{service}")]
+pub async fn dlna_service_description(
+    request: HttpRequest,
+    service: web::Path<DlnaService>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    dlna_server(&request, &app)?;
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_XML)
+        .body(service.description()))
+}
+
+#[post("/dlna/control/{service}")]
+pub async fn dlna_control(
+    request: HttpRequest,
+    service: web::Path<DlnaService>,
+    body: String,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let soap_action = request
+        .headers()
+        .get("soapaction")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let result = dlna_server(&request, &app)?
+        .control(*service, soap_action, &body, &base_url(&request))
+        .await;
+    Ok(match result {
+        Ok(envelope) => HttpResponse::Ok()
+            .content_type(mime::TEXT_XML)
+            .body(envelope),
+        Err(e) => {
+            error!("DLNA request {soap_action} failed: {e}");
+            HttpResponse::InternalServerError()
+                .content_type(mime::TEXT_XML)
+                .body(e.soap_fault())
+        }
+    })
+}
+
+#[get("/dlna/recording/{id}")]
+pub async fn dlna_recording(
+    request: HttpRequest,
+    recording_id: web::Path<i64>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    dlna_server(&request, &app)?;
+    let recording = app
+        .piano
+        .recording_storage
+        .get(*recording_id)
+        .await
+        .map_err(|err| match err {
+            RecordingStorageError::RecordingNotExists => ErrorNotFound("recording does not exist"),
+            err => ErrorInternalServerError(err),
+        })?;
+    let mut response = NamedFile::open_async(&recording.flac_path)
+        .await
+        .map_err(ErrorInternalServerError)?
+        .into_response(&request);
+    for (name, value) in dlna::media_headers() {
+        response.headers_mut().insert(
+            header::HeaderName::from_static(name),
+            header::HeaderValue::from_static(value),
+        );
+    }
+    Ok(response)
+}
+
+#[get("/dlna/cover/{id}")]
+pub async fn dlna_cover(
+    request: HttpRequest,
+    recording_id: web::Path<i64>,
+    app: web::Data<App>,
+) -> Result<HttpResponse> {
+    let cover = dlna_server(&request, &app)?
+        .cover_art(*recording_id)
+        .await
+        .ok_or_else(|| ErrorNotFound("cover art not found"))?;
+    Ok(HttpResponse::Ok()
+        .content_type(cover.mime_type)
+        .body(cover.data))
 }
 
 mod guard {
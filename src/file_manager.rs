@@ -0,0 +1,319 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use chrono::{DateTime, Utc};
+use futures::{pin_mut, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    audio::recorder::RECORDING_EXTENSION,
+    core::{timezone, Broadcaster},
+    device::piano::{
+        recordings::{RecordingStorage, RecordingStorageError},
+        Piano, PianoEvent,
+    },
+    files::{BaseDir, Data, DataDir},
+};
+
+/// Extension of the files which are being uploaded. Such files are hidden.
+const UPLOAD_EXTENSION: &str = ".upload";
+
+/// Makes the names of the concurrent uploads of the same file unique.
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Data subdirectories which can be managed using the API.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ManagedFolder {
+    /// Only the saved recordings are accessible (`<TIMESTAMP_MILLIS>.flac`).
+    Recordings,
+    Scripts,
+}
+
+impl ManagedFolder {
+    fn data(&self) -> Data {
+        match self {
+            Self::Recordings => Data::PianoRecordings,
+            Self::Scripts => Data::Scripts,
+        }
+    }
+
+    /// Whether uploading and deleting the files requires the admin access.
+    /// Scripts are executed by the server, so only the admin can change them.
+    pub fn admin_writable(&self) -> bool {
+        matches!(self, Self::Scripts)
+    }
+
+    /// Maximum size of an uploaded file.
+    fn max_size(&self) -> u64 {
+        match self {
+            Self::Recordings => 2 * 1024 * 1024 * 1024,
+            Self::Scripts => 1024 * 1024,
+        }
+    }
+
+    fn accepts(&self, name: &str) -> bool {
+        match self {
+            // It also protects the recording which is in progress.
+            Self::Recordings => name
+                .to_lowercase()
+                .strip_suffix(RECORDING_EXTENSION)
+                .is_some_and(|stem| !stem.is_empty() && stem.bytes().all(|b| b.is_ascii_digit())),
+            Self::Scripts => true,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileManagerError {
+    #[error("Invalid file name")]
+    InvalidName,
+    #[error("File not found")]
+    NotFound,
+    #[error("File is too large")]
+    TooLarge,
+    #[error("Invalid recording: {0}")]
+    InvalidRecording(RecordingStorageError),
+    #[error("File system error: {0}")]
+    FileSystemError(io::Error),
+}
+
+impl From<RecordingStorageError> for FileManagerError {
+    fn from(err: RecordingStorageError) -> Self {
+        match err {
+            RecordingStorageError::RecordingNotExists => Self::NotFound,
+            RecordingStorageError::FileSystemError(e) => Self::FileSystemError(e),
+            _ => Self::InvalidRecording(err),
+        }
+    }
+}
+
+impl From<io::Error> for FileManagerError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::NotFound {
+            Self::NotFound
+        } else {
+            Self::FileSystemError(err)
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEntry {
+    name: String,
+    size_bytes: u64,
+    /// RFC 3339.
+    modified_at: Option<String>,
+}
+
+/// Basic management of the files in the data directory. Only regular files
+/// which are placed directly in a [ManagedFolder] are accessible.
+/// Recordings are changed through the [RecordingStorage], so they are validated and reindexed.
+#[derive(Clone)]
+pub struct FileManager {
+    data_dir: DataDir,
+    recording_storage: RecordingStorage,
+    piano_event_broadcaster: Broadcaster<PianoEvent>,
+}
+
+impl FileManager {
+    pub fn new(data_dir: DataDir, piano: &Piano) -> Self {
+        Self {
+            data_dir,
+            recording_storage: piano.recording_storage.clone(),
+            piano_event_broadcaster: piano.event_broadcaster.clone(),
+        }
+    }
+
+    /// Returns the files sorted by name.
+    pub async fn list(&self, folder: ManagedFolder) -> Result<Vec<FileEntry>, FileManagerError> {
+        let mut files = Vec::new();
+        let mut read_dir = fs::read_dir(&*self.data_dir.path(folder.data())).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() || name.starts_with('.') || !folder.accepts(&name) {
+                continue;
+            }
+            files.push(FileEntry {
+                name,
+                size_bytes: metadata.len(),
+                modified_at: metadata
+                    .modified()
+                    .ok()
+                    .map(|time| timezone::localize(DateTime::<Utc>::from(time)).to_rfc3339()),
+            });
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(files)
+    }
+
+    /// Returns a path of the existing file. Symbolic links are not followed.
+    pub async fn path(
+        &self,
+        folder: ManagedFolder,
+        name: &str,
+    ) -> Result<PathBuf, FileManagerError> {
+        let path = self.resolve(folder, name)?;
+        if fs::symlink_metadata(&path).await?.is_file() {
+            Ok(path)
+        } else {
+            Err(FileManagerError::NotFound)
+        }
+    }
+
+    /// Write the file atomically, replacing the existing one.
+    /// Fails if the content exceeds the folder limit.
+    pub async fn upload<S, E>(
+        &self,
+        folder: ManagedFolder,
+        name: &str,
+        content: S,
+    ) -> Result<(), FileManagerError>
+    where
+        S: Stream<Item = Result<actix_web::web::Bytes, E>>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let path = self.resolve(folder, name)?;
+        let dir = self.data_dir.path(folder.data());
+        fs::create_dir_all(&*dir).await?;
+        let upload_path = dir.join(format!(
+            ".{name}.{}{UPLOAD_EXTENSION}",
+            UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let written = async {
+            let mut file = fs::File::create(&upload_path).await?;
+            let mut size = 0;
+            pin_mut!(content);
+            while let Some(chunk) = content.next().await {
+                let chunk =
+                    chunk.map_err(|e| FileManagerError::FileSystemError(io::Error::other(e)))?;
+                size += chunk.len() as u64;
+                if size > folder.max_size() {
+                    return Err(FileManagerError::TooLarge);
+                }
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+            match recording_id(folder, name) {
+                Some(recording_id) => {
+                    self.recording_storage
+                        .import(&upload_path, recording_id)
+                        .await?;
+                    self.piano_event_broadcaster
+                        .send(PianoEvent::RecordingsModified);
+                }
+                None => fs::rename(&upload_path, &path).await?,
+            }
+            Ok::<_, FileManagerError>(())
+        }
+        .await;
+        if written.is_err() {
+            let _ = fs::remove_file(&upload_path).await;
+        }
+        written
+    }
+
+    pub async fn delete(&self, folder: ManagedFolder, name: &str) -> Result<(), FileManagerError> {
+        let path = self.path(folder, name).await?;
+        match recording_id(folder, name) {
+            Some(recording_id) => {
+                self.recording_storage.remove(recording_id).await?;
+                self.piano_event_broadcaster
+                    .send(PianoEvent::RecordingsModified);
+                Ok(())
+            }
+            None => fs::remove_file(path).await.map_err(Into::into),
+        }
+    }
+
+    fn resolve(&self, folder: ManagedFolder, name: &str) -> Result<PathBuf, FileManagerError> {
+        check_name(folder, name)?;
+        Ok(self.data_dir.path(folder.data()).join(name))
+    }
+}
+
+/// Protects from the path traversal: `name` must be a plain file name.
+fn check_name(folder: ManagedFolder, name: &str) -> Result<(), FileManagerError> {
+    let mut components = Path::new(name).components();
+    let is_plain =
+        matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none();
+    if !is_plain || name.starts_with('.') || !folder.accepts(name) {
+        return Err(FileManagerError::InvalidName);
+    }
+    Ok(())
+}
+
+/// [None] if the file is not in the recordings folder. `name` must be accepted by the folder.
+fn recording_id(folder: ManagedFolder, name: &str) -> Option<i64> {
+    match folder {
+        ManagedFolder::Recordings => name
+            .to_lowercase()
+            .strip_suffix(RECORDING_EXTENSION)?
+            .parse()
+            .ok(),
+        ManagedFolder::Scripts => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_traversal() {
+        for name in [
+            "../1700000000000.flac",
+            "/1700000000000.flac",
+            "a/1700000000000.flac",
+        ] {
+            assert!(
+                check_name(ManagedFolder::Recordings, name).is_err(),
+                "{name}"
+            );
+        }
+        for name in [
+            "..",
+            ".",
+            "../script.lua",
+            "/etc/passwd",
+            "dir/script.lua",
+            "",
+        ] {
+            assert!(check_name(ManagedFolder::Scripts, name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn hidden_files() {
+        assert!(check_name(ManagedFolder::Scripts, ".script.lua").is_err());
+        assert!(check_name(ManagedFolder::Scripts, ".script.lua.0.upload").is_err());
+    }
+
+    #[test]
+    fn recording_names() {
+        assert!(check_name(ManagedFolder::Recordings, "1700000000000.flac").is_ok());
+        assert!(check_name(ManagedFolder::Recordings, "1700000000000.FLAC").is_ok());
+        // The recording which is in progress.
+        assert!(check_name(ManagedFolder::Recordings, "new.flac").is_err());
+        assert!(check_name(ManagedFolder::Recordings, ".flac").is_err());
+        assert!(check_name(ManagedFolder::Recordings, "1700000000000.wav").is_err());
+        assert_eq!(
+            recording_id(ManagedFolder::Recordings, "1700000000000.FLAC"),
+            Some(1_700_000_000_000)
+        );
+        assert_eq!(recording_id(ManagedFolder::Scripts, "1.flac"), None);
+    }
+
+    #[test]
+    fn script_names() {
+        assert!(check_name(ManagedFolder::Scripts, "script.lua").is_ok());
+        assert!(check_name(ManagedFolder::Scripts, "..script.lua").is_err());
+    }
+}
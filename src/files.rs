@@ -4,6 +4,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use rust_embed::RustEmbed;
 use serde::Deserialize;
 use serde_valid::{validation, Validate};
 use strum::{EnumIter, IntoEnumIterator};
@@ -17,15 +18,16 @@ pub trait BaseDir<'a, T>: Clone + Deserialize<'a> + Validate {
 pub enum Asset {
     /// A site to host on `/`.
     Site,
-    /// Optional GraphQL IDE to host on `/api/graphql`.
+    /// GraphQL IDE to host on `/api/graphql`. If it's absent, [EmbeddedGraphiQL] is used.
     GraphiQL,
     Sound(Sound),
     /// Optional cover image to embed into the piano recordings.
     PianoRecordingCoverJPEG,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, strum::Display, EnumIter)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, strum::Display, EnumIter, Deserialize)]
 #[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
 pub enum Sound {
     Error,
     PauseResume,
@@ -34,6 +36,12 @@ pub enum Sound {
     RecordStop,
 }
 
+/// Default [Asset::GraphiQL] built into the binary. Dependencies are pinned and vendored
+/// using `embedded/vendor-graphiql.sh`, so the page doesn't load the third-party scripts.
+#[derive(RustEmbed)]
+#[folder = "embedded/graphiql"]
+pub struct EmbeddedGraphiQL;
+
 /// Read-only resources.
 #[derive(Clone, Deserialize)]
 pub struct AssetsDir(PathBuf);
@@ -42,6 +50,10 @@ impl AssetsDir {
     pub fn unset() -> Self {
         Self(PathBuf::new())
     }
+
+    pub fn root(&self) -> &Path {
+        &self.0
+    }
 }
 
 impl BaseDir<'_, Asset> for AssetsDir {
@@ -81,7 +93,7 @@ impl Validate for AssetsDir {
         }
         .validate()?;
 
-        [Asset::Site, Asset::GraphiQL, Asset::PianoRecordingCoverJPEG]
+        [Asset::Site, Asset::PianoRecordingCoverJPEG]
             .into_iter()
             .try_for_each(|asset| self.path(asset).validate())?;
         Sound::iter().try_for_each(|sound| self.path(Asset::Sound(sound)).validate())
@@ -92,12 +104,31 @@ impl Validate for AssetsDir {
 pub enum Data {
     Preferences,
     PianoRecordings,
+    /// Temporary files made by the audio conversion.
+    Transcodes,
+    /// Recordings included into the last incremental backup.
+    BackupManifest,
+    /// Pairings and identity of the HomeKit bridge.
+    HomeKit,
+    /// Lua scripts, see [crate::scripting::Scripting].
+    Scripts,
+    /// Configuration changes made using the API, see [crate::config_editor::ConfigEditor].
+    ConfigOverlay,
+    /// SQLite database, see [crate::storage::Storage].
+    Database,
+    VoiceMemos,
 }
 
 /// A directory where the server stores all the data.
 #[derive(Clone, Deserialize)]
 pub struct DataDir(PathBuf);
 
+impl DataDir {
+    pub fn root(&self) -> &Path {
+        &self.0
+    }
+}
+
 impl BaseDir<'_, Data> for DataDir {
     fn path(&self, item: Data) -> PathEntry {
         let (relative_path, kind, requirement) = match item {
@@ -107,6 +138,21 @@ impl BaseDir<'_, Data> for DataDir {
                 EntryKind::Directory,
                 Some(EntryRequirement::WritableOrCreate),
             ),
+            Data::Transcodes => (
+                "transcodes",
+                EntryKind::Directory,
+                Some(EntryRequirement::WritableOrCreate),
+            ),
+            Data::BackupManifest => ("backup-manifest.json", EntryKind::File, None),
+            Data::Database => ("homie.db", EntryKind::File, None),
+            Data::VoiceMemos => (
+                "memos",
+                EntryKind::Directory,
+                Some(EntryRequirement::WritableOrCreate),
+            ),
+            Data::HomeKit => ("homekit", EntryKind::Directory, None),
+            Data::Scripts => ("scripts", EntryKind::Directory, None),
+            Data::ConfigOverlay => ("config-overlay.yaml", EntryKind::File, None),
         };
         PathEntry {
             path: self.0.join(relative_path),
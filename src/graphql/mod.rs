@@ -4,10 +4,10 @@ mod subscription;
 
 use std::{fmt::Display, ops::Deref};
 
-use async_graphql::{scalar, Error, ErrorExtensions, Schema};
+use async_graphql::{scalar, Context, Error, ErrorExtensions, Guard, Schema};
 use serde::{Deserialize, Serialize};
 
-use crate::App;
+use crate::{rest::AdminAccess, App};
 use mutation::MutationRoot;
 use query::QueryRoot;
 use subscription::SubscriptionRoot;
@@ -31,11 +31,34 @@ pub fn build_schema(app: App) -> GraphQLSchema {
     Schema::build(
         QueryRoot(app.clone()),
         MutationRoot(app.clone()),
-        SubscriptionRoot(app),
+        SubscriptionRoot(app.clone()),
     )
+    // Accessed by the nested objects (e.g. bookmarks of a recording).
+    .data(app.storage)
     .finish()
 }
 
+/// Allows only the requests with [AdminAccess].
+struct AdminGuard;
+
+impl Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        match ctx.data_opt::<AdminAccess>() {
+            Some(_) => Ok(()),
+            None => Err(AdminError::Forbidden.extend()),
+        }
+    }
+}
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+enum AdminError {
+    #[error("Admin token is required")]
+    Forbidden,
+}
+
+impl GraphQLError for AdminError {}
+
 pub trait GraphQLError: AsRef<str> + Display + Sized {
     fn extend(self) -> Error {
         // Include error identifier.
@@ -1,12 +1,26 @@
 use std::{ops::Deref, time::Duration};
 
-use async_graphql::{Object, Result};
+use async_graphql::{Context, Json, Object, Result};
+use chrono::{DateTime, FixedOffset};
 
-use super::{GraphQLError, Scalar};
+use super::{AdminGuard, GraphQLError, Scalar};
 use crate::{
-    audio::player::SeekTo,
-    device::piano::{self, recordings::Recording as PianoRecording, Piano},
+    audio::{
+        player::{LoopRegion, SeekTo},
+        router::{OutputRoute, OutputTarget},
+        AudioOutput,
+    },
+    bluetooth::MediaControlCommand,
+    config_editor::ConfigKey,
+    core::logger::{AppLogger, LogLevelFilter, LogLevels},
+    device::{
+        piano::{self, recordings::Recording as PianoRecording, Piano},
+        voice_memo::{VoiceMemo, VoiceMemoError, VoiceMemos},
+    },
+    poweroff::ScheduledPoweroff,
     prefs::PreferencesUpdate,
+    storage::{Bookmark, BookmarkError, Storage},
+    updater::{AvailableUpdate, UpdateError},
     App,
 };
 
@@ -18,6 +32,15 @@ impl MutationRoot {
         PianoMutation(&self.piano)
     }
 
+    /// Fails if voice memos are not configured.
+    async fn memos(&self) -> Result<MemosMutation> {
+        self.voice_memos
+            .as_ref()
+            .map(MemosMutation)
+            .ok_or(VoiceMemoError::NotConfigured)
+            .map_err(GraphQLError::extend)
+    }
+
     async fn update_preferences(&self, update: PreferencesUpdate) -> Result<bool> {
         self.prefs
             .update(self, update)
@@ -25,6 +48,185 @@ impl MutationRoot {
             .map(|_| true)
             .map_err(GraphQLError::extend)
     }
+
+    /// Send `command` to all connected Bluetooth audio sources (e.g. phones).
+    /// Fails if it was not sent to some of them.
+    async fn bluetooth_media_control(&self, command: MediaControlCommand) -> Result<bool> {
+        self.a2dp_source_handler
+            .send_media_control_command(&self.dbus, command)
+            .await?;
+        Ok(true)
+    }
+
+    /// Disconnect the Bluetooth audio sources which hold the piano audio device,
+    /// so the player and recorder are initialized right away.
+    /// Returns names of the disconnected devices.
+    async fn reclaim_piano_audio(&self) -> Result<Vec<String>> {
+        let result = self
+            .a2dp_source_handler
+            .disconnect_all(&self.bluetooth)
+            .await;
+        // Some devices may be disconnected even if the others failed.
+        self.piano.update_audio_io().await;
+        Ok(result?)
+    }
+
+    /// Initialize the piano player and recorder again without replugging the piano,
+    /// e.g. if the player initialization gave up. It waits until the player is initialized.
+    async fn reinit_piano_audio(&self) -> Result<piano::AudioReinitResult> {
+        self.piano
+            .reinit_audio()
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Restart the systemd unit (e.g. `bluetooth.service`). It must be listed in the
+    /// `manageable_units` configuration parameter. If the server restarts itself,
+    /// the response is sent before stopping.
+    #[graphql(guard = "AdminGuard")]
+    async fn restart_unit(&self, name: String) -> Result<bool> {
+        self.dbus
+            .restart_unit(&name, &self.config.manageable_units)
+            .await
+            .map_err(GraphQLError::extend)?;
+        self.storage.audit("restart_unit", name).await;
+        Ok(true)
+    }
+
+    /// Gracefully power off the system at the given time: the recording is saved and
+    /// the preferences are flushed. Replaces the previously scheduled one-time power off.
+    #[graphql(guard = "AdminGuard")]
+    async fn schedule_poweroff(&self, at: DateTime<FixedOffset>) -> Result<ScheduledPoweroff> {
+        let scheduled = self
+            .poweroff_scheduler
+            .schedule(at)
+            .await
+            .map_err(GraphQLError::extend)?;
+        self.storage
+            .audit("schedule_poweroff", at.to_rfc3339())
+            .await;
+        Ok(scheduled)
+    }
+
+    /// Cancel the one-time power off and skip the next daily one.
+    /// Returns `false` if there was nothing to cancel.
+    #[graphql(guard = "AdminGuard")]
+    async fn cancel_poweroff(&self) -> bool {
+        self.poweroff_scheduler.cancel().await
+    }
+
+    /// Speak `text` using the piano along with the playing recording.
+    /// Returns after the speech is synthesized and the playback started.
+    async fn announce(&self, text: String) -> Result<bool> {
+        let speech = self
+            .tts
+            .synthesize(&text)
+            .await
+            .map_err(GraphQLError::extend)?;
+        self.piano
+            .play_secondary(speech)
+            .await
+            .map(|_| true)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Turn the HDMI display on or off (e.g. when it shows a wall dashboard at night).
+    #[graphql(guard = "AdminGuard")]
+    async fn set_display_power(&self, on: bool) -> Result<bool> {
+        self.display
+            .set_power(on)
+            .await
+            .map(|_| on)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Look for a newer release. Returns null if the running version is the latest one.
+    async fn check_for_update(&self) -> Result<Option<AvailableUpdate>> {
+        let updater = self
+            .updater
+            .as_ref()
+            .ok_or(UpdateError::NotConfigured)
+            .map_err(GraphQLError::extend)?;
+        updater.check().await.map_err(GraphQLError::extend)
+    }
+
+    /// Install the latest release and restart the server. Returns the installed version.
+    /// Progress is reported by the `UPDATE_PROGRESS` global events.
+    #[graphql(guard = "AdminGuard")]
+    async fn apply_update(&self) -> Result<String> {
+        let updater = self
+            .updater
+            .as_ref()
+            .ok_or(UpdateError::NotConfigured)
+            .map_err(GraphQLError::extend)?;
+        let version = updater.apply().await.map_err(GraphQLError::extend)?;
+        self.storage.audit("apply_update", version.clone()).await;
+        Ok(version)
+    }
+
+    /// Change the max log verbosity of `module` (e.g. `homie_home::bluetooth`) and all its
+    /// nested children. If `module` is not passed, the default level will be changed.
+    /// Changes are not persisted across restarts.
+    #[graphql(guard = "AdminGuard")]
+    async fn set_log_level(&self, level: LogLevelFilter, module: Option<String>) -> LogLevels {
+        AppLogger::set_level(module, level)
+    }
+
+    /// Remove the module override set by `setLogLevel`.
+    #[graphql(guard = "AdminGuard")]
+    async fn reset_log_level(&self, module: String) -> LogLevels {
+        AppLogger::reset_level(&module)
+    }
+
+    /// Enable or disable the automation rule until restart.
+    #[graphql(guard = "AdminGuard")]
+    async fn set_automation_rule_enabled(&self, name: String, enabled: bool) -> Result<bool> {
+        self.automation
+            .set_enabled(&name, enabled)
+            .map(|_| enabled)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Perform all actions of the scene. Returns after they are finished.
+    #[graphql(guard = "AdminGuard")]
+    async fn activate_scene(&self, name: String) -> Result<bool> {
+        self.automation
+            .activate_scene(&name)
+            .await
+            .map(|_| true)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Override the configuration value (in the same format as in the YAML file).
+    /// Pass null to use the value from the configuration file again. The configuration is
+    /// validated before saving, changes take effect after `reloadConfig`.
+    #[graphql(guard = "AdminGuard")]
+    async fn set_config_value(
+        &self,
+        key: ConfigKey,
+        value: Json<serde_json::Value>,
+    ) -> Result<bool> {
+        let details = format!("{key:?} = {}", value.0);
+        self.config_editor
+            .set(key, value.0)
+            .await
+            .map_err(GraphQLError::extend)?;
+        self.storage.audit("set_config_value", details).await;
+        Ok(true)
+    }
+
+    /// Restart the server to apply the configuration changes.
+    /// The response is sent before stopping.
+    #[graphql(guard = "AdminGuard")]
+    async fn reload_config(&self) -> Result<bool> {
+        // Recorded before, as the server is stopped by the restart.
+        self.storage.audit("reload_config", "").await;
+        self.config_editor
+            .reload()
+            .await
+            .map(|_| true)
+            .map_err(GraphQLError::extend)
+    }
 }
 
 impl Deref for MutationRoot {
@@ -35,20 +237,92 @@ impl Deref for MutationRoot {
     }
 }
 
+struct MemosMutation<'a>(&'a VoiceMemos);
+
+#[Object]
+impl MemosMutation<'_> {
+    async fn record(&self) -> Result<bool> {
+        self.0
+            .record()
+            .await
+            .map(|_| true)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Returns the saved memo.
+    async fn stop(&self) -> Result<VoiceMemo> {
+        self.0.stop().await.map_err(GraphQLError::extend)
+    }
+
+    async fn remove(&self, id: Scalar<i64>) -> Result<bool> {
+        self.0
+            .remove(*id)
+            .await
+            .map(|_| true)
+            .map_err(GraphQLError::extend)
+    }
+}
+
 struct PianoMutation<'a>(&'a Piano);
 
 #[Object]
 impl PianoMutation<'_> {
     /// Executing this mutation can take a long time as it _decodes_ entire recording.
     /// If there is already playing recording, it will be stopped.
-    async fn play_recording(&self, id: Scalar<i64>) -> Result<i64> {
+    /// Playback control mutations are applied to the passed `output`.
+    /// If it's null, the preferred output is used (see `setOutputOverride`).
+    async fn play_recording(&self, id: Scalar<i64>, output: Option<AudioOutput>) -> Result<i64> {
+        self.0
+            .play_recording(*id, output)
+            .await
+            .map(|_| *id)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Play the recording on several outputs simultaneously (e.g. piano and the room DAC),
+    /// each with its own volume. The first output is primary: its position is reported and
+    /// automatic switching doesn't move the playback. Control mutations apply to all outputs.
+    async fn play_recording_on_outputs(
+        &self,
+        id: Scalar<i64>,
+        outputs: Vec<OutputTarget>,
+    ) -> Result<i64> {
+        let route = OutputRoute::new(outputs).map_err(GraphQLError::extend)?;
+        self.0
+            .play_recording_on(*id, route)
+            .await
+            .map(|_| *id)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Add the recording to the end of the playback queue (or play it right away if nothing
+    /// is playing) on the outputs of the last played recording. The player switches to the
+    /// next queued recording when the current one finishes. Returns ID of the recording.
+    async fn enqueue_recording(&self, id: Scalar<i64>) -> Result<i64> {
         self.0
-            .play_recording(*id)
+            .enqueue_recording(*id)
             .await
             .map(|_| *id)
             .map_err(GraphQLError::extend)
     }
 
+    /// Remove all recordings from the playback queue. The current one continues playing.
+    /// Returns the number of removed recordings.
+    async fn clear_queue(&self) -> usize {
+        self.0.clear_queue().await
+    }
+
+    /// Play the next queued recording right away. Returns its ID.
+    async fn skip_next(&self) -> Result<i64> {
+        self.0.skip_next().await.map_err(GraphQLError::extend)
+    }
+
+    /// Play the recording which was played before the current one, the current recording
+    /// is placed at the beginning of the queue. Returns ID of the played recording.
+    async fn skip_previous(&self) -> Result<i64> {
+        self.0.skip_previous().await.map_err(GraphQLError::extend)
+    }
+
     /// Takes a number in range `[0.00, 1.00]`, where `0.00` is the beginning of an audio source
     /// and `1.00` is the end. Returns `false` if there is no playing (or paused) audio.
     async fn seek_player_to_percents(&self, percents: f64) -> Result<bool> {
@@ -67,6 +341,112 @@ impl PianoMutation<'_> {
             .map_err(GraphQLError::extend)
     }
 
+    /// Store a named position inside the recording.
+    async fn add_bookmark(
+        &self,
+        ctx: &Context<'_>,
+        id: Scalar<i64>,
+        position_ms: u64,
+        label: String,
+    ) -> Result<Bookmark> {
+        let recording = self
+            .0
+            .recording_storage
+            .get(*id)
+            .await
+            .map_err(|e| BookmarkError::GetRecording(e).extend())?;
+        ctx.data::<Storage>()?
+            .add_bookmark(&recording, Duration::from_millis(position_ms), label)
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Returns `false` if the bookmark doesn't exist.
+    async fn remove_bookmark(&self, ctx: &Context<'_>, bookmark_id: Scalar<i64>) -> Result<bool> {
+        ctx.data::<Storage>()?
+            .remove_bookmark(*bookmark_id)
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Play the last played recording on the preferred output from the saved position,
+    /// e.g. after the server restarted or piano reconnected. Returns ID of the recording.
+    async fn resume_last_session(&self, ctx: &Context<'_>) -> Result<i64> {
+        let session = ctx
+            .data::<Storage>()?
+            .playback_session()
+            .await
+            .map_err(GraphQLError::extend)?
+            .ok_or(piano::PlayRecordingError::NoPlaybackSession)
+            .map_err(GraphQLError::extend)?;
+        self.0
+            .play_recording_from(
+                session.recording_id,
+                Duration::from_millis(session.position_ms),
+            )
+            .await
+            .map(|_| session.recording_id)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Play the recording of the bookmark on the preferred output starting from its position.
+    /// Returns ID of the recording.
+    async fn play_from_bookmark(&self, ctx: &Context<'_>, bookmark_id: Scalar<i64>) -> Result<i64> {
+        let bookmark = ctx
+            .data::<Storage>()?
+            .bookmark(*bookmark_id)
+            .await
+            .map_err(GraphQLError::extend)?;
+        self.0
+            .play_recording_from(
+                bookmark.recording_id,
+                Duration::from_millis(bookmark.position_ms),
+            )
+            .await
+            .map(|_| bookmark.recording_id)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Set the title of the recording, which is also used as the name of the downloaded file.
+    async fn rename_recording(&self, id: Scalar<i64>, title: String) -> Result<PianoRecording> {
+        self.0
+            .recording_storage
+            .rename(*id, &title)
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Play the part of the recording between `fromMs` and `toMs` repeatedly, which is handy
+    /// to practice a difficult passage. Recording is started on the preferred output
+    /// if it's not playing. The region is cleared when another recording is played.
+    async fn set_loop_region(&self, id: Scalar<i64>, from_ms: u64, to_ms: u64) -> Result<bool> {
+        let region = LoopRegion {
+            from: Duration::from_millis(from_ms),
+            to: Duration::from_millis(to_ms),
+        };
+        self.0
+            .set_loop_region(*id, region)
+            .await
+            .map(|_| true)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Continue the playback after the loop region.
+    /// Returns `false` if there was no loop region.
+    async fn clear_loop_region(&self) -> Result<bool> {
+        self.0
+            .clear_loop_region()
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Force the recordings to play on `output`. Pass null to route them automatically:
+    /// to the monitor output while it's plugged in (if `auto_switch` is enabled), otherwise
+    /// to the piano. Playing recording is moved to the new output. Returns the active output.
+    async fn set_output_override(&self, output: Option<AudioOutput>) -> AudioOutput {
+        self.0.set_output_override(output).await
+    }
+
     /// Returns `true` if there is was paused recording.
     async fn resume_player(&self) -> Result<bool> {
         self.0.resume_player().await.map_err(GraphQLError::extend)
@@ -77,14 +457,32 @@ impl PianoMutation<'_> {
         self.0.pause_player().await.map_err(GraphQLError::extend)
     }
 
-    /// Start the recorder. Piano event `RECORDING_LENGTH_LIMIT_REACHED`
-    /// will be triggered if recording takes too long.
-    async fn record(&self) -> Result<bool> {
-        self.0
-            .record()
-            .await
-            .map(|_| true)
-            .map_err(GraphQLError::extend)
+    /// Start the recorder. If recording takes longer than `piano.max_recording_duration_secs`,
+    /// piano event `RECORDING_LENGTH_LIMIT_REACHED` is triggered, the recording is saved and
+    /// then `RECORDER_STOPPED_AUTOMATICALLY` is triggered.
+    ///
+    /// If `wait_for_device` is set and the recorder is not available (e.g. the audio device
+    /// is held by an A2DP source), recording starts as soon as it becomes available
+    /// and `false` is returned. Piano events `RECORD_WAITING_FOR_DEVICE`
+    /// and `RECORD_WAIT_TIMED_OUT` are triggered in this case.
+    async fn record(&self, #[graphql(default)] wait_for_device: bool) -> Result<bool> {
+        if wait_for_device {
+            self.0.record_when_available().await
+        } else {
+            self.0.record().await.map(|_| true)
+        }
+        .map_err(GraphQLError::extend)
+    }
+
+    /// Discard the captured audio until `resumeRecorder` is called, so the recording
+    /// continues in the same file. Returns `false` if the recorder is already paused.
+    async fn pause_recorder(&self) -> Result<bool> {
+        self.0.pause_recorder().await.map_err(GraphQLError::extend)
+    }
+
+    /// Returns `false` if the recorder is not paused.
+    async fn resume_recorder(&self) -> Result<bool> {
+        self.0.resume_recorder().await.map_err(GraphQLError::extend)
     }
 
     /// Stop recorder and preserve a new recording.
@@ -1,12 +1,35 @@
-use std::ops::Deref;
+use std::{cmp::Reverse, ops::Deref};
 
-use async_graphql::{Object, Result};
+use async_graphql::{Context, Json, Object, Result};
 
-use super::GraphQLError;
+use super::{AdminGuard, GraphQLError};
 use crate::{
-    core::SortOrder,
-    device::piano::{recordings::Recording as PianoRecording, Piano},
+    audio::{self, transcode::TranscodeJob},
+    automation::{AutomationRuleStatus, CalendarTrigger},
+    calendar::CalendarEvent,
+    core::{
+        logger::{AppLogger, LogLevel, LogLevels, LogRecord},
+        metrics::{self, Metric},
+        task::TaskStatus,
+        timezone, SortOrder,
+    },
+    dbus::{MediaTrack, NetworkInfo},
+    device::{
+        midi::MidiController,
+        piano::{
+            recordings::{Recording as PianoRecording, RecordingSort},
+            Piano, PianoAudioInfo,
+        },
+        plugin::DeviceStatus,
+        power::PowerStatus,
+        voice_memo::{VoiceMemo, VoiceMemoError, VoiceMemos},
+    },
+    poweroff::ScheduledPoweroff,
     prefs::Preferences,
+    presence::PersonPresence,
+    remote_backup::BackupUploadStatus,
+    storage::{AuditRecord, LoggedEvent, SensorReading, Storage, StorageStats},
+    weather::OutdoorWeather,
     App,
 };
 
@@ -18,9 +41,179 @@ impl QueryRoot {
         PianoQuery(&self.piano)
     }
 
+    /// Fails if voice memos are not configured.
+    async fn memos(&self) -> Result<MemosQuery> {
+        self.voice_memos
+            .as_ref()
+            .map(MemosQuery)
+            .ok_or(VoiceMemoError::NotConfigured)
+            .map_err(GraphQLError::extend)
+    }
+
+    async fn system(&self) -> SystemQuery {
+        SystemQuery(&self.0)
+    }
+
     async fn preferences(&self) -> Preferences {
         self.prefs.read().await.clone()
     }
+
+    /// Up to `limit` most recent log records with at least `min_level` severity,
+    /// ordered from the oldest to the newest.
+    async fn logs(
+        &self,
+        #[graphql(default = 100)] limit: u32,
+        #[graphql(default_with = "LogLevel::Info")] min_level: LogLevel,
+    ) -> Vec<LogRecord> {
+        AppLogger::buffer()
+            .map(|buffer| buffer.recent(limit as usize, min_level))
+            .unwrap_or_default()
+    }
+
+    /// Current log verbosity levels.
+    async fn log_levels(&self) -> LogLevels {
+        AppLogger::levels()
+    }
+
+    /// Statuses of all registered devices.
+    async fn devices(&self) -> Vec<DeviceStatus> {
+        self.devices.statuses().await
+    }
+
+    /// Piano devices and negotiated stream formats, useful to debug the format problems.
+    async fn piano_audio_info(&self) -> PianoAudioInfo {
+        self.piano.audio_info().await
+    }
+
+    /// Names of the devices which can be set as `audio.outputDevice` in the preferences.
+    async fn available_output_devices(&self) -> Vec<String> {
+        audio::output_device_names()
+    }
+
+    /// Currently plugged in MIDI controllers.
+    async fn midi_controllers(&self) -> Vec<MidiController> {
+        self.midi_controllers.list().await
+    }
+
+    /// Track which is playing on a connected Bluetooth audio source.
+    /// It's null if there is no such device or it doesn't provide the metadata.
+    async fn bluetooth_track(&self) -> Option<MediaTrack> {
+        self.a2dp_source_handler.current_track(&self.dbus).await
+    }
+
+    /// Battery status. It's null if power monitoring is not configured or there is no battery.
+    async fn power(&self) -> Option<PowerStatus> {
+        match &self.power {
+            Some(power) => power.status().await,
+            None => None,
+        }
+    }
+
+    /// Hostname and network interfaces reported by NetworkManager,
+    /// so it's known how to reach the server.
+    async fn network(&self) -> Result<NetworkInfo> {
+        Ok(self.dbus.network_info().await?)
+    }
+
+    /// Waiting and running audio conversions.
+    async fn transcode_jobs(&self) -> Vec<TranscodeJob> {
+        self.transcoder.jobs()
+    }
+
+    /// Statuses of the background jobs, useful for diagnostics.
+    async fn background_tasks(&self) -> Vec<TaskStatus> {
+        self.tasks.list().await
+    }
+
+    /// Internal counters and gauges. Also exported on `/api/metrics` in the Prometheus format.
+    async fn metrics(&self) -> Vec<Metric> {
+        metrics::snapshot()
+    }
+
+    /// Home / away state of the configured people. Changes are sent
+    /// as the `PRESENCE_CHANGED` global events. Empty if presence is not configured.
+    async fn presence(&self) -> Vec<PersonPresence> {
+        match &self.presence {
+            Some(presence) => presence.people().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Current outdoor weather to compare with the `loungeTempMonitorData` subscription.
+    /// Null if weather is not configured or it's not fetched yet.
+    async fn outdoor_weather(&self) -> Option<OutdoorWeather> {
+        match &self.weather {
+            Some(weather) => weather.current().await,
+            None => None,
+        }
+    }
+
+    /// Rules from the `automation` configuration section.
+    async fn automation_rules(&self) -> Vec<AutomationRuleStatus> {
+        self.automation.rules()
+    }
+
+    /// Events of the configured calendar which are not finished yet, ordered by the start time.
+    async fn calendar_events(&self) -> Vec<CalendarEvent> {
+        match &self.calendar {
+            Some(calendar) => calendar.upcoming().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// The nearest automation rule trigger by a calendar event.
+    async fn next_calendar_trigger(&self) -> Option<CalendarTrigger> {
+        self.automation.next_calendar_trigger().await
+    }
+
+    /// Names of the configured scenes which can be passed to `activateScene`.
+    async fn scenes(&self) -> Vec<String> {
+        self.automation.scene_names()
+    }
+
+    /// Configuration values changed using `setConfigValue`. Null if there are no changes.
+    #[graphql(guard = "AdminGuard")]
+    async fn config_overlay(&self) -> Result<Json<serde_json::Value>> {
+        self.config_editor
+            .overlay()
+            .await
+            .map(Json)
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Size of the database and number of the stored records.
+    async fn storage_stats(&self) -> Result<StorageStats> {
+        self.storage.stats().await.map_err(GraphQLError::extend)
+    }
+
+    /// Lounge sensor readings for the last `hours`, ordered from the oldest to the newest.
+    async fn sensor_history(
+        &self,
+        #[graphql(default = 24)] hours: u32,
+    ) -> Result<Vec<SensorReading>> {
+        let since = timezone::now() - chrono::Duration::hours(hours.into());
+        self.storage
+            .sensor_history(since)
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Up to `limit` most recent piano and global events, ordered from the newest.
+    async fn event_log(&self, #[graphql(default = 100)] limit: u32) -> Result<Vec<LoggedEvent>> {
+        self.storage
+            .event_log(limit)
+            .await
+            .map_err(GraphQLError::extend)
+    }
+
+    /// Up to `limit` most recent administrative actions, ordered from the newest.
+    #[graphql(guard = "AdminGuard")]
+    async fn audit_log(&self, #[graphql(default = 100)] limit: u32) -> Result<Vec<AuditRecord>> {
+        self.storage
+            .audit_log(limit)
+            .await
+            .map_err(GraphQLError::extend)
+    }
 }
 
 impl Deref for QueryRoot {
@@ -31,19 +224,80 @@ impl Deref for QueryRoot {
     }
 }
 
+struct SystemQuery<'a>(&'a App);
+
+#[Object]
+impl SystemQuery<'_> {
+    /// Version of the server.
+    async fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// Whether the HDMI display is on.
+    async fn display_on(&self) -> Result<bool> {
+        self.0.display.is_on().await.map_err(GraphQLError::extend)
+    }
+
+    /// The nearest power off (one-time or daily). It's null if there is no one.
+    async fn scheduled_poweroff(&self) -> Option<ScheduledPoweroff> {
+        self.0.poweroff_scheduler.pending().await
+    }
+
+    /// Results of the last scheduled backup uploads, one per target.
+    async fn backup_uploads(&self) -> Vec<BackupUploadStatus> {
+        self.0.remote_backup.last_uploads().await
+    }
+}
+
+struct MemosQuery<'a>(&'a VoiceMemos);
+
+#[Object]
+impl MemosQuery<'_> {
+    /// From the newest to the oldest.
+    async fn list(&self) -> Result<Vec<VoiceMemo>> {
+        self.0.list().await.map_err(GraphQLError::extend)
+    }
+
+    async fn is_recording(&self) -> bool {
+        self.0.is_recording().await
+    }
+}
+
 struct PianoQuery<'a>(&'a Piano);
 
 #[Object]
 impl PianoQuery<'_> {
-    /// Recordings ordered by the creation time.
+    /// Recordings ordered by the creation time. If `sort` is set, they are ordered
+    /// by the play statistics, and `order` is only applied to the equal ones.
     async fn recordings(
         &self,
+        ctx: &Context<'_>,
         #[graphql(default_with = "SortOrder::Descending")] order: SortOrder,
+        sort: Option<RecordingSort>,
     ) -> Result<Vec<PianoRecording>> {
-        self.0
+        let mut recordings = self
+            .0
             .recording_storage
             .list(order)
             .await
-            .map_err(GraphQLError::extend)
+            .map_err(GraphQLError::extend)?;
+        if let Some(sort) = sort {
+            let stats = ctx
+                .data::<Storage>()?
+                .all_play_stats()
+                .await
+                .map_err(GraphQLError::extend)?;
+            // Stable sort keeps `order` for the equal keys.
+            recordings.sort_by_key(|recording| {
+                let stats = stats.get(&recording.id());
+                Reverse(match sort {
+                    RecordingSort::RecentlyPlayed => {
+                        stats.map_or(i64::MIN, |stats| stats.last_played_at.timestamp_millis())
+                    }
+                    RecordingSort::MostPlayed => stats.map_or(0, |stats| stats.play_count.into()),
+                })
+            });
+        }
+        Ok(recordings)
     }
 }
@@ -2,35 +2,82 @@ use std::{ops::Deref, sync::Arc, time::Duration};
 
 use async_graphql::{Result, Subscription};
 use async_stream::stream;
-use futures::{Stream, TryStreamExt};
-use tokio::select;
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio::{select, sync::broadcast};
 
 use super::GraphQLError;
 use crate::{
+    core::{
+        logger::{AppLogger, LogBuffer, LogLevel, LogRecord},
+        Event,
+    },
+    dbus::BluetoothDeviceChange,
     device::{
         mi_temp_monitor,
         piano::{PianoEvent, PianoPlaybackStatus, PianoStatus},
+        power::PowerStatus,
     },
-    App, GlobalEvent,
+    udev::HotplugEvent,
+    App, GlobalEvent, GlobalEventKind,
 };
 
 pub struct SubscriptionRoot(pub(super) App);
 
 #[Subscription]
 impl SubscriptionRoot {
-    async fn global_events(&self) -> impl Stream<Item = GlobalEvent> {
+    /// Only the kinds of the events, use `globalEventDetails` to receive their data.
+    async fn global_events(&self) -> impl Stream<Item = Event<GlobalEventKind>> {
+        self.event_broadcaster
+            .recv_continuously(self.shutdown_notify.clone())
+            .await
+            .then(|event| async move {
+                Event {
+                    id: event.id,
+                    timestamp: event.timestamp,
+                    payload: event.payload.kind().await,
+                }
+            })
+    }
+
+    async fn global_event_details(&self) -> impl Stream<Item = Event<GlobalEvent>> {
         self.event_broadcaster
             .recv_continuously(self.shutdown_notify.clone())
             .await
     }
 
-    async fn piano_events(&self) -> impl Stream<Item = PianoEvent> {
+    async fn piano_events(&self) -> impl Stream<Item = Event<PianoEvent>> {
         self.piano
             .event_broadcaster
             .recv_continuously(self.shutdown_notify.clone())
             .await
     }
 
+    /// Devices plugged in or unplugged within the monitored udev subsystems.
+    async fn device_hotplug(&self) -> impl Stream<Item = Event<HotplugEvent>> {
+        self.hotplug_broadcaster
+            .recv_continuously(self.shutdown_notify.clone())
+            .await
+    }
+
+    /// Connection state, RSSI and battery changes of the Bluetooth devices.
+    async fn bluetooth_device_changes(&self) -> impl Stream<Item = Event<BluetoothDeviceChange>> {
+        self.bluetooth_device_broadcaster
+            .recv_continuously(self.shutdown_notify.clone())
+            .await
+    }
+
+    /// Changes of the battery status. Returns an error if power monitoring is not configured.
+    async fn power_status(&self) -> Result<impl Stream<Item = Event<PowerStatus>>> {
+        let power = self
+            .power
+            .as_ref()
+            .ok_or("power monitoring is not configured")?;
+        Ok(power
+            .status_broadcaster
+            .recv_continuously(self.shutdown_notify.clone())
+            .await)
+    }
+
     async fn piano_status(&self) -> impl Stream<Item = Result<PianoStatus>> {
         self.piano
             .clone()
@@ -53,6 +100,31 @@ impl SubscriptionRoot {
             .map_err(GraphQLError::extend)
     }
 
+    /// New log records with at least `min_level` severity.
+    async fn log_stream(
+        &self,
+        #[graphql(default_with = "LogLevel::Trace")] min_level: LogLevel,
+    ) -> impl Stream<Item = LogRecord> {
+        let receiver = AppLogger::buffer().map(LogBuffer::subscribe);
+        let shutdown_notify = self.shutdown_notify.clone();
+        stream! {
+            // Logger is not installed.
+            let Some(mut receiver) = receiver else {
+                return;
+            };
+            loop {
+                select! {
+                    result = receiver.recv() => match result {
+                        Ok(record) if record.is_at_least(min_level) => yield record,
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = shutdown_notify.notified() => break,
+                }
+            }
+        }
+    }
+
     async fn lounge_temp_monitor_data(
         &self,
     ) -> Result<impl Stream<Item = Option<mi_temp_monitor::Data>>> {
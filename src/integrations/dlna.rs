@@ -0,0 +1,544 @@
+use std::{
+    fs, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket},
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::{pin_mut, StreamExt};
+use log::{debug, error, info};
+use metaflac::block::PictureType;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{net::UdpSocket, select};
+use uuid::Uuid;
+
+use crate::{
+    config,
+    core::{HumanDateParams, ShutdownNotify, SortOrder},
+    device::piano::{
+        recordings::{Recording, RecordingStorageError},
+        Piano, PianoEvent,
+    },
+};
+
+const SSDP_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+/// How long the announcements are valid.
+const SSDP_MAX_AGE_SECS: u64 = 1800;
+/// Announcements are repeated before they expire.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(SSDP_MAX_AGE_SECS / 3);
+const SERVER_HEADER: &str = concat!(
+    "Linux UPnP/1.0 ",
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION")
+);
+
+const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+const ROOT_CONTAINER_ID: &str = "0";
+/// Byte seeking is supported, the file is streamed.
+const FLAC_CONTENT_FEATURES: &str =
+    "DLNA.ORG_OP=01;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DlnaService {
+    ContentDirectory,
+    ConnectionManager,
+}
+
+impl DlnaService {
+    fn service_type(&self) -> &'static str {
+        match self {
+            Self::ContentDirectory => "urn:schemas-upnp-org:service:ContentDirectory:1",
+            Self::ConnectionManager => "urn:schemas-upnp-org:service:ConnectionManager:1",
+        }
+    }
+
+    fn service_id(&self) -> &'static str {
+        match self {
+            Self::ContentDirectory => "urn:upnp-org:serviceId:ContentDirectory",
+            Self::ConnectionManager => "urn:upnp-org:serviceId:ConnectionManager",
+        }
+    }
+
+    /// Last path segment of the endpoints.
+    fn path_segment(&self) -> &'static str {
+        match self {
+            Self::ContentDirectory => "content-directory",
+            Self::ConnectionManager => "connection-manager",
+        }
+    }
+
+    /// Service description (SCPD).
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::ContentDirectory => CONTENT_DIRECTORY_SCPD,
+            Self::ConnectionManager => CONNECTION_MANAGER_SCPD,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DlnaError {
+    #[error("Invalid action")]
+    InvalidAction,
+    #[error("Invalid arguments")]
+    InvalidArgs,
+    #[error("No such object")]
+    NoSuchObject,
+    #[error("Failed to read the recordings: {0}")]
+    StorageError(RecordingStorageError),
+}
+
+impl DlnaError {
+    /// UPnP error code.
+    fn code(&self) -> u16 {
+        match self {
+            Self::InvalidAction => 401,
+            Self::InvalidArgs => 402,
+            Self::NoSuchObject => 701,
+            Self::StorageError(_) => 501,
+        }
+    }
+
+    /// SOAP fault which must be returned with the 500 status code.
+    pub fn soap_fault(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"#,
+            self.code(),
+            escape(&self.to_string()),
+        )
+    }
+}
+
+/// Cover art embedded into a recording.
+pub struct CoverArt {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Minimal DLNA media server which exposes the piano recordings as a flat list,
+/// so TVs and streamers in the local network can play them.
+///
+/// Devices are discovered using SSDP, while the descriptions, control and media
+/// are served by the HTTP server under `/dlna` (without authorization).
+#[derive(Clone)]
+pub struct DlnaServer {
+    config: config::Dlna,
+    server_port: u16,
+    /// Derived from the name, so the clients recognize the server after restart.
+    uuid: Uuid,
+    piano: Piano,
+    /// Changes when recordings are added or removed.
+    system_update_id: Arc<AtomicU32>,
+}
+
+impl DlnaServer {
+    pub fn new(config: config::Dlna, server_port: u16, piano: Piano) -> Self {
+        Self {
+            uuid: Uuid::new_v5(&Uuid::NAMESPACE_OID, config.friendly_name.as_bytes()),
+            config,
+            server_port,
+            piano,
+            system_update_id: Arc::new(AtomicU32::new(1)),
+        }
+    }
+
+    /// Answer the discovery requests and announce the server periodically until shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) -> io::Result<()> {
+        let socket = ssdp_socket()?;
+        info!("DLNA server {} started", self.uuid);
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await;
+        pin_mut!(piano_events);
+        let mut announce_interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+        let mut buffer = [0; 2048];
+
+        loop {
+            select! {
+                _ = announce_interval.tick() => self.announce(&socket, true).await,
+                result = socket.recv_from(&mut buffer) => {
+                    let (len, peer) = result?;
+                    let message = String::from_utf8_lossy(&buffer[..len]);
+                    if let Some(search_target) = parse_search_target(&message) {
+                        self.answer_search(&socket, search_target, peer).await;
+                    }
+                }
+                Some(event) = piano_events.next() => {
+                    if matches!(
+                        event.payload,
+                        PianoEvent::NewRecordingSaved
+                            | PianoEvent::OldRecordingsRemoved
+                            | PianoEvent::RecordingsModified
+                    ) {
+                        self.system_update_id.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                _ = shutdown_notify.notified() => break,
+            }
+        }
+        self.announce(&socket, false).await;
+        Ok(())
+    }
+
+    /// `base_url` is the address of the HTTP server which the client used.
+    pub fn device_description(&self, base_url: &str) -> String {
+        let services: String = [
+            DlnaService::ContentDirectory,
+            DlnaService::ConnectionManager,
+        ]
+        .iter()
+        .map(|service| {
+            let segment = service.path_segment();
+            format!(
+                "<service><serviceType>{}</serviceType><serviceId>{}</serviceId>\
+                    <SCPDURL>/dlna/scpd/{segment}</SCPDURL>\
+                    <controlURL>/dlna/control/{segment}</controlURL>\
+                    <eventSubURL>/dlna/events/{segment}</eventSubURL></service>",
+                service.service_type(),
+                service.service_id(),
+            )
+        })
+        .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0"><specVersion><major>1</major><minor>0</minor></specVersion><URLBase>{base_url}</URLBase><device><deviceType>{DEVICE_TYPE}</deviceType><friendlyName>{}</friendlyName><manufacturer>{}</manufacturer><modelName>{}</modelName><modelNumber>{}</modelNumber><UDN>uuid:{}</UDN><serviceList>{services}</serviceList></device></root>"#,
+            escape(&self.config.friendly_name),
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            self.uuid,
+        )
+    }
+
+    /// Handle a SOAP request. `soap_action` is the value of the `SOAPACTION` header.
+    /// Returns the response envelope.
+    pub async fn control(
+        &self,
+        service: DlnaService,
+        soap_action: &str,
+        body: &str,
+        base_url: &str,
+    ) -> Result<String, DlnaError> {
+        let action = soap_action
+            .trim_matches('"')
+            .split_once('#')
+            .filter(|(service_type, _)| *service_type == service.service_type())
+            .map(|(_, action)| action)
+            .ok_or(DlnaError::InvalidAction)?;
+        let arguments = match (service, action) {
+            (DlnaService::ContentDirectory, "Browse") => self.browse(body, base_url).await?,
+            (DlnaService::ContentDirectory, "GetSystemUpdateID") => {
+                format!("<Id>{}</Id>", self.system_update_id.load(Ordering::Relaxed))
+            }
+            (DlnaService::ContentDirectory, "GetSearchCapabilities") => {
+                "<SearchCaps></SearchCaps>".to_string()
+            }
+            (DlnaService::ContentDirectory, "GetSortCapabilities") => {
+                "<SortCaps></SortCaps>".to_string()
+            }
+            (DlnaService::ConnectionManager, "GetProtocolInfo") => {
+                "<Source>http-get:*:audio/flac:*</Source><Sink></Sink>".to_string()
+            }
+            (DlnaService::ConnectionManager, "GetCurrentConnectionIDs") => {
+                "<ConnectionIDs>0</ConnectionIDs>".to_string()
+            }
+            _ => return Err(DlnaError::InvalidAction),
+        };
+        Ok(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{action}Response xmlns:u="{}">{arguments}</u:{action}Response></s:Body></s:Envelope>"#,
+            service.service_type(),
+        ))
+    }
+
+    /// Returns [None] if the recording doesn't exist or doesn't have a picture.
+    pub async fn cover_art(&self, recording_id: i64) -> Option<CoverArt> {
+        let recording = self.piano.recording_storage.get(recording_id).await.ok()?;
+        let tag = metaflac::Tag::read_from_path(&recording.flac_path).ok()?;
+        let mut pictures: Vec<_> = tag.pictures().collect();
+        pictures.sort_by_key(|picture| picture.picture_type != PictureType::CoverFront);
+        pictures.first().map(|picture| CoverArt {
+            mime_type: picture.mime_type.clone(),
+            data: picture.data.clone(),
+        })
+    }
+
+    async fn browse(&self, body: &str, base_url: &str) -> Result<String, DlnaError> {
+        let object_id = xml_element(body, "ObjectID").ok_or(DlnaError::InvalidArgs)?;
+        let browse_flag = xml_element(body, "BrowseFlag").ok_or(DlnaError::InvalidArgs)?;
+        let starting_index: usize = xml_element(body, "StartingIndex")
+            .and_then(|index| index.parse().ok())
+            .unwrap_or(0);
+        // Zero means all.
+        let requested_count: usize = xml_element(body, "RequestedCount")
+            .and_then(|count| count.parse().ok())
+            .filter(|count| *count != 0)
+            .unwrap_or(usize::MAX);
+
+        let recordings = self
+            .piano
+            .recording_storage
+            .list(SortOrder::Descending)
+            .await
+            .map_err(DlnaError::StorageError)?;
+        let (objects, number_returned, total_matches) = match (object_id, browse_flag) {
+            (ROOT_CONTAINER_ID, "BrowseMetadata") => (
+                format!(
+                    r#"<container id="{ROOT_CONTAINER_ID}" parentID="-1" restricted="1" childCount="{}"><dc:title>Piano recordings</dc:title><upnp:class>object.container.storageFolder</upnp:class></container>"#,
+                    recordings.len()
+                ),
+                1,
+                1,
+            ),
+            (ROOT_CONTAINER_ID, "BrowseDirectChildren") => {
+                let page: Vec<_> = recordings
+                    .iter()
+                    .skip(starting_index)
+                    .take(requested_count)
+                    .map(|recording| didl_item(recording, base_url))
+                    .collect();
+                (page.concat(), page.len(), recordings.len())
+            }
+            (object_id, browse_flag) => {
+                let recording = recordings
+                    .iter()
+                    .find(|recording| recording.id().to_string() == object_id)
+                    .ok_or(DlnaError::NoSuchObject)?;
+                match browse_flag {
+                    "BrowseMetadata" => (didl_item(recording, base_url), 1, 1),
+                    "BrowseDirectChildren" => (String::new(), 0, 0),
+                    _ => return Err(DlnaError::InvalidArgs),
+                }
+            }
+        };
+        let didl = format!(
+            r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/" xmlns:dlna="urn:schemas-dlna-org:metadata-1-0/">{objects}</DIDL-Lite>"#
+        );
+        Ok(format!(
+            "<Result>{}</Result><NumberReturned>{number_returned}</NumberReturned>\
+            <TotalMatches>{total_matches}</TotalMatches><UpdateID>{}</UpdateID>",
+            escape(&didl),
+            self.system_update_id.load(Ordering::Relaxed),
+        ))
+    }
+
+    async fn answer_search(&self, socket: &UdpSocket, search_target: &str, peer: SocketAddr) {
+        let Ok(ip) = local_ip(peer) else {
+            return;
+        };
+        for (notification_type, usn) in self.notification_types() {
+            if search_target != "ssdp:all" && search_target != notification_type {
+                continue;
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={SSDP_MAX_AGE_SECS}\r\nEXT:\r\n\
+                LOCATION: {}\r\nSERVER: {SERVER_HEADER}\r\nST: {notification_type}\r\n\
+                USN: {usn}\r\n\r\n",
+                self.description_url(ip),
+            );
+            if let Err(e) = socket.send_to(response.as_bytes(), peer).await {
+                debug!("Failed to answer the SSDP search: {e}");
+            }
+        }
+    }
+
+    /// Send `ssdp:alive` notifications if `alive` is `true`, `ssdp:byebye` otherwise.
+    async fn announce(&self, socket: &UdpSocket, alive: bool) {
+        let multicast = SocketAddr::V4(SocketAddrV4::new(SSDP_ADDRESS, SSDP_PORT));
+        let ip = match local_ip(multicast) {
+            Ok(ip) => ip,
+            Err(e) => return error!("Failed to determine the local address for SSDP: {e}"),
+        };
+        for (notification_type, usn) in self.notification_types() {
+            let message = if alive {
+                format!(
+                    "NOTIFY * HTTP/1.1\r\nHOST: {SSDP_ADDRESS}:{SSDP_PORT}\r\n\
+                    CACHE-CONTROL: max-age={SSDP_MAX_AGE_SECS}\r\nLOCATION: {}\r\n\
+                    NT: {notification_type}\r\nNTS: ssdp:alive\r\nSERVER: {SERVER_HEADER}\r\n\
+                    USN: {usn}\r\n\r\n",
+                    self.description_url(ip),
+                )
+            } else {
+                format!(
+                    "NOTIFY * HTTP/1.1\r\nHOST: {SSDP_ADDRESS}:{SSDP_PORT}\r\n\
+                    NT: {notification_type}\r\nNTS: ssdp:byebye\r\nUSN: {usn}\r\n\r\n"
+                )
+            };
+            if let Err(e) = socket.send_to(message.as_bytes(), multicast).await {
+                error!("Failed to send the SSDP notification: {e}");
+                return;
+            }
+        }
+    }
+
+    /// Pairs of the notification type and the unique service name.
+    fn notification_types(&self) -> Vec<(String, String)> {
+        let udn = format!("uuid:{}", self.uuid);
+        let mut types = vec![
+            (
+                "upnp:rootdevice".to_string(),
+                format!("{udn}::upnp:rootdevice"),
+            ),
+            (udn.clone(), udn.clone()),
+            (DEVICE_TYPE.to_string(), format!("{udn}::{DEVICE_TYPE}")),
+        ];
+        for service in [
+            DlnaService::ContentDirectory,
+            DlnaService::ConnectionManager,
+        ] {
+            let service_type = service.service_type();
+            types.push((service_type.to_string(), format!("{udn}::{service_type}")));
+        }
+        types
+    }
+
+    fn description_url(&self, ip: IpAddr) -> String {
+        format!("http://{ip}:{}/dlna/description.xml", self.server_port)
+    }
+}
+
+/// Headers which are expected by some TVs when streaming a recording (lowercase).
+pub fn media_headers() -> [(&'static str, &'static str); 2] {
+    [
+        ("transfermode.dlna.org", "Streaming"),
+        ("contentfeatures.dlna.org", FLAC_CONTENT_FEATURES),
+    ]
+}
+
+fn didl_item(recording: &Recording, base_url: &str) -> String {
+    let id = recording.id();
+    let tag = metaflac::Tag::read_from_path(&recording.flac_path).ok();
+    // Title is embedded by the recorder.
+    let title = recording.title().map(str::to_string).unwrap_or_else(|| {
+        recording.human_creation_date(HumanDateParams {
+            filename_safe: false,
+        })
+    });
+    let album_art = if tag.is_some_and(|tag| tag.pictures().next().is_some()) {
+        format!("<upnp:albumArtURI>{base_url}/dlna/cover/{id}</upnp:albumArtURI>")
+    } else {
+        String::new()
+    };
+    let size = file_size(&recording.flac_path)
+        .map(|size| format!(r#" size="{size}""#))
+        .unwrap_or_default();
+    let duration = recording.duration();
+    format!(
+        r#"<item id="{id}" parentID="{ROOT_CONTAINER_ID}" restricted="1"><dc:title>{}</dc:title><upnp:class>object.item.audioItem.musicTrack</upnp:class>{album_art}<res protocolInfo="http-get:*:audio/flac:{FLAC_CONTENT_FEATURES}" duration="{}:{:02}:{:02}.{:03}"{size}>{base_url}/dlna/recording/{id}</res></item>"#,
+        escape(&title),
+        duration.as_secs() / 3600,
+        duration.as_secs() / 60 % 60,
+        duration.as_secs() % 60,
+        duration.subsec_millis(),
+    )
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path).map(|metadata| metadata.len()).ok()
+}
+
+/// Socket which receives the multicast discovery requests.
+/// Port is shared with other SSDP services (e.g. `minissdpd`).
+fn ssdp_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SSDP_PORT)).into())?;
+    socket.join_multicast_v4(&SSDP_ADDRESS, &Ipv4Addr::UNSPECIFIED)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Whether `ip` belongs to the local network: DLNA endpoints don't require authorization,
+/// so they must not be reachable from the Internet (e.g. through a forwarded port).
+pub fn is_local_peer(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        IpAddr::V6(ip) => {
+            let first_segment = ip.segments()[0];
+            // Unique local (fc00::/7) and link-local (fe80::/10) addresses.
+            (first_segment & 0xfe00) == 0xfc00
+                || (first_segment & 0xffc0) == 0xfe80
+                || ip.is_loopback()
+        }
+    }
+}
+
+/// Address of the interface which is used to reach `peer`. Nothing is sent.
+fn local_ip(peer: SocketAddr) -> io::Result<IpAddr> {
+    let socket = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(peer)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Returns [None] if it's not an `M-SEARCH` request.
+fn parse_search_target(message: &str) -> Option<&str> {
+    let mut lines = message.lines();
+    if !lines.next()?.starts_with("M-SEARCH") {
+        return None;
+    }
+    lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("ST").then(|| value.trim())
+    })
+}
+
+/// Text of the first element with the `name` (without a namespace prefix).
+/// SOAP arguments don't have attributes, so it's enough for them.
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{name}>"))?;
+    Some(xml[start..end].trim())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+const CONTENT_DIRECTORY_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0"><specVersion><major>1</major><minor>0</minor></specVersion><actionList><action><name>Browse</name><argumentList><argument><name>ObjectID</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_ObjectID</relatedStateVariable></argument><argument><name>BrowseFlag</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_BrowseFlag</relatedStateVariable></argument><argument><name>Filter</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Filter</relatedStateVariable></argument><argument><name>StartingIndex</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Index</relatedStateVariable></argument><argument><name>RequestedCount</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument><argument><name>SortCriteria</name><direction>in</direction><relatedStateVariable>A_ARG_TYPE_SortCriteria</relatedStateVariable></argument><argument><name>Result</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Result</relatedStateVariable></argument><argument><name>NumberReturned</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument><argument><name>TotalMatches</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_Count</relatedStateVariable></argument><argument><name>UpdateID</name><direction>out</direction><relatedStateVariable>A_ARG_TYPE_UpdateID</relatedStateVariable></argument></argumentList></action><action><name>GetSearchCapabilities</name><argumentList><argument><name>SearchCaps</name><direction>out</direction><relatedStateVariable>SearchCapabilities</relatedStateVariable></argument></argumentList></action><action><name>GetSortCapabilities</name><argumentList><argument><name>SortCaps</name><direction>out</direction><relatedStateVariable>SortCapabilities</relatedStateVariable></argument></argumentList></action><action><name>GetSystemUpdateID</name><argumentList><argument><name>Id</name><direction>out</direction><relatedStateVariable>SystemUpdateID</relatedStateVariable></argument></argumentList></action></actionList><serviceStateTable><stateVariable sendEvents="no"><name>A_ARG_TYPE_ObjectID</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_BrowseFlag</name><dataType>string</dataType><allowedValueList><allowedValue>BrowseMetadata</allowedValue><allowedValue>BrowseDirectChildren</allowedValue></allowedValueList></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_Filter</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_Index</name><dataType>ui4</dataType></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_Count</name><dataType>ui4</dataType></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_SortCriteria</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_Result</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="no"><name>A_ARG_TYPE_UpdateID</name><dataType>ui4</dataType></stateVariable><stateVariable sendEvents="no"><name>SearchCapabilities</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="no"><name>SortCapabilities</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="yes"><name>SystemUpdateID</name><dataType>ui4</dataType></stateVariable></serviceStateTable></scpd>"#;
+
+const CONNECTION_MANAGER_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0"><specVersion><major>1</major><minor>0</minor></specVersion><actionList><action><name>GetProtocolInfo</name><argumentList><argument><name>Source</name><direction>out</direction><relatedStateVariable>SourceProtocolInfo</relatedStateVariable></argument><argument><name>Sink</name><direction>out</direction><relatedStateVariable>SinkProtocolInfo</relatedStateVariable></argument></argumentList></action><action><name>GetCurrentConnectionIDs</name><argumentList><argument><name>ConnectionIDs</name><direction>out</direction><relatedStateVariable>CurrentConnectionIDs</relatedStateVariable></argument></argumentList></action></actionList><serviceStateTable><stateVariable sendEvents="yes"><name>SourceProtocolInfo</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="yes"><name>SinkProtocolInfo</name><dataType>string</dataType></stateVariable><stateVariable sendEvents="yes"><name>CurrentConnectionIDs</name><dataType>string</dataType></stateVariable></serviceStateTable></scpd>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_peers() {
+        for ip in [
+            "192.168.1.10",
+            "10.0.0.1",
+            "172.16.5.4",
+            "169.254.0.2",
+            "127.0.0.1",
+            "fd12::1",
+            "fe80::1",
+            "::1",
+            "::ffff:192.168.1.10",
+        ] {
+            assert!(is_local_peer(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn public_peers() {
+        for ip in ["8.8.8.8", "172.32.0.1", "2001:db8::1", "::ffff:1.1.1.1"] {
+            assert!(!is_local_peer(ip.parse().unwrap()), "{ip}");
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use futures::{pin_mut, FutureExt, StreamExt};
+use hap::{
+    accessory::{
+        bridge::BridgeAccessory, humidity_sensor::HumiditySensorAccessory, switch::SwitchAccessory,
+        temperature_sensor::TemperatureSensorAccessory, AccessoryCategory, AccessoryInformation,
+        HapAccessory,
+    },
+    server::{IpServer, Server},
+    storage::{FileStorage, Storage},
+    Config, HapType, MacAddress, Pin,
+};
+use log::{error, info, warn};
+use serde_json::Value;
+use tokio::{join, select, sync::Mutex};
+
+use crate::{
+    bluetooth::{Bluetooth, DeviceHolder},
+    config,
+    core::ShutdownNotify,
+    device::{
+        description::LoungeTempMonitor,
+        mi_temp_monitor::MiTempMonitor,
+        piano::{Piano, StopRecorderParams},
+    },
+};
+
+/// How often to try to connect to the temperature monitor if it's unavailable.
+const TEMP_MONITOR_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+type AccessoryPointer = Arc<Mutex<Box<dyn HapAccessory>>>;
+
+/// HomeKit bridge (HAP server) with the lounge temperature and humidity sensors
+/// and a switch to control the piano recording.
+#[derive(Clone)]
+pub struct HomeKitBridge {
+    config: config::HomeKit,
+    /// Pairings are stored here.
+    storage_dir: PathBuf,
+    piano: Piano,
+    bluetooth: Bluetooth,
+    lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+}
+
+impl HomeKitBridge {
+    pub fn new(
+        config: config::HomeKit,
+        storage_dir: PathBuf,
+        piano: Piano,
+        bluetooth: Bluetooth,
+        lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+    ) -> Self {
+        Self {
+            config,
+            storage_dir,
+            piano,
+            bluetooth,
+            lounge_temp_monitor,
+        }
+    }
+
+    /// Serve the accessories until shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) -> Result<(), hap::Error> {
+        let mut storage = FileStorage::new(&self.storage_dir).await?;
+        let pin = parse_pin(&self.config.pin)?;
+        let config = match storage.load_config().await {
+            Ok(mut config) => {
+                config.redetermine_local_ip();
+                // The identifier is kept, so the existing pairings remain valid.
+                if config.pin != pin {
+                    warn!("HomeKit pin is changed, it's used for the new pairings");
+                    config.pin = pin;
+                }
+                if config.name != self.config.name {
+                    info!("HomeKit bridge is renamed to {}", self.config.name);
+                    config.name = self.config.name.clone();
+                }
+                if config.port != self.config.port {
+                    info!("HomeKit port is changed to {}", self.config.port);
+                    config.port = self.config.port;
+                }
+                storage.save_config(&config).await?;
+                config
+            }
+            Err(_) => {
+                let id = uuid::Uuid::new_v4().into_bytes();
+                let config = Config {
+                    pin,
+                    name: self.config.name.clone(),
+                    device_id: MacAddress::new([id[0], id[1], id[2], id[3], id[4], id[5]]),
+                    category: AccessoryCategory::Bridge,
+                    port: self.config.port,
+                    ..Default::default()
+                };
+                storage.save_config(&config).await?;
+                config
+            }
+        };
+        let server = IpServer::new(config, storage).await?;
+
+        let information = |name: &str| AccessoryInformation {
+            name: name.to_string(),
+            manufacturer: "lem0nez".to_string(),
+            model: env!("CARGO_PKG_NAME").to_string(),
+            firmware_revision: Some(env!("CARGO_PKG_VERSION").to_string()),
+            ..Default::default()
+        };
+        server
+            .add_accessory(BridgeAccessory::new(1, information(&self.config.name))?)
+            .await?;
+        let temperature = server
+            .add_accessory(TemperatureSensorAccessory::new(
+                2,
+                information("Lounge Temperature"),
+            )?)
+            .await?;
+        let humidity = server
+            .add_accessory(HumiditySensorAccessory::new(
+                3,
+                information("Lounge Humidity"),
+            )?)
+            .await?;
+
+        let mut recording_switch = SwitchAccessory::new(4, information("Piano Recording"))?;
+        let piano = self.piano.clone();
+        recording_switch.switch.power_state.on_update_async(Some(
+            move |_current: bool, new: bool| {
+                let piano = piano.clone();
+                async move {
+                    let result = if new {
+                        piano.record().await.map_err(|e| e.to_string())
+                    } else {
+                        piano
+                            .stop_recorder(StopRecorderParams {
+                                play_feedback: true,
+                            })
+                            .await
+                            .map(|_| ())
+                            .map_err(|e| e.to_string())
+                    };
+                    if let Err(e) = result {
+                        error!("Failed to switch the recording using HomeKit: {e}");
+                    }
+                    Ok(())
+                }
+                .boxed()
+            },
+        ));
+        let recording_switch = server.add_accessory(recording_switch).await?;
+
+        info!("HomeKit bridge started");
+        select! {
+            result = server.run_handle() => result,
+            _ = async {
+                join!(
+                    self.update_recording_state(&recording_switch, shutdown_notify.clone()),
+                    self.update_temp_monitor_data(&temperature, &humidity),
+                )
+            } => Ok(()),
+            _ = shutdown_notify.notified() => Ok(()),
+        }
+    }
+
+    /// Reflect the recorder state on the switch.
+    async fn update_recording_state(
+        &self,
+        recording_switch: &AccessoryPointer,
+        shutdown_notify: ShutdownNotify,
+    ) {
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify)
+            .await;
+        pin_mut!(piano_events);
+        loop {
+            let is_recording = self
+                .piano
+                .status()
+                .await
+                .is_ok_and(|status| status.is_recording);
+            set_value(
+                recording_switch,
+                HapType::Switch,
+                HapType::PowerState,
+                is_recording.into(),
+            )
+            .await;
+            if piano_events.next().await.is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Publish each data update of the lounge temperature monitor. Returns never.
+    async fn update_temp_monitor_data(
+        &self,
+        temperature: &AccessoryPointer,
+        humidity: &AccessoryPointer,
+    ) {
+        loop {
+            let connected_monitor = self
+                .bluetooth
+                .ensure_connected_and_healthy(Arc::clone(&self.lounge_temp_monitor))
+                .await;
+            let data_notify = match connected_monitor {
+                Ok(monitor) => monitor
+                    .read()
+                    .await
+                    .get_connected()
+                    .map(|monitor| monitor.data_notify())
+                    .ok(),
+                Err(_) => None,
+            };
+            if let Some((shared_data, notify)) = data_notify {
+                loop {
+                    notify.notified().await;
+                    // Device is no longer available.
+                    let Some(data) = *shared_data.lock().await else {
+                        break;
+                    };
+                    set_value(
+                        temperature,
+                        HapType::TemperatureSensor,
+                        HapType::CurrentTemperature,
+                        data.temperature().into(),
+                    )
+                    .await;
+                    set_value(
+                        humidity,
+                        HapType::HumiditySensor,
+                        HapType::CurrentRelativeHumidity,
+                        (data.humidity() as f32).into(),
+                    )
+                    .await;
+                }
+            }
+            tokio::time::sleep(TEMP_MONITOR_RETRY_INTERVAL).await;
+        }
+    }
+}
+
+async fn set_value(
+    accessory: &AccessoryPointer,
+    service: HapType,
+    characteristic: HapType,
+    value: Value,
+) {
+    let mut accessory = accessory.lock().await;
+    let Some(characteristic) = accessory
+        .get_mut_service(service)
+        .and_then(|service| service.get_mut_characteristic(characteristic))
+    else {
+        error!("HomeKit characteristic {characteristic:?} is not found");
+        return;
+    };
+    if let Err(e) = characteristic.set_value(value).await {
+        error!("Failed to update HomeKit characteristic: {e}");
+    }
+}
+
+/// Takes `XXX-XX-XXX` (the format is checked by the configuration validation).
+fn parse_pin(pin: &str) -> Result<Pin, hap::Error> {
+    let mut digits = [0; 8];
+    for (digit, char) in digits
+        .iter_mut()
+        .zip(pin.chars().filter(char::is_ascii_digit))
+    {
+        *digit = char as u8 - b'0';
+    }
+    Pin::new(digits)
+}
@@ -0,0 +1,6 @@
+pub mod dlna;
+pub mod homekit;
+pub mod mqtt;
+pub mod notifications;
+pub mod ntfy;
+pub mod telegram;
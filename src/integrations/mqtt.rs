@@ -0,0 +1,347 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info, warn};
+use rumqttc::{
+    AsyncClient, ClientError, Event, EventLoop, LastWill, MqttOptions, Packet, Publish, QoS,
+};
+use serde_json::{json, Value};
+use tokio::{join, select, sync::Mutex};
+
+use crate::{
+    bluetooth::{Bluetooth, DeviceHolder},
+    config,
+    core::{task::TaskManager, ShutdownNotify},
+    device::{
+        description::LoungeTempMonitor,
+        mi_temp_monitor::MiTempMonitor,
+        piano::{Piano, StopRecorderParams},
+    },
+    SharedMutex,
+};
+
+const ONLINE_PAYLOAD: &str = "online";
+const OFFLINE_PAYLOAD: &str = "offline";
+/// Used for both the switch state and commands.
+const ON_PAYLOAD: &str = "ON";
+const OFF_PAYLOAD: &str = "OFF";
+const PRESS_PAYLOAD: &str = "PRESS";
+/// Capacity of the outgoing requests queue.
+const REQUESTS_CAPACITY: usize = 32;
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// How often to try to connect to the temperature monitor if it's unavailable.
+const TEMP_MONITOR_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Publishes the sensors data and the piano state to an MQTT broker and accepts commands.
+/// Entities are announced using the Home Assistant MQTT discovery.
+/// The connection is plain TCP, TLS is not supported.
+#[derive(Clone)]
+pub struct MqttIntegration {
+    config: config::Mqtt,
+    piano: Piano,
+    bluetooth: Bluetooth,
+    lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+    /// Used to execute the commands.
+    tasks: TaskManager,
+    client: AsyncClient,
+    /// Taken by [Self::run].
+    eventloop: SharedMutex<Option<EventLoop>>,
+}
+
+impl MqttIntegration {
+    pub fn new(
+        config: config::Mqtt,
+        piano: Piano,
+        bluetooth: Bluetooth,
+        lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+        tasks: TaskManager,
+    ) -> Self {
+        let mut options =
+            MqttOptions::new(config.base_topic.clone(), config.host.clone(), config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            format!("{}/availability", config.base_topic),
+            OFFLINE_PAYLOAD,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+        let (client, eventloop) = AsyncClient::new(options, REQUESTS_CAPACITY);
+        Self {
+            config,
+            piano,
+            bluetooth,
+            lounge_temp_monitor,
+            tasks,
+            client,
+            eventloop: Arc::new(Mutex::new(Some(eventloop))),
+        }
+    }
+
+    /// Keep the connection with the broker. Returns never, the task is cancelled at shutdown
+    /// and the broker publishes the last will (offline availability) when the connection drops.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let Some(eventloop) = self.eventloop.lock().await.take() else {
+            error!("MQTT integration is already running");
+            return;
+        };
+        join!(
+            self.handle_events(&self.client, eventloop, shutdown_notify),
+            self.publish_temp_monitor_data(&self.client),
+        );
+    }
+
+    /// Publish `payload` to the absolute `topic`. The message is queued
+    /// if the broker is not connected at the moment.
+    pub async fn publish(
+        &self,
+        topic: &str,
+        payload: String,
+        retain: bool,
+    ) -> Result<(), ClientError> {
+        self.client
+            .publish(topic, QoS::AtLeastOnce, retain, payload)
+            .await
+    }
+
+    async fn handle_events(
+        &self,
+        client: &AsyncClient,
+        mut eventloop: EventLoop,
+        shutdown_notify: ShutdownNotify,
+    ) {
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify)
+            .await;
+        pin_mut!(piano_events);
+        loop {
+            select! {
+                event = eventloop.poll() => match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to the MQTT broker");
+                        self.announce(client);
+                        self.publish_piano_state(client).await;
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => self.handle_command(publish),
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection error: {e}. Reconnecting...");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                },
+                Some(_) = piano_events.next() => self.publish_piano_state(client).await,
+            }
+        }
+    }
+
+    /// Publish the discovery configurations, the availability and subscribe to the commands.
+    /// Requests are queued as the event loop is not polled while this method runs.
+    fn announce(&self, client: &AsyncClient) {
+        let device = json!({
+            "identifiers": [self.config.base_topic],
+            "name": "Homie Home",
+            "manufacturer": "lem0nez",
+            "model": env!("CARGO_PKG_NAME"),
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+        let piano_state_topic = self.topic("piano/state");
+        let lounge_state_topic = self.topic("lounge/state");
+        let entities = [
+            (
+                "sensor",
+                "lounge_temperature",
+                json!({
+                    "name": "Lounge temperature",
+                    "state_topic": lounge_state_topic,
+                    "value_template": "{{ value_json.temperature }}",
+                    "device_class": "temperature",
+                    "state_class": "measurement",
+                    "unit_of_measurement": "°C",
+                }),
+            ),
+            (
+                "sensor",
+                "lounge_humidity",
+                json!({
+                    "name": "Lounge humidity",
+                    "state_topic": lounge_state_topic,
+                    "value_template": "{{ value_json.humidity }}",
+                    "device_class": "humidity",
+                    "state_class": "measurement",
+                    "unit_of_measurement": "%",
+                }),
+            ),
+            (
+                "binary_sensor",
+                "piano_connected",
+                json!({
+                    "name": "Piano connected",
+                    "state_topic": piano_state_topic,
+                    "value_template": "{{ 'ON' if value_json.connected else 'OFF' }}",
+                    "device_class": "plug",
+                }),
+            ),
+            (
+                "switch",
+                "piano_recording",
+                json!({
+                    "name": "Piano recording",
+                    "state_topic": piano_state_topic,
+                    "value_template": "{{ 'ON' if value_json.recording else 'OFF' }}",
+                    "command_topic": self.topic("piano/recording/set"),
+                    "icon": "mdi:record-rec",
+                }),
+            ),
+            (
+                "button",
+                "piano_play_last_recording",
+                json!({
+                    "name": "Play last recording",
+                    "command_topic": self.topic("piano/play-last-recording/set"),
+                    "payload_press": PRESS_PAYLOAD,
+                    "icon": "mdi:play",
+                }),
+            ),
+        ];
+
+        for (component, object_id, mut config) in entities {
+            let unique_id = format!("{}_{object_id}", self.config.base_topic);
+            if let Value::Object(config) = &mut config {
+                config.insert("unique_id".to_string(), unique_id.clone().into());
+                config.insert("object_id".to_string(), unique_id.clone().into());
+                config.insert(
+                    "availability_topic".to_string(),
+                    self.availability_topic().into(),
+                );
+                config.insert("device".to_string(), device.clone());
+            }
+            let topic = format!(
+                "{}/{component}/{unique_id}/config",
+                self.config.discovery_prefix
+            );
+            self.try_publish(client, topic, true, config.to_string());
+        }
+        self.try_publish(
+            client,
+            self.availability_topic(),
+            true,
+            ONLINE_PAYLOAD.to_string(),
+        );
+        if let Err(e) = client.try_subscribe(self.topic("+/+/set"), QoS::AtLeastOnce) {
+            error!("Failed to subscribe to the MQTT commands: {e}");
+        }
+    }
+
+    fn handle_command(&self, publish: Publish) {
+        let Some(command) = publish
+            .topic
+            .strip_prefix(&format!("{}/", self.config.base_topic))
+            .and_then(|topic| topic.strip_suffix("/set"))
+        else {
+            return;
+        };
+        let payload = String::from_utf8_lossy(&publish.payload).into_owned();
+        let (command, piano) = (command.to_string(), self.piano.clone());
+        // Don't block the event loop as recording control can take a while.
+        self.tasks.spawn("mqtt-command", async move {
+            info!("MQTT command {command} received with payload {payload}");
+            let result = match (command.as_str(), payload.as_str()) {
+                ("piano/recording", ON_PAYLOAD) => piano.record().await.map_err(|e| e.to_string()),
+                ("piano/recording", OFF_PAYLOAD) => piano
+                    .stop_recorder(StopRecorderParams {
+                        play_feedback: true,
+                    })
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                ("piano/play-last-recording", PRESS_PAYLOAD) => piano
+                    .play_last_recording()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                _ => Err("unknown command".to_string()),
+            };
+            if let Err(e) = result {
+                error!("Failed to execute MQTT command {command}: {e}");
+            }
+        });
+    }
+
+    async fn publish_piano_state(&self, client: &AsyncClient) {
+        let state = match self.piano.status().await {
+            Ok(status) => json!({
+                "connected": status.connected,
+                "recording": status.is_recording,
+                "output": status.output.to_string(),
+            }),
+            Err(e) => {
+                error!("Failed to get the piano status: {e}");
+                return;
+            }
+        };
+        self.try_publish(client, self.topic("piano/state"), true, state.to_string());
+    }
+
+    /// Publish each data update of the lounge temperature monitor. Returns never.
+    async fn publish_temp_monitor_data(&self, client: &AsyncClient) {
+        loop {
+            let connected_monitor = self
+                .bluetooth
+                .ensure_connected_and_healthy(Arc::clone(&self.lounge_temp_monitor))
+                .await;
+            let data_notify = match connected_monitor {
+                Ok(monitor) => monitor
+                    .read()
+                    .await
+                    .get_connected()
+                    .map(|monitor| monitor.data_notify())
+                    .ok(),
+                Err(_) => None,
+            };
+            if let Some((shared_data, notify)) = data_notify {
+                loop {
+                    notify.notified().await;
+                    // Device is no longer available.
+                    let Some(data) = *shared_data.lock().await else {
+                        break;
+                    };
+                    let state = json!({
+                        "temperature": data.temperature(),
+                        "humidity": data.humidity(),
+                        "battery": data.battery_percents(),
+                    });
+                    if let Err(e) = client
+                        .publish(
+                            self.topic("lounge/state"),
+                            QoS::AtLeastOnce,
+                            true,
+                            state.to_string(),
+                        )
+                        .await
+                    {
+                        error!("Failed to publish the temperature: {e}");
+                    }
+                }
+            }
+            tokio::time::sleep(TEMP_MONITOR_RETRY_INTERVAL).await;
+        }
+    }
+
+    fn try_publish(&self, client: &AsyncClient, topic: String, retain: bool, payload: String) {
+        if let Err(e) = client.try_publish(&topic, QoS::AtLeastOnce, retain, payload) {
+            error!("Failed to publish to {topic}: {e}");
+        }
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{name}", self.config.base_topic)
+    }
+
+    fn availability_topic(&self) -> String {
+        self.topic("availability")
+    }
+}
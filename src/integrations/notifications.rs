@@ -0,0 +1,64 @@
+use futures::{Stream, StreamExt};
+
+use crate::{
+    config::NotificationKind,
+    core::{Broadcaster, ShutdownNotify},
+    device::piano::{Piano, PianoEvent},
+    GlobalEvent,
+};
+
+/// Human-readable notifications derived from the piano and global events,
+/// shared by the push channels. Stream closes at shutdown.
+pub async fn notifications(
+    piano: &Piano,
+    event_broadcaster: &Broadcaster<GlobalEvent>,
+    shutdown_notify: ShutdownNotify,
+) -> impl Stream<Item = (NotificationKind, String)> {
+    let piano_events = piano
+        .event_broadcaster
+        .recv_continuously(shutdown_notify.clone())
+        .await
+        .filter_map(|event| async move {
+            match event.payload {
+                PianoEvent::NewRecordingSaved => Some((
+                    NotificationKind::NewRecordingSaved,
+                    "New recording saved".to_string(),
+                )),
+                PianoEvent::DiskSpaceLow => Some((
+                    NotificationKind::DiskSpaceLow,
+                    "Free disk space is low, new recordings are refused".to_string(),
+                )),
+                _ => None,
+            }
+        });
+    let global_events = event_broadcaster
+        .recv_continuously(shutdown_notify)
+        .await
+        .filter_map(|event| async move {
+            match event.payload {
+                GlobalEvent::Notification { message } => {
+                    Some((NotificationKind::Notification, message))
+                }
+                GlobalEvent::BackupUploadFinished(status) => Some(match status.error {
+                    Some(e) => (
+                        NotificationKind::BackupUploadFailed,
+                        format!("Failed to upload the backup to {}: {e}", status.target),
+                    ),
+                    None => (
+                        NotificationKind::BackupUploaded,
+                        format!("Backup uploaded to {}", status.target),
+                    ),
+                }),
+                GlobalEvent::UsbOffloadFinished { success } => Some((
+                    NotificationKind::UsbOffloadFinished,
+                    if success {
+                        "Files offloaded to the USB drive".to_string()
+                    } else {
+                        "Failed to offload files to the USB drive".to_string()
+                    },
+                )),
+                _ => None,
+            }
+        });
+    futures::stream::select(piano_events, global_events)
+}
@@ -0,0 +1,100 @@
+use futures::{pin_mut, StreamExt};
+use log::error;
+use tokio::process::Command;
+
+use crate::{
+    config::{self, Location, NotificationKind, NtfyAuth, NtfyPriority, QuietHours},
+    core::{curl_config::CurlConfig, timezone, Broadcaster, ShutdownNotify},
+    device::piano::Piano,
+    integrations::notifications,
+    GlobalEvent,
+};
+
+/// Title of the pushed messages.
+const TITLE: &str = env!("CARGO_PKG_NAME");
+
+/// Publishes the selected notifications to an ntfy server.
+/// The minimum priority is used during the quiet hours.
+#[derive(Clone)]
+pub struct NtfyNotifier {
+    config: config::Ntfy,
+    quiet_hours: Option<QuietHours>,
+    location: Option<Location>,
+    piano: Piano,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+impl NtfyNotifier {
+    pub fn new(
+        config: config::Ntfy,
+        quiet_hours: Option<QuietHours>,
+        location: Option<Location>,
+        piano: Piano,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        Self {
+            config,
+            quiet_hours,
+            location,
+            piano,
+            event_broadcaster,
+        }
+    }
+
+    /// Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let notifications =
+            notifications::notifications(&self.piano, &self.event_broadcaster, shutdown_notify)
+                .await;
+        pin_mut!(notifications);
+        while let Some((kind, text)) = notifications.next().await {
+            if let Err(e) = self.publish(kind, &text).await {
+                error!("Failed to publish a notification to ntfy: {e}");
+            }
+        }
+    }
+
+    /// Does nothing if the notification kind is not configured.
+    async fn publish(&self, kind: NotificationKind, text: &str) -> Result<(), String> {
+        let Some(notification) = self.config.notifications.get(&kind) else {
+            return Ok(());
+        };
+        let topic = notification.topic.as_ref().unwrap_or(&self.config.topic);
+        let is_quiet = self.quiet_hours.is_some_and(|quiet_hours| {
+            timezone::is_quiet(&quiet_hours, self.location.as_ref(), timezone::now())
+        });
+        let priority = if is_quiet {
+            NtfyPriority::Min
+        } else {
+            notification
+                .priority
+                .unwrap_or_else(|| kind.default_ntfy_priority())
+        };
+
+        let mut command = Command::new("curl");
+        command
+            .args(["--fail", "--silent", "--show-error"])
+            .args(["--header", &format!("Title: {TITLE}")])
+            .args(["--header", &format!("Priority: {}", priority.as_ref())]);
+        let auth = match &self.config.auth {
+            Some(NtfyAuth::AccessToken(token)) => {
+                CurlConfig::default().option("header", &format!("Authorization: Bearer {token}"))
+            }
+            Some(NtfyAuth::Basic { username, password }) => {
+                CurlConfig::default().option("user", &format!("{username}:{password}"))
+            }
+            None => CurlConfig::default(),
+        };
+        // Raw, so the text starting with "@" is not treated as a file name.
+        command.args(["--data-raw", text]).arg(format!(
+            "{}/{topic}",
+            self.config.server_url.trim_end_matches('/')
+        ));
+        let output = auth.output(&mut command).await.map_err(|e| e.to_string())?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use tokio::{join, process::Command};
+
+use crate::{
+    bluetooth::{Bluetooth, DeviceHolder},
+    config::{self, Location, QuietHours},
+    core::{curl_config::CurlConfig, timezone, Broadcaster, ShutdownNotify},
+    device::{
+        description::LoungeTempMonitor,
+        mi_temp_monitor::MiTempMonitor,
+        piano::{Piano, StopRecorderParams},
+    },
+    integrations::notifications,
+    GlobalEvent,
+};
+
+const API_URL: &str = "https://api.telegram.org";
+/// Long polling timeout of `getUpdates`.
+const POLL_TIMEOUT_SECS: u64 = 50;
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+const HELP: &str = "/status - piano and lounge state\n\
+                    /record - start recording\n\
+                    /stop - stop recording\n\
+                    /temp - lounge temperature";
+
+/// Accepts commands from the allowed chats and pushes the selected notifications to them.
+/// Notifications are sent without a sound during the quiet hours.
+#[derive(Clone)]
+pub struct TelegramBot {
+    config: config::Telegram,
+    quiet_hours: Option<QuietHours>,
+    location: Option<Location>,
+    piano: Piano,
+    bluetooth: Bluetooth,
+    lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+impl TelegramBot {
+    pub fn new(
+        config: config::Telegram,
+        quiet_hours: Option<QuietHours>,
+        location: Option<Location>,
+        piano: Piano,
+        bluetooth: Bluetooth,
+        lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        Self {
+            config,
+            quiet_hours,
+            location,
+            piano,
+            bluetooth,
+            lounge_temp_monitor,
+            event_broadcaster,
+        }
+    }
+
+    /// Returns never, the task is cancelled at shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        join!(
+            self.handle_commands(),
+            self.push_notifications(shutdown_notify)
+        );
+    }
+
+    async fn handle_commands(&self) {
+        // Identifier of the next update to receive.
+        let mut offset = 0;
+        loop {
+            let updates = match self
+                .call(
+                    "getUpdates",
+                    json!({
+                        "offset": offset,
+                        "timeout": POLL_TIMEOUT_SECS,
+                        "allowed_updates": ["message"],
+                    }),
+                )
+                .await
+            {
+                Ok(Value::Array(updates)) => updates,
+                Ok(_) => Vec::new(),
+                Err(e) => {
+                    warn!("Failed to get Telegram updates: {e}. Retrying...");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            for update in updates {
+                if let Some(update_id) = update["update_id"].as_i64() {
+                    offset = update_id + 1;
+                }
+                let (Some(chat_id), Some(text)) = (
+                    update["message"]["chat"]["id"].as_i64(),
+                    update["message"]["text"].as_str(),
+                ) else {
+                    continue;
+                };
+                if !self.config.chat_ids.contains(&chat_id) {
+                    warn!("Telegram message from the unknown chat {chat_id} is ignored");
+                    continue;
+                }
+                // Commands in groups can be suffixed with the bot name, e.g. "/status@bot".
+                let command = text.split_whitespace().next().unwrap_or_default();
+                let command = command.split('@').next().unwrap_or_default();
+                info!("Telegram command {command} received");
+                let reply = self.execute(command).await;
+                self.send(chat_id, &reply, false).await;
+            }
+        }
+    }
+
+    /// Returns a reply to the command.
+    async fn execute(&self, command: &str) -> String {
+        let result = match command {
+            "/status" => self.status().await,
+            "/record" => self
+                .piano
+                .record()
+                .await
+                .map(|_| "Recording started".to_string())
+                .map_err(|e| e.to_string()),
+            "/stop" => self
+                .piano
+                .stop_recorder(StopRecorderParams {
+                    play_feedback: true,
+                })
+                .await
+                .map(|recording| format!("Recording {} saved", recording.id()))
+                .map_err(|e| e.to_string()),
+            "/temp" => self.lounge_data().await,
+            _ => Ok(HELP.to_string()),
+        };
+        result.unwrap_or_else(|e| format!("Failed: {e}"))
+    }
+
+    async fn status(&self) -> Result<String, String> {
+        let status = self.piano.status().await.map_err(|e| e.to_string())?;
+        let piano = if !status.connected {
+            "not connected".to_string()
+        } else if status.is_recording {
+            "recording".to_string()
+        } else {
+            format!("connected, playing on {}", status.output)
+        };
+        let lounge = self
+            .lounge_data()
+            .await
+            .unwrap_or_else(|e| format!("unavailable ({e})"));
+        Ok(format!("Piano: {piano}\nLounge: {lounge}"))
+    }
+
+    async fn lounge_data(&self) -> Result<String, String> {
+        self.bluetooth
+            .ensure_connected_and_healthy(Arc::clone(&self.lounge_temp_monitor))
+            .await
+            .map_err(|e| e.to_string())?;
+        let data = self
+            .lounge_temp_monitor
+            .read()
+            .await
+            .get_connected()
+            .map_err(|e| e.to_string())?
+            .last_data()
+            .await;
+        data.map(|data| data.to_string())
+            .ok_or_else(|| "no data received yet".to_string())
+    }
+
+    async fn push_notifications(&self, shutdown_notify: ShutdownNotify) {
+        let notifications =
+            notifications::notifications(&self.piano, &self.event_broadcaster, shutdown_notify)
+                .await;
+        pin_mut!(notifications);
+        while let Some((kind, text)) = notifications.next().await {
+            if !self.config.notifications.contains(&kind) {
+                continue;
+            }
+            let silent = self.quiet_hours.is_some_and(|quiet_hours| {
+                timezone::is_quiet(&quiet_hours, self.location.as_ref(), timezone::now())
+            });
+            for chat_id in &self.config.chat_ids {
+                self.send(*chat_id, &text, silent).await;
+            }
+        }
+    }
+
+    /// `silent` messages are received without a sound.
+    async fn send(&self, chat_id: i64, text: &str, silent: bool) {
+        let result = self
+            .call(
+                "sendMessage",
+                json!({"chat_id": chat_id, "text": text, "disable_notification": silent}),
+            )
+            .await;
+        if let Err(e) = result {
+            error!("Failed to send a Telegram message to {chat_id}: {e}");
+        }
+    }
+
+    /// Call the Bot API method. Returns the `result` field of the response.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        // URL contains the token.
+        let output = CurlConfig::default()
+            .option(
+                "url",
+                &format!("{API_URL}/bot{}/{method}", self.config.token),
+            )
+            .output(
+                Command::new("curl")
+                    .args(["--silent", "--show-error", "--max-time"])
+                    .arg((POLL_TIMEOUT_SECS + 10).to_string())
+                    .args(["--header", "Content-Type: application/json", "--data"])
+                    .arg(params.to_string()),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let mut response: Value =
+            serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+        if response["ok"].as_bool() == Some(true) {
+            Ok(response["result"].take())
+        } else {
+            Err(response["description"]
+                .as_str()
+                .unwrap_or("unknown error")
+                .to_string())
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt::Write, time::Duration};
+
+use chrono::DateTime;
+use serde::Serialize;
+
+use crate::{
+    core::{human_duration, timezone},
+    storage::{Storage, StorageError},
+};
+
+/// Practice history made from the recording index, for archiving outside the server.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Journal {
+    /// From the oldest to the newest.
+    days: Vec<JournalDay>,
+    total_duration_ms: u64,
+    /// RFC 3339.
+    generated_at: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalDay {
+    /// `YYYY-MM-DD` in the configured timezone.
+    date: String,
+    total_duration_ms: u64,
+    recordings: Vec<JournalEntry>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalEntry {
+    id: i64,
+    /// RFC 3339.
+    recorded_at: String,
+    duration_ms: u64,
+    integrated_lufs: Option<f64>,
+    /// Bookmarks in the form `MM:SS label`.
+    notes: Vec<String>,
+}
+
+impl Journal {
+    pub async fn collect(storage: &Storage) -> Result<Self, StorageError> {
+        let recordings = storage.indexed_recordings().await?;
+        let bookmarks = storage.all_bookmarks().await?;
+
+        let mut days: Vec<JournalDay> = Vec::new();
+        for recording in recordings {
+            let recorded_at = timezone::localize(
+                DateTime::from_timestamp_millis(recording.id).unwrap_or_default(),
+            );
+            let date = recorded_at.format("%F").to_string();
+            let notes = bookmarks
+                .iter()
+                .filter(|bookmark| bookmark.recording_id == recording.id)
+                .map(|bookmark| {
+                    let position = Duration::from_millis(bookmark.position_ms);
+                    format!("{} {}", human_duration(position), bookmark.label)
+                })
+                .collect();
+            let entry = JournalEntry {
+                id: recording.id,
+                recorded_at: recorded_at.to_rfc3339(),
+                duration_ms: recording.duration_ms,
+                integrated_lufs: recording.integrated_lufs,
+                notes,
+            };
+            match days.last_mut().filter(|day| day.date == date) {
+                Some(day) => {
+                    day.total_duration_ms += entry.duration_ms;
+                    day.recordings.push(entry);
+                }
+                None => days.push(JournalDay {
+                    date,
+                    total_duration_ms: entry.duration_ms,
+                    recordings: vec![entry],
+                }),
+            }
+        }
+        Ok(Self {
+            total_duration_ms: days.iter().map(|day| day.total_duration_ms).sum(),
+            days,
+            generated_at: timezone::now().to_rfc3339(),
+        })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let recordings_count: usize = self.days.iter().map(|day| day.recordings.len()).sum();
+        let mut markdown = format!(
+            "# Practice journal\n\n{} in {recordings_count} recordings over {} days.\n",
+            total_duration(self.total_duration_ms),
+            self.days.len(),
+        );
+        for day in &self.days {
+            let _ = write!(
+                markdown,
+                "\n## {} ({})\n\n",
+                day.date,
+                total_duration(day.total_duration_ms)
+            );
+            for entry in &day.recordings {
+                // Time is at 11..16 in RFC 3339.
+                let time = entry.recorded_at.get(11..16).unwrap_or_default();
+                let duration = human_duration(Duration::from_millis(entry.duration_ms));
+                let _ = match entry.integrated_lufs {
+                    Some(lufs) => writeln!(markdown, "- {time}, {duration}, {lufs:.1} LUFS"),
+                    None => writeln!(markdown, "- {time}, {duration}"),
+                };
+                for note in &entry.notes {
+                    let _ = writeln!(markdown, "  - {note}");
+                }
+            }
+        }
+        markdown
+    }
+}
+
+/// E.g. `1 h 5 min`.
+fn total_duration(duration_ms: u64) -> String {
+    let mins = duration_ms / 60_000;
+    match (mins / 60, mins % 60) {
+        (0, mins) => format!("{mins} min"),
+        (hours, mins) => format!("{hours} h {mins} min"),
+    }
+}
@@ -5,40 +5,237 @@ pub mod graphql;
 pub mod rest;
 pub mod udev;
 
+mod assets_watcher;
 mod audio;
+mod automation;
+mod calendar;
+mod config_editor;
 mod dbus;
 mod device;
 mod endpoint;
+mod file_manager;
 mod files;
+mod integrations;
+mod journal;
+mod poweroff;
 mod prefs;
+mod presence;
+mod recording_upload;
+mod remote_backup;
+mod scripting;
+mod storage;
+mod summary;
+mod updater;
+mod weather;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use actix_web::dev::ServerHandle;
 use anyhow::Context;
-use log::info;
+use log::{error, info};
 use tokio::sync::{Mutex, RwLock};
 
-use audio::SoundLibrary;
-use bluetooth::{A2DPSourceHandler, Bluetooth, DeviceHolder};
+use assets_watcher::AssetsWatcher;
+use audio::{transcode::TranscodeQueue, tts::Tts, SoundLibrary};
+use automation::{Automation, AutomationDependencies};
+use bluetooth::{A2DPSourceHandler, Bluetooth, BluetoothDevicePlugin, DeviceHolder};
+use calendar::Calendar;
 use config::Config;
-use core::{Broadcaster, ShutdownNotify};
-use dbus::DBus;
+use config_editor::ConfigEditor;
+use core::{
+    backup::Backup,
+    metrics::{self, Gauge},
+    shutdown::ShutdownCoordinator,
+    task::TaskManager,
+    Broadcaster, ShutdownNotify,
+};
+use dbus::{BluetoothDeviceChange, DBus};
 use device::{
     description::LoungeTempMonitor,
+    display::Display,
     hotspot::Hotspot,
+    led::StatusLed,
     mi_temp_monitor::MiTempMonitor,
-    piano::{self, Piano},
+    midi::MidiControllers,
+    monitor_output::MonitorOutput,
+    piano::{Piano, PianoDependencies},
+    plugin::DeviceRegistry,
+    power::PowerMonitor,
+    usb_storage::{OffloadProgress, UsbStorage},
+    voice_memo::VoiceMemos,
 };
+use file_manager::FileManager;
 use files::{BaseDir, Data};
+use integrations::{
+    dlna::DlnaServer, homekit::HomeKitBridge, mqtt::MqttIntegration, ntfy::NtfyNotifier,
+    telegram::TelegramBot,
+};
+use poweroff::PoweroffScheduler;
 use prefs::PreferencesStorage;
+use presence::{PersonPresence, PresenceMonitor};
+use recording_upload::{RecordingUploadStatus, RecordingUploader};
+use remote_backup::{BackupUploadStatus, RemoteBackup};
+use scripting::Scripting;
+use storage::Storage;
+use udev::HotplugEvent;
+use updater::{UpdateStage, Updater};
+use weather::WeatherMonitor;
 
 pub type SharedMutex<T> = Arc<Mutex<T>>;
 pub type SharedRwLock<T> = Arc<RwLock<T>>;
 
-#[derive(Clone, Copy, PartialEq, Eq, async_graphql::Enum)]
+#[derive(Clone, PartialEq, Eq)]
 pub enum GlobalEvent {
     Shutdown,
     PreferencesUpdated,
+    /// Subsystem stopped working and it has been restarted by the supervisor.
+    SubsystemRestarted {
+        name: Subsystem,
+    },
+    /// Sent before copying the first file and after each copied one.
+    UsbOffloadProgress(OffloadProgress),
+    UsbOffloadFinished {
+        success: bool,
+    },
+    /// Device event matched the configured udev rule.
+    UdevRuleMatched {
+        rule: String,
+    },
+    /// MIDI controller plugged in or unplugged.
+    MidiControllersChanged,
+    /// Sent when the self-update moves to the next stage.
+    UpdateProgress(UpdateStage),
+    /// Scheduled backup is uploaded to a target (or it failed).
+    BackupUploadFinished(BackupUploadStatus),
+    /// Recording is uploaded to the `recording_upload` target (or all attempts failed).
+    RecordingUploadFinished(RecordingUploadStatus),
+    /// Message to show to the user (e.g. sent by an automation rule).
+    Notification {
+        message: String,
+    },
+    /// Person came home or left.
+    PresenceChanged(PersonPresence),
+}
+
+// Named as the event itself, so the `globalEvents` subscription keeps its schema.
+#[derive(Clone, Copy, PartialEq, Eq, async_graphql::Enum, strum::AsRefStr)]
+#[graphql(name = "GlobalEvent")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum GlobalEventKind {
+    Shutdown,
+    PreferencesUpdated,
+    SubsystemRestarted,
+    UsbOffloadProgress,
+    UsbOffloadFinished,
+    UdevRuleMatched,
+    MidiControllersChanged,
+    UpdateProgress,
+    BackupUploadFinished,
+    RecordingUploadFinished,
+    Notification,
+    PresenceChanged,
+}
+
+/// Event with its data, available using the `globalEventDetails` subscription.
+#[async_graphql::Object(name = "GlobalEventDetails")]
+impl GlobalEvent {
+    async fn kind(&self) -> GlobalEventKind {
+        match self {
+            Self::Shutdown => GlobalEventKind::Shutdown,
+            Self::PreferencesUpdated => GlobalEventKind::PreferencesUpdated,
+            Self::SubsystemRestarted { .. } => GlobalEventKind::SubsystemRestarted,
+            Self::UsbOffloadProgress(_) => GlobalEventKind::UsbOffloadProgress,
+            Self::UsbOffloadFinished { .. } => GlobalEventKind::UsbOffloadFinished,
+            Self::UdevRuleMatched { .. } => GlobalEventKind::UdevRuleMatched,
+            Self::MidiControllersChanged => GlobalEventKind::MidiControllersChanged,
+            Self::UpdateProgress(_) => GlobalEventKind::UpdateProgress,
+            Self::BackupUploadFinished(_) => GlobalEventKind::BackupUploadFinished,
+            Self::RecordingUploadFinished(_) => GlobalEventKind::RecordingUploadFinished,
+            Self::Notification { .. } => GlobalEventKind::Notification,
+            Self::PresenceChanged(_) => GlobalEventKind::PresenceChanged,
+        }
+    }
+
+    /// Set if the event kind is `SUBSYSTEM_RESTARTED`.
+    async fn subsystem(&self) -> Option<Subsystem> {
+        match self {
+            Self::SubsystemRestarted { name } => Some(*name),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `USB_OFFLOAD_PROGRESS`.
+    async fn usb_offload_progress(&self) -> Option<OffloadProgress> {
+        match self {
+            Self::UsbOffloadProgress(progress) => Some(*progress),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `USB_OFFLOAD_FINISHED`.
+    async fn usb_offload_success(&self) -> Option<bool> {
+        match self {
+            Self::UsbOffloadFinished { success } => Some(*success),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `UDEV_RULE_MATCHED`.
+    async fn udev_rule(&self) -> Option<&str> {
+        match self {
+            Self::UdevRuleMatched { rule } => Some(rule),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `UPDATE_PROGRESS`.
+    async fn update_stage(&self) -> Option<UpdateStage> {
+        match self {
+            Self::UpdateProgress(stage) => Some(*stage),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `BACKUP_UPLOAD_FINISHED`.
+    async fn backup_upload(&self) -> Option<&BackupUploadStatus> {
+        match self {
+            Self::BackupUploadFinished(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `RECORDING_UPLOAD_FINISHED`.
+    async fn recording_upload(&self) -> Option<&RecordingUploadStatus> {
+        match self {
+            Self::RecordingUploadFinished(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `NOTIFICATION`.
+    async fn notification(&self) -> Option<&str> {
+        match self {
+            Self::Notification { message } => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Set if the event kind is `PRESENCE_CHANGED`.
+    async fn presence(&self) -> Option<&PersonPresence> {
+        match self {
+            Self::PresenceChanged(presence) => Some(presence),
+            _ => None,
+        }
+    }
+}
+
+/// Subsystems watched by the supervisor.
+#[derive(Clone, Copy, PartialEq, Eq, strum::Display, async_graphql::Enum)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Subsystem {
+    UdevMonitor,
+    BluetoothEventHandler,
+    Player,
 }
 
 /// Main object to access all the stuff: configuration, services, devices etc.
@@ -46,17 +243,49 @@ pub enum GlobalEvent {
 pub struct App {
     pub config: Config,
     pub prefs: PreferencesStorage,
+    pub storage: Storage,
     pub sounds: SoundLibrary,
     pub event_broadcaster: Broadcaster<GlobalEvent>,
+    /// Add / remove events of the monitored udev subsystems.
+    pub hotplug_broadcaster: Broadcaster<HotplugEvent>,
+    /// Connection state, RSSI and battery changes of the Bluetooth devices.
+    pub bluetooth_device_broadcaster: Broadcaster<BluetoothDeviceChange>,
     pub shutdown_notify: ShutdownNotify,
+    pub tasks: TaskManager,
+    pub transcoder: TranscodeQueue,
+    pub file_manager: FileManager,
+    pub config_editor: ConfigEditor,
+    pub tts: Tts,
+    pub backup: Backup,
 
     pub dbus: DBus,
     pub bluetooth: Bluetooth,
     pub a2dp_source_handler: A2DPSourceHandler,
 
+    /// All devices with the unified lifecycle.
+    pub devices: DeviceRegistry,
     /// If hotspot configuration is not passed, it will be [None].
     pub hotspot: Option<Hotspot>,
     pub piano: Piano,
+    pub midi_controllers: MidiControllers,
+    /// If power configuration is not passed, it will be [None].
+    pub power: Option<PowerMonitor>,
+    /// If updater configuration is not passed, it will be [None].
+    pub updater: Option<Updater>,
+    /// If presence configuration is not passed, it will be [None].
+    pub presence: Option<PresenceMonitor>,
+    /// If weather configuration is not passed, it will be [None].
+    pub weather: Option<WeatherMonitor>,
+    /// If calendar configuration is not passed, it will be [None].
+    pub calendar: Option<Calendar>,
+    /// If DLNA configuration is not passed, it will be [None].
+    pub dlna: Option<DlnaServer>,
+    /// If voice memo configuration is not passed, it will be [None].
+    pub voice_memos: Option<VoiceMemos>,
+    pub poweroff_scheduler: PoweroffScheduler,
+    pub remote_backup: RemoteBackup,
+    pub automation: Automation,
+    pub display: Display,
     pub lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
 }
 
@@ -75,34 +304,116 @@ impl App {
                     prefs_path.to_string_lossy()
                 )
             })?;
+        let database_path = config.data_dir.path(Data::Database);
+        let storage = Storage::open(&database_path).with_context(|| {
+            format!(
+                "Unable to open the database {}",
+                database_path.to_string_lossy()
+            )
+        })?;
 
-        info!("Loading sounds...");
-        let sounds =
-            SoundLibrary::load(&config.assets_dir).with_context(|| "Unable to load sounds")?;
-        info!("Sounds loaded");
+        if config.preload_sounds {
+            info!("Loading sounds...");
+        }
+        let sounds = SoundLibrary::new(config.assets_dir.clone(), config.preload_sounds)
+            .with_context(|| "Unable to load sounds")?;
+        if config.preload_sounds {
+            info!("Sounds loaded");
+        }
 
-        let event_broadcaster = Broadcaster::default();
+        let event_broadcaster = Broadcaster::new("global", config.broadcaster_capacity);
+        let hotplug_broadcaster = Broadcaster::new("hotplug", config.broadcaster_capacity);
+        let bluetooth_device_broadcaster =
+            Broadcaster::new("bluetooth-device", config.broadcaster_capacity);
+        metrics::set(Gauge::BroadcastCapacity, config.broadcaster_capacity as i64);
         let shutdown_notify = ShutdownNotify::listen(event_broadcaster.clone())
             .with_context(|| "Unable to listen for shutdown signals")?;
+        let tasks = TaskManager::new(shutdown_notify.clone());
+        let transcoder = TranscodeQueue::new(
+            config.transcode.clone(),
+            config.data_dir.path(Data::Transcodes).to_path_buf(),
+        );
+        let voice_memos = config.voice_memo.clone().map(|voice_memo_config| {
+            VoiceMemos::new(
+                voice_memo_config,
+                &config.data_dir.path(Data::VoiceMemos),
+                tasks.clone(),
+                shutdown_notify.clone(),
+            )
+        });
+        if let Some(voice_memos) = &voice_memos {
+            voice_memos.recover_unsaved().await;
+        }
+        let tts = Tts::new(config.tts.clone());
         let dbus = DBus::new()
             .await
             .with_context(|| "Unable to create a connection to the message bus")?;
+        let config_editor = ConfigEditor::new(
+            config.data_dir.path(Data::ConfigOverlay).to_path_buf(),
+            dbus.clone(),
+        );
 
+        let monitor_output = config.monitor_output.clone().map(|monitor_output_config| {
+            MonitorOutput::new(
+                monitor_output_config,
+                config.backoff.audio_output_stream_wait.clone(),
+                config.resample_quality,
+                tasks.clone(),
+            )
+        });
         let piano = Piano::new(
             &config,
-            prefs.clone(),
-            sounds.clone(),
-            shutdown_notify.clone(),
-            a2dp_source_handler.clone(),
+            PianoDependencies {
+                prefs: prefs.clone(),
+                storage: storage.clone(),
+                sounds: sounds.clone(),
+                shutdown_notify: shutdown_notify.clone(),
+                tasks: tasks.clone(),
+                a2dp_source_handler: a2dp_source_handler.clone(),
+                dbus: dbus.clone(),
+                monitor_output: monitor_output.clone(),
+            },
         );
-        if let Some(devpath) = piano.find_devpath() {
-            let init_params = piano::InitParams {
-                after_piano_connected: false,
-            };
-            piano.init(devpath, init_params).await;
+        piano.recording_storage.recover_unsaved().await;
+        let file_manager = FileManager::new(config.data_dir.clone(), &piano);
+
+        if config.dbus_service {
+            dbus.serve(piano.clone())
+                .await
+                .with_context(|| "Unable to register the D-Bus service")?;
         }
 
-        let hotspot = config.hotspot.clone().map(Hotspot::from);
+        let midi_controllers = MidiControllers::new(event_broadcaster.clone());
+        let power = config.power.clone().map(|power_config| {
+            PowerMonitor::new(power_config, dbus.clone(), config.broadcaster_capacity)
+        });
+        let updater = config.updater.clone().map(|updater_config| {
+            Updater::new(updater_config, dbus.clone(), event_broadcaster.clone())
+        });
+        let presence = config.presence.clone().map(|presence_config| {
+            PresenceMonitor::new(
+                presence_config,
+                bluetooth.clone(),
+                event_broadcaster.clone(),
+            )
+        });
+        let poweroff_scheduler = PoweroffScheduler::new(
+            config.daily_poweroff_at,
+            dbus.clone(),
+            piano.clone(),
+            prefs.clone(),
+        );
+        let backup = Backup::new(config.backup.clone(), config.data_dir.clone());
+        let remote_backup = RemoteBackup::new(
+            config.backup.clone(),
+            backup.clone(),
+            event_broadcaster.clone(),
+        );
+        let display = Display::new(config.display.clone(), config.location);
+        let hotspot = config
+            .hotspot
+            .clone()
+            .map(|hotspot_config| Hotspot::new(hotspot_config, tasks.clone()));
         let lounge_temp_monitor = bluetooth::new_device(
             config
                 .bluetooth
@@ -111,20 +422,267 @@ impl App {
                 .expect("server configuration is not validated"),
         );
 
+        let mut devices = DeviceRegistry::default();
+        devices.register(piano.clone());
+        if let Some(monitor_output) = monitor_output {
+            devices.register(monitor_output);
+        }
+        devices.register(midi_controllers.clone());
+        let status_led = config.led.clone().map(|led_config| {
+            StatusLed::new(
+                led_config,
+                config.quiet_hours,
+                config.location,
+                piano.clone(),
+                a2dp_source_handler.clone(),
+            )
+        });
+        if let Some(status_led) = &status_led {
+            tasks.spawn(
+                "status-led",
+                status_led.clone().run(
+                    event_broadcaster.clone(),
+                    bluetooth_device_broadcaster.clone(),
+                    shutdown_notify.clone(),
+                ),
+            );
+            devices.register(status_led.clone());
+        }
+        devices.register(BluetoothDevicePlugin::new(
+            "lounge-temp-monitor",
+            bluetooth.clone(),
+            Arc::clone(&lounge_temp_monitor),
+        ));
+        if let Some(usb_storage_config) = config.usb_storage.clone() {
+            devices.register(UsbStorage::new(
+                usb_storage_config,
+                backup.clone(),
+                piano.recording_storage.clone(),
+                event_broadcaster.clone(),
+                tasks.clone(),
+            ));
+        }
+        devices.init().await;
+        tasks.spawn(
+            "bluetooth-device-watcher",
+            bluetooth::forward_device_changes(dbus.clone(), bluetooth_device_broadcaster.clone()),
+        );
+        if let Some(power) = &power {
+            tasks.spawn("power-monitor", power.clone().run());
+        }
+        if let Some(presence) = &presence {
+            tasks.spawn(
+                "presence-monitor",
+                presence.clone().run(shutdown_notify.clone()),
+            );
+        }
+        let calendar = config.calendar.clone().map(Calendar::new);
+        if let Some(calendar) = &calendar {
+            tasks.spawn("calendar", calendar.clone().run(shutdown_notify.clone()));
+        }
+        let weather = config
+            .weather
+            .clone()
+            .zip(config.location)
+            .map(|(weather_config, location)| WeatherMonitor::new(weather_config, location));
+        if let Some(weather) = &weather {
+            tasks.spawn(
+                "weather-monitor",
+                weather.clone().run(shutdown_notify.clone()),
+            );
+        }
+        let mqtt = config.mqtt.clone().map(|mqtt_config| {
+            MqttIntegration::new(
+                mqtt_config,
+                piano.clone(),
+                bluetooth.clone(),
+                Arc::clone(&lounge_temp_monitor),
+                tasks.clone(),
+            )
+        });
+        if let Some(mqtt) = &mqtt {
+            tasks.spawn("mqtt", mqtt.clone().run(shutdown_notify.clone()));
+        }
+        let automation = Automation::new(
+            config.automation.rules.clone(),
+            config.scenes.clone(),
+            AutomationDependencies {
+                piano: piano.clone(),
+                bluetooth: bluetooth.clone(),
+                lounge_temp_monitor: Arc::clone(&lounge_temp_monitor),
+                tts: tts.clone(),
+                prefs: prefs.clone(),
+                display: display.clone(),
+                status_led,
+                weather: weather.clone(),
+                location: config.location,
+                calendar: calendar.clone(),
+                mqtt,
+                event_broadcaster: event_broadcaster.clone(),
+            },
+        );
+        if !config.automation.rules.is_empty() {
+            tasks.spawn(
+                "automation",
+                automation.clone().run(shutdown_notify.clone()),
+            );
+        }
+        if config.scripting {
+            let scripting = Scripting::new(
+                config.data_dir.path(Data::Scripts).to_path_buf(),
+                piano.clone(),
+                Arc::clone(&lounge_temp_monitor),
+                event_broadcaster.clone(),
+            );
+            tasks.spawn("scripting", scripting.run(shutdown_notify.clone()));
+        }
+        if let Some(ntfy_config) = config.ntfy.clone() {
+            let ntfy = NtfyNotifier::new(
+                ntfy_config,
+                config.quiet_hours,
+                config.location,
+                piano.clone(),
+                event_broadcaster.clone(),
+            );
+            tasks.spawn("ntfy", ntfy.run(shutdown_notify.clone()));
+        }
+        if let Some(telegram_config) = config.telegram.clone() {
+            let telegram = TelegramBot::new(
+                telegram_config,
+                config.quiet_hours,
+                config.location,
+                piano.clone(),
+                bluetooth.clone(),
+                Arc::clone(&lounge_temp_monitor),
+                event_broadcaster.clone(),
+            );
+            tasks.spawn("telegram", telegram.run(shutdown_notify.clone()));
+        }
+        if let Some(homekit_config) = config.homekit.clone() {
+            let homekit = HomeKitBridge::new(
+                homekit_config,
+                config.data_dir.path(Data::HomeKit).to_path_buf(),
+                piano.clone(),
+                bluetooth.clone(),
+                Arc::clone(&lounge_temp_monitor),
+            );
+            tasks.spawn("homekit", homekit.run(shutdown_notify.clone()));
+        }
+        if let Some(target) = config.recording_upload.clone() {
+            let uploader = RecordingUploader::new(
+                target,
+                config.backoff.recording_upload.clone(),
+                piano.clone(),
+                storage.clone(),
+                event_broadcaster.clone(),
+            );
+            tasks.spawn("recording-upload", uploader.run(shutdown_notify.clone()));
+        }
+        let dlna = config
+            .dlna
+            .clone()
+            .map(|dlna_config| DlnaServer::new(dlna_config, config.server_port, piano.clone()));
+        if let Some(dlna) = dlna.clone() {
+            tasks.spawn("dlna", dlna.run(shutdown_notify.clone()));
+        }
+        tasks.spawn(
+            "shutdown-inhibitor",
+            piano.clone().inhibit_system_shutdown(),
+        );
+        tasks.spawn(
+            "poweroff-scheduler",
+            poweroff_scheduler.clone().run(shutdown_notify.clone()),
+        );
+        if config.backup.daily_at.is_some() && !config.backup.targets.is_empty() {
+            tasks.spawn(
+                "remote-backup",
+                remote_backup.clone().run(shutdown_notify.clone()),
+            );
+        }
+        if config.display.sleep_at.is_some() || config.display.wake_at.is_some() {
+            tasks.spawn(
+                "display-schedule",
+                display.clone().follow_schedule(shutdown_notify.clone()),
+            );
+        }
+        tasks.spawn("disk-watchdog", piano.clone().watch_disk_space());
+        if config.watch_assets {
+            tasks.spawn(
+                "assets-watcher",
+                AssetsWatcher::new(config.assets_dir.clone(), sounds.clone(), piano.clone())
+                    .run(shutdown_notify.clone()),
+            );
+        }
+        tasks.spawn(
+            "storage",
+            storage.clone().run(
+                config.storage.clone(),
+                piano.clone(),
+                Arc::clone(&lounge_temp_monitor),
+                event_broadcaster.clone(),
+                shutdown_notify.clone(),
+            ),
+        );
+        tasks.spawn(
+            "piano-output-router",
+            piano.clone().route_output_continuously(),
+        );
+
         Ok(Self {
             config,
             prefs,
+            storage,
             sounds,
             event_broadcaster,
+            hotplug_broadcaster,
+            bluetooth_device_broadcaster,
             shutdown_notify,
+            tasks,
+            transcoder,
+            file_manager,
+            config_editor,
+            tts,
+            backup,
 
             dbus,
             bluetooth,
             a2dp_source_handler,
 
+            devices,
             hotspot,
             piano,
+            midi_controllers,
+            power,
+            updater,
+            presence,
+            weather,
+            calendar,
+            dlna,
+            voice_memos,
+            poweroff_scheduler,
+            remote_backup,
+            automation,
+            display,
             lounge_temp_monitor,
         })
     }
+
+    /// Perform the graceful shutdown. Must be called after the shutdown is triggered.
+    pub async fn shutdown(&self, http_server: ServerHandle) {
+        let (devices, prefs) = (self.devices.clone(), self.prefs.clone());
+
+        ShutdownCoordinator::new(Duration::from_secs(self.config.shutdown_timeout_secs))
+            .step("stop accepting HTTP requests", async move {
+                http_server.stop(true).await
+            })
+            // Piano finishes the recorder, Bluetooth devices are disconnected.
+            .step("shut down devices", async move { devices.shutdown().await })
+            .step("flush preferences", async move {
+                if let Err(e) = prefs.flush().await {
+                    error!("Failed to flush preferences: {e}");
+                }
+            })
+            .run()
+            .await
+    }
 }
@@ -1,65 +1,106 @@
 use std::io;
 
-use actix_web::{middleware, web, HttpServer};
-use anyhow::Context;
+use actix_web::{dev::ServerHandle, middleware, web, HttpServer};
+use anyhow::{bail, Context};
 use bluez_async::BluetoothSession;
 use log::{info, warn};
 
 use homie_home::{
     bluetooth::{self, A2DPSourceHandler, Bluetooth},
     config::Config,
-    core::logger::AppLogger,
-    graphql, rest, udev, App,
+    core::{i18n, logger::AppLogger, panic, supervisor::Supervisor, timezone},
+    graphql, rest,
+    udev::UdevMonitor,
+    App,
 };
 
+/// Part of the shutdown timeout given to the in-flight HTTP requests (1/N). The remaining time
+/// is left for the devices, so a long request can't prevent finishing an active recording.
+const HTTP_SHUTDOWN_TIMEOUT_DIVISOR: u64 = 3;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let config =
         Config::new().with_context(|| "Failed to initialize the server from configuration")?;
-    AppLogger::install(config.log_level).with_context(|| "Failed to install the global logger")?;
+    AppLogger::install(&config).with_context(|| "Failed to install the global logger")?;
+    i18n::init(config.locale);
+    if let Some(tz) = config.timezone {
+        timezone::init(tz);
+    }
 
     // This session can be cloned and shared between different [Bluetooth] instances.
     let (_, bluetooth_session) = BluetoothSession::new()
         .await
         .with_context(|| "Failed to establish communication with BlueZ")?;
-    let bluetooth = Bluetooth::new(bluetooth_session.clone(), config.bluetooth.clone())
-        .await
-        .with_context(|| "Failed to initialize Bluetooth")?;
+    let bluetooth = Bluetooth::new(
+        bluetooth_session.clone(),
+        config.bluetooth.clone(),
+        config.backoff.clone(),
+    )
+    .await
+    .with_context(|| "Failed to initialize Bluetooth")?;
     let a2dp_source_handler = A2DPSourceHandler::new(&bluetooth_session)
         .await
         .with_context(|| "Failed to initialize the A2DP source handler")?;
     let app = App::new(config, bluetooth, a2dp_source_handler)
         .await
         .with_context(|| "Failed to initialize the application")?;
+    panic::install_hook(app.shutdown_notify.clone());
 
-    spawn_http_server(app.clone()).with_context(|| "Failed to start the HTTP server")?;
+    let http_server =
+        spawn_http_server(app.clone()).with_context(|| "Failed to start the HTTP server")?;
     spawn_bluetooth(app.clone());
-    bluetooth::spawn_global_event_handler(bluetooth_session, app.clone())
-        .await
-        .with_context(|| "Failed to start the Bluetooth event handler")?;
-    // Running it in the main thread, because
-    // [tokio_udev::AsyncMonitorSocket] can not be sent between threads.
-    udev::handle_events_until_shutdown(app)
-        .await
-        .with_context(|| "Failed to handle device events")
+    let bluetooth_event_handler =
+        bluetooth::GlobalEventHandler::spawn(bluetooth_session, app.clone())
+            .await
+            .with_context(|| "Failed to start the Bluetooth event handler")?;
+
+    let supervisor = Supervisor::new(app.event_broadcaster.clone(), app.shutdown_notify.clone());
+    let udev_monitor = UdevMonitor::spawn(app.clone(), supervisor.clone())
+        .with_context(|| "Failed to start the device events monitor")?;
+    app.tasks.spawn(
+        "supervisor",
+        supervisor
+            .watch(udev_monitor)
+            .watch(bluetooth_event_handler)
+            .watch(app.piano.clone())
+            .run(),
+    );
+
+    app.shutdown_notify.notified().await;
+    app.shutdown(http_server).await;
+    if panic::has_panicked() {
+        // Exit with an error to let systemd restart the service.
+        bail!("Stopped because of a panic");
+    }
+    Ok(())
 }
 
-fn spawn_http_server(app: App) -> io::Result<()> {
+fn spawn_http_server(app: App) -> io::Result<ServerHandle> {
     let (address, port) = (app.config.server_address.clone(), app.config.server_port);
+    let http_shutdown_timeout_secs =
+        app.config.shutdown_timeout_secs / HTTP_SHUTDOWN_TIMEOUT_DIVISOR;
     let server = HttpServer::new(move || {
         actix_web::App::new()
             // Data MUST be wrapped with [web::Data].
             .app_data(web::Data::new(app.clone()))
             .app_data(web::Data::new(graphql::build_schema(app.clone())))
             .wrap(middleware::NormalizePath::trim())
+            .wrap_fn(rest::with_request_id)
+            // Must be the outermost to log the request ID added to the response.
+            .wrap(middleware::Logger::new(rest::ACCESS_LOG_FORMAT).exclude("/api/live"))
             .configure(|service_config| rest::configure_service(service_config, &app))
     })
+    // Server will be stopped by the shutdown coordinator.
+    .disable_signals()
+    .shutdown_timeout(http_shutdown_timeout_secs)
     .bind((address.clone(), port))?
     .run();
 
+    let handle = server.handle();
     tokio::spawn(server);
     info!("HTTP server bound to {address}:{port}");
-    Ok(())
+    Ok(handle)
 }
 
 fn spawn_bluetooth(app: App) {
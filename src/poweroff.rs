@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset, NaiveTime};
+use log::{error, info, warn};
+use tokio::{select, sync::Notify};
+
+use crate::{
+    core::{timezone, ShutdownNotify},
+    dbus::DBus,
+    device::piano::{Piano, StopRecorderParams},
+    graphql::GraphQLError,
+    prefs::PreferencesStorage,
+    SharedMutex,
+};
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum PoweroffScheduleError {
+    #[error("Time is in the past")]
+    TimeInPast,
+}
+
+impl GraphQLError for PoweroffScheduleError {}
+
+#[derive(Clone, Copy, SimpleObject)]
+pub struct ScheduledPoweroff {
+    pub at: DateTime<FixedOffset>,
+    /// Whether it's the daily power off from the configuration.
+    pub recurring: bool,
+}
+
+#[derive(Default)]
+struct Schedule {
+    /// Set using [PoweroffScheduler::schedule].
+    one_time: Option<DateTime<FixedOffset>>,
+    /// Daily power offs before this time are skipped (used to cancel the next one).
+    skip_daily_until: Option<DateTime<FixedOffset>>,
+}
+
+/// Gracefully powers off the system at the requested time or every day.
+#[derive(Clone)]
+pub struct PoweroffScheduler {
+    /// [None] if the daily power off is not configured.
+    daily_at: Option<NaiveTime>,
+    dbus: DBus,
+    piano: Piano,
+    prefs: PreferencesStorage,
+    schedule: SharedMutex<Schedule>,
+    /// Wakes up [Self::run] when the schedule is changed.
+    changed: Arc<Notify>,
+}
+
+impl PoweroffScheduler {
+    pub fn new(
+        daily_at: Option<NaiveTime>,
+        dbus: DBus,
+        piano: Piano,
+        prefs: PreferencesStorage,
+    ) -> Self {
+        Self {
+            daily_at,
+            dbus,
+            piano,
+            prefs,
+            schedule: Arc::default(),
+            changed: Arc::default(),
+        }
+    }
+
+    /// Returns the nearest power off.
+    pub async fn pending(&self) -> Option<ScheduledPoweroff> {
+        let schedule = self.schedule.lock().await;
+        let one_time = schedule.one_time.map(|at| ScheduledPoweroff {
+            at,
+            recurring: false,
+        });
+        let daily = self.next_daily(&schedule).map(|at| ScheduledPoweroff {
+            at,
+            recurring: true,
+        });
+        [one_time, daily]
+            .into_iter()
+            .flatten()
+            .min_by_key(|poweroff| poweroff.at)
+    }
+
+    /// Replaces the previously scheduled one-time power off.
+    pub async fn schedule(
+        &self,
+        at: DateTime<FixedOffset>,
+    ) -> Result<ScheduledPoweroff, PoweroffScheduleError> {
+        if at <= timezone::now() {
+            return Err(PoweroffScheduleError::TimeInPast);
+        }
+        self.schedule.lock().await.one_time = Some(at);
+        self.changed.notify_one();
+        info!("Power off scheduled at {at}");
+        Ok(ScheduledPoweroff {
+            at,
+            recurring: false,
+        })
+    }
+
+    /// Cancel the one-time power off and skip the next daily one.
+    /// Returns `false` if there was nothing to cancel.
+    pub async fn cancel(&self) -> bool {
+        let mut schedule = self.schedule.lock().await;
+        let next_daily = self.next_daily(&schedule);
+        let cancelled = schedule.one_time.take().is_some() || next_daily.is_some();
+        if let Some(next_daily) = next_daily {
+            schedule.skip_daily_until = Some(next_daily);
+        }
+        drop(schedule);
+        if cancelled {
+            self.changed.notify_one();
+            info!("Scheduled power off cancelled");
+        }
+        cancelled
+    }
+
+    /// Wait for the scheduled time and power off. Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        loop {
+            let pending = self.pending().await;
+            let sleep_duration =
+                pending.and_then(|poweroff| (poweroff.at - timezone::now()).to_std().ok());
+            select! {
+                _ = async {
+                    match sleep_duration {
+                        Some(duration) => tokio::time::sleep(duration).await,
+                        None => std::future::pending().await,
+                    }
+                } => {}
+                _ = self.changed.notified() => continue,
+                _ = shutdown_notify.notified() => return,
+            }
+            let Some(poweroff) = pending else {
+                continue;
+            };
+
+            let mut schedule = self.schedule.lock().await;
+            if poweroff.recurring {
+                schedule.skip_daily_until = Some(poweroff.at);
+            } else if schedule.one_time == Some(poweroff.at) {
+                schedule.one_time = None;
+            } else {
+                // Rescheduled just before the time came.
+                continue;
+            }
+            drop(schedule);
+            self.power_off().await;
+        }
+    }
+
+    async fn power_off(&self) {
+        warn!("Scheduled power off. Finishing the recording...");
+        // Result will be logged by the method.
+        let _ = self
+            .piano
+            .stop_recorder(StopRecorderParams {
+                play_feedback: false,
+            })
+            .await;
+        if let Err(e) = self.prefs.flush().await {
+            error!("Failed to flush preferences: {e}");
+        }
+        let result = match self.dbus.login_manager_proxy().await {
+            Ok(proxy) => proxy.power_off(false).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Failed to power off: {e}");
+        }
+    }
+
+    fn next_daily(&self, schedule: &Schedule) -> Option<DateTime<FixedOffset>> {
+        let daily_at = self.daily_at?;
+        let now = timezone::now();
+        let after = schedule
+            .skip_daily_until
+            .filter(|skip_until| *skip_until > now)
+            .unwrap_or(now);
+        timezone::next_time_of_day(daily_at, after)
+    }
+}
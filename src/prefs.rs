@@ -9,7 +9,7 @@ use tokio::{
     sync::{RwLock, RwLockReadGuard},
 };
 
-use crate::{graphql::GraphQLError, App, GlobalEvent, SharedRwLock};
+use crate::{core::Broadcaster, graphql::GraphQLError, App, GlobalEvent, SharedRwLock};
 
 #[derive(Default, Clone, Deserialize, Serialize, SimpleObject)]
 pub struct Preferences {
@@ -19,9 +19,27 @@ pub struct Preferences {
     pub hotspot_handling_enabled: bool,
     /// Piano-related settings.
     pub piano: PianoPreferences,
+    /// Audio output settings.
+    #[serde(default)]
+    pub audio: AudioPreferences,
+}
+
+#[derive(Default, Clone, Deserialize, Serialize, SimpleObject)]
+#[serde(default)]
+pub struct AudioPreferences {
+    /// Name of the device to play the recordings and sounds on (see `availableOutputDevices`),
+    /// so the piano stays dedicated to recording. If not set, the piano is used.
+    pub output_device: Option<String>,
+    /// Bring the recordings to the same loudness while playing them.
+    /// Files are not modified, the measured gain is applied at playback.
+    pub normalize_loudness: bool,
+    /// Fade duration between the queued recordings (see `enqueueRecording`).
+    /// If it's zero, the recordings are played without gaps.
+    pub queue_transition_ms: u32,
 }
 
 #[derive(Clone, Deserialize, Serialize, SimpleObject)]
+#[serde(default)]
 pub struct PianoPreferences {
     /// Volume of the secondary sounds. Each sample will be multiplied by this value.
     /// `1.0` is the normal (original) volume.
@@ -30,6 +48,9 @@ pub struct PianoPreferences {
     pub record_amplitude_scale: Option<f32>,
     /// If provided, embed ARTIST metadata into the recordings using the given value.
     pub recordings_artist: Option<String>,
+    /// Skip the silence at the start and at the end of the recordings.
+    /// Takes effect only if `piano.recorder.trim_silence` is configured.
+    pub trim_silence: bool,
 }
 
 impl Default for PianoPreferences {
@@ -38,6 +59,7 @@ impl Default for PianoPreferences {
             sounds_volume: f32::IDENTITY,
             record_amplitude_scale: None,
             recordings_artist: None,
+            trim_silence: true,
         }
     }
 }
@@ -57,6 +79,7 @@ impl GraphQLError for PreferencesUpdateError {}
 pub struct PreferencesUpdate {
     hotspot_handling_enabled: Option<bool>,
     piano: Option<PianoPreferencesUpdate>,
+    audio: Option<AudioPreferencesUpdate>,
 }
 
 #[derive(InputObject)]
@@ -65,6 +88,14 @@ struct PianoPreferencesUpdate {
     // If we want to set null, we must do it explicitly using OptionUpdate.
     record_amplitude_scale: Option<OptionUpdate<f32>>,
     recordings_artist: Option<OptionUpdate<String>>,
+    trim_silence: Option<bool>,
+}
+
+#[derive(InputObject)]
+struct AudioPreferencesUpdate {
+    output_device: Option<OptionUpdate<String>>,
+    normalize_loudness: Option<bool>,
+    queue_transition_ms: Option<u32>,
 }
 
 #[derive(InputObject)]
@@ -132,13 +163,69 @@ impl PreferencesStorage {
             if let Some(recordings_artist) = piano.recordings_artist {
                 prefs_lock.piano.recordings_artist = recordings_artist.into();
             }
+            if let Some(trim_silence) = piano.trim_silence {
+                prefs_lock.piano.trim_silence = trim_silence;
+            }
+        }
+
+        let mut output_device_changed = false;
+        if let Some(audio) = update.audio {
+            if let Some(output_device) = audio.output_device {
+                let output_device = Option::from(output_device);
+                output_device_changed = prefs_lock.audio.output_device != output_device;
+                prefs_lock.audio.output_device = output_device;
+            }
+            if let Some(normalize_loudness) = audio.normalize_loudness {
+                prefs_lock.audio.normalize_loudness = normalize_loudness;
+            }
+            if let Some(queue_transition_ms) = audio.queue_transition_ms {
+                prefs_lock.audio.queue_transition_ms = queue_transition_ms;
+            }
         }
 
         app.event_broadcaster.send(GlobalEvent::PreferencesUpdated);
+        let result = self.write_file(&prefs_lock).await;
+        // Player reads the preferences while initializing.
+        drop(prefs_lock);
+        if output_device_changed {
+            app.piano.switch_output_device().await;
+        }
+        result
+    }
+
+    /// Unlike [Self::update], it doesn't require [App] as the hotspot handling
+    /// has no side effects. Used by the automation rules.
+    pub async fn set_hotspot_handling_enabled(
+        &self,
+        enabled: bool,
+        event_broadcaster: &Broadcaster<GlobalEvent>,
+    ) -> Result<(), PreferencesUpdateError> {
+        let mut prefs_lock = self.preferences.write().await;
+        prefs_lock.hotspot_handling_enabled = enabled;
+        event_broadcaster.send(GlobalEvent::PreferencesUpdated);
+        self.write_file(&prefs_lock).await
+    }
+
+    pub async fn set_sounds_volume(
+        &self,
+        volume: f32,
+        event_broadcaster: &Broadcaster<GlobalEvent>,
+    ) -> Result<(), PreferencesUpdateError> {
+        let mut prefs_lock = self.preferences.write().await;
+        prefs_lock.piano.sounds_volume = volume;
+        event_broadcaster.send(GlobalEvent::PreferencesUpdated);
+        self.write_file(&prefs_lock).await
+    }
+
+    /// Write the current preferences to the file.
+    pub async fn flush(&self) -> Result<(), PreferencesUpdateError> {
+        self.write_file(&*self.preferences.read().await).await
+    }
+
+    async fn write_file(&self, prefs: &Preferences) -> Result<(), PreferencesUpdateError> {
         fs::write(
             &self.yaml_file,
-            serde_yaml::to_string(&*prefs_lock)
-                .map_err(PreferencesUpdateError::SerializationFailed)?,
+            serde_yaml::to_string(prefs).map_err(PreferencesUpdateError::SerializationFailed)?,
         )
         .await
         .map_err(PreferencesUpdateError::FailedToSave)
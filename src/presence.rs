@@ -0,0 +1,188 @@
+use std::{net::Ipv4Addr, process::Stdio, sync::Arc, time::Duration};
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset};
+use log::{info, warn};
+use serde_json::Value;
+use tokio::{net::UdpSocket, process::Command, select, sync::Mutex};
+
+use crate::{
+    bluetooth::Bluetooth,
+    config,
+    core::{timezone, Broadcaster, ShutdownNotify},
+    GlobalEvent, SharedMutex,
+};
+
+/// Neighbour states of `ip neigh` which mean that the address was recently reachable.
+const PRESENT_NEIGHBOUR_STATES: [&str; 3] = ["REACHABLE", "DELAY", "PROBE"];
+/// The entry can remain in this state for a long time after the host has left.
+/// Such hosts are probed, so their real state is known on the next check.
+const STALE_NEIGHBOUR_STATE: &str = "STALE";
+/// "Discard" service: the datagram is only needed to make the kernel verify the neighbour.
+const PROBE_PORT: u16 = 9;
+
+#[derive(Clone, PartialEq, Eq, SimpleObject)]
+pub struct PersonPresence {
+    /// Name of the person from the configuration.
+    pub name: String,
+    pub home: bool,
+    /// When any device of the person was seen last time. Null if it was not seen since startup.
+    pub last_seen: Option<DateTime<FixedOffset>>,
+}
+
+/// Derives the home / away state of the configured people
+/// from their phones in the local network and nearby Bluetooth devices.
+#[derive(Clone)]
+pub struct PresenceMonitor {
+    config: config::Presence,
+    bluetooth: Bluetooth,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+    /// In the order of the configured people.
+    people: SharedMutex<Vec<PersonPresence>>,
+}
+
+impl PresenceMonitor {
+    pub fn new(
+        config: config::Presence,
+        bluetooth: Bluetooth,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        let people = config
+            .people
+            .iter()
+            .map(|person| PersonPresence {
+                name: person.name.clone(),
+                home: false,
+                last_seen: None,
+            })
+            .collect();
+        Self {
+            config,
+            bluetooth,
+            event_broadcaster,
+            people: Arc::new(Mutex::new(people)),
+        }
+    }
+
+    pub async fn people(&self) -> Vec<PersonPresence> {
+        self.people.lock().await.clone()
+    }
+
+    /// Check the devices periodically. Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let away_after = chrono::Duration::seconds(self.config.away_after_secs as i64);
+        loop {
+            let (wifi_addresses, bluetooth_addresses) = (
+                neighbour_addresses().await,
+                self.bluetooth_addresses().await,
+            );
+            let now = timezone::now();
+
+            let mut people = self.people.lock().await;
+            for (person, config) in people.iter_mut().zip(&self.config.people) {
+                let seen = [
+                    (&config.wifi_mac_address, &wifi_addresses),
+                    (&config.bluetooth_mac_address, &bluetooth_addresses),
+                ]
+                .into_iter()
+                .any(|(address, addresses)| {
+                    address.as_ref().is_some_and(|address| {
+                        addresses
+                            .iter()
+                            .any(|seen| seen.eq_ignore_ascii_case(address))
+                    })
+                });
+                if seen {
+                    person.last_seen = Some(now);
+                }
+                let home = person
+                    .last_seen
+                    .is_some_and(|last_seen| now - last_seen < away_after);
+                if home != person.home {
+                    person.home = home;
+                    info!("{} is {}", person.name, if home { "home" } else { "away" });
+                    self.event_broadcaster
+                        .send(GlobalEvent::PresenceChanged(person.clone()));
+                }
+            }
+            drop(people);
+
+            select! {
+                _ = tokio::time::sleep(Duration::from_secs(self.config.check_interval_secs)) => {}
+                _ = shutdown_notify.notified() => break,
+            }
+        }
+    }
+
+    async fn bluetooth_addresses(&self) -> Vec<String> {
+        match self.bluetooth.nearby_devices().await {
+            Ok(addresses) => addresses
+                .into_iter()
+                .map(|address| address.to_string())
+                .collect(),
+            Err(e) => {
+                warn!("Failed to get nearby Bluetooth devices: {e}");
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// MAC addresses of the recently reachable hosts in the local network.
+async fn neighbour_addresses() -> Vec<String> {
+    let output = Command::new("ip")
+        .args(["-json", "neigh", "show"])
+        .stdin(Stdio::null())
+        .output()
+        .await;
+    let neighbours = match output {
+        Ok(output) if output.status.success() => {
+            serde_json::from_slice::<Vec<Value>>(&output.stdout).unwrap_or_default()
+        }
+        Ok(output) => {
+            warn!(
+                "Failed to get the network neighbours: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Vec::new();
+        }
+        Err(e) => {
+            warn!("Failed to run ip: {e}");
+            return Vec::new();
+        }
+    };
+
+    let has_state = |neighbour: &Value, expected: &[&str]| {
+        neighbour["state"].as_array().is_some_and(|states| {
+            states
+                .iter()
+                .any(|state| expected.contains(&state.as_str().unwrap_or("")))
+        })
+    };
+    for neighbour in &neighbours {
+        if has_state(neighbour, &[STALE_NEIGHBOUR_STATE]) {
+            // IPv6 neighbours are skipped, as the link-local addresses require the interface scope.
+            let address = neighbour["dst"].as_str().and_then(|dst| dst.parse().ok());
+            if let Some(address) = address {
+                probe(address).await;
+            }
+        }
+    }
+    neighbours
+        .into_iter()
+        .filter(|neighbour| has_state(neighbour, &PRESENT_NEIGHBOUR_STATES))
+        .filter_map(|neighbour| neighbour["lladdr"].as_str().map(str::to_string))
+        .collect()
+}
+
+/// Send an empty datagram to the host, so the kernel moves its stale neighbour entry
+/// to the "REACHABLE" or "FAILED" state.
+async fn probe(address: Ipv4Addr) {
+    let result = match UdpSocket::bind("0.0.0.0:0").await {
+        Ok(socket) => socket.send_to(&[], (address, PROBE_PORT)).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("Failed to probe the network neighbour {address}: {e}");
+    }
+}
@@ -0,0 +1,123 @@
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset};
+use futures::{pin_mut, StreamExt};
+use log::{error, info, warn};
+use tokio::select;
+
+use crate::{
+    audio::recorder::RECORDING_EXTENSION,
+    config::{BackoffPolicy, BackupTargetKind},
+    core::{timezone, Broadcaster, ShutdownNotify, SortOrder},
+    device::piano::{recordings::Recording, Piano, PianoEvent},
+    remote_backup,
+    storage::Storage,
+    GlobalEvent,
+};
+
+#[derive(Clone, PartialEq, Eq, SimpleObject)]
+pub struct RecordingUploadStatus {
+    pub recording_id: i64,
+    pub finished_at: DateTime<FixedOffset>,
+    /// Null if the upload succeeded.
+    pub error: Option<String>,
+}
+
+/// Mirrors the saved recordings to the remote target. Failed uploads are retried using
+/// the backoff policy, and then again on the next start or when a new recording is saved.
+#[derive(Clone)]
+pub struct RecordingUploader {
+    target: BackupTargetKind,
+    retry_policy: BackoffPolicy,
+    piano: Piano,
+    storage: Storage,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+impl RecordingUploader {
+    pub fn new(
+        target: BackupTargetKind,
+        retry_policy: BackoffPolicy,
+        piano: Piano,
+        storage: Storage,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        Self {
+            target,
+            retry_policy,
+            piano,
+            storage,
+            event_broadcaster,
+        }
+    }
+
+    /// Upload the recordings which are not uploaded yet, and then every new one.
+    /// Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await;
+        pin_mut!(piano_events);
+        // Including the ones saved while uploading was disabled.
+        self.upload_pending(&shutdown_notify).await;
+        while let Some(event) = piano_events.next().await {
+            if let PianoEvent::NewRecordingSaved = event.payload {
+                self.upload_pending(&shutdown_notify).await;
+            }
+        }
+    }
+
+    async fn upload_pending(&self, shutdown_notify: &ShutdownNotify) {
+        let recordings = match self
+            .piano
+            .recording_storage
+            .list(SortOrder::Ascending)
+            .await
+        {
+            Ok(recordings) => recordings,
+            Err(e) => return error!("Failed to list the recordings to upload: {e}"),
+        };
+        let uploaded = match self.storage.uploaded_recordings().await {
+            Ok(uploaded) => uploaded,
+            Err(e) => return error!("Failed to get the uploaded recordings: {e}"),
+        };
+        for recording in recordings
+            .iter()
+            .filter(|recording| !uploaded.contains(&recording.id()))
+        {
+            select! {
+                status = self.upload(recording) => {
+                    if let Err(e) = self.storage.set_upload_status(status.clone()).await {
+                        error!("Failed to save the upload status: {e}");
+                    }
+                    self.event_broadcaster
+                        .send(GlobalEvent::RecordingUploadFinished(status));
+                }
+                _ = shutdown_notify.notified() => return,
+            }
+        }
+    }
+
+    async fn upload(&self, recording: &Recording) -> RecordingUploadStatus {
+        let name = format!("{}{RECORDING_EXTENSION}", recording.id());
+        let result = backoff::future::retry(self.retry_policy.exponential(), || async {
+            remote_backup::upload(&self.target, &recording.flac_path, &name)
+                .await
+                .map_err(|e| {
+                    warn!("Failed to upload recording {recording}: {e}");
+                    backoff::Error::transient(e)
+                })
+        })
+        .await;
+        match &result {
+            Ok(()) => info!("Recording {recording} uploaded"),
+            Err(e) => error!("Gave up uploading recording {recording}: {e}"),
+        }
+        RecordingUploadStatus {
+            recording_id: recording.id(),
+            finished_at: timezone::now(),
+            error: result.err(),
+        }
+    }
+}
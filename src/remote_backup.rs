@@ -0,0 +1,383 @@
+use std::{
+    env, io,
+    path::Path,
+    process::{Output, Stdio},
+    sync::Arc,
+};
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset};
+use log::{error, info, warn};
+use tokio::{fs, process::Command, select};
+
+use crate::{
+    config::{self, BackupTarget, BackupTargetKind},
+    core::{
+        backup::{Backup, IncrementalBackup},
+        curl_config::CurlConfig,
+        timezone, Broadcaster, ShutdownNotify,
+    },
+    GlobalEvent, SharedMutex,
+};
+
+#[derive(Clone, PartialEq, Eq, SimpleObject)]
+pub struct BackupUploadStatus {
+    /// Name of the target from the configuration.
+    pub target: String,
+    pub finished_at: DateTime<FixedOffset>,
+    /// Null if the upload succeeded.
+    pub error: Option<String>,
+}
+
+/// Makes a backup every day and uploads it to the configured targets,
+/// keeping only the last backups on each of them.
+#[derive(Clone)]
+pub struct RemoteBackup {
+    config: config::Backup,
+    backup: Backup,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+    /// Results of the last uploads in the order of the targets.
+    last_uploads: SharedMutex<Vec<BackupUploadStatus>>,
+}
+
+impl RemoteBackup {
+    pub fn new(
+        config: config::Backup,
+        backup: Backup,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        Self {
+            config,
+            backup,
+            event_broadcaster,
+            last_uploads: Arc::default(),
+        }
+    }
+
+    pub async fn last_uploads(&self) -> Vec<BackupUploadStatus> {
+        self.last_uploads.lock().await.clone()
+    }
+
+    /// Wait for the configured time and upload a backup every day. Returns on shutdown
+    /// or immediately if the time is not configured.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let Some(daily_at) = self.config.daily_at else {
+            return;
+        };
+        loop {
+            let now = timezone::now();
+            let Some(duration) =
+                timezone::next_time_of_day(daily_at, now).and_then(|at| (at - now).to_std().ok())
+            else {
+                return;
+            };
+            select! {
+                _ = tokio::time::sleep(duration) => {}
+                _ = shutdown_notify.notified() => return,
+            }
+            self.upload().await;
+        }
+    }
+
+    async fn upload(&self) {
+        let name = format!(
+            "{}{}{}",
+            timezone::now().format("%F_%H-%M-%S"),
+            if self.config.incremental {
+                "-incremental"
+            } else {
+                ""
+            },
+            self.backup.extension()
+        );
+        // Not in the data directory, so the backup doesn't include itself.
+        let path = self
+            .config
+            .staging_dir
+            .clone()
+            .unwrap_or_else(env::temp_dir)
+            .join(&name);
+        info!("Making a backup to upload...");
+        let incremental = match self.make_backup(&path).await {
+            Ok(incremental) => incremental,
+            Err(e) => {
+                error!("Failed to make a backup: {e}");
+                for target in &self.config.targets {
+                    self.set_status(target, Some(format!("unable to make a backup: {e}")))
+                        .await;
+                }
+                return;
+            }
+        };
+
+        let mut all_uploaded = true;
+        for target in &self.config.targets {
+            let result = match upload(&target.kind, &path, &name).await {
+                // Every incremental backup depends on the previous ones.
+                Ok(()) if self.config.incremental => Ok(()),
+                Ok(()) => prune(target).await,
+                Err(e) => Err(e),
+            };
+            match &result {
+                Ok(()) => info!("Backup uploaded to {}", target.name),
+                Err(e) => error!("Failed to upload the backup to {}: {e}", target.name),
+            }
+            all_uploaded &= result.is_ok();
+            self.set_status(target, result.err()).await;
+        }
+        if let Err(e) = fs::remove_file(&path).await {
+            warn!("Failed to remove {}: {e}", path.to_string_lossy());
+        }
+        // Otherwise the next backup will include the same changes again.
+        if let Some(incremental) = incremental.filter(|_| all_uploaded) {
+            if let Err(e) = incremental.commit().await {
+                error!("Failed to save the backup manifest: {e}");
+            }
+        }
+    }
+
+    /// Returns [Some] if the backup is incremental.
+    async fn make_backup(&self, path: &Path) -> std::io::Result<Option<IncrementalBackup>> {
+        let file = std::fs::File::create(path)?;
+        let result = if self.config.incremental {
+            self.backup
+                .write_incremental(file)
+                .await
+                .map(|incremental| {
+                    info!(
+                        "{} new or changed recordings are included into the backup",
+                        incremental.changed_recordings
+                    );
+                    Some(incremental)
+                })
+        } else {
+            self.backup.write(file).await.map(|_| None)
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(path).await;
+        }
+        result
+    }
+
+    async fn set_status(&self, target: &BackupTarget, error: Option<String>) {
+        let status = BackupUploadStatus {
+            target: target.name.clone(),
+            finished_at: timezone::now(),
+            error,
+        };
+        let mut last_uploads = self.last_uploads.lock().await;
+        match last_uploads
+            .iter_mut()
+            .find(|last_upload| last_upload.target == target.name)
+        {
+            Some(last_upload) => *last_upload = status.clone(),
+            None => last_uploads.push(status.clone()),
+        }
+        drop(last_uploads);
+        self.event_broadcaster
+            .send(GlobalEvent::BackupUploadFinished(status));
+    }
+}
+
+/// Upload the file at `path` to the target as `name`.
+pub async fn upload(target: &BackupTargetKind, path: &Path, name: &str) -> Result<(), String> {
+    match target {
+        BackupTargetKind::S3 { .. } | BackupTargetKind::Webdav { .. } => {
+            run_curl(
+                target,
+                curl().arg("--upload-file").arg(path).arg(url(target, name)),
+            )
+            .await
+        }
+        BackupTargetKind::Rsync { destination } => {
+            run(Command::new("rsync")
+                .arg("--partial")
+                .arg(path)
+                .arg(format!("{destination}/{name}")))
+            .await
+        }
+    }
+    .map(|_| ())
+}
+
+/// Remove the oldest backups, so only [BackupTarget::keep_last] are left.
+async fn prune(target: &BackupTarget) -> Result<(), String> {
+    let mut names = list(&target.kind).await?;
+    // Names start with a timestamp.
+    names.sort_unstable();
+    let remove_count = names.len().saturating_sub(target.keep_last as usize);
+    for name in names.into_iter().take(remove_count) {
+        info!("Removing the old backup {name} from {}", target.name);
+        remove(&target.kind, &name).await?;
+    }
+    Ok(())
+}
+
+/// Returns names of the backups.
+async fn list(target: &BackupTargetKind) -> Result<Vec<String>, String> {
+    let names: Vec<_> = match target {
+        BackupTargetKind::S3 {
+            endpoint,
+            bucket,
+            prefix,
+            ..
+        } => {
+            let output = run_curl(
+                target,
+                curl().arg(format!(
+                    "{}/{bucket}?list-type=2&prefix={prefix}",
+                    endpoint.trim_end_matches('/')
+                )),
+            )
+            .await?;
+            xml_values(&output, "Key")
+                .into_iter()
+                .map(|key| key.trim_start_matches(prefix.as_str()).to_string())
+                .collect()
+        }
+        BackupTargetKind::Webdav { .. } => {
+            let output = run_curl(
+                target,
+                curl()
+                    .args(["--request", "PROPFIND", "--header", "Depth: 1"])
+                    .arg(url(target, "")),
+            )
+            .await?;
+            xml_values(&output, "href")
+                .into_iter()
+                .filter_map(|href| {
+                    href.trim_end_matches('/')
+                        .rsplit('/')
+                        .next()
+                        .map(str::to_string)
+                })
+                .collect()
+        }
+        BackupTargetKind::Rsync { destination } => {
+            // Each line is "<PERMISSIONS> <SIZE> <DATE> <TIME> <NAME>".
+            run(Command::new("rsync")
+                .arg("--list-only")
+                .arg(format!("{destination}/")))
+            .await?
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(4).map(str::to_string))
+            .collect()
+        }
+    };
+    Ok(names
+        .into_iter()
+        .filter(|name| Backup::is_archive_name(name))
+        .collect())
+}
+
+async fn remove(target: &BackupTargetKind, name: &str) -> Result<(), String> {
+    match target {
+        BackupTargetKind::S3 { .. } | BackupTargetKind::Webdav { .. } => {
+            run_curl(
+                target,
+                curl().args(["--request", "DELETE"]).arg(url(target, name)),
+            )
+            .await
+        }
+        // rsync can't remove a file directly, so the directory is synchronized with an empty one
+        // and only this file is included.
+        BackupTargetKind::Rsync { destination } => {
+            let empty_dir = env::temp_dir().join(concat!(env!("CARGO_PKG_NAME"), "-empty"));
+            fs::create_dir_all(&empty_dir)
+                .await
+                .map_err(|e| format!("unable to create {}: {e}", empty_dir.to_string_lossy()))?;
+            run(Command::new("rsync")
+                .args(["--recursive", "--delete"])
+                .arg(format!("--include=/{name}"))
+                .arg("--exclude=*")
+                .arg(format!("{}/", empty_dir.to_string_lossy()))
+                .arg(format!("{destination}/")))
+            .await
+        }
+    }
+    .map(|_| ())
+}
+
+fn curl() -> Command {
+    let mut command = Command::new("curl");
+    command.args(["--fail", "--silent", "--show-error", "--location"]);
+    command
+}
+
+/// Credentials of the target. Must be called only for the HTTP-based targets.
+fn curl_config(target: &BackupTargetKind) -> CurlConfig {
+    let config = CurlConfig::default();
+    match target {
+        BackupTargetKind::S3 {
+            region,
+            access_key,
+            secret_key,
+            ..
+        } => config
+            .option("aws-sigv4", &format!("aws:amz:{region}:s3"))
+            .option("user", &format!("{access_key}:{secret_key}")),
+        BackupTargetKind::Webdav {
+            username: Some(username),
+            password,
+            ..
+        } => config.option(
+            "user",
+            &format!("{username}:{}", password.as_deref().unwrap_or_default()),
+        ),
+        _ => config,
+    }
+}
+
+/// URL of the file `name` on the HTTP-based target.
+fn url(target: &BackupTargetKind, name: &str) -> String {
+    match target {
+        BackupTargetKind::S3 {
+            endpoint,
+            bucket,
+            prefix,
+            ..
+        } => format!("{}/{bucket}/{prefix}{name}", endpoint.trim_end_matches('/')),
+        BackupTargetKind::Webdav { url, .. } => format!("{}/{name}", url.trim_end_matches('/')),
+        BackupTargetKind::Rsync { .. } => unreachable!("rsync target has no URL"),
+    }
+}
+
+/// Returns stdout or stderr on failure.
+async fn run(command: &mut Command) -> Result<String, String> {
+    into_result(command.stdin(Stdio::null()).output().await)
+}
+
+/// The same as [run], but passes the target credentials to the curl `command`.
+async fn run_curl(target: &BackupTargetKind, command: &mut Command) -> Result<String, String> {
+    into_result(curl_config(target).output(command).await)
+}
+
+fn into_result(output: io::Result<Output>) -> Result<String, String> {
+    let output = output.map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Text of all elements with the local name `tag` (namespace prefix is ignored).
+fn xml_values<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let name = &rest[..end];
+        rest = &rest[end + 1..];
+        if name.rsplit(':').next() != Some(tag) {
+            continue;
+        }
+        if let Some(value_end) = rest.find('<') {
+            values.push(&rest[..value_end]);
+        }
+    }
+    values
+}
@@ -1,16 +1,31 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::{
+    future::Future,
+    net::{Ipv4Addr, Ipv6Addr},
+    path::Path,
+};
 
+use actix_files::NamedFile;
 use actix_web::{
-    dev::ServiceRequest,
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse},
     error::ErrorUnauthorized,
-    http::header,
+    http::{
+        header::{self, ContentEncoding, HeaderName, HeaderValue},
+        StatusCode,
+    },
     web::{self, ServiceConfig},
+    HttpMessage,
 };
 use actix_web_httpauth::extractors::{
     bearer::{self, BearerAuth},
     AuthenticationError,
 };
+use futures::{
+    future::{self, Either},
+    TryFutureExt,
+};
 use log::{debug, warn};
+use uuid::Uuid;
 
 use crate::{
     endpoint,
@@ -18,6 +33,161 @@ use crate::{
     App,
 };
 
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// Format of the HTTP requests logging.
+pub const ACCESS_LOG_FORMAT: &str = r#"[%{x-request-id}o] %a "%r" %s %Dms"#;
+
+const SITE_INDEX_FILE: &str = "index.html";
+/// One year is the conventional maximum.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Identifier to correlate an HTTP request with the logs. It's stored in the request extensions.
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+/// Marks a request which is made by the admin (see `admin_token` in the configuration).
+/// It's stored in the request extensions.
+#[derive(Clone, Copy)]
+pub struct AdminAccess;
+
+/// Middleware which assigns [RequestId] to a request and returns it in the response header.
+/// If a client passed a valid ID in the request header, it will be reused.
+pub fn with_request_id<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let header_value = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .filter(|value| !value.is_empty() && value.len() <= 64 && value.to_str().is_ok())
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&Uuid::new_v4().to_string())
+                .expect("UUID is not a valid header value")
+        });
+    request.extensions_mut().insert(RequestId(
+        header_value.to_str().unwrap_or_default().to_string(),
+    ));
+
+    let response = service.call(request);
+    async move {
+        let mut response = response.await?;
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, header_value);
+        Ok(response)
+    }
+}
+
+/// Middleware of the static site which sets `Cache-Control` and serves
+/// the pre-compressed files (see [crate::config::Site]).
+pub fn with_site_caching<S, B>(
+    request: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<EitherBody<B>>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let app = request
+        .app_data::<web::Data<App>>()
+        .expect("App data is not provided")
+        .clone();
+    let config = &app.config.site;
+    let is_immutable = config
+        .immutable_prefixes
+        .iter()
+        .any(|prefix| request.path().starts_with(prefix));
+    let precompressed = config
+        .precompressed
+        .then(|| precompressed_file(&request, &app.config.assets_dir.path(Asset::Site)))
+        .flatten();
+
+    let response = match precompressed {
+        Some(file) => {
+            let response = file.into_response(request.request());
+            Either::Left(future::ok(
+                request.into_response(response).map_into_right_body(),
+            ))
+        }
+        None => Either::Right(
+            service
+                .call(request)
+                .map_ok(ServiceResponse::map_into_left_body),
+        ),
+    };
+    let vary_encoding = config.precompressed;
+    async move {
+        let mut response = response.await?;
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with(mime::TEXT_HTML.essence_str()));
+        let headers = response.headers_mut();
+        if is_html {
+            headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        } else if is_immutable {
+            headers.insert(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+            );
+        }
+        if vary_encoding {
+            headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        Ok(response)
+    }
+}
+
+/// Returns [None] if there is no compressed variant of the requested file
+/// which is accepted by the client.
+fn precompressed_file(request: &ServiceRequest, site_dir: &Path) -> Option<NamedFile> {
+    let accepted: Vec<_> = request
+        .headers()
+        .get(header::ACCEPT_ENCODING)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .filter_map(|encoding| encoding.split(';').next())
+        .map(str::trim)
+        .collect();
+    let path = request.path().trim_start_matches('/');
+    // Hidden files and parent directories are not allowed.
+    // Percent-encoded paths are rare, so they are left to actix-files.
+    if path.contains('%') || path.split('/').any(|segment| segment.starts_with('.')) {
+        return None;
+    }
+    let mut file = site_dir.join(path);
+    if file.is_dir() {
+        file.push(SITE_INDEX_FILE);
+    }
+    let content_type = actix_files::file_extension_to_mime(file.extension()?.to_str()?);
+
+    [
+        (ContentEncoding::Brotli, ".br"),
+        (ContentEncoding::Gzip, ".gz"),
+    ]
+    .into_iter()
+    .filter(|(encoding, _)| accepted.contains(&encoding.as_str()))
+    .find_map(|(encoding, extension)| {
+        let mut compressed = file.clone().into_os_string();
+        compressed.push(extension);
+        NamedFile::open(compressed).ok().map(|compressed| {
+            compressed
+                .set_content_type(content_type.clone())
+                .set_content_encoding(encoding)
+                .disable_content_disposition()
+        })
+    })
+}
+
 pub fn configure_service(service_config: &mut ServiceConfig, app: &App) {
     service_config
         .service(endpoint::live)
@@ -28,15 +198,37 @@ pub fn configure_service(service_config: &mut ServiceConfig, app: &App) {
         .service(endpoint::graphql)
         .service(endpoint::graphql_playground)
         .service(endpoint::graphql_schema)
+        .service(endpoint::metrics)
         .service(endpoint::backup)
+        .service(endpoint::verify_backup)
+        .service(endpoint::announce)
         .service(endpoint::poweroff)
+        .service(endpoint::reboot)
+        .service(endpoint::trigger)
+        .service(endpoint::control)
+        .service(endpoint::status_summary)
         .service(endpoint::piano_recording)
+        .service(endpoint::transcoded_piano_recording)
+        .service(endpoint::piano_recording_waveform)
+        .service(endpoint::practice_journal)
+        .service(endpoint::voice_memo)
+        .service(endpoint::list_files)
+        .service(endpoint::download_file)
+        .service(endpoint::upload_file)
+        .service(endpoint::delete_file)
+        .service(endpoint::dlna_description)
+        .service(endpoint::dlna_service_description)
+        .service(endpoint::dlna_control)
+        .service(endpoint::dlna_recording)
+        .service(endpoint::dlna_cover)
         // Host the static files.
         .service(
-            actix_files::Files::new("/", &*app.config.assets_dir.path(Asset::Site))
-                // Be able to access the sub-directories.
-                .show_files_listing()
-                .index_file("index.html"),
+            web::scope("").wrap_fn(with_site_caching).service(
+                actix_files::Files::new("/", &*app.config.assets_dir.path(Asset::Site))
+                    // Be able to access the sub-directories.
+                    .show_files_listing()
+                    .index_file(SITE_INDEX_FILE),
+            ),
         );
 }
 
@@ -48,20 +240,16 @@ pub async fn auth_validator(
         let ip = addr.ip();
         if ip == Ipv4Addr::LOCALHOST || ip == Ipv6Addr::LOCALHOST {
             debug!("Authentication skipped, because client's address is localhost");
+            request.extensions_mut().insert(AdminAccess);
             return Ok(request);
         }
     }
 
-    let access_token = request
+    let config = &request
         .app_data::<web::Data<App>>()
         .expect("App data is not provided")
-        .config
-        .access_token
-        .as_ref();
-
-    if access_token.is_none() {
-        return Ok(request);
-    }
+        .config;
+    let (access_token, admin_token) = (config.access_token.clone(), config.admin_token.clone());
 
     let request_token = bearer_header
         .map(|auth| auth.token().to_string())
@@ -71,8 +259,16 @@ pub async fn auth_validator(
                 .map(|cookie| cookie.value().to_string())
         });
 
+    if admin_token.is_some() && request_token == admin_token {
+        request.extensions_mut().insert(AdminAccess);
+        return Ok(request);
+    }
+    let Some(access_token) = access_token else {
+        return Ok(request);
+    };
+
     if let Some(request_token) = request_token {
-        if *access_token.unwrap() == request_token {
+        if access_token == request_token {
             Ok(request)
         } else {
             let config = request
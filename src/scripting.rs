@@ -0,0 +1,299 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures::{pin_mut, StreamExt};
+use log::{error, info};
+use mlua::{Function, HookTriggers, Lua, LuaOptions, StdLib, Table, VmState};
+use strum::IntoEnumIterator;
+use tokio::{fs, select};
+
+use crate::{
+    bluetooth::DeviceHolder,
+    core::{Broadcaster, ShutdownNotify},
+    device::{
+        description::LoungeTempMonitor,
+        mi_temp_monitor::MiTempMonitor,
+        piano::{Piano, StopRecorderParams},
+    },
+    files::Sound,
+    GlobalEvent,
+};
+
+const SCRIPT_EXTENSION: &str = "lua";
+/// How often to check the scripts for changes.
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+/// Name of the global function which is called on each event.
+const EVENT_HANDLER: &str = "on_event";
+/// Maximum time of loading a script or handling an event by it.
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the interpreter checks whether [SCRIPT_TIMEOUT] is exceeded.
+const TIMEOUT_CHECK_INSTRUCTIONS: u32 = 10_000;
+/// Memory which can be allocated by a script, so a runaway one doesn't exhaust the system.
+const SCRIPT_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+/// Functions of the base library which read the files.
+const FILE_ACCESS_FUNCTIONS: [&str; 2] = ["dofile", "loadfile"];
+
+/// Paths and modification times of the scripts, used to detect changes.
+type Fingerprint = Vec<(PathBuf, SystemTime)>;
+
+struct Script {
+    name: String,
+    lua: Lua,
+}
+
+/// Runs the Lua scripts from the scripts directory. Every script has its own state
+/// and receives the events using the `on_event(source, name)` global function.
+#[derive(Clone)]
+pub struct Scripting {
+    scripts_dir: PathBuf,
+    piano: Piano,
+    lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+}
+
+impl Scripting {
+    pub fn new(
+        scripts_dir: PathBuf,
+        piano: Piano,
+        lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        Self {
+            scripts_dir,
+            piano,
+            lounge_temp_monitor,
+            event_broadcaster,
+        }
+    }
+
+    /// Pass the events to the scripts and reload them on changes. Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        let piano_events = self
+            .piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await;
+        let global_events = self
+            .event_broadcaster
+            .recv_continuously(shutdown_notify)
+            .await;
+        pin_mut!(piano_events, global_events);
+        let mut reload_check_interval = tokio::time::interval(RELOAD_CHECK_INTERVAL);
+        let (mut scripts, mut fingerprint) = (Vec::new(), None);
+
+        loop {
+            select! {
+                _ = reload_check_interval.tick() => {
+                    let new_fingerprint = self.fingerprint().await;
+                    if fingerprint.as_ref() != Some(&new_fingerprint) {
+                        scripts = self.load(&new_fingerprint).await;
+                        fingerprint = Some(new_fingerprint);
+                    }
+                }
+                Some(event) = piano_events.next() => {
+                    handle_event(&scripts, "piano", event.payload.as_ref()).await;
+                }
+                Some(event) = global_events.next() => {
+                    let kind = event.payload.kind().await;
+                    handle_event(&scripts, "global", kind.as_ref()).await;
+                }
+                else => break,
+            }
+        }
+    }
+
+    async fn fingerprint(&self) -> Fingerprint {
+        let mut fingerprint = Vec::new();
+        // Directory is optional.
+        let Ok(mut read_dir) = fs::read_dir(&self.scripts_dir).await else {
+            return fingerprint;
+        };
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION) {
+                if let Ok(modified) = entry.metadata().await.and_then(|meta| meta.modified()) {
+                    fingerprint.push((path, modified));
+                }
+            }
+        }
+        fingerprint.sort_unstable();
+        fingerprint
+    }
+
+    /// Scripts which failed to load are skipped.
+    async fn load(&self, fingerprint: &Fingerprint) -> Vec<Script> {
+        let mut scripts = Vec::new();
+        for (path, _) in fingerprint {
+            match self.load_script(path).await {
+                Ok(script) => {
+                    info!("Script {} loaded", script.name);
+                    scripts.push(script);
+                }
+                Err(e) => error!("Failed to load script {}: {e}", path.to_string_lossy()),
+            }
+        }
+        scripts
+    }
+
+    async fn load_script(&self, path: &Path) -> anyhow::Result<Script> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let source = fs::read_to_string(path).await?;
+        let lua = self.new_lua(&name)?;
+        run_limited(&lua, lua.load(source).set_name(&name).exec_async()).await?;
+        Ok(Script { name, lua })
+    }
+
+    /// Create a state with the `homie` global table, which is the scripts API.
+    /// Only the libraries without access to the system are loaded.
+    fn new_lua(&self, script_name: &str) -> mlua::Result<Lua> {
+        let lua = Lua::new_with(
+            StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8 | StdLib::COROUTINE,
+            LuaOptions::default(),
+        )?;
+        // Base library is always loaded.
+        for function in FILE_ACCESS_FUNCTIONS {
+            lua.globals().raw_remove(function)?;
+        }
+        lua.set_memory_limit(SCRIPT_MEMORY_LIMIT)?;
+        // Timeout of the future can't interrupt a loop which never yields.
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(TIMEOUT_CHECK_INSTRUCTIONS),
+            |lua, _| match lua.app_data_ref::<Instant>() {
+                Some(started) if started.elapsed() > SCRIPT_TIMEOUT => {
+                    Err(mlua::Error::runtime("script timed out"))
+                }
+                _ => Ok(VmState::Continue),
+            },
+        );
+        let api = lua.create_table()?;
+
+        let script_name = script_name.to_string();
+        api.set(
+            "log",
+            lua.create_function(move |_, message: String| {
+                info!("[{script_name}] {message}");
+                Ok(())
+            })?,
+        )?;
+
+        let event_broadcaster = self.event_broadcaster.clone();
+        api.set(
+            "notify",
+            lua.create_function(move |_, message: String| {
+                event_broadcaster.send(GlobalEvent::Notification { message });
+                Ok(())
+            })?,
+        )?;
+
+        let piano = self.piano.clone();
+        api.set(
+            "play_sound",
+            lua.create_async_function(move |_, name: String| {
+                let piano = piano.clone();
+                async move {
+                    let sound = Sound::iter()
+                        .find(|sound| sound.to_string() == name)
+                        .ok_or_else(|| mlua::Error::runtime(format!("unknown sound {name}")))?;
+                    piano.play_sound(sound).await;
+                    Ok(())
+                }
+            })?,
+        )?;
+
+        let piano = self.piano.clone();
+        api.set(
+            "start_recording",
+            lua.create_async_function(move |_, ()| {
+                let piano = piano.clone();
+                async move {
+                    piano
+                        .record()
+                        .await
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))
+                }
+            })?,
+        )?;
+
+        let piano = self.piano.clone();
+        api.set(
+            "stop_recording",
+            // Returns identifier of the saved recording.
+            lua.create_async_function(move |_, ()| {
+                let piano = piano.clone();
+                async move {
+                    piano
+                        .stop_recorder(StopRecorderParams {
+                            play_feedback: true,
+                        })
+                        .await
+                        .map(|recording| recording.id())
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))
+                }
+            })?,
+        )?;
+
+        let lounge_temp_monitor = Arc::clone(&self.lounge_temp_monitor);
+        api.set(
+            "lounge_sensor",
+            // Returns nil if the monitor is not connected or has not sent the data yet.
+            lua.create_async_function(move |lua, ()| {
+                let lounge_temp_monitor = Arc::clone(&lounge_temp_monitor);
+                async move {
+                    let data = match lounge_temp_monitor.read().await.get_connected() {
+                        Ok(monitor) => monitor.last_data().await,
+                        Err(_) => None,
+                    };
+                    let Some(data) = data else {
+                        return Ok(None);
+                    };
+                    let table: Table = lua.create_table()?;
+                    table.set("temperature", data.temperature())?;
+                    table.set("humidity", data.humidity())?;
+                    table.set("battery", data.battery_percents())?;
+                    Ok(Some(table))
+                }
+            })?,
+        )?;
+
+        lua.globals().set("homie", api)?;
+        Ok(lua)
+    }
+}
+
+/// Scripts handle the event sequentially, so handlers should return quickly.
+async fn handle_event(scripts: &[Script], source: &str, name: &str) {
+    for script in scripts {
+        let handler = match script.lua.globals().get::<Option<Function>>(EVENT_HANDLER) {
+            Ok(Some(handler)) => handler,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Script {} has invalid {EVENT_HANDLER}: {e}", script.name);
+                continue;
+            }
+        };
+        if let Err(e) = run_limited(&script.lua, handler.call_async::<()>((source, name))).await {
+            error!(
+                "Script {} failed to handle the {source} event {name}: {e}",
+                script.name
+            );
+        }
+    }
+}
+
+/// Fail if `execution` runs longer than [SCRIPT_TIMEOUT].
+async fn run_limited(
+    lua: &Lua,
+    execution: impl Future<Output = mlua::Result<()>>,
+) -> mlua::Result<()> {
+    lua.set_app_data(Instant::now());
+    tokio::time::timeout(SCRIPT_TIMEOUT, execution)
+        .await
+        .unwrap_or_else(|_| Err(mlua::Error::runtime("script timed out")))
+}
@@ -0,0 +1,833 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset};
+use futures::{pin_mut, StreamExt};
+use log::{error, info};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use tokio::{select, task};
+
+use crate::{
+    audio::waveform::Waveform,
+    bluetooth::DeviceHolder,
+    config,
+    core::{timezone, ShutdownNotify, SortOrder},
+    device::{
+        description::LoungeTempMonitor,
+        mi_temp_monitor::MiTempMonitor,
+        piano::{
+            recordings::{Recording, RecordingStorageError},
+            Piano, PianoEvent,
+        },
+    },
+    graphql::GraphQLError,
+    recording_upload::RecordingUploadStatus,
+    Broadcaster, GlobalEvent,
+};
+
+/// Applied in order, the number of the applied ones is stored in `user_version`.
+/// Never change the released migrations, add new ones instead.
+const MIGRATIONS: &[&str] = &[
+    r#"
+CREATE TABLE recordings (
+    id INTEGER PRIMARY KEY,
+    duration_ms INTEGER NOT NULL,
+    integrated_lufs REAL,
+    true_peak_dbtp REAL
+);
+CREATE TABLE sensor_history (
+    timestamp_ms INTEGER NOT NULL,
+    temperature_celsius REAL NOT NULL,
+    humidity_percents INTEGER NOT NULL,
+    battery_percents INTEGER NOT NULL
+);
+CREATE INDEX sensor_history_timestamp ON sensor_history (timestamp_ms);
+CREATE TABLE event_log (
+    timestamp_ms INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    kind TEXT NOT NULL
+);
+CREATE INDEX event_log_timestamp ON event_log (timestamp_ms);
+CREATE TABLE audit_log (
+    timestamp_ms INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT NOT NULL
+);
+CREATE INDEX audit_log_timestamp ON audit_log (timestamp_ms);
+"#,
+    r#"
+CREATE TABLE bookmarks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    recording_id INTEGER NOT NULL,
+    position_ms INTEGER NOT NULL,
+    label TEXT NOT NULL
+);
+CREATE INDEX bookmarks_recording ON bookmarks (recording_id);
+"#,
+    r#"
+CREATE TABLE recording_uploads (
+    recording_id INTEGER PRIMARY KEY,
+    finished_at_ms INTEGER NOT NULL,
+    error TEXT
+);
+"#,
+    r#"
+CREATE TABLE recording_plays (
+    recording_id INTEGER PRIMARY KEY,
+    play_count INTEGER NOT NULL,
+    last_played_ms INTEGER NOT NULL
+);
+"#,
+    r#"
+CREATE TABLE playback_session (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    recording_id INTEGER NOT NULL,
+    position_ms INTEGER NOT NULL
+);
+"#,
+    r#"
+CREATE TABLE recording_waveforms (
+    recording_id INTEGER PRIMARY KEY,
+    peaks BLOB NOT NULL
+);
+"#,
+];
+
+/// How often to remove the records which are older than the retention period.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How often to save the position of the played recording (see [PlaybackSession]).
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum StorageError {
+    #[error("Database error: {0}")]
+    DatabaseError(rusqlite::Error),
+    #[error("Storage task failed: {0}")]
+    TaskFailed(task::JoinError),
+}
+
+impl GraphQLError for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::DatabaseError(err)
+    }
+}
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum BookmarkError {
+    #[error("Bookmark does not exist")]
+    BookmarkNotExists,
+    #[error("Position is beyond the end of the recording")]
+    InvalidPosition,
+    #[error("Unable to get a recording: {0}")]
+    GetRecording(RecordingStorageError),
+    #[error(transparent)]
+    StorageError(StorageError),
+}
+
+impl GraphQLError for BookmarkError {}
+
+#[derive(SimpleObject)]
+pub struct StorageStats {
+    pub size_bytes: u64,
+    /// Number of the applied migrations.
+    pub schema_version: u32,
+    pub indexed_recordings: u64,
+    pub sensor_readings: u64,
+    pub logged_events: u64,
+    pub audit_records: u64,
+}
+
+#[derive(SimpleObject)]
+pub struct SensorReading {
+    pub at: DateTime<FixedOffset>,
+    pub temperature_celsius: f32,
+    pub humidity_percents: u8,
+    pub battery_percents: u8,
+}
+
+#[derive(SimpleObject)]
+pub struct LoggedEvent {
+    pub at: DateTime<FixedOffset>,
+    /// `piano` or `global`.
+    pub source: String,
+    /// The same as in the GraphQL schema (e.g. `NEW_RECORDING_SAVED`).
+    pub kind: String,
+}
+
+/// Recording as it's stored in the index.
+pub struct IndexedRecording {
+    pub id: i64,
+    pub duration_ms: u64,
+    pub integrated_lufs: Option<f64>,
+}
+
+/// Named position inside a recording.
+#[derive(Clone, SimpleObject)]
+pub struct Bookmark {
+    pub id: i64,
+    pub recording_id: i64,
+    pub position_ms: u64,
+    pub label: String,
+}
+
+/// How many times and when a recording was played.
+#[derive(Clone, Copy)]
+pub struct PlayStats {
+    pub play_count: u32,
+    pub last_played_at: DateTime<FixedOffset>,
+}
+
+/// Last played recording and its position, so playback can be resumed after a restart.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PlaybackSession {
+    pub recording_id: i64,
+    pub position_ms: u64,
+}
+
+#[derive(SimpleObject)]
+pub struct AuditRecord {
+    pub at: DateTime<FixedOffset>,
+    pub action: String,
+    pub details: String,
+}
+
+/// Embedded SQLite database with the recording index, the lounge sensor history,
+/// the event log and the audit log of the administrative actions.
+#[derive(Clone)]
+pub struct Storage {
+    path: PathBuf,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Storage {
+    /// Creates the database if it doesn't exist and applies the migrations.
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let mut connection = Connection::open(path)?;
+        // Reduces the number of writes to the SD card.
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        let applied: usize =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index + 1)?;
+            transaction.commit()?;
+            info!("Database migration {} applied", index + 1);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Keep the recording index, sensor history and event log up to date. Returns on shutdown.
+    pub async fn run(
+        self,
+        config: config::Storage,
+        piano: Piano,
+        lounge_temp_monitor: DeviceHolder<MiTempMonitor, LoungeTempMonitor>,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+        shutdown_notify: ShutdownNotify,
+    ) {
+        self.reindex_recordings(&piano).await;
+        let piano_events = piano
+            .event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await;
+        let global_events = event_broadcaster
+            .recv_continuously(shutdown_notify.clone())
+            .await;
+        pin_mut!(piano_events, global_events);
+        let mut sensor_interval = tokio::time::interval(Duration::from_secs(
+            config.sensor_history_interval_mins * 60,
+        ));
+        let mut prune_interval = tokio::time::interval(PRUNE_INTERVAL);
+        let mut session_interval = tokio::time::interval(SESSION_SAVE_INTERVAL);
+        let mut saved_session = None;
+
+        loop {
+            select! {
+                Some(event) = piano_events.next() => {
+                    self.log_event("piano", event.payload.as_ref()).await;
+                    if matches!(
+                        event.payload,
+                        PianoEvent::NewRecordingSaved
+                            | PianoEvent::OldRecordingsRemoved
+                            | PianoEvent::RecordingsModified
+                            | PianoEvent::RecordingAnalyzed
+                    ) {
+                        self.reindex_recordings(&piano).await;
+                    }
+                }
+                Some(event) = global_events.next() => {
+                    // Progress events are too frequent and not useful in the history.
+                    if !matches!(
+                        event.payload,
+                        GlobalEvent::UsbOffloadProgress(_) | GlobalEvent::UpdateProgress(_)
+                    ) {
+                        let kind = event.payload.kind().await;
+                        self.log_event("global", kind.as_ref()).await;
+                    }
+                }
+                _ = sensor_interval.tick() => {
+                    let data = match lounge_temp_monitor.read().await.get_connected() {
+                        Ok(monitor) => monitor.last_data().await,
+                        Err(_) => None,
+                    };
+                    if let Some(data) = data {
+                        self.add_sensor_reading(
+                            data.temperature(),
+                            data.humidity(),
+                            data.battery_percents(),
+                        )
+                        .await;
+                    }
+                }
+                _ = prune_interval.tick() => self.prune(config.retention_days).await,
+                _ = session_interval.tick() => {
+                    self.save_session(&piano, &mut saved_session).await;
+                }
+                _ = shutdown_notify.notified() => break,
+            }
+        }
+    }
+
+    pub async fn stats(&self) -> Result<StorageStats, StorageError> {
+        let size_bytes = tokio::fs::metadata(&self.path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        self.call(move |connection| {
+            let count = |table: &str| -> rusqlite::Result<u64> {
+                connection.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get(0)
+                })
+            };
+            Ok(StorageStats {
+                size_bytes,
+                schema_version: connection
+                    .pragma_query_value(None, "user_version", |row| row.get(0))?,
+                indexed_recordings: count("recordings")?,
+                sensor_readings: count("sensor_history")?,
+                logged_events: count("event_log")?,
+                audit_records: count("audit_log")?,
+            })
+        })
+        .await
+    }
+
+    /// Returns the readings since `since` (oldest first).
+    pub async fn sensor_history(
+        &self,
+        since: DateTime<FixedOffset>,
+    ) -> Result<Vec<SensorReading>, StorageError> {
+        self.call(move |connection| {
+            connection
+                .prepare(
+                    "SELECT timestamp_ms, temperature_celsius, humidity_percents, battery_percents \
+                    FROM sensor_history WHERE timestamp_ms >= ?1 ORDER BY timestamp_ms",
+                )?
+                .query_map([since.timestamp_millis()], |row| {
+                    Ok(SensorReading {
+                        at: from_millis(row.get(0)?),
+                        temperature_celsius: row.get(1)?,
+                        humidity_percents: row.get(2)?,
+                        battery_percents: row.get(3)?,
+                    })
+                })?
+                .collect()
+        })
+        .await
+    }
+
+    /// Returns the last `limit` events (newest first).
+    pub async fn event_log(&self, limit: u32) -> Result<Vec<LoggedEvent>, StorageError> {
+        self.call(move |connection| {
+            connection
+                .prepare(
+                    "SELECT timestamp_ms, source, kind FROM event_log \
+                    ORDER BY timestamp_ms DESC LIMIT ?1",
+                )?
+                .query_map([limit], |row| {
+                    Ok(LoggedEvent {
+                        at: from_millis(row.get(0)?),
+                        source: row.get(1)?,
+                        kind: row.get(2)?,
+                    })
+                })?
+                .collect()
+        })
+        .await
+    }
+
+    /// Returns the last `limit` records (newest first).
+    pub async fn audit_log(&self, limit: u32) -> Result<Vec<AuditRecord>, StorageError> {
+        self.call(move |connection| {
+            connection
+                .prepare(
+                    "SELECT timestamp_ms, action, details FROM audit_log \
+                    ORDER BY timestamp_ms DESC LIMIT ?1",
+                )?
+                .query_map([limit], |row| {
+                    Ok(AuditRecord {
+                        at: from_millis(row.get(0)?),
+                        action: row.get(1)?,
+                        details: row.get(2)?,
+                    })
+                })?
+                .collect()
+        })
+        .await
+    }
+
+    /// Bookmarks of the recording ordered by the position.
+    pub async fn bookmarks(&self, recording_id: i64) -> Result<Vec<Bookmark>, StorageError> {
+        self.call(move |connection| {
+            connection
+                .prepare(
+                    "SELECT id, recording_id, position_ms, label FROM bookmarks \
+                    WHERE recording_id = ?1 ORDER BY position_ms",
+                )?
+                .query_map([recording_id], bookmark_from_row)?
+                .collect()
+        })
+        .await
+    }
+
+    /// Returns the recordings from the oldest to the newest.
+    pub async fn indexed_recordings(&self) -> Result<Vec<IndexedRecording>, StorageError> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT id, duration_ms, integrated_lufs FROM recordings ORDER BY id")?
+                .query_map([], |row| {
+                    Ok(IndexedRecording {
+                        id: row.get(0)?,
+                        duration_ms: row.get(1)?,
+                        integrated_lufs: row.get(2)?,
+                    })
+                })?
+                .collect()
+        })
+        .await
+    }
+
+    /// Bookmarks of all recordings ordered by the recording and the position.
+    pub async fn all_bookmarks(&self) -> Result<Vec<Bookmark>, StorageError> {
+        self.call(|connection| {
+            connection
+                .prepare(
+                    "SELECT id, recording_id, position_ms, label FROM bookmarks \
+                    ORDER BY recording_id, position_ms",
+                )?
+                .query_map([], bookmark_from_row)?
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn bookmark(&self, id: i64) -> Result<Bookmark, BookmarkError> {
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT id, recording_id, position_ms, label FROM bookmarks WHERE id = ?1",
+                    [id],
+                    bookmark_from_row,
+                )
+                .optional()
+        })
+        .await
+        .map_err(BookmarkError::StorageError)?
+        .ok_or(BookmarkError::BookmarkNotExists)
+    }
+
+    /// Position must be within `recording`.
+    pub async fn add_bookmark(
+        &self,
+        recording: &Recording,
+        position: Duration,
+        label: String,
+    ) -> Result<Bookmark, BookmarkError> {
+        if position > recording.duration() {
+            return Err(BookmarkError::InvalidPosition);
+        }
+        let recording_id = recording.id();
+        let position_ms = position.as_millis() as u64;
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT INTO bookmarks (recording_id, position_ms, label) VALUES (?1, ?2, ?3)",
+                params![recording_id, position_ms, label],
+            )?;
+            Ok(Bookmark {
+                id: connection.last_insert_rowid(),
+                recording_id,
+                position_ms,
+                label,
+            })
+        })
+        .await
+        .map_err(BookmarkError::StorageError)
+    }
+
+    /// Returns `false` if the bookmark doesn't exist.
+    pub async fn remove_bookmark(&self, id: i64) -> Result<bool, StorageError> {
+        self.call(move |connection| {
+            connection
+                .execute("DELETE FROM bookmarks WHERE id = ?1", [id])
+                .map(|removed| removed > 0)
+        })
+        .await
+    }
+
+    /// Returns [None] if uploading of the recording has not been finished yet.
+    pub async fn upload_status(
+        &self,
+        recording_id: i64,
+    ) -> Result<Option<RecordingUploadStatus>, StorageError> {
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT finished_at_ms, error FROM recording_uploads WHERE recording_id = ?1",
+                    [recording_id],
+                    |row| {
+                        Ok(RecordingUploadStatus {
+                            recording_id,
+                            finished_at: from_millis(row.get(0)?),
+                            error: row.get(1)?,
+                        })
+                    },
+                )
+                .optional()
+        })
+        .await
+    }
+
+    /// Identifiers of the successfully uploaded recordings.
+    pub async fn uploaded_recordings(&self) -> Result<Vec<i64>, StorageError> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT recording_id FROM recording_uploads WHERE error IS NULL")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn set_upload_status(
+        &self,
+        status: RecordingUploadStatus,
+    ) -> Result<(), StorageError> {
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO recording_uploads (recording_id, finished_at_ms, error) \
+                VALUES (?1, ?2, ?3)",
+                params![
+                    status.recording_id,
+                    status.finished_at.timestamp_millis(),
+                    status.error
+                ],
+            )
+        })
+        .await
+        .map(|_| ())
+    }
+
+    /// Returns [None] if the recording has never been played.
+    pub async fn play_stats(&self, recording_id: i64) -> Result<Option<PlayStats>, StorageError> {
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT play_count, last_played_ms FROM recording_plays \
+                    WHERE recording_id = ?1",
+                    [recording_id],
+                    play_stats_from_row,
+                )
+                .optional()
+        })
+        .await
+    }
+
+    /// Statistics of all played recordings by their identifiers.
+    pub async fn all_play_stats(&self) -> Result<HashMap<i64, PlayStats>, StorageError> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT recording_id, play_count, last_played_ms FROM recording_plays")?
+                .query_map([], |row| {
+                    let stats = PlayStats {
+                        play_count: row.get(1)?,
+                        last_played_at: from_millis(row.get(2)?),
+                    };
+                    Ok((row.get(0)?, stats))
+                })?
+                .collect()
+        })
+        .await
+    }
+
+    /// Increment the play count of the recording. Errors are only logged.
+    pub async fn add_play(&self, recording_id: i64) {
+        let played_at = timezone::now().timestamp_millis();
+        let result = self
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO recording_plays (recording_id, play_count, last_played_ms) \
+                    VALUES (?1, 1, ?2) ON CONFLICT (recording_id) \
+                    DO UPDATE SET play_count = play_count + 1, last_played_ms = ?2",
+                    params![recording_id, played_at],
+                )
+            })
+            .await;
+        if let Err(e) = result {
+            error!("Failed to save the play of recording {recording_id}: {e}");
+        }
+    }
+
+    /// Returns [None] if nothing has been played yet.
+    pub async fn playback_session(&self) -> Result<Option<PlaybackSession>, StorageError> {
+        self.call(|connection| {
+            connection
+                .query_row(
+                    "SELECT recording_id, position_ms FROM playback_session WHERE id = 1",
+                    [],
+                    |row| {
+                        Ok(PlaybackSession {
+                            recording_id: row.get(0)?,
+                            position_ms: row.get(1)?,
+                        })
+                    },
+                )
+                .optional()
+        })
+        .await
+    }
+
+    /// Returns [None] if the waveform is not cached.
+    pub async fn waveform(&self, recording_id: i64) -> Result<Option<Waveform>, StorageError> {
+        let bytes: Option<Vec<u8>> = self
+            .call(move |connection| {
+                connection
+                    .query_row(
+                        "SELECT peaks FROM recording_waveforms WHERE recording_id = ?1",
+                        [recording_id],
+                        |row| row.get(0),
+                    )
+                    .optional()
+            })
+            .await?;
+        Ok(bytes.and_then(|bytes| Waveform::from_bytes(&bytes)))
+    }
+
+    pub async fn set_waveform(
+        &self,
+        recording_id: i64,
+        waveform: &Waveform,
+    ) -> Result<(), StorageError> {
+        let peaks = waveform.to_bytes();
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO recording_waveforms (recording_id, peaks) VALUES (?1, ?2)",
+                params![recording_id, peaks],
+            )
+        })
+        .await
+        .map(|_| ())
+    }
+
+    /// Record an administrative action (e.g. a configuration change). Errors are only logged.
+    pub async fn audit(&self, action: &str, details: impl Into<String>) {
+        let (action, details) = (action.to_string(), details.into());
+        info!("Audit: {action} {details}");
+        let result = self
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO audit_log (timestamp_ms, action, details) VALUES (?1, ?2, ?3)",
+                    params![timezone::now().timestamp_millis(), action, details],
+                )
+            })
+            .await;
+        if let Err(e) = result {
+            error!("Failed to write the audit log: {e}");
+        }
+    }
+
+    async fn log_event(&self, source: &'static str, kind: &str) {
+        let kind = kind.to_string();
+        let result = self
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO event_log (timestamp_ms, source, kind) VALUES (?1, ?2, ?3)",
+                    params![timezone::now().timestamp_millis(), source, kind],
+                )
+            })
+            .await;
+        if let Err(e) = result {
+            error!("Failed to log the event: {e}");
+        }
+    }
+
+    async fn add_sensor_reading(&self, temperature: f32, humidity: u8, battery: u8) {
+        let result = self
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO sensor_history \
+                    (timestamp_ms, temperature_celsius, humidity_percents, battery_percents) \
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        timezone::now().timestamp_millis(),
+                        temperature,
+                        humidity,
+                        battery
+                    ],
+                )
+            })
+            .await;
+        if let Err(e) = result {
+            error!("Failed to save the sensor reading: {e}");
+        }
+    }
+
+    /// Replace the index with the recordings which are currently stored.
+    async fn reindex_recordings(&self, piano: &Piano) {
+        let recordings = match piano.recording_storage.list(SortOrder::Ascending).await {
+            Ok(recordings) => recordings,
+            Err(e) => return error!("Failed to list the recordings for indexing: {e}"),
+        };
+        if let Err(e) = self
+            .call(move |connection| index(connection, &recordings))
+            .await
+        {
+            error!("Failed to index the recordings: {e}");
+        }
+    }
+
+    /// Save the position of the last played recording if it's changed since `saved`.
+    async fn save_session(&self, piano: &Piano, saved: &mut Option<PlaybackSession>) {
+        let Ok(status) = piano.playback_status().await else {
+            return;
+        };
+        let (Some(recording), Some(position)) = (status.last_played_recording, status.position)
+        else {
+            return;
+        };
+        let session = PlaybackSession {
+            recording_id: recording.id(),
+            position_ms: position.current.as_millis() as u64,
+        };
+        if *saved == Some(session) {
+            return;
+        }
+        let result = self
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT OR REPLACE INTO playback_session (id, recording_id, position_ms) \
+                    VALUES (1, ?1, ?2)",
+                    params![session.recording_id, session.position_ms],
+                )
+            })
+            .await;
+        match result {
+            Ok(_) => *saved = Some(session),
+            Err(e) => error!("Failed to save the playback session: {e}"),
+        }
+    }
+
+    async fn prune(&self, retention_days: u32) {
+        let before =
+            (timezone::now() - chrono::Duration::days(retention_days.into())).timestamp_millis();
+        let result = self
+            .call(move |connection| {
+                let mut removed = 0;
+                for table in ["sensor_history", "event_log", "audit_log"] {
+                    removed += connection.execute(
+                        &format!("DELETE FROM {table} WHERE timestamp_ms < ?1"),
+                        [before],
+                    )?;
+                }
+                Ok(removed)
+            })
+            .await;
+        match result {
+            Ok(0) => {}
+            Ok(removed) => info!("{removed} old records removed from the database"),
+            Err(e) => error!("Failed to remove the old records: {e}"),
+        }
+    }
+
+    /// Run `f` on a blocking thread, as SQLite calls are synchronous.
+    async fn call<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut connection)
+        })
+        .await
+        .map_err(StorageError::TaskFailed)?
+        .map_err(StorageError::DatabaseError)
+    }
+}
+
+fn index(connection: &mut Connection, recordings: &[Recording]) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    transaction.execute("DELETE FROM recordings", [])?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO recordings (id, duration_ms, integrated_lufs, true_peak_dbtp) \
+            VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for recording in recordings {
+            let loudness = recording.loudness();
+            insert.execute(params![
+                recording.id(),
+                recording.duration().as_millis() as i64,
+                loudness.map(|loudness| loudness.integrated_lufs),
+                loudness.map(|loudness| loudness.true_peak_dbtp),
+            ])?;
+        }
+    }
+    // Data of the removed recordings.
+    for table in [
+        "bookmarks",
+        "recording_uploads",
+        "recording_plays",
+        "playback_session",
+        "recording_waveforms",
+    ] {
+        transaction.execute(
+            &format!("DELETE FROM {table} WHERE recording_id NOT IN (SELECT id FROM recordings)"),
+            [],
+        )?;
+    }
+    transaction.commit()
+}
+
+fn bookmark_from_row(row: &Row) -> rusqlite::Result<Bookmark> {
+    Ok(Bookmark {
+        id: row.get(0)?,
+        recording_id: row.get(1)?,
+        position_ms: row.get(2)?,
+        label: row.get(3)?,
+    })
+}
+
+fn play_stats_from_row(row: &Row) -> rusqlite::Result<PlayStats> {
+    Ok(PlayStats {
+        play_count: row.get(0)?,
+        last_played_at: from_millis(row.get(1)?),
+    })
+}
+
+fn from_millis(timestamp_ms: i64) -> DateTime<FixedOffset> {
+    timezone::localize(DateTime::from_timestamp_millis(timestamp_ms).unwrap_or_default())
+}
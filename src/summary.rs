@@ -0,0 +1,114 @@
+use log::error;
+use serde::Serialize;
+
+use crate::{
+    core::{timezone, HumanDateParams},
+    device::piano::PianoStatus,
+    App,
+};
+
+/// Lounge monitor battery percents below which the alert is shown.
+const LOW_SENSOR_BATTERY_PERCENTS: u8 = 10;
+
+/// Compact state of the server for the dashboards which poll it (e.g. e-ink displays).
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusSummary {
+    /// [None] if the status can't be read.
+    piano: Option<PianoStatus>,
+    /// [None] if nothing is playing or paused.
+    playback: Option<PlaybackSummary>,
+    /// [None] if the monitor is not connected or has not sent the data yet.
+    lounge: Option<LoungeSummary>,
+    /// Human-readable problems which require attention.
+    alerts: Vec<String>,
+    /// RFC 3339.
+    updated_at: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaybackSummary {
+    is_playing: bool,
+    recording_id: Option<i64>,
+    recording_title: Option<String>,
+    position_ms: u64,
+    /// [None] if it's unknown.
+    duration_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoungeSummary {
+    temperature_celsius: f32,
+    humidity_percents: u8,
+    battery_percents: u8,
+}
+
+impl StatusSummary {
+    pub async fn collect(app: &App) -> Self {
+        let mut alerts = Vec::new();
+
+        let piano = app
+            .piano
+            .status()
+            .await
+            .map_err(|e| error!("Failed to get the piano status: {e}"))
+            .ok();
+        let playback = match app.piano.playback_status().await {
+            Ok(status) => status.position.map(|position| PlaybackSummary {
+                is_playing: status.is_playing,
+                recording_id: status.last_played_recording.as_ref().map(|rec| rec.id()),
+                recording_title: status.last_played_recording.as_ref().map(|rec| {
+                    rec.human_creation_date(HumanDateParams {
+                        filename_safe: false,
+                    })
+                }),
+                position_ms: position.current.as_millis() as u64,
+                duration_ms: position.total().map(|total| total.as_millis() as u64),
+            }),
+            Err(e) => {
+                alerts.push(format!("Player failed: {e}"));
+                None
+            }
+        };
+        if app.piano.is_disk_space_low() {
+            alerts.push("Free disk space is low, new recordings are refused".to_string());
+        }
+
+        let lounge_data = match app.lounge_temp_monitor.read().await.get_connected() {
+            Ok(monitor) => monitor.last_data().await,
+            Err(_) => None,
+        };
+        let lounge = lounge_data.map(|data| LoungeSummary {
+            temperature_celsius: data.temperature(),
+            humidity_percents: data.humidity(),
+            battery_percents: data.battery_percents(),
+        });
+        if lounge
+            .as_ref()
+            .is_some_and(|lounge| lounge.battery_percents < LOW_SENSOR_BATTERY_PERCENTS)
+        {
+            alerts.push("Battery of the lounge sensor is low".to_string());
+        }
+
+        if let Some(power) = &app.power {
+            if let Some(status) = power.status().await.filter(|status| status.on_battery()) {
+                alerts.push(format!("Running on battery ({:.0} %)", status.percentage()));
+            }
+        }
+        for upload in app.remote_backup.last_uploads().await {
+            if let Some(e) = upload.error {
+                alerts.push(format!("Backup upload to {} failed: {e}", upload.target));
+            }
+        }
+
+        Self {
+            piano,
+            playback,
+            lounge,
+            alerts,
+            updated_at: timezone::now().to_rfc3339(),
+        }
+    }
+}
@@ -1,22 +1,169 @@
-use std::io;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io,
+    process::Stdio,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
 
-use futures::StreamExt;
-use log::{error, info};
-use tokio::select;
-use tokio_udev::{AsyncMonitorSocket, MonitorBuilder};
+use async_graphql::{Enum, SimpleObject};
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use log::{error, info, warn};
+use tokio::{process::Command, select, sync::Mutex};
+use tokio_udev::{AsyncMonitorSocket, EventType, MonitorBuilder};
 
-use crate::{bluetooth, device::piano::HandledPianoEvent, App};
+use crate::{
+    config::{UdevRule, UdevRuleAction},
+    core::{
+        panic::SupervisedThread,
+        supervisor::{Supervised, Supervisor},
+    },
+    App, GlobalEvent, SharedMutex, Subsystem,
+};
 
-const MONITOR_SUBSYSTEMS: [&str; 1] = ["sound"];
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum HotplugAction {
+    Add,
+    Remove,
+}
+
+/// Device event with only the fields which are useful to identify a device.
+#[derive(Clone, PartialEq, Eq, SimpleObject)]
+pub struct HotplugEvent {
+    action: HotplugAction,
+    subsystem: String,
+    devpath: String,
+    /// Kernel name of the device, e.g. `card1` or `sda1`.
+    sysname: String,
+    /// `ID_VENDOR_ID` property (if present).
+    vendor_id: Option<String>,
+    /// `ID_MODEL_ID` property (if present).
+    model_id: Option<String>,
+}
+
+impl HotplugEvent {
+    /// Returns [None] if it's neither add nor remove event.
+    fn new(event: &tokio_udev::Event) -> Option<Self> {
+        let action = match event.event_type() {
+            EventType::Add => HotplugAction::Add,
+            EventType::Remove => HotplugAction::Remove,
+            _ => return None,
+        };
+        let property = |name| {
+            event
+                .property_value(name)
+                .map(|value| value.to_string_lossy().into_owned())
+        };
+        Some(Self {
+            action,
+            subsystem: event
+                .subsystem()
+                .map(|subsystem| subsystem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            devpath: event.devpath().to_string_lossy().into_owned(),
+            sysname: event.sysname().to_string_lossy().into_owned(),
+            vendor_id: property("ID_VENDOR_ID"),
+            model_id: property("ID_MODEL_ID"),
+        })
+    }
+}
+
+/// Handles device events in a dedicated thread with its own single-threaded runtime,
+/// because [AsyncMonitorSocket] can not be sent between threads.
+pub struct UdevMonitor {
+    app: App,
+    supervisor: Supervisor,
+    thread: SharedMutex<JoinHandle<()>>,
+}
+
+impl UdevMonitor {
+    pub fn spawn(app: App, supervisor: Supervisor) -> io::Result<Self> {
+        let thread = spawn_thread(app.clone(), supervisor.clone(), false)?;
+        Ok(Self {
+            app,
+            supervisor,
+            thread: Arc::new(Mutex::new(thread)),
+        })
+    }
+}
+
+impl Supervised for UdevMonitor {
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::UdevMonitor
+    }
+
+    fn is_alive(&self) -> BoxFuture<'_, bool> {
+        async { !self.thread.lock().await.is_finished() }.boxed()
+    }
+
+    fn restart(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        async {
+            // Thread is already finished, so there is nothing to stop.
+            *self.thread.lock().await =
+                spawn_thread(self.app.clone(), self.supervisor.clone(), true)?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Short-term failures are handled inside the thread using the `udev_monitor_restart` backoff.
+/// The thread finishes if the backoff gave up or the monitor failed to start.
+fn spawn_thread(app: App, supervisor: Supervisor, restarted: bool) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name("udev-monitor".to_string())
+        .spawn(move || {
+            // Panic finishes the thread, so it's respawned by the supervisor.
+            let _supervised = SupervisedThread::enter();
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    error!("Failed to build a runtime for the device events monitor: {e}");
+                    return;
+                }
+            };
+            let result = runtime.block_on(supervisor.run_local(
+                Subsystem::UdevMonitor,
+                app.config.backoff.udev_monitor_restart.exponential(),
+                |restarted_locally| handle_events(app.clone(), restarted || restarted_locally),
+            ));
+            if let Err(e) = result {
+                error!("Failed to handle device events: {e}");
+            }
+        })
+}
+
+/// Returns when shutdown is triggered or the device events stream is closed.
+/// If `restarted`, devices will be re-scanned to catch the changes missed while the monitor
+/// was not running.
+async fn handle_events(app: App, restarted: bool) -> io::Result<()> {
+    let mut subsystems: Vec<_> = app.devices.udev_subsystems();
+    subsystems.extend(
+        app.config
+            .udev
+            .rules
+            .iter()
+            .map(|rule| rule.subsystem.as_str()),
+    );
+    subsystems.sort_unstable();
+    subsystems.dedup();
 
-pub async fn handle_events_until_shutdown(app: App) -> io::Result<()> {
     let mut monitor_builder = MonitorBuilder::new()?;
-    for subsystem in MONITOR_SUBSYSTEMS {
+    for subsystem in subsystems {
         monitor_builder = monitor_builder.match_subsystem(subsystem)?;
     }
     let mut socket: AsyncMonitorSocket = monitor_builder.listen()?.try_into()?;
 
     info!("Listening for device events...");
+    if restarted {
+        // Socket is already listening, so no changes will be missed.
+        app.devices.resync().await;
+        info!("Device events monitor recovered");
+    }
     loop {
         select! {
             result = socket.next() => {
@@ -26,24 +173,21 @@ pub async fn handle_events_until_shutdown(app: App) -> io::Result<()> {
                         continue;
                     },
                     None => {
-                        error!("Device events stream closed. No more events will be handled");
-                        app.shutdown_notify.notified().await;
+                        error!("Device events stream closed");
                         break;
                     },
                     _ => {}
                 }
 
                 let event = result.unwrap().unwrap();
-                let handled_piano_event = app.piano.handle_udev_event(&event).await;
-
-                if let Some(HandledPianoEvent::Remove) = handled_piano_event {
-                    // Pause playback because the output device removed.
-                    app.a2dp_source_handler
-                        .send_media_control_command(
-                            &app.dbus,
-                            bluetooth::MediaControlCommand::Pause,
-                        )
-                        .await;
+                if let Some(hotplug_event) = HotplugEvent::new(&event) {
+                    app.hotplug_broadcaster.send(hotplug_event);
+                }
+                app.devices.handle_udev_event(&event).await;
+                for rule in &app.config.udev.rules {
+                    if rule_matches(rule, &event) {
+                        apply_rule(&app, rule, &event).await;
+                    }
                 }
             },
             _ = app.shutdown_notify.notified() => break,
@@ -52,3 +196,63 @@ pub async fn handle_events_until_shutdown(app: App) -> io::Result<()> {
     info!("Device events listening stopped");
     Ok(())
 }
+
+fn rule_matches(rule: &UdevRule, event: &tokio_udev::Event) -> bool {
+    let subsystem_matches = event
+        .subsystem()
+        .map(|subsystem| subsystem == rule.subsystem.as_str())
+        .unwrap_or(false);
+    let event_type_matches = rule
+        .event_type
+        .as_ref()
+        .map(|event_type| *event_type == event.event_type().to_string())
+        .unwrap_or(true);
+    subsystem_matches
+        && event_type_matches
+        && values_match(&rule.properties, |name| event.property_value(name))
+        && values_match(&rule.attributes, |name| event.attribute_value(name))
+}
+
+/// Returns `true` if all `expected` values are equal to the actual ones.
+fn values_match<'a>(
+    expected: &HashMap<String, String>,
+    get_actual: impl Fn(&str) -> Option<&'a OsStr>,
+) -> bool {
+    expected.iter().all(|(name, value)| {
+        get_actual(name)
+            .map(|actual| actual == value.as_str())
+            .unwrap_or(false)
+    })
+}
+
+async fn apply_rule(app: &App, rule: &UdevRule, event: &tokio_udev::Event) {
+    info!("Device event matched the rule \"{}\"", rule.name);
+    match &rule.action {
+        UdevRuleAction::BroadcastEvent => {
+            app.event_broadcaster.send(GlobalEvent::UdevRuleMatched {
+                rule: rule.name.clone(),
+            })
+        }
+        UdevRuleAction::PlaySound(sound) => app.piano.play_sound(*sound).await,
+        UdevRuleAction::RunProgram(command) => {
+            let (program, args) = command
+                .split_first()
+                .expect("empty commands are rejected by the config");
+            let mut command = Command::new(program);
+            command.args(args).stdin(Stdio::null());
+            for property in event.properties() {
+                command.env(property.name(), property.value());
+            }
+            // Don't block the events handling.
+            let rule_name = rule.name.clone();
+            app.tasks
+                .spawn(format!("udev-rule-{rule_name}"), async move {
+                    let status = command.status().await?;
+                    if !status.success() {
+                        warn!("Program of the rule \"{rule_name}\" exited with {status}");
+                    }
+                    io::Result::Ok(())
+                });
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::{
+    cmp::Ordering,
+    env, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_graphql::{Enum, SimpleObject};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ed25519_dalek::Signature;
+use log::info;
+use serde::Deserialize;
+use tokio::{fs, process::Command, sync::Mutex};
+
+use crate::{
+    config::{self, UpdateSource},
+    core::Broadcaster,
+    dbus::{DBus, UnitControlError},
+    graphql::GraphQLError,
+    GlobalEvent,
+};
+
+pub const SERVICE_UNIT: &str = concat!(env!("CARGO_PKG_NAME"), ".service");
+
+#[derive(Debug, strum::AsRefStr, thiserror::Error)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum UpdateError {
+    #[error("Self-update is not configured")]
+    NotConfigured,
+    #[error("There is no newer version")]
+    NoUpdate,
+    #[error("Update is already in progress")]
+    AlreadyInProgress,
+    #[error("Failed to download {url}: {message}")]
+    DownloadFailed { url: String, message: String },
+    #[error("Invalid release information: {0}")]
+    InvalidRelease(String),
+    #[error("Signature of the downloaded binary is invalid")]
+    InvalidSignature,
+    #[error("Failed to install the binary: {0}")]
+    InstallFailed(io::Error),
+    #[error("Failed to restart the service: {0}")]
+    RestartFailed(UnitControlError),
+}
+
+impl GraphQLError for UpdateError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+pub enum UpdateStage {
+    Downloading,
+    Verifying,
+    Installing,
+    /// New binary is in place, the server is going to restart.
+    Restarting,
+}
+
+#[derive(Clone, SimpleObject)]
+pub struct AvailableUpdate {
+    pub version: String,
+    #[graphql(skip)]
+    binary_url: String,
+    /// Detached Ed25519 signature of the version and the binary (see [signed_message]).
+    #[graphql(skip)]
+    signature: Signature,
+}
+
+/// Content of [UpdateSource::ManifestUrl].
+#[derive(Deserialize)]
+struct Manifest {
+    version: String,
+    url: String,
+    /// Base64.
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GithubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Replaces the running binary with a newer release and restarts the service.
+#[derive(Clone)]
+pub struct Updater {
+    config: config::Updater,
+    dbus: DBus,
+    event_broadcaster: Broadcaster<GlobalEvent>,
+    /// Held while an update is being applied.
+    apply_lock: Arc<Mutex<()>>,
+}
+
+impl Updater {
+    pub fn new(
+        config: config::Updater,
+        dbus: DBus,
+        event_broadcaster: Broadcaster<GlobalEvent>,
+    ) -> Self {
+        Self {
+            config,
+            dbus,
+            event_broadcaster,
+            apply_lock: Arc::default(),
+        }
+    }
+
+    /// Returns [None] if the running version is the latest one.
+    pub async fn check(&self) -> Result<Option<AvailableUpdate>, UpdateError> {
+        let latest = match &self.config.source {
+            UpdateSource::ManifestUrl(url) => {
+                let manifest: Manifest = serde_json::from_slice(&download(url).await?)
+                    .map_err(|e| UpdateError::InvalidRelease(e.to_string()))?;
+                AvailableUpdate {
+                    version: manifest.version,
+                    binary_url: manifest.url,
+                    signature: parse_signature(&manifest.signature)?,
+                }
+            }
+            UpdateSource::GithubRepo(repo) => latest_github_release(repo).await?,
+        };
+        let is_newer = match (
+            parse_version(&latest.version),
+            parse_version(env!("CARGO_PKG_VERSION")),
+        ) {
+            (Some(latest), Some(current)) => latest > current,
+            _ => {
+                return Err(UpdateError::InvalidRelease(format!(
+                    "unable to compare version {}",
+                    latest.version
+                )))
+            }
+        };
+        Ok(is_newer.then_some(latest))
+    }
+
+    /// Download, verify and install the latest release, then restart the service.
+    /// Returns the installed version.
+    pub async fn apply(&self) -> Result<String, UpdateError> {
+        let Ok(_lock) = self.apply_lock.try_lock() else {
+            return Err(UpdateError::AlreadyInProgress);
+        };
+        let update = self.check().await?.ok_or(UpdateError::NoUpdate)?;
+        let current_exe = env::current_exe().map_err(UpdateError::InstallFailed)?;
+        // Must be on the same file system to replace the binary atomically.
+        let new_exe = PathBuf::from(format!("{}.new", current_exe.to_string_lossy()));
+
+        let result = self.install(&update, &new_exe, &current_exe).await;
+        if result.is_err() {
+            let _ = fs::remove_file(&new_exe).await;
+        }
+        result?;
+        info!("Version {} installed", update.version);
+
+        self.send_stage(UpdateStage::Restarting);
+        self.dbus
+            .restart_unit(SERVICE_UNIT, &[SERVICE_UNIT.to_string()])
+            .await
+            .map_err(UpdateError::RestartFailed)?;
+        Ok(update.version)
+    }
+
+    async fn install(
+        &self,
+        update: &AvailableUpdate,
+        new_exe: &Path,
+        current_exe: &Path,
+    ) -> Result<(), UpdateError> {
+        info!("Downloading version {}...", update.version);
+        self.send_stage(UpdateStage::Downloading);
+        let output = Command::new("curl")
+            .args([
+                "--fail",
+                "--silent",
+                "--show-error",
+                "--location",
+                "--output",
+            ])
+            .arg(new_exe)
+            // URL is taken from the release, so it must not be parsed as an option.
+            .arg("--url")
+            .arg(&update.binary_url)
+            .output()
+            .await
+            .map_err(|e| UpdateError::DownloadFailed {
+                url: update.binary_url.clone(),
+                message: e.to_string(),
+            })?;
+        if !output.status.success() {
+            return Err(UpdateError::DownloadFailed {
+                url: update.binary_url.clone(),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+
+        // Signature is checked against the configured key, so a compromised
+        // release source can't substitute the binary or downgrade it.
+        self.send_stage(UpdateStage::Verifying);
+        let binary = fs::read(new_exe)
+            .await
+            .map_err(UpdateError::InstallFailed)?;
+        self.config
+            .public_key
+            .verify_strict(&signed_message(&update.version, &binary), &update.signature)
+            .map_err(|_| UpdateError::InvalidSignature)?;
+
+        self.send_stage(UpdateStage::Installing);
+        fs::set_permissions(new_exe, std::fs::Permissions::from_mode(0o755))
+            .await
+            .map_err(UpdateError::InstallFailed)?;
+        // Running process keeps using the old inode.
+        fs::rename(new_exe, current_exe)
+            .await
+            .map_err(UpdateError::InstallFailed)
+    }
+
+    fn send_stage(&self, stage: UpdateStage) {
+        self.event_broadcaster
+            .send(GlobalEvent::UpdateProgress(stage));
+    }
+}
+
+/// Release must have the binary asset `<PACKAGE>-<ARCH>` and its signature `<ASSET>.sig`.
+async fn latest_github_release(repo: &str) -> Result<AvailableUpdate, UpdateError> {
+    let release: GithubRelease = serde_json::from_slice(
+        &download(&format!(
+            "https://api.github.com/repos/{repo}/releases/latest"
+        ))
+        .await?,
+    )
+    .map_err(|e| UpdateError::InvalidRelease(e.to_string()))?;
+
+    let binary_name = format!("{}-{}", env!("CARGO_PKG_NAME"), env::consts::ARCH);
+    let signature_name = format!("{binary_name}.sig");
+    let asset_url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.clone())
+            .ok_or_else(|| UpdateError::InvalidRelease(format!("no {name} asset")))
+    };
+    let binary_url = asset_url(&binary_name)?;
+    let signature = parse_signature(&String::from_utf8_lossy(
+        &download(&asset_url(&signature_name)?).await?,
+    ))?;
+    Ok(AvailableUpdate {
+        version: release.tag_name,
+        binary_url,
+        signature,
+    })
+}
+
+/// Takes Base64 of the raw 64-byte signature.
+fn parse_signature(base64: &str) -> Result<Signature, UpdateError> {
+    let invalid = |message: String| UpdateError::InvalidRelease(format!("signature {message}"));
+    let bytes = BASE64_STANDARD
+        .decode(base64.trim())
+        .map_err(|e| invalid(e.to_string()))?;
+    Signature::from_slice(&bytes).map_err(|e| invalid(e.to_string()))
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, UpdateError> {
+    let error = |message| UpdateError::DownloadFailed {
+        url: url.to_string(),
+        message,
+    };
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--url"])
+        .arg(url)
+        .output()
+        .await
+        .map_err(|e| error(e.to_string()))?;
+    if !output.status.success() {
+        return Err(error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Version is signed along with the binary, so an older release can't be
+/// offered as a newer one: `<VERSION>\n<BINARY>`.
+fn signed_message(version: &str, binary: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(version.len() + 1 + binary.len());
+    message.extend(version.as_bytes());
+    message.push(b'\n');
+    message.extend(binary);
+    message
+}
+
+/// Pre-release identifier. Numeric ones have lower precedence than alphanumeric.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Identifier {
+    Numeric(u64),
+    Alphanumeric(String),
+}
+
+/// Semantic version, the build metadata is ignored.
+#[derive(Debug, PartialEq, Eq)]
+struct Version {
+    numbers: [u64; 3],
+    pre_release: Vec<Identifier>,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numbers.cmp(&other.numbers).then_with(|| {
+            match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+                (true, true) => Ordering::Equal,
+                // Pre-release precedes the release.
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre_release.cmp(&other.pre_release),
+            }
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Takes `[v]MAJOR.MINOR.PATCH[-PRE_RELEASE][+BUILD]`.
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim_start_matches('v');
+    let version = version
+        .split_once('+')
+        .map_or(version, |(version, _)| version);
+    let (numbers, pre_release) = match version.split_once('-') {
+        Some((numbers, pre_release)) => (numbers, Some(pre_release)),
+        None => (version, None),
+    };
+    let numbers: Vec<u64> = numbers
+        .split('.')
+        .map(|number| number.parse().ok())
+        .collect::<Option<_>>()?;
+    let pre_release = match pre_release {
+        Some(pre_release) => pre_release
+            .split('.')
+            .map(|identifier| match identifier.parse() {
+                _ if identifier.is_empty() => None,
+                Ok(number) => Some(Identifier::Numeric(number)),
+                Err(_) => Some(Identifier::Alphanumeric(identifier.to_string())),
+            })
+            .collect::<Option<_>>()?,
+        None => Vec::new(),
+    };
+    Some(Version {
+        numbers: numbers.try_into().ok()?,
+        pre_release,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        parse_version(version).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            version("v1.2.3-rc.1+build.5"),
+            Version {
+                numbers: [1, 2, 3],
+                pre_release: vec![
+                    Identifier::Alphanumeric("rc".to_string()),
+                    Identifier::Numeric(1)
+                ],
+            }
+        );
+        assert_eq!(parse_version("1.2"), None);
+        assert_eq!(parse_version("1.2.x"), None);
+        assert_eq!(parse_version("1.2.3-"), None);
+    }
+
+    #[test]
+    fn precedence() {
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+            "1.10.0",
+            "2.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{pair:?}");
+        }
+        assert_eq!(version("v1.0.0+1"), version("1.0.0+2"));
+    }
+
+    #[test]
+    fn message() {
+        assert_eq!(signed_message("1.1.0", b"\x7fELF"), b"1.1.0\n\x7fELF");
+    }
+}
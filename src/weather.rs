@@ -0,0 +1,100 @@
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, FixedOffset};
+use log::{info, warn};
+use serde_json::Value;
+use tokio::{process::Command, select, sync::Mutex};
+
+use crate::{
+    config,
+    core::{timezone, ShutdownNotify},
+    SharedMutex,
+};
+
+const API_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, PartialEq, SimpleObject)]
+pub struct OutdoorWeather {
+    pub temperature_celsius: f32,
+    pub humidity_percents: f32,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+/// Fetches the current outdoor weather for the configured location from Open-Meteo.
+#[derive(Clone)]
+pub struct WeatherMonitor {
+    config: config::Weather,
+    location: config::Location,
+    current: SharedMutex<Option<OutdoorWeather>>,
+}
+
+impl WeatherMonitor {
+    pub fn new(config: config::Weather, location: config::Location) -> Self {
+        Self {
+            config,
+            location,
+            current: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// [None] if the weather is not fetched yet.
+    pub async fn current(&self) -> Option<OutdoorWeather> {
+        *self.current.lock().await
+    }
+
+    /// Update the weather periodically. Returns on shutdown.
+    pub async fn run(self, shutdown_notify: ShutdownNotify) {
+        loop {
+            let delay = match self.fetch().await {
+                Ok(weather) => {
+                    info!(
+                        "Outdoor weather updated: {} °C, {} %",
+                        weather.temperature_celsius, weather.humidity_percents
+                    );
+                    *self.current.lock().await = Some(weather);
+                    Duration::from_secs(self.config.update_interval_mins * 60)
+                }
+                Err(e) => {
+                    warn!("Failed to fetch the outdoor weather: {e}");
+                    RETRY_DELAY
+                }
+            };
+            select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown_notify.notified() => break,
+            }
+        }
+    }
+
+    async fn fetch(&self) -> Result<OutdoorWeather, String> {
+        let output = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--get"])
+            .arg("--data")
+            .arg(format!("latitude={}", self.location.latitude))
+            .arg("--data")
+            .arg(format!("longitude={}", self.location.longitude))
+            .args(["--data", "current=temperature_2m,relative_humidity_2m"])
+            .arg(API_URL)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let response: Value = serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+        let value = |name| {
+            response["current"][name]
+                .as_f64()
+                .map(|value| value as f32)
+                .ok_or_else(|| format!("{name} is missing in the response"))
+        };
+        Ok(OutdoorWeather {
+            temperature_celsius: value("temperature_2m")?,
+            humidity_percents: value("relative_humidity_2m")?,
+            updated_at: timezone::now(),
+        })
+    }
+}